@@ -0,0 +1,223 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! Simple sound playback
+//!
+//! Sounds are played using the waveOut API on a background thread, one thread
+//! per sound. Only uncompressed WAV data (PCM or IEEE float) is supported.
+
+use crate::logging::{debug, error};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic;
+
+use windows::Win32::Media;
+use windows::Win32::Media::Audio;
+use windows::Win32::Foundation;
+use windows::Win32::System::Threading;
+
+const WAVE_FORMAT_PCM       : u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// waveOut handles for sounds that are currently playing, by sound ID
+static SOUNDS: Mutex<Option<HashMap<u64, usize>>> = Mutex::new(None);
+static NEXT_ID: atomic::AtomicU64 = atomic::AtomicU64::new(1);
+
+/// Decoded WAV data, ready to be sent to a waveOut device.
+struct Wav {
+    format: Audio::WAVEFORMATEX,
+    data: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Parses a RIFF WAVE file.
+fn parse_wav(data: &[u8]) -> Result<Wav, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(String::from("Not a WAV file."));
+    }
+
+    let mut format: Option<Audio::WAVEFORMATEX> = None;
+    let mut samples: Option<Vec<u8>> = None;
+
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let chunk_id = &data[offset..offset + 4];
+        let chunk_size = read_u32(data, offset + 4) as usize;
+        let chunk_start = offset + 8;
+        let chunk_end = (chunk_start + chunk_size).min(data.len());
+
+        if chunk_id == b"fmt " {
+            if chunk_size < 16 { return Err(String::from("Invalid fmt chunk.")); }
+
+            let mut tag = read_u16(data, chunk_start);
+
+            // the actual format of an extensible WAV is the first two bytes of
+            // the sub format GUID
+            if tag == WAVE_FORMAT_EXTENSIBLE {
+                if chunk_size < 40 { return Err(String::from("Invalid extensible fmt chunk.")); }
+
+                tag = read_u16(data, chunk_start + 24);
+            }
+
+            if tag != WAVE_FORMAT_PCM && tag != WAVE_FORMAT_IEEE_FLOAT {
+                return Err(format!("Unsupported WAV format: {:#x}", tag));
+            }
+
+            format = Some(Audio::WAVEFORMATEX {
+                wFormatTag     : tag,
+                nChannels      : read_u16(data, chunk_start + 2),
+                nSamplesPerSec : read_u32(data, chunk_start + 4),
+                nAvgBytesPerSec: read_u32(data, chunk_start + 8),
+                nBlockAlign    : read_u16(data, chunk_start + 12),
+                wBitsPerSample : read_u16(data, chunk_start + 14),
+                cbSize         : 0,
+            });
+        } else if chunk_id == b"data" {
+            samples = Some(data[chunk_start..chunk_end].to_vec());
+        }
+
+        // chunks are padded to an even size
+        offset = chunk_start + chunk_size + (chunk_size & 1);
+    }
+
+    match (format, samples) {
+        (Some(format), Some(data)) => Ok(Wav { format: format, data: data }),
+        _ => Err(String::from("WAV file is missing fmt or data chunk.")),
+    }
+}
+
+/// Scales the samples in `wav` by `volume`.
+fn apply_volume(wav: &mut Wav, volume: f32) {
+    if volume >= 1.0 { return; }
+
+    match (wav.format.wFormatTag, wav.format.wBitsPerSample) {
+        (WAVE_FORMAT_PCM, 8) => {
+            // 8 bit samples are unsigned, centered on 128
+            for s in wav.data.iter_mut() {
+                *s = (((*s as f32 - 128.0) * volume) + 128.0) as u8;
+            }
+        },
+        (WAVE_FORMAT_PCM, 16) => {
+            for s in wav.data.chunks_exact_mut(2) {
+                let v = (i16::from_le_bytes([s[0], s[1]]) as f32 * volume) as i16;
+                s.copy_from_slice(&v.to_le_bytes());
+            }
+        },
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => {
+            for s in wav.data.chunks_exact_mut(4) {
+                let v = f32::from_le_bytes([s[0], s[1], s[2], s[3]]) * volume;
+                s.copy_from_slice(&v.to_le_bytes());
+            }
+        },
+        _ => {
+            debug!("Volume not supported for {} bit samples, playing at full volume.", wav.format.wBitsPerSample);
+        }
+    }
+}
+
+/// Starts playing the WAV file contained in `data` and returns an ID that can
+/// be used to stop it with [stop].
+///
+/// `volume` is a value from `0.0` to `1.0`. Playback happens on a background
+/// thread, this function returns immediately.
+pub fn play(data: &[u8], volume: f32) -> Result<u64, String> {
+    let mut wav = parse_wav(data)?;
+
+    apply_volume(&mut wav, volume.clamp(0.0, 1.0));
+
+    let event = match unsafe { Threading::CreateEventA(None, false, false, None) } {
+        Ok(e) => e,
+        Err(err) => return Err(format!("Couldn't create event: {}", err)),
+    };
+
+    let mut hwo = Audio::HWAVEOUT::default();
+
+    let r = unsafe { Audio::waveOutOpen(
+        Some(&mut hwo),
+        Audio::WAVE_MAPPER,
+        &wav.format,
+        event.0 as usize,
+        0,
+        Audio::CALLBACK_EVENT,
+    ) };
+
+    if r != Media::MMSYSERR_NOERROR {
+        unsafe { let _ = Foundation::CloseHandle(event); }
+        return Err(format!("Couldn't open audio device: {}", r));
+    }
+
+    let id = NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed);
+
+    SOUNDS.lock().unwrap().get_or_insert_with(HashMap::new).insert(id, hwo.0 as usize);
+
+    let hwo_addr = hwo.0 as usize;
+    let event_addr = event.0 as usize;
+
+    std::thread::Builder::new()
+        .name(format!("EG-Overlay Sound {}", id))
+        .spawn(move || {
+            let hwo = Audio::HWAVEOUT(hwo_addr as *mut std::ffi::c_void);
+            let event = Foundation::HANDLE(event_addr as *mut std::ffi::c_void);
+
+            let mut hdr = Audio::WAVEHDR {
+                lpData: windows::core::PSTR::from_raw(wav.data.as_mut_ptr()),
+                dwBufferLength: wav.data.len() as u32,
+                ..Default::default()
+            };
+
+            let hdrsize = std::mem::size_of::<Audio::WAVEHDR>() as u32;
+
+            unsafe {
+                if Audio::waveOutPrepareHeader(hwo, &mut hdr, hdrsize) == Media::MMSYSERR_NOERROR {
+                    if Audio::waveOutWrite(hwo, &mut hdr, hdrsize) == Media::MMSYSERR_NOERROR {
+                        // the event is signaled on open, close and when the buffer is done
+                        while std::ptr::read_volatile(&hdr.dwFlags) & Audio::WHDR_DONE == 0 {
+                            Threading::WaitForSingleObject(event, 1000);
+                        }
+                    } else {
+                        error!("Couldn't start sound {}.", id);
+                    }
+
+                    let _ = Audio::waveOutUnprepareHeader(hwo, &mut hdr, hdrsize);
+                } else {
+                    error!("Couldn't prepare sound {}.", id);
+                }
+
+                if let Some(sounds) = SOUNDS.lock().unwrap().as_mut() {
+                    sounds.remove(&id);
+                }
+
+                let _ = Audio::waveOutClose(hwo);
+                let _ = Foundation::CloseHandle(event);
+            }
+        })
+        .unwrap();
+
+    return Ok(id);
+}
+
+/// Stops the sound `id`, if it is still playing.
+///
+/// Returns `true` if the sound was playing.
+pub fn stop(id: u64) -> bool {
+    let sounds = SOUNDS.lock().unwrap();
+
+    if let Some(hwo) = sounds.as_ref().and_then(|s| s.get(&id)) {
+        // the playback thread will clean up once the buffer is marked done
+        unsafe { Audio::waveOutReset(Audio::HWAVEOUT(*hwo as *mut std::ffi::c_void)); }
+
+        return true;
+    }
+
+    return false;
+}
@@ -0,0 +1,180 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! GW2 chat codes
+//!
+//! GW2 shares items, waypoints and other game objects in chat as chat codes,
+//! `[&...]` where the text between `[&` and `]` is a base64 encoded binary
+//! payload. The first byte of the payload is the type of link, followed by
+//! type specific data with all integers in little endian order.
+//!
+//! Only the types that contain plain IDs are supported, build templates, PvP
+//! games and user links are not.
+use crate::utils;
+
+const TYPE_COIN     : u8 = 0x01;
+const TYPE_ITEM     : u8 = 0x02;
+const TYPE_OBJECTIVE: u8 = 0x0C;
+
+// links that contain only a single 4 byte ID, and the kind name used for them
+const ID_LINKS: [(u8, &str); 8] = [
+    (0x03, "npctext"),
+    (0x04, "waypoint"), // also used for points of interest and vistas
+    (0x06, "skill"),
+    (0x07, "trait"),
+    (0x09, "recipe"),
+    (0x0A, "skin"),
+    (0x0B, "outfit"),
+    (0x0E, "achievement"),
+];
+
+// item flags, each indicates an additional 4 byte ID after the item ID
+const ITEM_SKIN    : u8 = 0x80;
+const ITEM_UPGRADE1: u8 = 0x40;
+const ITEM_UPGRADE2: u8 = 0x20;
+
+/// The contents of a chat code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatCode {
+    /// An amount of coin, in copper.
+    Coin { amount: u32 },
+
+    /// A stack of items, with an optional skin and up to 2 upgrades.
+    Item { id: u32, count: u8, skin: Option<u32>, upgrades: Vec<u32> },
+
+    /// A WvW objective.
+    Objective { id: u32, map_id: u32 },
+
+    /// Any of the link types that contain a single ID, see [ChatCode::kind].
+    Id { kind: &'static str, id: u32 },
+}
+
+impl ChatCode {
+    /// The kind of link, i.e. `"item"` or `"waypoint"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChatCode::Coin { .. }      => "coin",
+            ChatCode::Item { .. }      => "item",
+            ChatCode::Objective { .. } => "objective",
+            ChatCode::Id { kind, .. }  => kind,
+        }
+    }
+
+    /// Returns the [ChatCode::Id] for `kind`, or [None] if it isn't an ID only
+    /// link type.
+    pub fn id(kind: &str, id: u32) -> Option<ChatCode> {
+        let (_, kind) = ID_LINKS.iter().find(|(_, k)| *k == kind)?;
+
+        Some(ChatCode::Id { kind: kind, id: id })
+    }
+
+    /// Parses a chat code, including the surrounding `[&` and `]`.
+    ///
+    /// Returns [None] if `code` is malformed or an unsupported type.
+    pub fn parse(code: &str) -> Option<ChatCode> {
+        let payload = code.trim().strip_prefix("[&")?.strip_suffix(']')?;
+        let data = utils::base64_decode(payload)?;
+
+        let mut reader = Reader { data: &data, pos: 1 };
+
+        let code = match *data.first()? {
+            TYPE_COIN => ChatCode::Coin { amount: reader.u32()? },
+            TYPE_ITEM => {
+                let count = reader.u8()?;
+                let id_flags = reader.u32()?;
+
+                let flags = (id_flags >> 24) as u8;
+
+                let skin = if flags & ITEM_SKIN != 0 { Some(reader.id()?) } else { None };
+
+                let mut upgrades: Vec<u32> = Vec::new();
+
+                if flags & ITEM_UPGRADE1 != 0 { upgrades.push(reader.id()?); }
+                if flags & ITEM_UPGRADE2 != 0 { upgrades.push(reader.id()?); }
+
+                ChatCode::Item { id: id_flags & 0xFFFFFF, count: count, skin: skin, upgrades: upgrades }
+            },
+            TYPE_OBJECTIVE => ChatCode::Objective { id: reader.u32()?, map_id: reader.u32()? },
+            t => {
+                let (_, kind) = ID_LINKS.iter().find(|(lt, _)| *lt == t)?;
+
+                ChatCode::Id { kind: kind, id: reader.u32()? }
+            },
+        };
+
+        // trailing data means this isn't the type of link it claims to be
+        if reader.pos != data.len() { return None; }
+
+        Some(code)
+    }
+
+    /// Encodes this as a chat code, including the surrounding `[&` and `]`.
+    pub fn encode(&self) -> String {
+        let mut data: Vec<u8> = Vec::new();
+
+        match self {
+            ChatCode::Coin { amount } => {
+                data.push(TYPE_COIN);
+                data.extend_from_slice(&amount.to_le_bytes());
+            },
+            ChatCode::Item { id, count, skin, upgrades } => {
+                let mut flags: u8 = 0;
+
+                if skin.is_some() { flags |= ITEM_SKIN; }
+                if upgrades.len() > 0 { flags |= ITEM_UPGRADE1; }
+                if upgrades.len() > 1 { flags |= ITEM_UPGRADE2; }
+
+                data.push(TYPE_ITEM);
+                data.push(*count);
+                data.extend_from_slice(&((id & 0xFFFFFF) | ((flags as u32) << 24)).to_le_bytes());
+
+                if let Some(s) = skin { data.extend_from_slice(&(s & 0xFFFFFF).to_le_bytes()); }
+
+                for u in upgrades.iter().take(2) {
+                    data.extend_from_slice(&(u & 0xFFFFFF).to_le_bytes());
+                }
+            },
+            ChatCode::Objective { id, map_id } => {
+                data.push(TYPE_OBJECTIVE);
+                data.extend_from_slice(&id.to_le_bytes());
+                data.extend_from_slice(&map_id.to_le_bytes());
+            },
+            ChatCode::Id { kind, id } => {
+                let (t, _) = ID_LINKS.iter().find(|(_, k)| k == kind).unwrap();
+
+                data.push(*t);
+                data.extend_from_slice(&id.to_le_bytes());
+            },
+        }
+
+        format!("[&{}]", utils::base64_encode(&data))
+    }
+}
+
+// reads little endian values from a chat code payload
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let v = *self.data.get(self.pos)?;
+        self.pos += 1;
+
+        Some(v)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    // IDs in item links are 3 bytes followed by a padding byte
+    fn id(&mut self) -> Option<u32> {
+        Some(self.u32()? & 0xFFFFFF)
+    }
+}
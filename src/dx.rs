@@ -9,6 +9,7 @@ pub mod lua;
 use std::sync::Mutex;
 use std::sync::Arc;
 use std::sync::MutexGuard;
+use std::sync::atomic;
 
 use std::collections::VecDeque;
 
@@ -38,12 +39,80 @@ use windows::core::Interface;
 /// displayed and can't be drawn to.
 const DX_FRAMES: u32 = 2;
 
-/// The number of SRV descriptors to allocate heap space for.
+/// The format of the depth/stencil buffer.
+///
+/// A 32 bit float is used because it pairs well with reversed depth, see
+/// [SwapChain::reverse_depth].
+pub const DEPTH_FORMAT: Dxgi::Common::DXGI_FORMAT = Dxgi::Common::DXGI_FORMAT_D32_FLOAT;
+
+/// The number of SRV descriptors to initially allocate heap space for.
 ///
 /// These descriptors are needed for textures, VBOs and other dynamic shader data
-/// that can't be set directly in the root signature.
+/// that can't be set directly in the root signature. The heap is doubled in
+/// size each time it runs out of space, up to [DX_MAX_SRV_DESCRIPTORS].
 const DX_SRV_DESCRIPTORS: u32 = 2048;
 
+/// The most descriptors a shader visible CBV/SRV/UAV heap can have on all
+/// hardware (resource binding tier 1).
+const DX_MAX_SRV_DESCRIPTORS: u32 = 1_000_000;
+
+/// An error from a fallible Direct3D operation.
+#[derive(Debug)]
+pub enum DxError {
+    /// The GPU device was removed, reset or hung. Nothing can be rendered
+    /// until the overlay is restarted.
+    DeviceRemoved(windows::core::Error),
+
+    /// There wasn't enough video or system memory for the resource.
+    OutOfMemory,
+
+    /// A compiled shader couldn't be loaded.
+    ShaderLoad(String),
+
+    /// The operation or parameters aren't supported, i.e. a texture larger than
+    /// the maximum size or an unknown format.
+    Unsupported(String),
+
+    /// Any other Direct3D error.
+    Api(windows::core::Error),
+}
+
+impl std::fmt::Display for DxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DxError::DeviceRemoved(err) => write!(f, "GPU device removed: {}", err),
+            DxError::OutOfMemory        => write!(f, "out of memory"),
+            DxError::ShaderLoad(msg)    => write!(f, "couldn't load shader: {}", msg),
+            DxError::Unsupported(msg)   => write!(f, "unsupported: {}", msg),
+            DxError::Api(err)           => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DxError {}
+
+impl From<windows::core::Error> for DxError {
+    fn from(err: windows::core::Error) -> Self {
+        match err.code() {
+            Dxgi::DXGI_ERROR_DEVICE_REMOVED |
+            Dxgi::DXGI_ERROR_DEVICE_RESET   |
+            Dxgi::DXGI_ERROR_DEVICE_HUNG    => DxError::DeviceRemoved(err),
+            Foundation::E_OUTOFMEMORY       => DxError::OutOfMemory,
+            Dxgi::DXGI_ERROR_UNSUPPORTED    |
+            Foundation::E_NOTIMPL           => DxError::Unsupported(err.message()),
+            _                               => DxError::Api(err),
+        }
+    }
+}
+
+/// Reads a compiled shader from `path`.
+pub fn read_shader(path: &str) -> Result<Vec<u8>, DxError> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(err) => Err(DxError::ShaderLoad(format!("{}: {}", path, err))),
+    }
+}
+
 /// Report D3D12 objects that are still alive.
 ///
 /// This will output any D3D12 objects that still have active references to them
@@ -68,23 +137,47 @@ pub fn report_live_objects() {
 /// The main Direct3D12 state.
 pub struct Dx {
     adapter: Dxgi::IDXGIAdapter4,
+    adapter_info: AdapterInfo,
     device: Direct3D12::ID3D12Device9,
 
     swapchain: Mutex<SwapChain>,
 
     copy_queue: Mutex<CopyQueue>,
 
-    srv_descriptorheap      : Direct3D12::ID3D12DescriptorHeap,
     srv_descriptorsize      : u32,
-    srv_descriptorheap_addresses: Mutex<DescriptorHeapAddresses>,
+    srv_heap                : Mutex<SrvHeap>,
+
+    // incremented each time the SRV heap is replaced, see SwapChain::set_texture
+    srv_heap_generation: atomic::AtomicU32,
+}
+
+/// Details about the GPU adapter, captured once in [Dx::new].
+struct AdapterInfo {
+    description: String,
+    vendor_id: u32,
+    device_id: u32,
+    budget: u64,
 }
 
-/// A record representing the next and resusable addresses in a descriptor heap.
+/// The SRV descriptor heaps and the next and reusable addresses in them.
+///
+/// Descriptors are created in `cpu_heap` and then copied to `heap`, which is
+/// the shader visible heap that is actually bound. Descriptors can't be copied
+/// out of a shader visible heap, so `cpu_heap` is kept to copy from when the
+/// heaps need to grow.
 ///
 /// This is separate so it can be in a [Mutex].
-struct DescriptorHeapAddresses {
+struct SrvHeap {
+    heap: Direct3D12::ID3D12DescriptorHeap,
+    cpu_heap: Direct3D12::ID3D12DescriptorHeap,
+    capacity: u32,
+
     next: u64,
     reuse: VecDeque<u64>,
+
+    // heaps that have been replaced. command lists that are still executing
+    // may reference these, so they are kept until Dx is dropped
+    retired: Vec<Direct3D12::ID3D12DescriptorHeap>,
 }
 
 impl Dx {
@@ -101,19 +194,14 @@ impl Dx {
             enable_debug_layer();
         }
 
-        let adapter = find_adapter();
+        let (adapter, adapter_info) = find_adapter();
         let device  = create_device(&adapter);
 
         let swapchain = Mutex::new(create_swapchain(&device, overlay::hwnd()));
         let copy_queue = Mutex::new(create_copyqueue(&device));
 
-        let srv_heap = create_descriptor_heap(
-            &device,
-            Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            DX_SRV_DESCRIPTORS,
-            Direct3D12::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE
-        );
-        object_set_name(&srv_heap, "EG-Overlay D3D12 SRV Descriptor Heap");
+        let (srv_heap, srv_cpu_heap) = create_srv_heaps(&device, DX_SRV_DESCRIPTORS);
+
         let srv_descriptorsize: u32 = unsafe {
             device.GetDescriptorHandleIncrementSize(Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV)
         };
@@ -125,18 +213,26 @@ impl Dx {
 
         return Arc::new(Dx {
             adapter: adapter,
+            adapter_info: adapter_info,
             device : device,
 
             swapchain: swapchain,
 
             copy_queue: copy_queue,
 
-            srv_descriptorheap: srv_heap,
             srv_descriptorsize: srv_descriptorsize,
-            srv_descriptorheap_addresses: Mutex::new( DescriptorHeapAddresses {
+            srv_heap: Mutex::new(SrvHeap {
+                heap: srv_heap,
+                cpu_heap: srv_cpu_heap,
+                capacity: DX_SRV_DESCRIPTORS,
+
                 next: 0,
                 reuse: VecDeque::new(),
+
+                retired: Vec::new(),
             }),
+
+            srv_heap_generation: atomic::AtomicU32::new(0),
         });
     }
 
@@ -151,6 +247,8 @@ impl Dx {
         if !swapchain.backbuffer_ready() { return None; }
 
         swapchain.frameind = unsafe { swapchain.swapchain.GetCurrentBackBufferIndex() };
+        swapchain.last_draw_calls = swapchain.draw_calls.swap(0, atomic::Ordering::Relaxed);
+        swapchain.last_culled = swapchain.culled.swap(0, atomic::Ordering::Relaxed);
         swapchain.flush_commands();
         swapchain.clear_backbuffer_resources();
         swapchain.clear_backbuffer_psos();
@@ -181,15 +279,16 @@ impl Dx {
             });
 
             swapchain.cmd_list.ResourceBarrier(&[barrier]);
-            swapchain.cmd_list.SetDescriptorHeaps(&[Some(self.srv_descriptorheap.clone())]);
             swapchain.cmd_list.SetGraphicsRootSignature(&swapchain.rootsig);
             swapchain.cmd_list.OMSetRenderTargets(1, Some(&rtv), false,  Some(&dsv));
             swapchain.cmd_list.ClearRenderTargetView(rtv, &clear_color, None);
-            swapchain.cmd_list.ClearDepthStencilView(dsv, Direct3D12::D3D12_CLEAR_FLAG_DEPTH, 1.0, 0, None);
+            swapchain.cmd_list.ClearDepthStencilView(dsv, Direct3D12::D3D12_CLEAR_FLAG_DEPTH, swapchain.depth_clear_value(), 0, None);
             swapchain.cmd_list.RSSetViewports(&[swapchain.base_viewport]);
             swapchain.cmd_list.RSSetScissorRects(&[swapchain.base_scissor]);
         }
 
+        self.bind_srv_heap(&mut swapchain);
+
         return Some(swapchain);
     }
 
@@ -208,6 +307,23 @@ impl Dx {
         self.swapchain.lock().unwrap().resize(hwnd);
     }
 
+    /// Sets the opacity of the entire overlay, from `0.0` to `1.0`.
+    ///
+    /// This is applied by DirectComposition after the overlay is rendered, so
+    /// it affects everything drawn without any cost to rendering.
+    pub fn set_opacity(&self, opacity: f32) {
+        self.swapchain.lock().unwrap().set_opacity(opacity);
+    }
+
+    /// Applies a DirectComposition effect to the entire overlay.
+    ///
+    /// Like [Dx::set_opacity] this is done by the system compositor, but
+    /// unlike opacity, filter effects are rendered by the compositor every
+    /// time the overlay is composited.
+    pub fn set_composition_effect(&self, effect: &CompositionEffect) {
+        self.swapchain.lock().unwrap().set_composition_effect(effect);
+    }
+
     /// Creates a new pipeline state.
     ///
     /// `desc` must be a valid pipeline state description; this function will
@@ -218,7 +334,7 @@ impl Dx {
         &self,
         desc: &mut Direct3D12::D3D12_GRAPHICS_PIPELINE_STATE_DESC,
         name: &str
-    ) -> Result<Direct3D12::ID3D12PipelineState,()> {
+    ) -> Result<Direct3D12::ID3D12PipelineState, DxError> {
         unsafe {
             // convince rust to copy the rootsig pointer without incrementing the
             // counter or releasing it later
@@ -226,30 +342,133 @@ impl Dx {
             desc.pRootSignature = std::mem::transmute_copy(&swapchain.rootsig);
         }
 
-        if let Ok(pso) = unsafe { self.device.CreateGraphicsPipelineState::<Direct3D12::ID3D12PipelineState>(desc) } {
-            object_set_name(&pso, name);
-            return Ok(pso);
-        } else {
-            return Err(());
-        }
+        let pso = unsafe { self.device.CreateGraphicsPipelineState::<Direct3D12::ID3D12PipelineState>(desc) }?;
+
+        object_set_name(&pso, name);
+
+        return Ok(pso);
     }
 
     /// Returns the next available location for a descriptor within the SRV heap.
-    fn get_new_srv_descriptor_loc(&self) -> u64 {
-        let mut addr = self.srv_descriptorheap_addresses.lock().unwrap();
-        if let Some(loc) = addr.reuse.pop_front() {
-            return loc;
-        } else {
-            let loc = addr.next;
-            addr.next += self.srv_descriptorsize as u64;
+    ///
+    /// The heap is grown if it is full.
+    fn get_new_srv_descriptor_loc(&self, heap: &mut SrvHeap) -> u64 {
+        if let Some(loc) = heap.reuse.pop_front() {
             return loc;
         }
+
+        if heap.next >= heap.capacity as u64 * self.srv_descriptorsize as u64 {
+            self.grow_srv_heap(heap);
+        }
+
+        let loc = heap.next;
+        heap.next += self.srv_descriptorsize as u64;
+
+        return loc;
+    }
+
+    /// Replaces the SRV heaps with ones twice the size, copying all existing
+    /// descriptors to the same locations in the new heaps.
+    ///
+    /// Panics if the heap is already at [DX_MAX_SRV_DESCRIPTORS].
+    fn grow_srv_heap(&self, heap: &mut SrvHeap) {
+        if heap.capacity >= DX_MAX_SRV_DESCRIPTORS {
+            panic!(
+                "SRV descriptor heap is full ({} descriptors), too many textures are loaded.",
+                heap.capacity
+            );
+        }
+
+        let capacity = (heap.capacity * 2).min(DX_MAX_SRV_DESCRIPTORS);
+
+        info!("SRV descriptor heap is full, growing from {} to {} descriptors.", heap.capacity, capacity);
+
+        let (new_heap, new_cpu_heap) = create_srv_heaps(&self.device, capacity);
+
+        let used = (heap.next / self.srv_descriptorsize as u64) as u32;
+
+        unsafe {
+            self.device.CopyDescriptorsSimple(
+                used,
+                new_cpu_heap.GetCPUDescriptorHandleForHeapStart(),
+                heap.cpu_heap.GetCPUDescriptorHandleForHeapStart(),
+                Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV
+            );
+            self.device.CopyDescriptorsSimple(
+                used,
+                new_heap.GetCPUDescriptorHandleForHeapStart(),
+                new_cpu_heap.GetCPUDescriptorHandleForHeapStart(),
+                Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV
+            );
+        }
+
+        let old_heap = std::mem::replace(&mut heap.heap, new_heap);
+        heap.retired.push(old_heap);
+        heap.cpu_heap = new_cpu_heap;
+        heap.capacity = capacity;
+
+        self.srv_heap_generation.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    /// Creates a shader resource view of `resource` in the SRV heap and returns
+    /// its location.
+    ///
+    /// If `desc` is [None] the default view for the resource is created.
+    fn new_srv(
+        &self,
+        resource: &Direct3D12::ID3D12Resource,
+        desc: Option<&Direct3D12::D3D12_SHADER_RESOURCE_VIEW_DESC>
+    ) -> u64 {
+        let mut heap = self.srv_heap.lock().unwrap();
+
+        let loc = self.get_new_srv_descriptor_loc(&mut heap);
+
+        let mut cpu_handle = unsafe { heap.cpu_heap.GetCPUDescriptorHandleForHeapStart() };
+        cpu_handle.ptr += loc as usize;
+
+        let mut gpu_heap_handle = unsafe { heap.heap.GetCPUDescriptorHandleForHeapStart() };
+        gpu_heap_handle.ptr += loc as usize;
+
+        unsafe {
+            self.device.CreateShaderResourceView(resource, desc.map(|d| d as *const _), cpu_handle);
+            self.device.CopyDescriptorsSimple(
+                1,
+                gpu_heap_handle,
+                cpu_handle,
+                Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV
+            );
+        }
+
+        return loc;
+    }
+
+    /// Binds the current SRV heap to `swapchain`'s command list.
+    ///
+    /// When drawing bindless this also sets the descriptor table of the entire
+    /// heap.
+    fn bind_srv_heap(&self, swapchain: &mut SwapChain) {
+        let heap = self.srv_heap.lock().unwrap();
+
+        unsafe {
+            swapchain.cmd_list.SetDescriptorHeaps(&[Some(heap.heap.clone())]);
+
+            swapchain.srv_gpu_start = heap.heap.GetGPUDescriptorHandleForHeapStart();
+
+            if swapchain.bindless {
+                swapchain.cmd_list.SetGraphicsRootDescriptorTable(2, swapchain.srv_gpu_start);
+            }
+        }
+
+        swapchain.srv_heap_generation = self.srv_heap_generation.load(atomic::Ordering::Relaxed);
     }
 
     /// Creates a new 2-dimensional [Texture] array.
     ///
     /// `levels` is the number of mip-map levels and should be at least 1.
     /// `size` is the number of layers.
+    ///
+    /// Returns [DxError::Unsupported] if `width` or `height` is larger than the
+    /// maximum texture size.
     pub fn new_texture_2d_array(
         self: &Arc<Self>,
         format: Dxgi::Common::DXGI_FORMAT,
@@ -257,7 +476,9 @@ impl Dx {
         height: u32,
         size: u16,
         levels: u16
-    ) -> Texture {
+    ) -> Result<Texture, DxError> {
+        check_texture_size(width, height)?;
+
         let mut heapprops = Direct3D12::D3D12_HEAP_PROPERTIES::default();
         heapprops.Type                 = Direct3D12::D3D12_HEAP_TYPE_DEFAULT;
         heapprops.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
@@ -279,57 +500,58 @@ impl Dx {
 
         let mut texptr: Option<Direct3D12::ID3D12Resource> = None;
 
-        let r = unsafe { self.device.CreateCommittedResource(
+        unsafe { self.device.CreateCommittedResource(
             &heapprops,
             heapflag,
             &resdesc,
             Direct3D12::D3D12_RESOURCE_STATE_COMMON,
             None,
             &mut texptr
-        ) };
-
-        if r.is_err() {
-            panic!("Couldn't create texture!");
-        }
+        ) }?;
 
         let tex = texptr.unwrap();
 
-        let srvheap_loc = self.get_new_srv_descriptor_loc();
-
-        let mut gpu_desc_handle = unsafe { self.srv_descriptorheap.GetGPUDescriptorHandleForHeapStart() };
-        gpu_desc_handle.ptr += srvheap_loc;
+        // the default view of an array with a single layer is a plain 2D
+        // texture, so the array view is explicit
+        let mut srvdesc = Direct3D12::D3D12_SHADER_RESOURCE_VIEW_DESC::default();
+        srvdesc.Format                                   = format;
+        srvdesc.ViewDimension                            = Direct3D12::D3D12_SRV_DIMENSION_TEXTURE2DARRAY;
+        srvdesc.Shader4ComponentMapping                  = Direct3D12::D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING;
+        srvdesc.Anonymous.Texture2DArray.MostDetailedMip = 0;
+        srvdesc.Anonymous.Texture2DArray.MipLevels       = levels as u32;
+        srvdesc.Anonymous.Texture2DArray.FirstArraySlice = 0;
+        srvdesc.Anonymous.Texture2DArray.ArraySize       = size as u32;
 
-        let mut tex_srvhandle = unsafe { self.srv_descriptorheap.GetCPUDescriptorHandleForHeapStart() };
-        tex_srvhandle.ptr += srvheap_loc as usize;
+        let srvheap_loc = self.new_srv(&tex, Some(&srvdesc));
 
-        unsafe { self.device.CreateShaderResourceView(&tex, None, tex_srvhandle) };
-
-        Texture {
-            /*
+        Ok(Texture {
             width: width,
             height: height,
             depth: size,
-            */
 
             srvheap_loc: srvheap_loc,
 
             texture: tex,
-            gpu_descriptor_handle: gpu_desc_handle,
 
             dx: self.clone(),
-        }
+        })
     }
 
     /// Creates a new 2-dimensional [Texture].
     ///
     /// `levels` is the number of mip-map levels and should be at least 1.
+    ///
+    /// Returns [DxError::Unsupported] if `width` or `height` is larger than the
+    /// maximum texture size.
     pub fn new_texture_2d(
         self: &Arc<Self>,
         format: Dxgi::Common::DXGI_FORMAT,
         width: u32,
         height: u32,
         levels: u16
-    ) -> Texture {
+    ) -> Result<Texture, DxError> {
+        check_texture_size(width, height)?;
+
         let mut heapprops = Direct3D12::D3D12_HEAP_PROPERTIES::default();
         heapprops.Type                 = Direct3D12::D3D12_HEAP_TYPE_DEFAULT;
         heapprops.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
@@ -351,45 +573,147 @@ impl Dx {
 
         let mut texptr: Option<Direct3D12::ID3D12Resource> = None;
 
-        let r = unsafe { self.device.CreateCommittedResource(
+        unsafe { self.device.CreateCommittedResource(
             &heapprops,
             heapflag,
             &resdesc,
             Direct3D12::D3D12_RESOURCE_STATE_COMMON,
             None,
             &mut texptr
-        ) };
-
-        if r.is_err() {
-            panic!("Couldn't create texture!");
-        }
+        ) }?;
 
         let tex = texptr.unwrap();
 
-        let srvheap_loc = self.get_new_srv_descriptor_loc();
+        let srvheap_loc = self.new_srv(&tex, None);
 
-        let mut gpu_desc_handle = unsafe { self.srv_descriptorheap.GetGPUDescriptorHandleForHeapStart() };
-        gpu_desc_handle.ptr += srvheap_loc;
-
-        let mut tex_srvhandle = unsafe { self.srv_descriptorheap.GetCPUDescriptorHandleForHeapStart() };
-        tex_srvhandle.ptr += srvheap_loc as usize;
-
-        unsafe { self.device.CreateShaderResourceView(&tex, None, tex_srvhandle) };
-
-        Texture {
-            /*
+        Ok(Texture {
             width: width,
             height: height,
-            depth: size,
-            */
+            depth: 1,
 
             srvheap_loc: srvheap_loc,
 
             texture: tex,
-            gpu_descriptor_handle: gpu_desc_handle,
 
             dx: self.clone(),
-        }
+        })
+    }
+
+    /// Creates a new [RenderTarget] the same format as the swapchain backbuffers.
+    ///
+    /// `name` is used to set the object name, used during debugging.
+    ///
+    /// Returns [DxError::Unsupported] if `width` or `height` is larger than the
+    /// maximum texture size.
+    pub fn new_render_target(self: &Arc<Self>, width: u32, height: u32, name: &str) -> Result<RenderTarget, DxError> {
+        check_texture_size(width, height)?;
+
+        let format = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
+
+        let mut heapprops = Direct3D12::D3D12_HEAP_PROPERTIES::default();
+        heapprops.Type                 = Direct3D12::D3D12_HEAP_TYPE_DEFAULT;
+        heapprops.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
+        heapprops.MemoryPoolPreference = Direct3D12::D3D12_MEMORY_POOL_UNKNOWN;
+
+        let mut resdesc = Direct3D12::D3D12_RESOURCE_DESC::default();
+        resdesc.Dimension        = Direct3D12::D3D12_RESOURCE_DIMENSION_TEXTURE2D;
+        resdesc.Alignment        = 0;
+        resdesc.Width            = width as u64;
+        resdesc.Height           = height;
+        resdesc.DepthOrArraySize = 1;
+        resdesc.MipLevels        = 1;
+        resdesc.Format           = format;
+        resdesc.SampleDesc.Count = 1;
+        resdesc.Layout           = Direct3D12::D3D12_TEXTURE_LAYOUT_UNKNOWN;
+        resdesc.Flags            = Direct3D12::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET;
+
+        let mut clear = Direct3D12::D3D12_CLEAR_VALUE::default();
+        clear.Format = format;
+
+        let mut texptr: Option<Direct3D12::ID3D12Resource> = None;
+
+        // render targets spend most of their time being read by shaders, they
+        // are only transitioned to a render target by SwapChain::push_render_target
+        unsafe { self.device.CreateCommittedResource(
+            &heapprops,
+            Direct3D12::D3D12_HEAP_FLAG_NONE,
+            &resdesc,
+            Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            Some(&clear),
+            &mut texptr
+        ) }?;
+
+        let tex = texptr.unwrap();
+        object_set_name(&tex, name);
+
+        let srvheap_loc = self.new_srv(&tex, None);
+
+        let rtv_heap = create_descriptor_heap(
+            &self.device,
+            Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+            1,
+            Direct3D12::D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+        );
+
+        unsafe { self.device.CreateRenderTargetView(&tex, None, rtv_heap.GetCPUDescriptorHandleForHeapStart()); }
+
+        Ok(RenderTarget {
+            width: width,
+            height: height,
+
+            rtv_descriptorheap: rtv_heap,
+
+            ds_descriptorheap: None,
+            ds_buffer        : None,
+
+            texture: Texture {
+                width: width,
+                height: height,
+                depth: 1,
+
+                srvheap_loc: srvheap_loc,
+
+                texture: tex,
+
+                dx: self.clone(),
+            },
+        })
+    }
+
+    /// Creates a new [RenderTarget] with its own depth buffer, for rendering
+    /// the 3D scene at a different size than the backbuffer.
+    ///
+    /// `reverse_depth` should be [SwapChain::reverse_depth]. See
+    /// [SwapChain::push_scene_target].
+    pub fn new_scene_target(
+        self: &Arc<Self>,
+        width: u32,
+        height: u32,
+        reverse_depth: bool,
+        name: &str
+    ) -> Result<RenderTarget, DxError> {
+        let mut target = self.new_render_target(width, height, name)?;
+
+        let ds_heap = create_descriptor_heap(
+            &self.device,
+            Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
+            1,
+            Direct3D12::D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+        );
+
+        let ds_buffer = create_depth_buffer(
+            &self.device,
+            &ds_heap,
+            width,
+            height,
+            if reverse_depth { 0.0 } else { 1.0 },
+            format!("{} Depth/Stencil Buffer", name).as_str()
+        );
+
+        target.ds_descriptorheap = Some(ds_heap);
+        target.ds_buffer = Some(ds_buffer);
+
+        Ok(target)
     }
 
     pub fn new_vertex_buffer(&self, size: u64) -> Direct3D12::ID3D12Resource {
@@ -453,6 +777,13 @@ impl Dx {
 
         buffer.unwrap()
     }
+    /// Creates a buffer that the GPU can copy to and the CPU can read from.
+    ///
+    /// See [SwapChain::copy_render_target_to_buffer].
+    pub fn new_readback_buffer(&self, size: u64) -> Direct3D12::ID3D12Resource {
+        create_readback_buffer(&self.device, size)
+    }
+
     /// Locks and returns the copy command queue, which can be used to perform
     /// memory transfers between the CPU and the GPU.
     pub fn copy_queue(&self) -> MutexGuard<'_, CopyQueue> {
@@ -470,6 +801,22 @@ impl Dx {
 
         return info.CurrentUsage;
     }
+
+    /// The description (name) of the GPU adapter.
+    pub fn adapter_description(&self) -> String {
+        self.adapter_info.description.clone()
+    }
+
+    /// The PCI vendor and device IDs of the GPU adapter.
+    pub fn adapter_ids(&self) -> (u32, u32) {
+        (self.adapter_info.vendor_id, self.adapter_info.device_id)
+    }
+
+    /// The video memory budget, in bytes, as reported when the adapter was
+    /// initialized.
+    pub fn memory_budget(&self) -> u64 {
+        self.adapter_info.budget
+    }
 }
 
 /// The copy command queue resources.
@@ -516,6 +863,38 @@ impl CopyQueue {
         unsafe { self.cmd_queue.ExecuteCommandLists(&[Some(self.cmd_list.clone().into())]); }
         self.flush_commands();
     }
+
+    /// Copies `size` bytes from the start of `from` to `to` at `dst_offset`.
+    pub fn copy_buffer_region(
+        &mut self,
+        from: &Direct3D12::ID3D12Resource,
+        to: &Direct3D12::ID3D12Resource,
+        dst_offset: u64,
+        size: u64
+    ) {
+        self.copy_buffer_regions(from, to, &[(0, dst_offset, size)]);
+    }
+
+    /// Copies multiple regions from `from` to `to` at once.
+    ///
+    /// Each region is a source offset, destination offset and size in bytes.
+    pub fn copy_buffer_regions(
+        &mut self,
+        from: &Direct3D12::ID3D12Resource,
+        to: &Direct3D12::ID3D12Resource,
+        regions: &[(u64, u64, u64)]
+    ) {
+        self.reset();
+
+        for (src_offset, dst_offset, size) in regions {
+            unsafe { self.cmd_list.CopyBufferRegion(to, *dst_offset, from, *src_offset, *size) };
+        }
+
+        unsafe { self.cmd_list.Close() }.expect("Couldn't close copy command list.");
+
+        unsafe { self.cmd_queue.ExecuteCommandLists(&[Some(self.cmd_list.clone().into())]); }
+        self.flush_commands();
+    }
 }
 
 /// The swapchain resource lock. Typically this represents a frame.
@@ -538,6 +917,9 @@ pub struct SwapChain {
     comp_visual_ptr: usize,
     comp_target_ptr: usize,
 
+    // the opacity of the visual, the starting point for fades
+    comp_opacity: f32,
+
     frameind: u32,
 
     fence: Direct3D12::ID3D12Fence,
@@ -554,6 +936,9 @@ pub struct SwapChain {
     ds_descriptorheap : Direct3D12::ID3D12DescriptorHeap,
     ds_buffer         : Option<Direct3D12::ID3D12Resource>,
 
+    reverse_depth: bool,
+    bindless: bool,
+
     base_scissor: Foundation::RECT,
     base_viewport: Direct3D12::D3D12_VIEWPORT,
 
@@ -564,8 +949,25 @@ pub struct SwapChain {
     scissors: VecDeque<Foundation::RECT>,
     viewports: VecDeque<Direct3D12::D3D12_VIEWPORT>,
 
+    // the size of the current scene target relative to the backbuffer, see
+    // push_scene_target
+    scene_scale: f32,
+
     backbuffer_resources: Vec<Vec<Direct3D12::ID3D12Resource>>,
     backbuffer_psos: Vec<Vec<Direct3D12::ID3D12PipelineState>>,
+
+    // draw calls made during the current and prior frame, see draw_calls
+    draw_calls: atomic::AtomicU32,
+    last_draw_calls: u32,
+
+    // objects skipped by frustum culling during the current and prior frame,
+    // see culled
+    culled: atomic::AtomicU32,
+    last_culled: u32,
+
+    // the SRV heap bound for this frame, see Dx::bind_srv_heap
+    srv_heap_generation: u32,
+    srv_gpu_start: Direct3D12::D3D12_GPU_DESCRIPTOR_HANDLE,
 }
 
 impl SwapChain {
@@ -577,6 +979,62 @@ impl SwapChain {
         self.rtv_height
     }
 
+    /// The number of draw calls made during the previous frame.
+    pub fn draw_calls(&self) -> u32 {
+        self.last_draw_calls
+    }
+
+    /// The number of draw calls made so far during the current frame.
+    pub fn frame_draw_calls(&self) -> u32 {
+        self.draw_calls.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Records that `count` objects were skipped by frustum culling during
+    /// this frame.
+    pub fn add_culled(&self, count: u32) {
+        self.culled.fetch_add(count, atomic::Ordering::Relaxed);
+    }
+
+    /// The number of objects skipped by frustum culling during the previous
+    /// frame.
+    pub fn culled(&self) -> u32 {
+        self.last_culled
+    }
+
+    /// The number of samples per pixel of the render target.
+    ///
+    /// The overlay does not currently render with MSAA, so this is always 1.
+    pub fn sample_count(&self) -> u32 {
+        1
+    }
+
+    /// Returns `true` if the depth buffer is reversed.
+    ///
+    /// When reversed, the near plane is at depth 1.0 and the far plane at 0.0,
+    /// the depth buffer is cleared to 0.0 and depth tests must use
+    /// `D3D12_COMPARISON_FUNC_GREATER`. Combined with a floating point depth
+    /// buffer this gives much better precision for distant objects.
+    ///
+    /// This is set by the `overlay.reverseDepth` setting.
+    pub fn reverse_depth(&self) -> bool {
+        self.reverse_depth
+    }
+
+    /// Returns `true` if textures can be accessed by their index in the SRV
+    /// descriptor heap, see [Texture::srv_index].
+    ///
+    /// When this is `true` the root signature has a third parameter, a
+    /// descriptor table of the entire SRV heap in `space1`, that is set at the
+    /// start of each frame. Otherwise textures must be bound with [SwapChain::set_texture].
+    pub fn bindless(&self) -> bool {
+        self.bindless
+    }
+
+    /// The value the depth buffer is cleared to each frame.
+    fn depth_clear_value(&self) -> f32 {
+        if self.reverse_depth { 0.0 } else { 1.0 }
+    }
+
     /// Updates the render target views and corresponding backbuffer resources.
     fn update_rtvs(&mut self) {
         unsafe {
@@ -596,49 +1054,359 @@ impl SwapChain {
 
     /// (Re)Creates the depth/stencil buffer.
     fn create_dsbuffer(&mut self) {
-        let mut dsvprops = Direct3D12::D3D12_HEAP_PROPERTIES::default();
-        dsvprops.Type                 = Direct3D12::D3D12_HEAP_TYPE_DEFAULT;
-        dsvprops.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
-        dsvprops.MemoryPoolPreference = Direct3D12::D3D12_MEMORY_POOL_UNKNOWN;
+        self.ds_buffer = None;
 
-        let mut desc = Direct3D12::D3D12_RESOURCE_DESC::default();
-        desc.Dimension        = Direct3D12::D3D12_RESOURCE_DIMENSION_TEXTURE2D;
-        desc.Alignment        = 0;
-        desc.Width            = self.rtv_width as u64;
-        desc.Height           = self.rtv_height;
-        desc.DepthOrArraySize = 1;
-        desc.MipLevels        = 1;
-        desc.Format           = Dxgi::Common::DXGI_FORMAT_D32_FLOAT;
-        desc.SampleDesc.Count = 1;
-        desc.Layout           = Direct3D12::D3D12_TEXTURE_LAYOUT_UNKNOWN;
-        desc.Flags            =
-            Direct3D12::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL |
-            Direct3D12::D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE;
+        self.ds_buffer = Some(create_depth_buffer(
+            &self.device,
+            &self.ds_descriptorheap,
+            self.rtv_width,
+            self.rtv_height,
+            self.depth_clear_value(),
+            "EG-Overlay D3D12 Depth/Stencil Buffer"
+        ));
+    }
 
-        let mut clear = Direct3D12::D3D12_CLEAR_VALUE::default();
-        clear.Format               = Dxgi::Common::DXGI_FORMAT_D32_FLOAT;
-        clear.Anonymous.DepthStencil.Depth   = 1.0;
-        clear.Anonymous.DepthStencil.Stencil = 0;
+    /// Returns the DirectComposition device and root visual.
+    ///
+    /// These are owned by the swapchain and must not be released.
+    fn comp_objects(&self) -> (
+        std::mem::ManuallyDrop<DirectComposition::IDCompositionDesktopDevice>,
+        std::mem::ManuallyDrop<DirectComposition::IDCompositionVisual3>,
+    ) {
+        unsafe {(
+            std::mem::ManuallyDrop::new(DirectComposition::IDCompositionDesktopDevice::from_raw(self.comp_dev_ptr as *mut std::ffi::c_void)),
+            std::mem::ManuallyDrop::new(DirectComposition::IDCompositionVisual3::from_raw(self.comp_visual_ptr as *mut std::ffi::c_void)),
+        )}
+    }
 
-        self.ds_buffer = None;
+    fn set_opacity(&mut self, opacity: f32) {
+        let (comp_dev, comp_visual) = self.comp_objects();
+
+        let opacity = opacity.clamp(0.0, 1.0);
 
         unsafe {
-            self.device.CreateCommittedResource(
-                &dsvprops,
-                Direct3D12::D3D12_HEAP_FLAG_NONE,
-                &desc,
-                Direct3D12::D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                Some(&clear),
-                &mut self.ds_buffer
-            ).expect("Failed to create Depth/Stencil buffer.");
+            if let Err(err) = comp_visual.SetOpacity2(opacity) {
+                error!("Couldn't set overlay opacity: {}", err);
+                return;
+            }
+
+            comp_dev.Commit().expect("Couldn't commit DirectComposition device.");
+        }
+
+        self.comp_opacity = opacity;
+    }
+
+    fn set_composition_effect(&mut self, effect: &CompositionEffect) {
+        let (comp_dev, comp_visual) = self.comp_objects();
+
+        let r = match effect {
+            CompositionEffect::Fade { opacity, duration } => self.fade(&comp_dev, &comp_visual, *opacity, *duration),
+            _                                             => set_visual_effect(&comp_dev, &comp_visual, effect),
+        };
+
+        if let Err(err) = r {
+            error!("Couldn't set composition effect: {}", err);
+            return;
         }
 
-        object_set_name(&self.ds_buffer.as_ref().unwrap(), "EG-Overlay D3D12 Depth/Stencil Buffer");
+        unsafe { comp_dev.Commit().expect("Couldn't commit DirectComposition device."); }
+    }
+
+    /// Animates the opacity of the visual from its current value to `opacity`.
+    fn fade(
+        &mut self,
+        comp_dev: &DirectComposition::IDCompositionDesktopDevice,
+        comp_visual: &DirectComposition::IDCompositionVisual3,
+        opacity: f32,
+        duration: f64,
+    ) -> windows::core::Result<()> {
+        let opacity = opacity.clamp(0.0, 1.0);
+
+        if duration <= 0.0 {
+            unsafe { comp_visual.SetOpacity2(opacity)?; }
+        } else {
+            let from = self.comp_opacity;
+
+            unsafe {
+                let anim = comp_dev.CreateAnimation()?;
+
+                // a linear segment from the current opacity, then hold the final value
+                anim.AddCubic(0.0, from, (opacity - from) / duration as f32, 0.0, 0.0)?;
+                anim.End(duration, opacity)?;
+
+                comp_visual.SetOpacity(&anim)?;
+            }
+        }
+
+        self.comp_opacity = opacity;
+
+        Ok(())
+    }
+
+    /// Returns the render target view of the current backbuffer.
+    fn backbuffer_rtv(&self) -> Direct3D12::D3D12_CPU_DESCRIPTOR_HANDLE {
+        let mut rtv = unsafe { self.rtv_descriptorheap.GetCPUDescriptorHandleForHeapStart() };
+
+        rtv.ptr += (self.frameind * self.rtv_descriptorsize) as usize;
+
+        rtv
+    }
+
+    /// Begins rendering to `target` instead of the current backbuffer.
+    ///
+    /// `target` is cleared. If `depth` is `true` the depth buffer is also
+    /// cleared and bound, otherwise no depth buffer is bound while `target` is
+    /// in use. [SwapChain::pop_render_target] must be called before the frame
+    /// ends.
+    ///
+    /// `target` should be the same size as the backbuffer, the viewport and
+    /// scissor are not changed.
+    pub fn push_render_target(&mut self, target: &RenderTarget, depth: bool) {
+        self.add_backbuffer_resources(&target.texture.texture);
+
+        let clear_color: [f32;4] = [0.0, 0.0, 0.0, 0.0];
+
+        let rtv = unsafe { target.rtv_descriptorheap.GetCPUDescriptorHandleForHeapStart() };
+
+        unsafe {
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &target.texture.texture,
+                Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                Direct3D12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+            )]);
+            self.cmd_list.ClearRenderTargetView(rtv, &clear_color, None);
+
+            if depth {
+                let dsv = self.ds_descriptorheap.GetCPUDescriptorHandleForHeapStart();
+
+                self.cmd_list.OMSetRenderTargets(1, Some(&rtv), false, Some(&dsv));
+                self.cmd_list.ClearDepthStencilView(dsv, Direct3D12::D3D12_CLEAR_FLAG_DEPTH, self.depth_clear_value(), 0, None);
+            } else {
+                self.cmd_list.OMSetRenderTargets(1, Some(&rtv), false, None);
+            }
+        }
+    }
+
+    /// Ends rendering to `target`, which must be the target given to the last
+    /// call to [SwapChain::push_render_target], and resumes rendering to the
+    /// backbuffer.
+    pub fn pop_render_target(&mut self, target: &RenderTarget) {
+        let rtv = self.backbuffer_rtv();
+
+        unsafe {
+            let dsv = self.ds_descriptorheap.GetCPUDescriptorHandleForHeapStart();
+
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &target.texture.texture,
+                Direct3D12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            )]);
+            self.cmd_list.OMSetRenderTargets(1, Some(&rtv), false, Some(&dsv));
+        }
+    }
+
+    /// Begins rendering to `target`, a scene target created with
+    /// [Dx::new_scene_target], instead of the current backbuffer.
+    ///
+    /// `target` is `scale` times the size of the backbuffer. Everything is
+    /// still drawn using backbuffer coordinates, the viewport is scaled to
+    /// fit `target`, including viewports set with [SwapChain::push_viewport]
+    /// until [SwapChain::pop_scene_target] is called.
+    ///
+    /// `target` and its depth buffer are cleared.
+    pub fn push_scene_target(&mut self, target: &RenderTarget, scale: f32) {
+        self.add_backbuffer_resources(&target.texture.texture);
+        self.add_backbuffer_resources(target.ds_buffer.as_ref().expect("push_scene_target: not a scene target."));
+
+        let clear_color: [f32;4] = [0.0, 0.0, 0.0, 0.0];
+
+        let rtv = unsafe { target.rtv_descriptorheap.GetCPUDescriptorHandleForHeapStart() };
+        let dsv = unsafe { target.ds_descriptorheap.as_ref().unwrap().GetCPUDescriptorHandleForHeapStart() };
+
+        self.scene_scale = scale;
+
+        let vp = self.scaled_viewport(self.viewports.front().unwrap_or(&self.base_viewport));
+
+        unsafe {
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &target.texture.texture,
+                Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                Direct3D12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+            )]);
+            self.cmd_list.ClearRenderTargetView(rtv, &clear_color, None);
+            self.cmd_list.ClearDepthStencilView(dsv, Direct3D12::D3D12_CLEAR_FLAG_DEPTH, self.depth_clear_value(), 0, None);
+            self.cmd_list.OMSetRenderTargets(1, Some(&rtv), false, Some(&dsv));
+            self.cmd_list.RSSetViewports(&[vp]);
+        }
+    }
+
+    /// Ends rendering to `target`, which must be the target given to the last
+    /// call to [SwapChain::push_scene_target], and resumes rendering to the
+    /// backbuffer at full size.
+    pub fn pop_scene_target(&mut self, target: &RenderTarget) {
+        self.scene_scale = 1.0;
+
+        self.pop_render_target(target);
+
+        let vp = *self.viewports.front().unwrap_or(&self.base_viewport);
+
+        unsafe { self.cmd_list.RSSetViewports(&[vp]); }
+    }
+
+    /// Returns `vp` scaled to the current scene target.
+    fn scaled_viewport(&self, vp: &Direct3D12::D3D12_VIEWPORT) -> Direct3D12::D3D12_VIEWPORT {
+        let mut scaled = *vp;
+
+        scaled.TopLeftX *= self.scene_scale;
+        scaled.TopLeftY *= self.scene_scale;
+        scaled.Width    *= self.scene_scale;
+        scaled.Height   *= self.scene_scale;
+
+        scaled
+    }
+
+    /// Copies the contents of `target` to `buffer`.
+    ///
+    /// `buffer` must be a readback buffer (see [Dx::new_readback_buffer]) at
+    /// least `row_pitch` x the height of `target` bytes, and `row_pitch` must
+    /// be a multiple of 256. The copy is done as part of the current frame, so
+    /// `buffer` can't be read until the frame's commands have completed.
+    pub fn copy_render_target_to_buffer(&mut self, target: &RenderTarget, buffer: &Direct3D12::ID3D12Resource, row_pitch: u32) {
+        self.add_backbuffer_resources(&target.texture.texture);
+        self.add_backbuffer_resources(buffer);
+
+        let mut srcloc = Direct3D12::D3D12_TEXTURE_COPY_LOCATION::default();
+        srcloc.pResource                  = unsafe { std::mem::transmute_copy(&target.texture.texture) };
+        srcloc.Type                       = Direct3D12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX;
+        srcloc.Anonymous.SubresourceIndex = 0;
+
+        let mut dstloc = Direct3D12::D3D12_TEXTURE_COPY_LOCATION::default();
+        dstloc.pResource = unsafe { std::mem::transmute_copy(buffer) };
+        dstloc.Type      = Direct3D12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT;
+
+        dstloc.Anonymous.PlacedFootprint.Offset = 0;
+
+        dstloc.Anonymous.PlacedFootprint.Footprint.Format   = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Width    = target.width;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Height   = target.height;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Depth    = 1;
+        dstloc.Anonymous.PlacedFootprint.Footprint.RowPitch = row_pitch;
+
+        unsafe {
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &target.texture.texture,
+                Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                Direct3D12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+            )]);
+            self.cmd_list.CopyTextureRegion(&dstloc, 0, 0, 0, &srcloc, None);
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &target.texture.texture,
+                Direct3D12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+                Direct3D12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            )]);
+        }
+    }
+
+    /// Copies the current backbuffer to the CPU and returns its pixels.
+    ///
+    /// The result is `render_target_width` x `render_target_height` pixels, 4
+    /// bytes per pixel in BGRA order with premultiplied alpha. Rows are from
+    /// top to bottom with no padding.
+    ///
+    /// This executes the frame's commands so far and waits for them to finish.
+    /// The command list is reset without any state afterwards, so this must be
+    /// the last thing done before [end_frame](SwapChain::end_frame).
+    pub fn capture_backbuffer(&mut self) -> Vec<u8> {
+        let backbuffer = self.backbuffers[self.frameind as usize].clone();
+
+        let width = self.rtv_width;
+        let height = self.rtv_height;
+
+        // rows in the readback buffer must be aligned to 256 bytes
+        let row_pitch = (width * 4 + 255) & !255;
+        let row_size = (width * 4) as usize;
+
+        let buffer = create_readback_buffer(&self.device, row_pitch as u64 * height as u64);
+
+        let mut srcloc = Direct3D12::D3D12_TEXTURE_COPY_LOCATION::default();
+        srcloc.pResource                  = unsafe { std::mem::transmute_copy(&backbuffer) };
+        srcloc.Type                       = Direct3D12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX;
+        srcloc.Anonymous.SubresourceIndex = 0;
+
+        let mut dstloc = Direct3D12::D3D12_TEXTURE_COPY_LOCATION::default();
+        dstloc.pResource = unsafe { std::mem::transmute_copy(&buffer) };
+        dstloc.Type      = Direct3D12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT;
+
+        dstloc.Anonymous.PlacedFootprint.Offset = 0;
+
+        dstloc.Anonymous.PlacedFootprint.Footprint.Format   = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Width    = width;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Height   = height;
+        dstloc.Anonymous.PlacedFootprint.Footprint.Depth    = 1;
+        dstloc.Anonymous.PlacedFootprint.Footprint.RowPitch = row_pitch;
 
         unsafe {
-            let dsvhandle = self.ds_descriptorheap.GetCPUDescriptorHandleForHeapStart();
-            self.device.CreateDepthStencilView(self.ds_buffer.as_ref().unwrap(), None, dsvhandle);
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &backbuffer,
+                Direct3D12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                Direct3D12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+            )]);
+            self.cmd_list.CopyTextureRegion(&dstloc, 0, 0, 0, &srcloc, None);
+            self.cmd_list.ResourceBarrier(&[render_target_barrier(
+                &backbuffer,
+                Direct3D12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+                Direct3D12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+            )]);
+
+            self.cmd_list.Close().expect("Failed to close command list.");
+
+            self.cmd_queue.ExecuteCommandLists(&[Some(self.cmd_list.clone().into())]);
         }
+
+        self.flush_commands();
+
+        // the commands are complete, so the allocator can be reused for the
+        // rest of the frame
+        let alloc = &self.cmd_allocs[self.frameind as usize];
+
+        unsafe {
+            alloc.Reset().unwrap();
+            self.cmd_list.Reset(alloc, None).unwrap();
+        }
+
+        let mut data: Vec<u8> = vec![0; row_size * height as usize];
+
+        let range = Direct3D12::D3D12_RANGE {
+            Begin: 0,
+            End: row_pitch as usize * height as usize,
+        };
+
+        let mut mapped: *mut std::ffi::c_void = std::ptr::null_mut();
+
+        if let Err(err) = unsafe { buffer.Map(0, Some(&range), Some(&mut mapped)) } {
+            error!("Couldn't map backbuffer readback buffer: {}", err);
+            return data;
+        }
+
+        // the buffer rows are padded to row_pitch
+        for y in 0..height as usize {
+            unsafe {
+                let src = (mapped as *const u8).add(y * row_pitch as usize);
+                std::ptr::copy_nonoverlapping(src, data.as_mut_ptr().add(y * row_size), row_size);
+            }
+        }
+
+        // nothing was written
+        let written = Direct3D12::D3D12_RANGE::default();
+
+        unsafe { buffer.Unmap(0, Some(&written)); }
+
+        // the backbuffers are RGBA
+        for px in data.chunks_exact_mut(4) {
+            px.swap(0, 2);
+        }
+
+        return data;
     }
 
     /// Returns [true] if a backbuffer is available for rendering, [false] otherwise.
@@ -865,6 +1633,15 @@ impl SwapChain {
         }
     }
 
+    /// Sets a 32-bit unsigned integer within the shader root signature.
+    ///
+    /// See [SwapChain::set_root_constant_float].
+    pub fn set_root_constant_uint(&self, value: u32, index: u32, offset: u32) {
+        unsafe {
+            self.cmd_list.SetGraphicsRoot32BitConstant(index, value, offset)
+        }
+    }
+
     /// Sets the current primitive topology.
     ///
     /// See [D3D_PRIMITIVE_TOPOLOGY](https://learn.microsoft.com/en-us/windows/win32/api/d3dcommon/ne-d3dcommon-d3d_primitive_topology)
@@ -878,6 +1655,8 @@ impl SwapChain {
     ///
     /// See [DrawInstanced](https://learn.microsoft.com/en-us/windows/win32/api/d3d12/nf-d3d12-id3d12graphicscommandlist-drawinstanced)
     pub fn draw_instanced(&self, vertexes: u32, instances: u32, first_vertex: u32, first_instance: u32) {
+        self.draw_calls.fetch_add(1, atomic::Ordering::Relaxed);
+
         unsafe {
             self.cmd_list.DrawInstanced(vertexes, instances, first_vertex, first_instance)
         }
@@ -885,12 +1664,39 @@ impl SwapChain {
 
     /// Sets the static texture within the root signature.
     pub fn set_texture(&mut self, index: u32, texture: &Texture) {
+        self.check_srv_heap(texture);
         self.add_backbuffer_resources(&texture.texture);
+
+        let mut handle = self.srv_gpu_start;
+        handle.ptr += texture.srvheap_loc;
+
         unsafe {
-            self.cmd_list.SetGraphicsRootDescriptorTable(index + 1, texture.gpu_descriptor_handle);
+            self.cmd_list.SetGraphicsRootDescriptorTable(index + 1, handle);
         }
     }
 
+    /// Binds the SRV heap again if it has grown since it was bound.
+    ///
+    /// Textures created after the heap grows only have descriptors in the new
+    /// heap, which may happen in the middle of a frame.
+    fn check_srv_heap(&mut self, texture: &Texture) {
+        let dx = &texture.dx;
+
+        if dx.srv_heap_generation.load(atomic::Ordering::Relaxed) != self.srv_heap_generation {
+            dx.bind_srv_heap(self);
+        }
+    }
+
+    /// Keeps `texture` alive until the current frame is finished without
+    /// binding it.
+    ///
+    /// This must be called for each texture that is accessed by index in a
+    /// bindless draw, see [SwapChain::bindless].
+    pub fn use_texture(&mut self, texture: &Texture) {
+        self.check_srv_heap(texture);
+        self.add_backbuffer_resources(&texture.texture);
+    }
+
     pub fn set_vertex_buffer(&mut self, slot: u32, view: &Direct3D12::D3D12_VERTEX_BUFFER_VIEW, vb: &Direct3D12::ID3D12Resource) {
         self.add_backbuffer_resources(vb);
         unsafe {
@@ -974,7 +1780,9 @@ impl SwapChain {
             MaxDepth: 1.0,
         };
 
-        unsafe { self.cmd_list.RSSetViewports(&[vp]); }
+        let scaled = self.scaled_viewport(&vp);
+
+        unsafe { self.cmd_list.RSSetViewports(&[scaled]); }
 
         self.viewports.push_front(vp);
     }
@@ -982,9 +1790,9 @@ impl SwapChain {
     pub fn pop_viewport(&mut self) {
         self.viewports.pop_front();
 
-        let r = self.viewports.front().unwrap_or(&self.base_viewport);
+        let r = self.scaled_viewport(self.viewports.front().unwrap_or(&self.base_viewport));
 
-        unsafe { self.cmd_list.RSSetViewports(&[*r]); }
+        unsafe { self.cmd_list.RSSetViewports(&[r]); }
     }
 }
 
@@ -998,8 +1806,8 @@ impl Drop for SwapChain {
 
         // Drop our manually held DirectComposition objects
         unsafe {
-            drop(DirectComposition::IDCompositionDevice::from_raw(self.comp_dev_ptr    as *mut std::ffi::c_void));
-            drop(DirectComposition::IDCompositionVisual::from_raw(self.comp_visual_ptr as *mut std::ffi::c_void));
+            drop(DirectComposition::IDCompositionDesktopDevice::from_raw(self.comp_dev_ptr as *mut std::ffi::c_void));
+            drop(DirectComposition::IDCompositionVisual3::from_raw(self.comp_visual_ptr    as *mut std::ffi::c_void));
             drop(DirectComposition::IDCompositionTarget::from_raw(self.comp_target_ptr as *mut std::ffi::c_void));
         }
     }
@@ -1021,7 +1829,7 @@ fn enable_debug_layer() {
     warn!("D3D12 debug validation layer enabled. This WILL negatively impact performance.");
 }
 
-fn find_adapter() -> Dxgi::IDXGIAdapter4 {
+fn find_adapter() -> (Dxgi::IDXGIAdapter4, AdapterInfo) {
     let factory: Dxgi::IDXGIFactory6;
 
     let mut flags: Dxgi::DXGI_CREATE_FACTORY_FLAGS = Dxgi::DXGI_CREATE_FACTORY_FLAGS(0);
@@ -1133,7 +1941,14 @@ fn find_adapter() -> Dxgi::IDXGIAdapter4 {
     info!("Driver Version   : {}.{}.{}.{}", driver_ver.prod, driver_ver.ver, driver_ver.sub, driver_ver.build);
     info!("Memory Budget    : {:.2} MiB", (meminfo.Budget as f64) / 1024.0 / 1024.0);
 
-    return adapter;
+    let info = AdapterInfo {
+        description: descstr.to_string(),
+        vendor_id: desc.VendorId,
+        device_id: desc.DeviceId,
+        budget: meminfo.Budget,
+    };
+
+    return (adapter, info);
 }
 
 fn create_device(adapter: &Dxgi::IDXGIAdapter4) -> Direct3D12::ID3D12Device9 {
@@ -1229,6 +2044,26 @@ fn create_device(adapter: &Dxgi::IDXGIAdapter4) -> Direct3D12::ID3D12Device9 {
     return device;
 }
 
+/// Returns `true` if the device supports indexing into the entire SRV
+/// descriptor heap from a shader, resource binding tier 2 or better.
+fn bindless_supported(device: &Direct3D12::ID3D12Device) -> bool {
+    let mut options = Direct3D12::D3D12_FEATURE_DATA_D3D12_OPTIONS::default();
+    let optionssize = std::mem::size_of::<Direct3D12::D3D12_FEATURE_DATA_D3D12_OPTIONS>() as u32;
+
+    if unsafe { device.CheckFeatureSupport(
+        Direct3D12::D3D12_FEATURE_D3D12_OPTIONS,
+        &mut options as *mut _ as *mut std::ffi::c_void,
+        optionssize
+    ) }.is_err() {
+        warn!("Couldn't get resource binding tier.");
+        return false;
+    }
+
+    info!("Resource Binding Tier: {}", options.ResourceBindingTier.0);
+
+    return options.ResourceBindingTier.0 >= Direct3D12::D3D12_RESOURCE_BINDING_TIER_2.0;
+}
+
 fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -> SwapChain {
     let factory: Dxgi::IDXGIFactory6;
 
@@ -1324,18 +2159,21 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
             None
         ).expect("Couldn't create swap chain.");
 
-        let comp_dev: DirectComposition::IDCompositionDevice;
+        let comp_dev: DirectComposition::IDCompositionDesktopDevice;
         let comp_target: DirectComposition::IDCompositionTarget;
-        let comp_visual: DirectComposition::IDCompositionVisual;
+        let comp_visual: DirectComposition::IDCompositionVisual3;
 
-        comp_dev = DirectComposition::DCompositionCreateDevice(None)
+        // a version 3 device is needed for filter effects, see set_composition_effect
+        comp_dev = DirectComposition::DCompositionCreateDevice3(None)
             .expect("Couldn't create DirectComposition device.");
 
         comp_target = comp_dev.CreateTargetForHwnd(hwnd, true)
             .expect("Couldn't create DirectComposition target.");
 
         comp_visual = comp_dev.CreateVisual()
-            .expect("Couldn't create DirectComposition visual.");
+            .expect("Couldn't create DirectComposition visual.")
+            .cast::<DirectComposition::IDCompositionVisual3>()
+            .expect("Couldn't get IDCompositionVisual3.");
 
         comp_visual.SetContent(&sc1)
             .expect("Couldn't set DirectComposition visual content.");
@@ -1376,14 +2214,27 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
 
     let rootsig: Direct3D12::ID3D12RootSignature;
 
-    info!("Loading root signature from shaders/root-sig.cso...");
+    // the bindless root signature is the same as the normal one with an extra
+    // descriptor table covering the entire SRV heap
+    let bindless = bindless_supported(device) &&
+                   overlay::settings().get_bool("overlay.bindlessTextures").unwrap_or(true);
+
+    let rootsigpath = if bindless { "shaders/root-sig-bindless.cso" } else { "shaders/root-sig.cso" };
 
-    let rootcso = std::fs::read("shaders/root-sig.cso").expect("Couldn't read shaders/root-sig.cso");
+    info!("Loading root signature from {}...", rootsigpath);
+
+    let rootcso = std::fs::read(rootsigpath).expect(format!("Couldn't read {}", rootsigpath).as_str());
     unsafe {
         rootsig = device.CreateRootSignature(0, rootcso.as_slice()).expect("Couldn't create root signature");
     }
     object_set_name(&rootsig, "EG-Overlay D3D12 Root Signature");
 
+    let reverse_depth = overlay::settings().get_bool("overlay.reverseDepth").unwrap_or(false);
+
+    if reverse_depth {
+        info!("Using reversed depth buffer.");
+    }
+
     let mut swapchain = SwapChain {
         device: device.clone(),
 
@@ -1400,6 +2251,8 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
         comp_target_ptr: comp_target_ptr,
         comp_visual_ptr: comp_visual_ptr,
 
+        comp_opacity: 1.0,
+
         ortho_proj: ortho,
 
         swapchain_frame_handle_ptr: swapchain_frame_handle_ptr,
@@ -1420,6 +2273,9 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
         ds_descriptorheap: ds_descriptorheap,
         ds_buffer: None,
 
+        reverse_depth: reverse_depth,
+        bindless: bindless,
+
         rootsig: rootsig,
 
         scissors: VecDeque::new(),
@@ -1427,6 +2283,17 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
 
         backbuffer_resources: Vec::new(),
         backbuffer_psos: Vec::new(),
+
+        draw_calls: atomic::AtomicU32::new(0),
+        last_draw_calls: 0,
+
+        culled: atomic::AtomicU32::new(0),
+        last_culled: 0,
+
+        scene_scale: 1.0,
+
+        srv_heap_generation: 0,
+        srv_gpu_start: Direct3D12::D3D12_GPU_DESCRIPTOR_HANDLE::default(),
     };
 
     for _ in 0..DX_FRAMES as usize {
@@ -1440,6 +2307,18 @@ fn create_swapchain(device: &Direct3D12::ID3D12Device, hwnd: Foundation::HWND) -
     return swapchain;
 }
 
+fn check_texture_size(width: u32, height: u32) -> Result<(), DxError> {
+    let max = Direct3D12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION;
+
+    if width > max || height > max {
+        return Err(DxError::Unsupported(format!(
+            "texture size {}x{}, the maximum is {}x{}", width, height, max, max
+        )));
+    }
+
+    return Ok(());
+}
+
 fn create_copyqueue(device: &Direct3D12::ID3D12Device) -> CopyQueue {
     let queue = create_command_queue(device    , Direct3D12::D3D12_COMMAND_LIST_TYPE_COPY);
     let alloc = create_command_allocator(device, Direct3D12::D3D12_COMMAND_LIST_TYPE_COPY);
@@ -1461,6 +2340,37 @@ fn create_copyqueue(device: &Direct3D12::ID3D12Device) -> CopyQueue {
     }
 }
 
+fn create_readback_buffer(device: &Direct3D12::ID3D12Device, size: u64) -> Direct3D12::ID3D12Resource {
+    let mut props = Direct3D12::D3D12_HEAP_PROPERTIES::default();
+    props.Type                 = Direct3D12::D3D12_HEAP_TYPE_READBACK;
+    props.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
+    props.MemoryPoolPreference = Direct3D12::D3D12_MEMORY_POOL_UNKNOWN;
+
+    let mut desc = Direct3D12::D3D12_RESOURCE_DESC::default();
+    desc.Dimension         = Direct3D12::D3D12_RESOURCE_DIMENSION_BUFFER;
+    desc.Alignment         = Direct3D12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64;
+    desc.Width             = size;
+    desc.Height            = 1;
+    desc.DepthOrArraySize  = 1;
+    desc.MipLevels         = 1;
+    desc.Format            = Dxgi::Common::DXGI_FORMAT_UNKNOWN;
+    desc.SampleDesc.Count  = 1;
+    desc.Layout            = Direct3D12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR;
+    desc.Flags             = Direct3D12::D3D12_RESOURCE_FLAG_NONE;
+
+    let mut buffer: Option<Direct3D12::ID3D12Resource> = None;
+    unsafe { device.CreateCommittedResource(
+        &props,
+        Direct3D12::D3D12_HEAP_FLAG_NONE,
+        &desc,
+        Direct3D12::D3D12_RESOURCE_STATE_COPY_DEST,
+        None,
+        &mut buffer
+    ).expect("Couldn't create readback buffer."); }
+
+    buffer.unwrap()
+}
+
 fn create_command_queue(
     device: &Direct3D12::ID3D12Device,
     queue_type: Direct3D12::D3D12_COMMAND_LIST_TYPE
@@ -1478,6 +2388,89 @@ fn create_command_queue(
     }
 }
 
+/// Creates a shader visible SRV heap and a CPU only heap to create descriptors
+/// in, each with `capacity` descriptors. See [SrvHeap].
+fn create_srv_heaps(
+    device: &Direct3D12::ID3D12Device,
+    capacity: u32
+) -> (Direct3D12::ID3D12DescriptorHeap, Direct3D12::ID3D12DescriptorHeap) {
+    let heap = create_descriptor_heap(
+        device,
+        Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        capacity,
+        Direct3D12::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE
+    );
+    object_set_name(&heap, "EG-Overlay D3D12 SRV Descriptor Heap");
+
+    let cpu_heap = create_descriptor_heap(
+        device,
+        Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        capacity,
+        Direct3D12::D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+    );
+    object_set_name(&cpu_heap, "EG-Overlay D3D12 SRV Descriptor Staging Heap");
+
+    (heap, cpu_heap)
+}
+
+/// Creates a depth buffer and its view in the first descriptor of `heap`.
+fn create_depth_buffer(
+    device     : &Direct3D12::ID3D12Device,
+    heap       : &Direct3D12::ID3D12DescriptorHeap,
+    width      : u32,
+    height     : u32,
+    clear_value: f32,
+    name       : &str,
+) -> Direct3D12::ID3D12Resource {
+    let mut dsvprops = Direct3D12::D3D12_HEAP_PROPERTIES::default();
+    dsvprops.Type                 = Direct3D12::D3D12_HEAP_TYPE_DEFAULT;
+    dsvprops.CPUPageProperty      = Direct3D12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
+    dsvprops.MemoryPoolPreference = Direct3D12::D3D12_MEMORY_POOL_UNKNOWN;
+
+    let mut desc = Direct3D12::D3D12_RESOURCE_DESC::default();
+    desc.Dimension        = Direct3D12::D3D12_RESOURCE_DIMENSION_TEXTURE2D;
+    desc.Alignment        = 0;
+    desc.Width            = width as u64;
+    desc.Height           = height;
+    desc.DepthOrArraySize = 1;
+    desc.MipLevels        = 1;
+    desc.Format           = DEPTH_FORMAT;
+    desc.SampleDesc.Count = 1;
+    desc.Layout           = Direct3D12::D3D12_TEXTURE_LAYOUT_UNKNOWN;
+    desc.Flags            =
+        Direct3D12::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL |
+        Direct3D12::D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE;
+
+    let mut clear = Direct3D12::D3D12_CLEAR_VALUE::default();
+    clear.Format               = DEPTH_FORMAT;
+    clear.Anonymous.DepthStencil.Depth   = clear_value;
+    clear.Anonymous.DepthStencil.Stencil = 0;
+
+    let mut buffer: Option<Direct3D12::ID3D12Resource> = None;
+
+    unsafe {
+        device.CreateCommittedResource(
+            &dsvprops,
+            Direct3D12::D3D12_HEAP_FLAG_NONE,
+            &desc,
+            Direct3D12::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            Some(&clear),
+            &mut buffer
+        ).expect("Failed to create Depth/Stencil buffer.");
+    }
+
+    let buffer = buffer.unwrap();
+
+    object_set_name(&buffer, name);
+
+    unsafe {
+        let dsvhandle = heap.GetCPUDescriptorHandleForHeapStart();
+        device.CreateDepthStencilView(&buffer, None, dsvhandle);
+    }
+
+    buffer
+}
+
 fn create_descriptor_heap(
     device         : &Direct3D12::ID3D12Device,
     heap_type      : Direct3D12::D3D12_DESCRIPTOR_HEAP_TYPE,
@@ -1547,23 +2540,334 @@ pub fn object_set_name(object: &Direct3D12::ID3D12Object, name: &str) {
     }
 }
 
+/// An effect applied to the entire overlay by DirectComposition.
+///
+/// See [Dx::set_composition_effect].
+pub enum CompositionEffect {
+    /// Removes any effect, opacity is not changed.
+    None,
+
+    /// A gaussian blur with the given standard deviation, in pixels.
+    Blur { radius: f32 },
+
+    /// Changes the color saturation, `0.0` is grayscale and `1.0` is unchanged.
+    Saturation { value: f32 },
+
+    /// A shadow or glow around everything drawn.
+    Shadow { radius: f32, color: crate::ui::Color },
+
+    /// Animates the opacity of the overlay to `opacity` over `duration` seconds.
+    ///
+    /// This does not replace any other effect.
+    Fade { opacity: f32, duration: f64 },
+}
+
+/// Creates the filter effect for `effect` and sets it as the effect of
+/// `visual`, replacing any existing effect.
+fn set_visual_effect(
+    comp_dev: &DirectComposition::IDCompositionDesktopDevice,
+    visual: &DirectComposition::IDCompositionVisual3,
+    effect: &CompositionEffect,
+) -> windows::core::Result<()> {
+    use windows::Win32::Graphics::Direct2D;
+
+    // filter effects are only available from IDCompositionDevice3
+    let dev3: DirectComposition::IDCompositionDevice3 = comp_dev.cast()?;
+
+    // a null input is the content of the visual
+    let content: Option<&windows::core::IUnknown> = None;
+
+    unsafe {
+        match effect {
+            CompositionEffect::None => {
+                visual.SetEffect(None::<&DirectComposition::IDCompositionEffect>)?;
+            },
+            CompositionEffect::Blur { radius } => {
+                let blur = dev3.CreateGaussianBlurEffect()?;
+                blur.SetStandardDeviation2(radius.max(0.0))?;
+                blur.SetBorderMode(Direct2D::Common::D2D1_BORDER_MODE_SOFT)?;
+                blur.SetInput(0, content, 0)?;
+
+                visual.SetEffect(&blur)?;
+            },
+            CompositionEffect::Saturation { value } => {
+                let saturation = dev3.CreateSaturationEffect()?;
+                saturation.SetSaturation2(value.clamp(0.0, 1.0))?;
+                saturation.SetInput(0, content, 0)?;
+
+                visual.SetEffect(&saturation)?;
+            },
+            CompositionEffect::Shadow { radius, color } => {
+                // the shadow effect only outputs the shadow, so it is
+                // composited below the content
+                let shadow = dev3.CreateShadowEffect()?;
+                shadow.SetStandardDeviation2(radius.max(0.0))?;
+                shadow.SetColor(&Direct2D::Common::D2D_VECTOR_4F {
+                    x: color.r_f32(),
+                    y: color.g_f32(),
+                    z: color.b_f32(),
+                    w: color.a_f32(),
+                })?;
+                shadow.SetInput(0, content, 0)?;
+
+                let composite = dev3.CreateCompositeEffect()?;
+                composite.SetMode(Direct2D::Common::D2D1_COMPOSITE_MODE_SOURCE_OVER)?;
+                composite.SetInput(0, &shadow, 0)?;
+                composite.SetInput(1, content, 0)?;
+
+                visual.SetEffect(&composite)?;
+            },
+            CompositionEffect::Fade { .. } => {},
+        }
+    }
+
+    Ok(())
+}
+
+fn render_target_barrier(
+    resource: &Direct3D12::ID3D12Resource,
+    before: Direct3D12::D3D12_RESOURCE_STATES,
+    after: Direct3D12::D3D12_RESOURCE_STATES,
+) -> Direct3D12::D3D12_RESOURCE_BARRIER {
+    let mut barrier = Direct3D12::D3D12_RESOURCE_BARRIER::default();
+    barrier.Type = Direct3D12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION;
+    barrier.Flags = Direct3D12::D3D12_RESOURCE_BARRIER_FLAG_NONE;
+    barrier.Anonymous.Transition = std::mem::ManuallyDrop::new(Direct3D12::D3D12_RESOURCE_TRANSITION_BARRIER {
+        pResource: unsafe { std::mem::transmute_copy(resource) },
+        Subresource: Direct3D12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+        StateBefore: before,
+        StateAfter: after,
+    });
+
+    barrier
+}
+
+/// A texture that can be rendered to and then sampled like any other
+/// [Texture].
+///
+/// See [Dx::new_render_target] and [SwapChain::push_render_target].
+pub struct RenderTarget {
+    width: u32,
+    height: u32,
+
+    rtv_descriptorheap: Direct3D12::ID3D12DescriptorHeap,
+
+    // only scene targets have their own depth buffer, see Dx::new_scene_target
+    ds_descriptorheap: Option<Direct3D12::ID3D12DescriptorHeap>,
+    ds_buffer        : Option<Direct3D12::ID3D12Resource>,
+
+    texture: Texture,
+}
+
+impl RenderTarget {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The texture that is rendered to.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+const SCENE_UPSCALE_VERT_CSO : &str = "shaders/image.vs.cso";
+const SCENE_UPSCALE_PIXEL_CSO: &str = "shaders/scene-upscale.ps.cso";
+
+/// Renders the 3D scene at a lower resolution.
+///
+/// When the scale is less than 1.0, the scene is drawn to a smaller render
+/// target between [SceneScaler::begin] and [SceneScaler::end] and then
+/// upscaled to the backbuffer. The UI is drawn after this at full resolution.
+pub struct SceneScaler {
+    pso: Direct3D12::ID3D12PipelineState,
+
+    scale: Mutex<f32>,
+
+    target: Mutex<Option<RenderTarget>>,
+}
+
+impl SceneScaler {
+    /// The smallest scale allowed, anything lower isn't worth looking at.
+    pub const MIN_SCALE: f32 = 0.25;
+
+    pub fn new(dx: &Arc<Dx>, scale: f32) -> SceneScaler {
+        debug!("Loading scene upscale vertex shader from {}...", SCENE_UPSCALE_VERT_CSO);
+        let vertcso = read_shader(SCENE_UPSCALE_VERT_CSO).unwrap_or_else(|err| panic!("{}", err));
+
+        debug!("Loading scene upscale pixel shader from {}...", SCENE_UPSCALE_PIXEL_CSO);
+        let pixelcso = read_shader(SCENE_UPSCALE_PIXEL_CSO).unwrap_or_else(|err| panic!("{}", err));
+
+        let mut psodesc = Direct3D12::D3D12_GRAPHICS_PIPELINE_STATE_DESC::default();
+        psodesc.VS.pShaderBytecode = vertcso.as_ptr() as *const _;
+        psodesc.VS.BytecodeLength  = vertcso.len();
+        psodesc.PS.pShaderBytecode = pixelcso.as_ptr() as *const _;
+        psodesc.PS.BytecodeLength  = pixelcso.len();
+
+        psodesc.RasterizerState.FillMode             = Direct3D12::D3D12_FILL_MODE_SOLID;
+        psodesc.RasterizerState.CullMode             = Direct3D12::D3D12_CULL_MODE_NONE;
+        psodesc.RasterizerState.DepthBias            = Direct3D12::D3D12_DEFAULT_DEPTH_BIAS;
+        psodesc.RasterizerState.DepthBiasClamp       = Direct3D12::D3D12_DEFAULT_DEPTH_BIAS_CLAMP;
+        psodesc.RasterizerState.SlopeScaledDepthBias = Direct3D12::D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS;
+        psodesc.RasterizerState.DepthClipEnable      = true.into();
+        psodesc.RasterizerState.ConservativeRaster   = Direct3D12::D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF;
+
+        psodesc.BlendState.RenderTarget[0].BlendEnable           = true.into();
+        psodesc.BlendState.RenderTarget[0].SrcBlend              = Direct3D12::D3D12_BLEND_ONE;
+        psodesc.BlendState.RenderTarget[0].DestBlend             = Direct3D12::D3D12_BLEND_INV_SRC_ALPHA;
+        psodesc.BlendState.RenderTarget[0].BlendOp               = Direct3D12::D3D12_BLEND_OP_ADD;
+        psodesc.BlendState.RenderTarget[0].SrcBlendAlpha         = Direct3D12::D3D12_BLEND_ONE;
+        psodesc.BlendState.RenderTarget[0].DestBlendAlpha        = Direct3D12::D3D12_BLEND_INV_SRC_ALPHA;
+        psodesc.BlendState.RenderTarget[0].BlendOpAlpha          = Direct3D12::D3D12_BLEND_OP_ADD;
+        psodesc.BlendState.RenderTarget[0].RenderTargetWriteMask = Direct3D12::D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8;
+
+        psodesc.DepthStencilState.DepthEnable   = false.into();
+        psodesc.DepthStencilState.StencilEnable = false.into();
+
+        psodesc.SampleMask = std::ffi::c_uint::MAX; //UINT_MAX;
+        psodesc.PrimitiveTopologyType = Direct3D12::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE;
+        psodesc.NumRenderTargets = 1;
+        psodesc.RTVFormats[0] = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
+        psodesc.SampleDesc.Count = 1;
+
+        let pso = dx.create_pipeline_state(&mut psodesc, "EG-Overlay D3D12 Scene Upscale Pipeline State")
+            .expect("Couldn't create scene upscale pipeline state.");
+
+        SceneScaler {
+            pso: pso,
+            scale: Mutex::new(scale.clamp(Self::MIN_SCALE, 1.0)),
+            target: Mutex::new(None),
+        }
+    }
+
+    /// The current scale, between [SceneScaler::MIN_SCALE] and 1.0.
+    pub fn scale(&self) -> f32 {
+        *self.scale.lock().unwrap()
+    }
+
+    /// Sets the scale the scene is rendered at, starting with the next frame.
+    ///
+    /// `scale` is clamped between [SceneScaler::MIN_SCALE] and 1.0.
+    pub fn set_scale(&self, scale: f32) {
+        let scale = scale.clamp(Self::MIN_SCALE, 1.0);
+
+        debug!("Scene render scale set to {:.2}", scale);
+
+        *self.scale.lock().unwrap() = scale;
+    }
+
+    /// Starts drawing the scene.
+    ///
+    /// If the scale is less than 1.0 all drawing until [SceneScaler::end] is
+    /// done to the scaled render target, otherwise this does nothing and
+    /// drawing is done directly to the backbuffer.
+    pub fn begin(&self, dx: &Arc<Dx>, frame: &mut SwapChainLock) {
+        let scale = self.scale();
+
+        let mut target = self.target.lock().unwrap();
+
+        if scale >= 1.0 {
+            // free the target, it's no longer needed
+            *target = None;
+            return;
+        }
+
+        let width = ((frame.render_target_width() as f32 * scale).round() as u32).max(1);
+        let height = ((frame.render_target_height() as f32 * scale).round() as u32).max(1);
+
+        let recreate = match target.as_ref() {
+            Some(t) => t.width() != width || t.height() != height,
+            None    => true,
+        };
+
+        if recreate {
+            debug!("Creating {}x{} scene target.", width, height);
+
+            match dx.new_scene_target(width, height, frame.reverse_depth(), "EG-Overlay D3D12 Scene Target") {
+                Ok(t) => *target = Some(t),
+                Err(err) => {
+                    // draw to the backbuffer at full size instead, from now on
+                    error!("Couldn't create scene target, rendering at full size: {}", err);
+                    *target = None;
+                    *self.scale.lock().unwrap() = 1.0;
+                    return;
+                },
+            }
+        }
+
+        frame.push_scene_target(target.as_ref().unwrap(), scale);
+    }
+
+    /// Ends drawing the scene and, if it was drawn to the scaled render
+    /// target, draws it to the backbuffer.
+    pub fn end(&self, frame: &mut SwapChainLock) {
+        let target = self.target.lock().unwrap();
+
+        let target = match target.as_ref() {
+            Some(t) => t,
+            None    => return,
+        };
+
+        frame.pop_scene_target(target);
+
+        frame.set_pipeline_state(&self.pso);
+
+        frame.set_root_constant_float(0.0                                , 0,  0); // left
+        frame.set_root_constant_float(0.0                                , 0,  1); // top
+        frame.set_root_constant_float(frame.render_target_width() as f32 , 0,  2); // right
+        frame.set_root_constant_float(frame.render_target_height() as f32, 0,  3); // bottom
+        frame.set_root_constant_float4(&[1.0, 1.0, 1.0, 1.0]             , 0,  4); // color
+        frame.set_root_constant_ortho_proj(                                0,  8); // proj
+        frame.set_root_constant_float(1.0                                , 0, 24); // maxu
+        frame.set_root_constant_float(1.0                                , 0, 25); // maxv
+
+        frame.set_texture(0, target.texture());
+
+        frame.set_primitive_topology(Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
+        frame.draw_instanced(4, 1, 0, 0);
+    }
+}
+
 /// A D3D12 texture and related resources.
 pub struct Texture {
-    /*
     width: u32,
     height: u32,
     depth: u16,
-    */
 
     srvheap_loc: u64,
 
     texture: Direct3D12::ID3D12Resource,
-    gpu_descriptor_handle: Direct3D12::D3D12_GPU_DESCRIPTOR_HANDLE,
 
     dx: Arc<Dx>,
 }
 
 impl Texture {
+    /// The width of the texture in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the texture in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of layers in the texture, 1 unless this is a texture array.
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    /// Returns the index of this texture's descriptor within the SRV heap.
+    ///
+    /// This is the index used to access the texture from shaders when
+    /// [SwapChain::bindless] is `true`.
+    pub fn srv_index(&self) -> u32 {
+        (self.srvheap_loc / self.dx.srv_descriptorsize as u64) as u32
+    }
+
     /// Sets this texture's name.
     pub fn set_name(&self, name: &str) {
         object_set_name(&self.texture, name);
@@ -1586,13 +2890,13 @@ impl Texture {
         h: u32,
         format: Dxgi::Common::DXGI_FORMAT,
         data: &[u8]
-    ) {
+    ) -> Result<(), DxError> {
         let bpp: u32;
         match format {
             Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM |
             Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM => bpp = 4,
             Dxgi::Common::DXGI_FORMAT_R8_UNORM => bpp = 1,
-            _ => panic!("format not implemented."),
+            _ => return Err(DxError::Unsupported(format!("pixel format {}", format.0))),
         }
 
         let rowwidth: u32 = w * bpp;
@@ -1617,16 +2921,14 @@ impl Texture {
 
         let mut upload_ptr: Option<Direct3D12::ID3D12Resource> = None;
 
-        if unsafe { self.dx.device.CreateCommittedResource(
+        unsafe { self.dx.device.CreateCommittedResource(
             &uploadprops,
             Direct3D12::D3D12_HEAP_FLAG_NONE,
             &uploaddesc,
             Direct3D12::D3D12_RESOURCE_STATE_GENERIC_READ,
             None,
             &mut upload_ptr
-        ) }.is_err() {
-            panic!("Couldn't create upload resource.");
-        }
+        ) }?;
 
         let upload = upload_ptr.unwrap();
 
@@ -1634,9 +2936,7 @@ impl Texture {
 
         let mut uploaddata: *mut std::ffi::c_void = std::ptr::null_mut();
 
-        if unsafe { upload.Map(0, Some(&rr), Some(&mut uploaddata)) }.is_err() {
-            panic!("Couldn't map upload data.");
-        }
+        unsafe { upload.Map(0, Some(&rr), Some(&mut uploaddata)) }?;
 
         for yi in 0..h {
             unsafe {
@@ -1679,6 +2979,8 @@ impl Texture {
         unsafe { copy_queue.cmd_queue.ExecuteCommandLists(&[Some(copy_queue.cmd_list.clone().into())]); }
 
         copy_queue.flush_commands(); // make sure the commands are executed before upload is dropped
+
+        return Ok(());
     }
 
     /// Copies entire subresources (levels/layers) from another texture to this one.
@@ -1715,6 +3017,6 @@ impl Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
-        self.dx.srv_descriptorheap_addresses.lock().unwrap().reuse.push_back(self.srvheap_loc);
+        self.dx.srv_heap.lock().unwrap().reuse.push_back(self.srvheap_loc);
     }
 }
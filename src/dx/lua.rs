@@ -45,6 +45,21 @@ with dimensions that are a power of 2.
 This should not affect sprites because the original dimensions of the image are
 stored and used when rendering them, but trails may not be rendered as expected
 if a non-square and/or no-power of 2 image is used.
+
+.. _render-channels:
+
+Render Channels
+---------------
+
+Every sprite list, trail list and marker, along with each top level UI element,
+is drawn in a named render channel. Everything is in the ``default`` channel
+unless set otherwise with :lua:func:`setchannel` or the ``channel`` method of
+UI elements.
+
+Channels do not change what is drawn on the overlay, but :lua:func:`capture`
+can render a separate image that only includes selected channels. For example,
+a streaming module could put lists and windows that show personal information
+in a ``private`` channel and capture only ``default`` for a clean feed.
 */
 
 use windows::Win32::System::Com;
@@ -56,6 +71,8 @@ use windows::Win32::Graphics::Direct3D12;
 use windows::Win32::Graphics::Direct3D;
 use windows::Win32::Graphics::Dxgi;
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::{luaL_Reg, luaL_Reg_list};
@@ -64,29 +81,57 @@ use crate::logging::{debug};
 use crate::overlay::lua::{luawarn, luaerror};
 
 use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic;
 use crate::dx;
 use crate::ml;
 use crate::ui;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::lamath;
 
 const SPRITE_LIST_VERT_CSO : &str = "shaders/sprite-list.vs.cso";
 const SPRITE_LIST_PIXEL_CSO: &str = "shaders/sprite-list.ps.cso";
+const SPRITE_LIST_BINDLESS_PIXEL_CSO: &str = "shaders/sprite-list-bindless.ps.cso";
 
 const TRAIL_VERT_CSO : &str = "shaders/trail.vs.cso";
 const TRAIL_PIXEL_CSO: &str = "shaders/trail.ps.cso";
 
+/// The render channel lists and UI elements are drawn in unless set otherwise.
+pub const DEFAULT_CHANNEL: &str = "default";
+
 pub struct DxLua {
     dx: Arc<dx::Dx>,
     ml: Arc<ml::MumbleLink>,
     ui: Arc<ui::Ui>,
-    sprite_list_pso: Direct3D12::ID3D12PipelineState,
-    trail_pso      : Direct3D12::ID3D12PipelineState,
+    sprite_list_pso        : Direct3D12::ID3D12PipelineState,
+    sprite_list_a2c_pso    : Direct3D12::ID3D12PipelineState,
+    sprite_list_nodepth_pso: Direct3D12::ID3D12PipelineState,
+    trail_pso              : Direct3D12::ID3D12PipelineState,
+
+    // only available if the device supports bindless textures
+    sprite_list_bindless_pso        : Option<Direct3D12::ID3D12PipelineState>,
+    sprite_list_bindless_nodepth_pso: Option<Direct3D12::ID3D12PipelineState>,
 
     sprite_lists: Mutex<VecDeque<Arc<SpriteList>>>,
     trail_lists : Mutex<VecDeque<Arc<TrailList>>>,
+    markers     : Mutex<VecDeque<Arc<Marker>>>,
+
+    // left mouse button state during the last frame, for marker clicks
+    lbutton_down: atomic::AtomicBool,
+
+    // the world up direction, used for the camera, trail orientation and
+    // vertically billboarded sprites. see setworldup
+    world_up: Mutex<lamath::Vec3F>,
+
+    // renders sprite and trail lists at a lower resolution, see setrenderscale
+    scene: dx::SceneScaler,
+
+    // captures waiting to be rendered and the capture rendered during the last
+    // frame, which is read back once that frame is complete
+    capture_requests: Mutex<VecDeque<CaptureRequest>>,
+    capture_pending : Mutex<Option<PendingCapture>>,
+    capture_target  : Mutex<Option<dx::RenderTarget>>,
 }
 
 static DX_LUA: Mutex<Option<Arc<DxLua>>> = Mutex::new(None);
@@ -101,11 +146,35 @@ pub fn init(dx: &Arc<dx::Dx>, ml: &Arc<ml::MumbleLink>, ui: &Arc<ui::Ui>) {
         dx: dx.clone(),
         ml: ml.clone(),
         ui: ui.clone(),
-        sprite_list_pso: create_sprite_list_pso(dx),
+        sprite_list_pso: create_sprite_list_pso(dx, false, false, true),
+        sprite_list_a2c_pso: create_sprite_list_pso(dx, true, false, true),
+        sprite_list_nodepth_pso: create_sprite_list_pso(dx, false, false, false),
         trail_pso: create_trail_pso(dx),
 
+        sprite_list_bindless_pso: if dx.swapchain().bindless() {
+            Some(create_sprite_list_pso(dx, false, true, true))
+        } else {
+            None
+        },
+        sprite_list_bindless_nodepth_pso: if dx.swapchain().bindless() {
+            Some(create_sprite_list_pso(dx, false, true, false))
+        } else {
+            None
+        },
+
         sprite_lists: Mutex::new(VecDeque::new()),
         trail_lists : Mutex::new(VecDeque::new()),
+        markers     : Mutex::new(VecDeque::new()),
+
+        lbutton_down: atomic::AtomicBool::new(false),
+
+        world_up: Mutex::new(lamath::Vec3F { x: 0.0, y: 1.0, z: 0.0 }),
+
+        scene: dx::SceneScaler::new(dx, crate::overlay::settings().get_f64("overlay.renderScale").unwrap() as f32),
+
+        capture_requests: Mutex::new(VecDeque::new()),
+        capture_pending : Mutex::new(None),
+        capture_target  : Mutex::new(None),
     }));
 }
 
@@ -115,10 +184,140 @@ pub fn cleanup() {
     *DX_LUA.lock().unwrap() = None;
 }
 
+/// Returns `true` if any sprite or trail list has changes that haven't been
+/// drawn yet.
+pub fn needs_redraw() -> bool {
+    let dx_lua = match DX_LUA.lock().unwrap().as_ref() {
+        Some(d) => d.clone(),
+        None    => return false,
+    };
+
+    for sprite_list in dx_lua.sprite_lists.lock().unwrap().iter() {
+        let sl_inner = sprite_list.inner.lock().unwrap();
+
+        if sl_inner.animating() { return true; }
+
+        // changes made during an update aren't drawn until it ends
+        if sl_inner.update_depth > 0 { continue; }
+
+        if sl_inner.update_vert_buffer { return true; }
+
+        if sl_inner.draw && !sl_inner.dirty_sprites.is_empty() { return true; }
+    }
+
+    for trail_list in dx_lua.trail_lists.lock().unwrap().iter() {
+        let tl_inner = trail_list.inner.lock().unwrap();
+
+        if tl_inner.update_depth == 0 && tl_inner.update_vert_buffer { return true; }
+
+        if tl_inner.update_depth == 0 && tl_inner.draw && !tl_inner.dirty_trails.is_empty() { return true; }
+
+        if tl_inner.caps.update_vert_buffer { return true; }
+
+        if tl_inner.draw && tl_inner.caps.animating() { return true; }
+    }
+
+    // captures are read back during the frame after they are rendered
+    if dx_lua.capture_requests.lock().unwrap().len() > 0 || dx_lua.capture_pending.lock().unwrap().is_some() {
+        return true;
+    }
+
+    return false;
+}
+
 
 pub fn render(frame: &mut dx::SwapChainLock) {
     let dx_lua = DX_LUA.lock().unwrap().as_ref().unwrap().clone();
 
+    dx_lua.scene.begin(&dx_lua.dx, frame);
+    render_lists(&dx_lua, frame, None);
+    dx_lua.scene.end(frame);
+
+    // hit testing was done while drawing the sprite lists above
+    update_markers(&dx_lua);
+}
+
+/// Renders the next requested capture, see `dx.capture`.
+///
+/// This must be called after the UI is drawn. The capture is copied to a
+/// readback buffer and sent to Lua during the next call, once the GPU has
+/// finished the frame it was rendered in.
+pub fn render_capture(frame: &mut dx::SwapChainLock) {
+    let dx_lua = DX_LUA.lock().unwrap().as_ref().unwrap().clone();
+
+    // start_frame waits for all prior commands, so the last capture is complete
+    if let Some(pending) = dx_lua.capture_pending.lock().unwrap().take() {
+        pending.send();
+    }
+
+    let request = match dx_lua.capture_requests.lock().unwrap().pop_front() {
+        Some(r) => r,
+        None    => return,
+    };
+
+    let width = frame.render_target_width();
+    let height = frame.render_target_height();
+
+    let mut target = dx_lua.capture_target.lock().unwrap();
+
+    let recreate = match target.as_ref() {
+        Some(t) => t.width() != width || t.height() != height,
+        None    => true,
+    };
+
+    if recreate {
+        debug!("Creating {}x{} capture target.", width, height);
+
+        match dx_lua.dx.new_render_target(width, height, "EG-Overlay D3D12 Capture Target") {
+            Ok(t) => *target = Some(t),
+            Err(err) => {
+                crate::logging::error!("Couldn't create capture target: {}", err);
+                *target = None;
+                crate::lua_manager::unref(request.callback);
+                return;
+            },
+        }
+    }
+
+    let target = target.as_ref().unwrap();
+
+    frame.push_render_target(target, true);
+    render_lists(&dx_lua, frame, Some(&request.channels));
+    dx_lua.ui.draw_channels(frame, &request.channels);
+    frame.pop_render_target(target);
+
+    // rows in the readback buffer must be aligned to 256 bytes
+    let row_pitch = (width * 4 + 255) & !255;
+
+    let buffer = dx_lua.dx.new_readback_buffer(row_pitch as u64 * height as u64);
+
+    frame.copy_render_target_to_buffer(target, &buffer, row_pitch);
+
+    *dx_lua.capture_pending.lock().unwrap() = Some(PendingCapture {
+        buffer: buffer,
+        width: width,
+        height: height,
+        row_pitch: row_pitch,
+        callback: request.callback,
+    });
+}
+
+/// Draws the trail and sprite lists.
+///
+/// If `channels` is given, only lists in those render channels are drawn. This
+/// is a capture, which happens after the lists have already been drawn this
+/// frame, so only the current vertex buffers are drawn. Mouse hover, vertex
+/// buffer updates and culling statistics are left to the normal pass.
+fn render_lists(dx_lua: &Arc<DxLua>, frame: &mut dx::SwapChainLock, channels: Option<&HashSet<String>>) {
+    let in_channels = |channel: &String| -> bool {
+        match channels {
+            Some(c) => c.contains(channel),
+            None    => true,
+        }
+    };
+
+    let capture = channels.is_some();
+
     let fov: f64;
 
     if let Some(f) = dx_lua.ml.identity_fov() {
@@ -148,14 +347,17 @@ pub fn render(frame: &mut dx::SwapChainLock) {
     camera_pos.y *= 39.3701;
     camera_pos.z *= 39.3701;
 
-    let camera_up = lamath::Vec3F {
-        x: 0.0,
-        y: 1.0,
-        z: 0.0,
-    };
+    let camera_up = *dx_lua.world_up.lock().unwrap();
+
+    // with a reversed depth buffer the near and far planes are swapped so
+    // that the far plane ends up at 0.0
+    let reverse_depth = frame.reverse_depth();
+
+    let (world_near, world_far) = if reverse_depth { (25000.0, 1.0) } else { (1.0, 25000.0) };
+    let (map_near  , map_far  ) = if reverse_depth { (1.0, 0.0) } else { (0.0, 1.0) };
 
     // world
-    let world_proj = lamath::Mat4F::perspective_lh(fov as f32, rtv_width as f32 / rtv_height as f32, 1.0, 25000.0);
+    let world_proj = lamath::Mat4F::perspective_lh(fov as f32, rtv_width as f32 / rtv_height as f32, world_near, world_far);
     let world_view = lamath::Mat4F::camera_facing(&camera_pos, &camera_front, &camera_up);
 
     // data for map view/projection matrices
@@ -174,19 +376,8 @@ pub fn render(frame: &mut dx::SwapChainLock) {
         mapw = rtv_width;
         maph = rtv_height;
     } else {
-        mapw = dx_lua.ml.context_compass_width() as u32;
-        maph = dx_lua.ml.context_compass_height() as u32;
-
-        minimapleft = rtv_width - mapw;
-        if (uistate & ml::UI_STATE_COMPASS_TOP_RIGHT) == 0 {
-            match dx_lua.ml.identity_uisz().unwrap() { // unwrap because by now we know identity is working
-                0 => minimaptop = rtv_height - 33 - maph, // small
-                1 => minimaptop = rtv_height - 35 - maph, // normal
-                2 => minimaptop = rtv_height - 42 - maph, // large
-                3 => minimaptop = rtv_height - 45 - maph, // larger
-                _ => minimaptop = rtv_height - 35 - maph,
-            }
-        }
+        // unwrap because by now we know identity is working
+        (minimapleft, minimaptop, mapw, maph) = dx_lua.ml.compass_rect(rtv_width, rtv_height).unwrap();
     }
 
     let mapxsize: f32 = mapw as f32 * mapscale;
@@ -200,7 +391,7 @@ pub fn render(frame: &mut dx::SwapChainLock) {
     let mapcenterx = dx_lua.ml.context_map_center_x();
     let mapcentery = dx_lua.ml.context_map_center_y();
 
-    let map_proj = lamath::Mat4F::ortho(mapleft, mapright, maptop, mapbottom, 0.0, 1.0);
+    let map_proj = lamath::Mat4F::ortho(mapleft, mapright, maptop, mapbottom, map_near, map_far);
 
     let map_view_translate = lamath::Mat4F::translate(-mapcenterx, -mapcentery, 0.0);
     let map_view_rotate = if !mapfullscreen && (uistate & ml::UI_STATE_COMPASS_ROTATE) > 0 {
@@ -236,6 +427,8 @@ pub fn render(frame: &mut dx::SwapChainLock) {
 
     let trail_lists = dx_lua.trail_lists.lock().unwrap();
 
+    let frustum = lamath::Frustum::from_matrix(&(world_view * world_proj));
+
     if trail_lists.len() > 0 {
         frame.set_pipeline_state(&dx_lua.trail_pso);
         frame.set_primitive_topology(Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
@@ -249,12 +442,19 @@ pub fn render(frame: &mut dx::SwapChainLock) {
         for trail_list in &*trail_lists {
             let mut tl_inner = trail_list.inner.lock().unwrap();
 
-            if !tl_inner.draw { continue; }
+            if !capture { tl_inner.mouse_hover_tags.clear(); }
+
+            if !tl_inner.draw || !in_channels(&tl_inner.channel) { continue; }
 
             if !tl_inner.is_map && mapfullscreen { continue; }
 
-            if tl_inner.update_vert_buffer {
-                tl_inner.update_vertex_buffer(frame, &dx_lua.dx);
+            if capture || tl_inner.update_depth > 0 {
+                // keep drawing the current vertex buffer, captures don't
+                // update it and updates in progress wait until they end
+            } else if tl_inner.update_vert_buffer {
+                tl_inner.update_vertex_buffer(frame, &dx_lua.dx, &camera_up);
+            } else if !tl_inner.dirty_trails.is_empty() {
+                tl_inner.update_vertex_buffer_range(frame, &dx_lua.dx, &camera_up);
             }
 
             if tl_inner.vert_buffer.is_none() { continue; }
@@ -272,6 +472,12 @@ pub fn render(frame: &mut dx::SwapChainLock) {
             }
             frame.set_root_constant_bool(tl_inner.is_map, 0, 39);
 
+            let visible = if tl_inner.frustum_cull && !tl_inner.is_map {
+                Some(tl_inner.visible_trails(&frustum))
+            } else {
+                None
+            };
+
             frame.set_vertex_buffer(0, &tl_inner.vert_buffer_view, tl_inner.vert_buffer.as_ref().unwrap());
 
             let mut first = 0;
@@ -294,13 +500,28 @@ pub fn render(frame: &mut dx::SwapChainLock) {
 
                 frame.set_texture(0, tex);
 
-                for trail in trails {
+                for (ti, trail) in trails.iter().enumerate() {
                     if trail.coord_count == 0 { continue; }
 
+                    if visible.as_ref().is_some_and(|v| !v[i][ti]) {
+                        if !capture { frame.add_culled(1); }
+                        first += trail.coord_count;
+                        continue;
+                    }
+
                     frame.set_root_constant_float(trail.fade_near, 0, 43);
                     frame.set_root_constant_float(trail.fade_far , 0, 44);
+                    frame.set_root_constant_uint (trail.fade_curve, 0, 48);
                     frame.set_root_constant_color(trail.color    , 0, 32);
 
+                    if trail.fade_by_age {
+                        frame.set_root_constant_float(trail.age_alpha.0, 0, 49);
+                        frame.set_root_constant_float(trail.age_alpha.1, 0, 50);
+                    } else {
+                        frame.set_root_constant_float(1.0, 0, 49);
+                        frame.set_root_constant_float(1.0, 0, 50);
+                    }
+
                     frame.draw_instanced(trail.coord_count, 1, first, 0);
 
                     first += trail.coord_count;
@@ -308,24 +529,48 @@ pub fn render(frame: &mut dx::SwapChainLock) {
             }
 
             if tl_inner.is_map && !mapfullscreen { frame.pop_viewport(); }
+
+            if !capture {
+                tl_inner.update_mouse_hover(&camera_pos, &mouse_ray, mouse_map_x, mouse_map_y, mouse_in_map);
+            }
         }
     }
 
     let sprite_lists = dx_lua.sprite_lists.lock().unwrap();
 
-    if sprite_lists.len() > 0 {
+    if sprite_lists.len() > 0 || trail_lists.len() > 0 {
         frame.set_pipeline_state(&dx_lua.sprite_list_pso);
         frame.set_primitive_topology(Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
 
+        // alpha to coverage is only useful with multisampling, otherwise lists
+        // that request it fall back to normal alpha blending
+        let msaa = frame.sample_count() > 1;
+        let mut cur_pso = &dx_lua.sprite_list_pso;
+
         frame.set_root_constant_vec3f(&avatar_pos       , 0, 32);
         frame.set_root_constant_vec3f(&camera_pos       , 0, 36);
         frame.set_root_constant_float(minimapleft as f32, 0, 39);
         frame.set_root_constant_float(minimaptop  as f32, 0, 40);
         frame.set_root_constant_float(maph        as f32, 0, 41);
+        frame.set_root_constant_float(crate::overlay::uptime().as_secs_f32(), 0, 42);
+        frame.set_root_constant_float(rtv_width         as f32, 0, 43);
+        frame.set_root_constant_float(rtv_height        as f32, 0, 44);
+        frame.set_root_constant_vec3f(&camera_up        , 0, 45);
+
+        let map_rects = dx_lua.ml.map_rects();
 
         for sprite_list in &*sprite_lists {
             let mut sl_inner = sprite_list.inner.lock().unwrap();
 
+            if !in_channels(&sl_inner.channel) { continue; }
+
+            let (pso, bindless) = sprite_list_pso(&dx_lua, msaa && sl_inner.alpha_to_coverage, sl_inner.depth_test);
+
+            if pso != cur_pso {
+                frame.set_pipeline_state(pso);
+                cur_pso = pso;
+            }
+
             sl_inner.draw(
                 frame,
                 &dx_lua.dx,
@@ -334,20 +579,123 @@ pub fn render(frame: &mut dx::SwapChainLock) {
                 &map_proj,
                 &map_view,
                 mapfullscreen,
+                mapscale,
+                &camera_pos,
+                &mouse_ray,
+                minimapleft,
+                minimaptop,
+                mapw,
+                maph,
+                mouse_x,
+                mouse_y,
+                mouse_map_x,
+                mouse_map_y,
+                mouse_in_map,
+                bindless,
+                &map_rects,
+                capture
+            );
+        }
+
+        // trail start/end caps are drawn like any other sprite list
+        let (pso, bindless) = sprite_list_pso(&dx_lua, false, true);
+
+        if pso != cur_pso { frame.set_pipeline_state(pso); }
+
+        for trail_list in &*trail_lists {
+            let mut tl_inner = trail_list.inner.lock().unwrap();
+
+            if !tl_inner.draw || !in_channels(&tl_inner.channel) { continue; }
+
+            tl_inner.caps.draw(
+                frame,
+                &dx_lua.dx,
+                &world_proj,
+                &world_view,
+                &map_proj,
+                &map_view,
+                mapfullscreen,
+                mapscale,
                 &camera_pos,
                 &mouse_ray,
                 minimapleft,
                 minimaptop,
                 mapw,
                 maph,
+                mouse_x,
+                mouse_y,
                 mouse_map_x,
                 mouse_map_y,
-                mouse_in_map
+                mouse_in_map,
+                bindless,
+                &map_rects,
+                capture
             );
         }
     }
 }
 
+/// Sends hover and click callbacks for markers.
+fn update_markers(dx_lua: &DxLua) {
+    use windows::Win32::UI::Input::KeyboardAndMouse;
+
+    let lbutton = unsafe { KeyboardAndMouse::GetAsyncKeyState(KeyboardAndMouse::VK_LBUTTON.0 as i32) } as u16 & 0x8000 != 0;
+
+    // a click is the button being released
+    let clicked = dx_lua.lbutton_down.swap(lbutton, atomic::Ordering::Relaxed) && !lbutton;
+
+    for marker in dx_lua.markers.lock().unwrap().iter() {
+        let hovered = {
+            let sl_inner = marker.sprites.inner.lock().unwrap();
+
+            sl_inner.draw && sl_inner.mouse_hover_tags.len() > 0
+        };
+
+        let mut inner = marker.inner.lock().unwrap();
+
+        if hovered != inner.hovered {
+            inner.hovered = hovered;
+
+            if inner.on_hover >= 0 {
+                crate::lua_manager::queue_targeted_event(inner.on_hover, Some(Box::new(hovered)));
+            }
+        }
+
+        if hovered && clicked && inner.on_click >= 0 {
+            crate::lua_manager::queue_targeted_event(inner.on_click, None);
+        }
+    }
+}
+
+/// Returns the pipeline state to draw a sprite list with and `true` if it
+/// draws bindless.
+///
+/// Lists are drawn bindless when the device supports it, unless they are using
+/// alpha to coverage.
+fn sprite_list_pso(
+    dx_lua: &DxLua,
+    alpha_to_coverage: bool,
+    depth_test: bool
+) -> (&Direct3D12::ID3D12PipelineState, bool) {
+    if !depth_test {
+        if let Some(pso) = &dx_lua.sprite_list_bindless_nodepth_pso {
+            return (pso, true);
+        }
+
+        return (&dx_lua.sprite_list_nodepth_pso, false);
+    }
+
+    if alpha_to_coverage {
+        return (&dx_lua.sprite_list_a2c_pso, false);
+    }
+
+    if let Some(pso) = &dx_lua.sprite_list_bindless_pso {
+        return (pso, true);
+    }
+
+    return (&dx_lua.sprite_list_pso, false);
+}
+
 fn calc_mouse_ray(
     mouse_x: i64,
     mouse_y: i64,
@@ -428,6 +776,66 @@ fn ray_points_at(x: f32, y: f32, z: f32, radius: f32, origin: &lamath::Vec3F, ra
     false
 }
 
+/// Returns `true` if `ray`, starting at `origin`, passes within `radius` of the
+/// line segment from `p1` to `p2`.
+fn ray_near_segment(p1: &lamath::Vec3F, p2: &lamath::Vec3F, radius: f32, origin: &lamath::Vec3F, ray: &lamath::Vec3F) -> bool {
+    // the closest points between the ray and the segment, see Real-Time
+    // Collision Detection, 5.1.9
+    let seg = *p2 - *p1;
+    let r = *origin - *p1;
+
+    let a = ray.dot(ray);
+    let e = seg.dot(&seg);
+
+    // a segment with no length is just a point
+    if e <= f32::EPSILON { return ray_points_at(p1.x, p1.y, p1.z, radius, origin, ray); }
+
+    let b = ray.dot(&seg);
+    let c = ray.dot(&r);
+    let f = seg.dot(&r);
+
+    let denom = (a * e) - (b * b);
+
+    // s is the distance along the ray, t is the fraction along the segment
+    let mut s = if denom > f32::EPSILON { (((b * f) - (c * e)) / denom).max(0.0) } else { 0.0 };
+    let mut t = ((b * s) + f) / e;
+
+    if t < 0.0 {
+        t = 0.0;
+        s = (-c / a).max(0.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = ((b - c) / a).max(0.0);
+    }
+
+    let on_ray = *origin + ray.mulf(s);
+    let on_seg = *p1 + seg.mulf(t);
+
+    let d = on_ray - on_seg;
+
+    return d.dot(&d) <= radius.powi(2);
+}
+
+/// Returns `true` if the point `x`,`y` is within `radius` of the line segment
+/// from `p1` to `p2`, ignoring Z.
+fn point_near_segment_2d(x: f32, y: f32, p1: &lamath::Vec3F, p2: &lamath::Vec3F, radius: f32) -> bool {
+    let segx = p2.x - p1.x;
+    let segy = p2.y - p1.y;
+
+    let lensq = (segx * segx) + (segy * segy);
+
+    let t = if lensq <= f32::EPSILON {
+        0.0
+    } else {
+        ((((x - p1.x) * segx) + ((y - p1.y) * segy)) / lensq).clamp(0.0, 1.0)
+    };
+
+    let dx = x - (p1.x + (segx * t));
+    let dy = y - (p1.y + (segy * t));
+
+    return (dx * dx) + (dy * dy) <= radius.powi(2);
+}
+
 macro_rules! inst_input {
     ($name:literal, $index:literal, $format:expr, $slot:literal, $offset:literal, $step: literal) => {{
         Direct3D12::D3D12_INPUT_ELEMENT_DESC {
@@ -456,12 +864,28 @@ macro_rules! vert_input {
     }}
 }
 
-fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
+/// The depth comparison used by the sprite and trail pipelines.
+fn depth_func(dx: &Arc<dx::Dx>) -> Direct3D12::D3D12_COMPARISON_FUNC {
+    if dx.swapchain().reverse_depth() {
+        Direct3D12::D3D12_COMPARISON_FUNC_GREATER
+    } else {
+        Direct3D12::D3D12_COMPARISON_FUNC_LESS
+    }
+}
+
+fn create_sprite_list_pso(
+    dx: &Arc<dx::Dx>,
+    alpha_to_coverage: bool,
+    bindless: bool,
+    depth_test: bool
+) -> Direct3D12::ID3D12PipelineState {
     debug!("Loading sprite list vertex shader from {}...", SPRITE_LIST_VERT_CSO);
-    let vertcso = std::fs::read(SPRITE_LIST_VERT_CSO).expect(format!("Couldn't read {}", SPRITE_LIST_VERT_CSO).as_str());
+    let vertcso = dx::read_shader(SPRITE_LIST_VERT_CSO).unwrap_or_else(|err| panic!("{}", err));
 
-    debug!("Loading sprite list pixel shader from {}...", SPRITE_LIST_PIXEL_CSO);
-    let pixelcso = std::fs::read(SPRITE_LIST_PIXEL_CSO).expect(format!("Couldn't read {}", SPRITE_LIST_PIXEL_CSO).as_str());
+    let pixelpath = if bindless { SPRITE_LIST_BINDLESS_PIXEL_CSO } else { SPRITE_LIST_PIXEL_CSO };
+
+    debug!("Loading sprite list pixel shader from {}...", pixelpath);
+    let pixelcso = dx::read_shader(pixelpath).unwrap_or_else(|err| panic!("{}", err));
 
     let inputs = [
         inst_input!{"POSITION" , 0, Dxgi::Common::DXGI_FORMAT_R32G32B32_FLOAT   , 0,   0, 1},
@@ -477,6 +901,11 @@ fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
         inst_input!{"ROTATION" , 1, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT, 0,  72, 1},
         inst_input!{"ROTATION" , 2, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT, 0,  88, 1},
         inst_input!{"ROTATION" , 3, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT, 0, 104, 1},
+        inst_input!{"TEX_INDEX", 0, Dxgi::Common::DXGI_FORMAT_R32_UINT          , 0, 120, 1},
+        inst_input!{"FRAMES"   , 0, Dxgi::Common::DXGI_FORMAT_R32_UINT          , 0, 124, 1},
+        inst_input!{"FPS"      , 0, Dxgi::Common::DXGI_FORMAT_R32_FLOAT         , 0, 128, 1},
+        inst_input!{"PARAMS"   , 0, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT, 0, 132, 1},
+        inst_input!{"EDGE_SIZE", 0, Dxgi::Common::DXGI_FORMAT_R32_FLOAT         , 0, 148, 1},
     ];
 
     let mut psodesc = Direct3D12::D3D12_GRAPHICS_PIPELINE_STATE_DESC::default();
@@ -497,7 +926,14 @@ fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
     psodesc.RasterizerState.DepthClipEnable      = true.into();
     psodesc.RasterizerState.ConservativeRaster   = Direct3D12::D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF;
 
-    psodesc.BlendState.RenderTarget[0].BlendEnable           = true.into();
+    if alpha_to_coverage {
+        // coverage is determined by the alpha output of the pixel shader
+        // instead of blending, which gives order independent cutouts
+        psodesc.BlendState.AlphaToCoverageEnable             = true.into();
+        psodesc.BlendState.RenderTarget[0].BlendEnable       = false.into();
+    } else {
+        psodesc.BlendState.RenderTarget[0].BlendEnable       = true.into();
+    }
     psodesc.BlendState.RenderTarget[0].SrcBlend              = Direct3D12::D3D12_BLEND_ONE;
     psodesc.BlendState.RenderTarget[0].DestBlend             = Direct3D12::D3D12_BLEND_INV_SRC_ALPHA;
     psodesc.BlendState.RenderTarget[0].BlendOp               = Direct3D12::D3D12_BLEND_OP_ADD;
@@ -506,11 +942,17 @@ fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
     psodesc.BlendState.RenderTarget[0].BlendOpAlpha          = Direct3D12::D3D12_BLEND_OP_ADD;
     psodesc.BlendState.RenderTarget[0].RenderTargetWriteMask = Direct3D12::D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8;
 
-    psodesc.DepthStencilState.DepthEnable    = true.into();
-    psodesc.DepthStencilState.DepthFunc      = Direct3D12::D3D12_COMPARISON_FUNC_LESS;
-    psodesc.DepthStencilState.DepthWriteMask = Direct3D12::D3D12_DEPTH_WRITE_MASK_ALL;
+    // sprites without depth testing are drawn on top of everything drawn
+    // before them and don't hide anything drawn after
+    psodesc.DepthStencilState.DepthEnable    = depth_test.into();
+    psodesc.DepthStencilState.DepthFunc      = depth_func(dx);
+    psodesc.DepthStencilState.DepthWriteMask = if depth_test {
+        Direct3D12::D3D12_DEPTH_WRITE_MASK_ALL
+    } else {
+        Direct3D12::D3D12_DEPTH_WRITE_MASK_ZERO
+    };
     psodesc.DepthStencilState.StencilEnable  = false.into();
-    psodesc.DSVFormat                        = Dxgi::Common::DXGI_FORMAT_D32_FLOAT;
+    psodesc.DSVFormat                        = dx::DEPTH_FORMAT;
 
     psodesc.SampleMask = std::ffi::c_uint::MAX; //UINT_MAX;
     psodesc.PrimitiveTopologyType = Direct3D12::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE;
@@ -518,7 +960,15 @@ fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
     psodesc.RTVFormats[0] = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
     psodesc.SampleDesc.Count = 1;
 
-    let pso = dx.create_pipeline_state(&mut psodesc, "EG-Overlay D3D12 Sprite List Pipeline State")
+    let name = match (alpha_to_coverage, bindless, depth_test) {
+        (true, _, _)      => "EG-Overlay D3D12 Sprite List Alpha to Coverage Pipeline State",
+        (_, true, true)   => "EG-Overlay D3D12 Sprite List Bindless Pipeline State",
+        (_, true, false)  => "EG-Overlay D3D12 Sprite List Bindless No Depth Pipeline State",
+        (_, false, true)  => "EG-Overlay D3D12 Sprite List Pipeline State",
+        (_, false, false) => "EG-Overlay D3D12 Sprite List No Depth Pipeline State",
+    };
+
+    let pso = dx.create_pipeline_state(&mut psodesc, name)
         .expect("Couldn't create sprite list pipeline state.");
 
     return pso;
@@ -526,14 +976,16 @@ fn create_sprite_list_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
 
 fn create_trail_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
     debug!("Loading trail vertex shader from {}...", TRAIL_VERT_CSO);
-    let vertcso = std::fs::read(TRAIL_VERT_CSO).expect(format!("Couldn't read {}", TRAIL_VERT_CSO).as_str());
+    let vertcso = dx::read_shader(TRAIL_VERT_CSO).unwrap_or_else(|err| panic!("{}", err));
 
     debug!("Loading trail pixel shader from {}...", TRAIL_PIXEL_CSO);
-    let pixelcso = std::fs::read(TRAIL_PIXEL_CSO).expect(format!("Couldn't read {}", TRAIL_PIXEL_CSO).as_str());
+    let pixelcso = dx::read_shader(TRAIL_PIXEL_CSO).unwrap_or_else(|err| panic!("{}", err));
 
     let inputs = [
-        vert_input!{"POSITION", 0, Dxgi::Common::DXGI_FORMAT_R32G32B32_FLOAT, 0,  0, 0},
-        vert_input!{"TEXUV"   , 0, Dxgi::Common::DXGI_FORMAT_R32G32_FLOAT   , 0, 12, 0},
+        vert_input!{"POSITION", 0, Dxgi::Common::DXGI_FORMAT_R32G32B32_FLOAT   , 0,  0, 0},
+        vert_input!{"TEXUV"   , 0, Dxgi::Common::DXGI_FORMAT_R32G32_FLOAT      , 0, 12, 0},
+        vert_input!{"COLOR"   , 0, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT, 0, 20, 0},
+        vert_input!{"AGE"     , 0, Dxgi::Common::DXGI_FORMAT_R32_FLOAT         , 0, 36, 0},
     ];
 
     let mut psodesc = Direct3D12::D3D12_GRAPHICS_PIPELINE_STATE_DESC::default();
@@ -564,10 +1016,10 @@ fn create_trail_pso(dx: &Arc<dx::Dx>) -> Direct3D12::ID3D12PipelineState {
     psodesc.BlendState.RenderTarget[0].RenderTargetWriteMask = Direct3D12::D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8;
 
     psodesc.DepthStencilState.DepthEnable    = true.into();
-    psodesc.DepthStencilState.DepthFunc      = Direct3D12::D3D12_COMPARISON_FUNC_LESS;
+    psodesc.DepthStencilState.DepthFunc      = depth_func(dx);
     psodesc.DepthStencilState.DepthWriteMask = Direct3D12::D3D12_DEPTH_WRITE_MASK_ALL;
     psodesc.DepthStencilState.StencilEnable  = false.into();
-    psodesc.DSVFormat                        = Dxgi::Common::DXGI_FORMAT_D32_FLOAT;
+    psodesc.DSVFormat                        = dx::DEPTH_FORMAT;
 
     psodesc.SampleMask = std::ffi::c_uint::MAX; //UINT_MAX;
     psodesc.PrimitiveTopologyType = Direct3D12::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE;
@@ -590,6 +1042,17 @@ const DX_LUA_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"texturemap", texturemap_new,
     c"spritelist", spritelist_new,
     c"traillist" , traillist_new,
+    c"marker"    , marker_new,
+    c"flush"     , flush,
+    c"setchannel", set_channel,
+    c"setworldup", set_world_up,
+    c"capture"   , capture,
+
+    c"setrenderscale", set_render_scale,
+    c"renderscale"   , render_scale,
+
+    c"setresourceloader"   , set_resource_loader,
+    c"removeresourceloader", remove_resource_loader,
 };
 
 /*** RST
@@ -605,6 +1068,7 @@ const DX_LUA_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
 unsafe extern "C" fn texturemap_new(l: &lua_State) -> i32 {
     let tm: Arc<TextureMap> = Arc::new(TextureMap {
         textures: Mutex::new(HashMap::new()),
+        generation: atomic::AtomicU64::new(0),
     });
 
     let tm_ptr = Arc::into_raw(tm.clone());
@@ -629,25 +1093,86 @@ unsafe extern "C" fn texturemap_new(l: &lua_State) -> i32 {
 }
 
 /*** RST
-.. lua:function:: spritelist(texturemap[, location])
+.. lua:function:: spritelist(texturemap[, location[, coordinates[, depthtest]]])
 
     Create a new :lua:class:`dxspritelist` object.
 
     :param dxtexturemap texturemap:
     :param string location: (Optional) How the sprites in this list will be
         positioned. See below. Default: ``'world'``.
+    :param string coordinates: (Optional) The coordinate system sprite
+        positions are given in, ``'world'`` or ``'continent'``. See below.
+        Default: the coordinates of ``location``.
+    :param boolean depthtest: (Optional) If ``false``, sprites in this list
+        are not hidden by sprites and trails in front of them, i.e. for
+        objective markers that should be visible through walls. Lists created
+        later can still be drawn over them.
+        :lua:meth:`dxspritelist.alphatocoverage` is ignored for these lists.
+        Default: ``true``.
     :rtype: dxspritelist
 
     **Location Values**
 
-    =========== ================================================================
-    Value       Description
-    =========== ================================================================
-    ``'world'`` Sprites are drawn within the 3D world, coordinates must be in
-                map coordinates.
-    ``'map'``   Sprites are dawn on the (mini)map, coordinates must be in
-                continent coordinates.
-    =========== ================================================================
+    ============ ===============================================================
+    Value        Description
+    ============ ===============================================================
+    ``'world'``  Sprites are drawn within the 3D world, coordinates must be in
+                 map coordinates.
+    ``'map'``    Sprites are dawn on the (mini)map, coordinates must be in
+                 continent coordinates.
+    ``'screen'`` Sprites are drawn at fixed positions on the screen, ``x`` and
+                 ``y`` are in pixels from the top left corner and ``size`` is in
+                 pixels.
+    ============ ===============================================================
+
+    Screen sprites are drawn below the overlay UI and are hidden while the map
+    is open, like world sprites. They always face the screen, so ``billboard``
+    and ``rotation`` are ignored, as are the fade distances. Mouse testing
+    checks if the mouse cursor is within the sprite's rectangle.
+
+    **Coordinate Conversion**
+
+    When ``coordinates`` doesn't match ``location``, sprite positions are
+    converted when they are drawn, so the same marker data can be added to
+    both a world and a map list:
+
+    - ``'world'`` positions on a ``'map'`` list: ``x`` and ``z`` are converted
+      to the continent ``x`` and ``y``, ``y`` is ignored.
+    - ``'continent'`` positions on a ``'world'`` list: ``x`` and ``y`` are
+      converted to the map ``x`` and ``z``, and ``z`` is used as the height
+      (the map ``y``).
+
+    Conversion uses the map and continent rectangles set with
+    :lua:func:`mumble-link.setmaprects`, which must be set for the current
+    map. Lists that need conversion are not drawn until the rectangles are
+    set, and are converted again whenever they change. Coordinate conversion
+    can't be used with screen lists.
+
+    .. code-block:: lua
+        :caption: Coordinate conversion
+
+        ml.setmaprects(map.map_rect, map.continent_rect)
+
+        local world = dx.spritelist(textures, 'world')
+        local map = dx.spritelist(textures, 'map', 'world')
+
+        -- the same world position for both
+        for _, poi in ipairs(pois) do
+            local attrs = { x = poi.x, y = poi.y, z = poi.z }
+
+            world:add('poi', attrs)
+            map:add('poi', attrs)
+        end
+
+    .. code-block:: lua
+        :caption: Example
+
+        local hud = dx.spritelist(textures, 'screen')
+
+        -- a row of icons along the top of the screen
+        for i, boon in ipairs({'might', 'fury', 'quickness'}) do
+            hud:add(boon, { x = 400 + (i * 36), y = 40, size = 32 })
+        end
 
     .. versionhistory::
         :0.3.0: Added
@@ -656,44 +1181,56 @@ unsafe extern "C" fn spritelist_new(l: &lua_State) -> i32 {
     let tm = unsafe { checktexturemap(l, 1) };
 
     let mut is_map = false;
+    let mut is_screen = false;
 
     if lua::gettop(l) >= 2 {
         if let Some(loc) = lua::tostring(l, 2) {
             match loc.as_str() {
                 "map" => is_map = true,
                 "world" => is_map = false,
+                "screen" => is_screen = true,
                 _ => {
-                    luaerror!(l, "location must be 'map' or 'world'");
+                    luaerror!(l, "location must be 'map', 'world', or 'screen'");
                     return 0;
                 }
             }
         } else {
-            luaerror!(l, "location must be 'map' or 'world'");
+            luaerror!(l, "location must be 'map', 'world', or 'screen'");
             return 0;
         }
     }
 
-    let inner = SpriteListInner {
-        vert_buffer: None,
-        vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW::default(),
-
-        vert_buffer_size: 0,
-        update_vert_buffer: false,
+    let mut convert = false;
 
-        texture_names: Vec::new(),
-        sprite_data  : Vec::new(),
-        sprite_tags  : Vec::new(),
-        mouse_test   : Vec::new(),
+    if lua::gettop(l) >= 3 && lua::luatype(l, 3) != lua::LuaType::LUA_TNIL {
+        let coords = lua::tostring(l, 3).unwrap_or_default();
 
-        texture_map: (*tm).clone(),
-
-        mouse_hover_tags: Vec::new(),
+        match coords.as_str() {
+            "world"     => convert = is_map,
+            "continent" => convert = !is_map,
+            _ => {
+                luaerror!(l, "coordinates must be 'world' or 'continent'");
+                return 0;
+            }
+        }
 
-        is_map: is_map,
+        if is_screen {
+            luaerror!(l, "coordinates can't be used with screen lists");
+            return 0;
+        }
+    }
 
-        draw: true,
+    let depth_test = if lua::gettop(l) >= 4 && lua::luatype(l, 4) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, 4, lua::LuaType::LUA_TBOOLEAN);
+        lua::toboolean(l, 4)
+    } else {
+        true
     };
 
+    let mut inner = SpriteListInner::new((*tm).clone(), is_map);
+    inner.is_screen = is_screen;
+    inner.convert = convert;
+    inner.depth_test = depth_test;
 
     let sl: Arc<SpriteList> = Arc::new(SpriteList {
         inner: Mutex::new(inner),
@@ -751,21 +1288,7 @@ unsafe extern "C" fn traillist_new(l: &lua_State) -> i32 {
         }
     }
 
-    let inner = TrailListInner {
-        vert_buffer: None,
-        vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW::default(),
-
-        vert_buffer_size: 0,
-        update_vert_buffer: false,
-
-        texture_map: (*tm).clone(),
-
-        texture_names: Vec::new(),
-        trails: Vec::new(),
-
-        is_map: is_map,
-        draw: true,
-    };
+    let inner = TrailListInner::new((*tm).clone(), is_map);
 
     let tl: Arc<TrailList> = Arc::new(TrailList {
         inner: Mutex::new(inner),
@@ -796,1083 +1319,3623 @@ unsafe extern "C" fn traillist_new(l: &lua_State) -> i32 {
     return 1;
 }
 
-unsafe extern "C" fn open_module(l: &lua_State) -> i32 {
-    let dx_lua_ptr = Weak::into_raw(Arc::downgrade(&DX_LUA.lock().unwrap().as_ref().unwrap().clone()));
+/*** RST
+.. lua:function:: marker(texturemap, attributes)
 
-    lua::newtable(l);
-    unsafe { lua::pushlightuserdata(l, dx_lua_ptr as *const std::ffi::c_void); }
-    lua::L::setfuncs(l, DX_LUA_FUNCS, 1);
+    Create a new :lua:class:`dxmarker` object.
 
-    return 1;
-}
+    A marker combines a single sprite with an optional trail leading to it and
+    handles mouse hover and clicks, which covers the most common use of sprite
+    and trail lists.
 
-fn get_dx_lua_upvalue(l: &lua_State) -> Option<Arc<DxLua>> {
-    let dx_lua_ptr: *const DxLua = unsafe {
-        std::mem::transmute(lua::touserdata(l, lua::LUA_REGISTRYINDEX -1))
-    };
+    ``attributes`` must be a table with a ``texture`` field, the name of a
+    texture in ``texturemap``, and may have any of the fields accepted by
+    :lua:meth:`dxspritelist.add`, along with the following:
 
-    let dx_lua_weak = ManuallyDrop::new(unsafe { Weak::from_raw(dx_lua_ptr) });
+    ========= =================================================================
+    Field     Description
+    ========= =================================================================
+    location  ``'world'`` or ``'map'``, see :lua:func:`spritelist`.
+              Default: ``'world'``.
+    trail     A table of trail attributes, see :lua:meth:`dxtraillist.add`,
+              along with a ``texture`` field.
+    onhover   A function that is called with ``true`` when the mouse moves over
+              the marker and ``false`` when it leaves.
+    onclick   A function that is called when the marker is left clicked.
+    ========= =================================================================
 
-    dx_lua_weak.upgrade()
-}
+    .. note::
 
+        Clicks are not consumed, they will still be received by the game.
 
-/*** RST
-Classes
--------
+    :param dxtexturemap texturemap:
+    :param table attributes: See above.
+    :rtype: dxmarker
 
-.. lua:class:: dxtexturemap
+    .. code-block:: lua
+        :caption: Example
 
-    A texture map holds a list of textures that other objects in this module use
-    when being displayed.
+        local m = dx.marker(textures, {
+            texture = 'waypoint',
+            x = 100, y = 200, z = 300,
+            trail = { texture = 'trail', points = points },
+            onhover = function(hovered) tooltip:show(hovered) end,
+            onclick = function() overlay.loginfo('clicked') end,
+        })
+
+    .. versionhistory::
+        :0.3.0: Added
 */
-struct TextureMap {
-    textures: Mutex<HashMap<String, Arc<Texture>>>,
-}
+unsafe extern "C" fn marker_new(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
 
-impl TextureMap {
-    pub fn get(&self, name: &str) -> Option<Arc<Texture>> {
-        match self.textures.lock().unwrap().get(name) {
-            Some(t) => Some(t.clone()),
-            None    => None,
-        }
-    }
-}
+    let tm = unsafe { checktexturemap(l, 1) };
 
-struct Texture {
-    //size: u32,
-    max_u: f32,
-    max_v: f32,
-    xy_ratio: f32,
-    texture: dx::Texture,
-}
+    let mut is_map = false;
 
-const TEXTUREMAP_METATABLE_NAME: &str = "dx::lua::TextureMap";
-
-const TEXTUREMAP_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
-    c"__gc" , texturemap_gc,
-    c"clear", texturemap_clear,
-    c"add"  , texturemap_add,
-    c"has"  , texturemap_has,
-};
+    if lua::getfield(l, 2, "location") != lua::LuaType::LUA_TNIL {
+        match lua::tostring(l, -1).unwrap_or_default().as_str() {
+            "map"   => is_map = true,
+            "world" => is_map = false,
+            _       => {
+                lua::pop(l, 1);
+                luaerror!(l, "location must be 'map' or 'world'");
+                return 0;
+            }
+        }
+    }
+    lua::pop(l, 1);
 
+    if lua::getfield(l, 2, "texture") != lua::LuaType::LUA_TSTRING {
+        lua::pop(l, 1);
+        luaerror!(l, "texture must be a string.");
+        return 0;
+    }
+    let texname = lua::tostring(l, -1).unwrap();
+    lua::pop(l, 1);
 
-unsafe fn checktexturemap(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<TextureMap>> {
-    let tm_lua_ptr: *mut *const TextureMap = unsafe {
-        std::mem::transmute(lua::L::checkudata(l, ind, TEXTUREMAP_METATABLE_NAME))
+    let texture = match tm.get(&texname) {
+        Some(t) => t,
+        None    => {
+            luaerror!(l, "Texture {} not found in texture map.", texname);
+            return 0;
+        }
     };
 
-    ManuallyDrop::new(unsafe { Arc::from_raw(*tm_lua_ptr) } )
-}
-
-unsafe extern "C" fn texturemap_gc(l: &lua_State) -> i32 {
-    let mut tm = unsafe { checktexturemap(l, 1) };
-
-    unsafe { ManuallyDrop::drop(&mut tm); }
-
-    return 0;
-}
-
-/*** RST
-    .. lua:method:: clear()
+    let mut sprites = SpriteListInner::new((*tm).clone(), is_map);
 
-        Remove all textures from this map.
+    let mut s = SpriteListSprite::new(&texture);
+    s.update_from_lua_table(l, 2);
 
-        .. danger::
+    sprites.push_sprite(&texname, s, -1, -1, true);
 
-            If objects are still referencing textures within this map after this
-            method is called, their draws will not function properly.
+    let mut trails: Option<Arc<TrailList>> = None;
 
-        .. versionhistory::
-            :0.3.0: Added
-*/
-unsafe extern "C" fn texturemap_clear(l: &lua_State) -> i32 {
-    let tm = unsafe { checktexturemap(l, 1) };
+    if lua::getfield(l, 2, "trail") == lua::LuaType::LUA_TTABLE {
+        let trailind = lua::gettop(l);
 
-    tm.textures.lock().unwrap().clear();
+        let trailtex = if lua::getfield(l, trailind, "texture") == lua::LuaType::LUA_TSTRING {
+            lua::tostring(l, -1)
+        } else {
+            None
+        };
+        lua::pop(l, 1);
 
-    return 0;
-}
+        let trailtex = match trailtex {
+            Some(t) if tm.get(&t).is_some() => t,
+            _ => {
+                lua::pop(l, 1);
+                luaerror!(l, "trail.texture must be the name of a texture in the texture map.");
+                return 0;
+            }
+        };
 
-/*** RST
-    .. lua:method:: add(name, data, mipmaps)
+        if lua::getfield(l, trailind, "points") != lua::LuaType::LUA_TTABLE {
+            lua::pop(l, 2);
+            luaerror!(l, "trail.points must be a table.");
+            return 0;
+        }
+        lua::pop(l, 1);
 
-        Add a texture.
+        let mut tl_inner = TrailListInner::new((*tm).clone(), is_map);
 
-        :param string name: The name of the texture, this will be used to
-            reference it later when adding data to sprite lists and other
-            objects.
-        :param string data: The texture data.
-        :param boolean mipmaps: Generate mipmaps, default ``true``.
+        let mut t = TrailListTrail::new();
+        t.update_from_lua_table(l, trailind);
 
+        tl_inner.push_trail(&trailtex, t);
 
-        .. admonition:: Implementation Detail
+        trails = Some(Arc::new(TrailList { inner: Mutex::new(tl_inner) }));
+    }
+    lua::pop(l, 1);
 
-            EG-Overlay uses the `Windows Imaging Component <https://learn.microsoft.com/en-us/windows/win32/wic/-wic-lh>`_
-            to load ``data``, so any `format <https://learn.microsoft.com/en-us/windows/win32/wic/native-wic-codecs>`_
-            it supports can be used.
+    let on_hover = if lua::getfield(l, 2, "onhover") == lua::LuaType::LUA_TFUNCTION {
+        module_permissions::ref_callback(l)
+    } else {
+        lua::pop(l, 1);
+        -1
+    };
 
-            All textures are loaded as 4 channel BGRA images.
+    let on_click = if lua::getfield(l, 2, "onclick") == lua::LuaType::LUA_TFUNCTION {
+        module_permissions::ref_callback(l)
+    } else {
+        lua::pop(l, 1);
+        -1
+    };
 
-        .. versionhistory::
-            :0.3.0: Added
-*/
-unsafe extern "C" fn texturemap_add(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 2);
+    let marker = Arc::new(Marker {
+        sprites: Arc::new(SpriteList { inner: Mutex::new(sprites) }),
+        trails: trails,
 
-    if lua::gettop(l) < 3 {
-        lua::pushstring(l, "texturemap:add takes at least 2 arguments");
-        unsafe { lua::error(l); }
-    }
+        inner: Mutex::new(MarkerInner {
+            on_hover: on_hover,
+            on_click: on_click,
+            hovered: false,
+        }),
+    });
 
-    let tm = unsafe { checktexturemap(l, 1) };
-    let name = lua::tostring(l, 2).unwrap();
-    let data: &[u8] = lua::tobytes(l, 3);
+    let marker_ptr = Arc::into_raw(marker.clone());
 
-    let mut mipmaps = true;
+    let lua_marker_ptr: *mut *const Marker = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<*const Marker>(), 0))
+    };
 
-    if lua::gettop(l) >= 4 {
-        mipmaps = lua::toboolean(l, 4);
-    }
+    unsafe { *lua_marker_ptr = marker_ptr; }
 
-    let mut textures = tm.textures.lock().unwrap();
+    if lua::L::newmetatable(l, MARKER_METATABLE_NAME) {
+        let dx_lua_ptr = Weak::into_raw(Arc::downgrade(&DX_LUA.lock().unwrap().as_ref().unwrap().clone()));
 
-    if textures.contains_key(&name) {
-        luawarn!(l, "Texture {} already exists in this texturemap, overwriting.", name);
+        lua::pushvalue(l, -1);
+        lua::setfield(l, -2, "__index");
+        unsafe { lua::pushlightuserdata(l, dx_lua_ptr as *const std::ffi::c_void); }
+        lua::L::setfuncs(l, MARKER_FUNCS, 1);
     }
+    lua::setmetatable(l, -2);
 
     let dx_lua = get_dx_lua_upvalue(l).unwrap();
 
-    // We'll use Windows Imaging Component to load the image data in. It's already
-    // present in Windows and can handle any of the formats we should be concerned
-    // with already. It'll also help with creating the mipmaps.
-    let wicfactory: Imaging::IWICImagingFactory;
-
-    // Get the factory
-    match unsafe { Com::CoCreateInstance::<_, Imaging::IWICImagingFactory>(
-        &Imaging::CLSID_WICImagingFactory,
-        None,
-        Com::CLSCTX_INPROC_SERVER
-    ) } {
-        Ok(fac) => wicfactory = fac,
-        Err(err) => {
-            luaerror!(l, "Couldn't create WIC factory: {}", err);
-            return 0;
-        }
+    dx_lua.sprite_lists.lock().unwrap().push_back(marker.sprites.clone());
+    if let Some(t) = &marker.trails {
+        dx_lua.trail_lists.lock().unwrap().push_back(t.clone());
     }
+    dx_lua.markers.lock().unwrap().push_back(marker);
 
-    let memstream : Imaging::IWICStream;
-    let decoder   : Imaging::IWICBitmapDecoder;
-    let frame     : Imaging::IWICBitmapFrameDecode;
-    let converter : Imaging::IWICFormatConverter;
-    let bitmap    : Imaging::IWICBitmap;
-    let bitmaplock: Imaging::IWICBitmapLock;
+    return 1;
+}
 
-    let mut pixels_len: u32     = 0;
-    let mut pixels    : *mut u8 = std::ptr::null_mut();
+/*** RST
+.. lua:function:: flush()
 
-    let mut width: u32 = 0;
-    let mut height: u32 = 0;
+    Wait for the GPU to complete all pending draw and copy commands.
 
-    // Create a stream to hold the image data that we are feeding in
-    match unsafe { wicfactory.CreateStream() } {
-        Ok(strm) => memstream = strm,
-        Err(err) => {
-            luaerror!(l, "Couldn't create a WIC stream: {}", err);
-            return 0;
-        }
-    }
+    This can be used to ensure that all queued work has finished before
+    reading back texture data.
 
-    if let Err(err) = unsafe { memstream.InitializeFromMemory(data) } {
-        luaerror!(l, "Couldn't initialize texture stream: {}", err);
-        return 0;
-    }
+    .. warning::
+        This stalls both the GPU and the Lua thread until all work is
+        complete. It is expensive and should be used sparingly, never every
+        update.
 
-    // Create a decoder for the input stream. If this errors with
-    // "Component not found" that usually means the data is invalid or the file
-    // format isn't one WIC can decode.
-    match unsafe { wicfactory.CreateDecoderFromStream(
-        &memstream,
-        std::ptr::null() as *const _,
-        Imaging::WICDecodeMetadataCacheOnDemand
-    ) } {
-        Ok(dec) => decoder = dec,
-        Err(err) => {
-            luaerror!(l, "Couldn't get image decoder: {}", err);
-            return 0;
-        }
-    }
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn flush(l: &lua_State) -> i32 {
+    let dx_lua = get_dx_lua_upvalue(l).unwrap();
 
-    // Get a frame...most images only have a single frame.
-    match unsafe { decoder.GetFrame(0) } {
-        Ok(frm) => frame = frm,
-        Err(err) => {
-            luaerror!(l, "Couldn't get image frame: {}", err);
-            return 0;
-        }
-    }
+    dx_lua.dx.swapchain().flush_commands();
+    dx_lua.dx.copy_queue().flush_commands();
 
-    // Create a converter to convert the data from whatever format it happens
-    // to be in to the exact format we want.
-    match unsafe { wicfactory.CreateFormatConverter() } {
-        Ok(con) => converter = con,
-        Err(err) => {
-            luaerror!(l, "Couldn't create image format converter: {}", err);
-            return 0;
-        }
-    }
+    return 0;
+}
 
-    // Initialize the converter with our input data frame and set the output
-    // format.
-    // BGRA here because RGBA was causing some weird things with B-R swapping
-    // channels in mipmaps. weird
-    if let Err(err) = unsafe { converter.Initialize(
-        &frame,
-        &Imaging::GUID_WICPixelFormat32bppBGRA,
-        Imaging::WICBitmapDitherTypeNone,
-        None,
-        0.0,
-        Imaging::WICBitmapPaletteTypeCustom
-    ) } {
-        luaerror!(l, "Couldn't initialize image converter: {}", err);
-        return 0;
-    }
+/*** RST
+.. lua:function:: setresourceloader(scheme, loader)
 
-    // Create a bitmap that reads from the output of the converter above. Reading
-    // from this bitmap will be reading converted pixel data.
-    match unsafe { wicfactory.CreateBitmapFromSource(&converter, Imaging::WICBitmapCacheOnDemand) } {
-        Ok(bm) => bitmap = bm,
-        Err(err) => {
-            luaerror!(l, "Couldnm't create WIC bitmap: {}", err);
-            return 0;
-        }
-    }
+    Set a function that loads texture data for URIs with the given scheme.
 
-    // We can finally see how big the image is too.
-    if let Err(err) = unsafe { bitmap.GetSize(&mut width, &mut height) } {
-        luaerror!(l, "Couldn't get bitmap size: {}", err);
-        return 0;
-    }
+    Once a loader is set, the ``data`` argument of :lua:meth:`dxtexturemap.add`
+    can be a URI such as ``pack://icons/foo.png`` instead of the texture data
+    itself. ``loader`` is called with the full URI and must return the data as
+    a string, or ``nil`` and an error message.
 
-    // In order to read the image data, we have to define what part we want.
-    // In this case, the entire thing.
-    let lockrect = Imaging::WICRect { X: 0, Y: 0, Width: width as i32, Height: height as i32};
+    This allows modules to load textures from zip files, remote URLs, or any
+    other source without other code needing to know where they are stored.
 
-    // Then lock it for reading.
-    match unsafe { bitmap.Lock(&lockrect, Imaging::WICBitmapLockRead.0 as u32) } {
-        Ok(lk) => bitmaplock = lk,
-        Err(err) => {
-            luaerror!(l, "Couldn't lock bitmap: {}", err);
-            return 0;
-        }
-    }
+    Setting a loader for a scheme that already has one replaces it. Loaders
+    are shared by all modules.
 
-    // Now we get a raw pointer and length. Woo!
-    if let Err(err) = unsafe { bitmaplock.GetDataPointer(&mut pixels_len, &mut pixels) } {
-        luaerror!(l, "Couldn't get bitmap data pointer: {}", err);
-        return 0;
-    }
+    :param string scheme: The URI scheme, without ``://``.
+    :param function loader:
 
-    // convert it to a slice for more convenient usage in Rust
-    let pixels_slice: &[u8] = unsafe { std::slice::from_raw_parts(pixels, pixels_len as usize) };
+    .. code-block:: lua
+        :caption: Example
 
-    // At this point pixels should have valid image data in our desired format.
-    // Now, we can create the texture.
+        local dx = require 'dx'
+        local overlay = require 'overlay'
 
-    // How big must we make a texture to hold our image? We'll use square,
-    // power of 2 texture sizes (8, 16, 32, 64, etc.)
-    let mut req_size = 1;
-    while req_size < width || req_size < height { req_size <<= 1; }
+        local pack = overlay.openzip('markers.zip')
 
-    let xy_ratio = width  as f32 / height   as f32;
-    let max_u    = width  as f32 / req_size as f32;
-    let max_v    = height as f32 / req_size as f32;
+        dx.setresourceloader('pack', function(uri)
+            local path = uri:sub(#'pack://' + 1)
+            local data = pack:content(path)
 
-    let mipmaplevels = if mipmaps {
-        (req_size as f64).log2().floor() as u16
-    } else { 1 };
+            if not data then return nil, 'not found in pack' end
 
-    let tex = dx_lua.dx.new_texture_2d(
-        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
-        req_size, req_size, mipmaplevels
-    );
-    tex.set_name(format!("EG-Overlay D3D12 TextureMap Texture: {}", name).as_str());
-    tex.write_pixels(0, 0, 0, width, height, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, pixels_slice);
+            return data
+        end)
 
-    // At this point we are done with the pixel data, so release the lock.
-    // pixels and pixels_slice are now invalid
-    drop(bitmaplock);
+        textures:add('foo', 'pack://icons/foo.png')
 
-    // Now generate mipmaps
-    for mlevel in 1..mipmaplevels {
-        let mipsize: u32 = req_size / 2.0f32.powi(mlevel as i32) as u32;
-        let mipw: u32 = (mipsize as f32 * max_u).floor() as u32;
-        let miph: u32 = (mipsize as f32 * max_v).floor() as u32;
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_resource_loader(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
 
-        let scaler      : Imaging::IWICBitmapScaler;
-        let scaledbitmap: Imaging::IWICBitmap;
-        let scaledlock  : Imaging::IWICBitmapLock;
+    let scheme = lua::tostring(l, 1).unwrap();
 
-        let scaledrect = Imaging::WICRect { X: 0, Y: 0, Width: mipw as i32, Height: miph as i32 };
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
 
-        // This is much the same as using the converter above, but with a scaler
-        // this time.
-        match unsafe { wicfactory.CreateBitmapScaler() } {
-            Ok(sc) => scaler = sc,
-            Err(err) => {
-                luaerror!(l, "Couldn't create bitmap scaler: {}", err);
-                return 0;
-            }
-        }
+    if let Some(old) = crate::lua_manager::set_resource_loader(&scheme, cbi) {
+        lua::L::unref(l, lua::LUA_REGISTRYINDEX, old);
+    }
 
-        if let Err(err) = unsafe { scaler.Initialize(
-            &bitmap,
-            mipw,
-            miph,
-            Imaging::WICBitmapInterpolationModeFant // this could eventually be an option to the function
-        ) } {
-            luaerror!(l, "Couldn't initialize bitmap scaler: {}", err);
-            return 0;
-        }
+    return 0;
+}
 
-        match unsafe { wicfactory.CreateBitmapFromSource(&scaler, Imaging::WICBitmapCacheOnDemand) } {
-            Ok(bm) => scaledbitmap = bm,
-            Err(err) => {
-                luaerror!(l, "Couldn't create scaled bitmap: {}", err);
-                return 0;
-            }
-        }
+/*** RST
+.. lua:function:: removeresourceloader(scheme)
 
-        match unsafe { scaledbitmap.Lock(&scaledrect, Imaging::WICBitmapLockRead.0 as u32) } {
-            Ok(lk) => scaledlock = lk,
-            Err(err) => {
-                luaerror!(l, "Couldn't lock scaled bitmap: {}", err);
-                return 0;
-            }
-        }
+    Remove the loader for the given scheme, see :lua:func:`setresourceloader`.
 
-        // raw pixel data, same as above
-        let mut mippixels_len: u32 = 0;
-        let mut mippixels    : *mut u8 = std::ptr::null_mut();
+    :param string scheme:
 
-        if let Err(err) = unsafe { scaledlock.GetDataPointer(&mut mippixels_len, &mut mippixels) } {
-            luaerror!(l, "Couldn't get mipmap pixels pointer: {}", err);
-            return 0;
-        }
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn remove_resource_loader(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
 
-        let mippixels_slice: &[u8] = unsafe { std::slice::from_raw_parts(mippixels, mippixels_len as usize) };
-        tex.write_pixels(0, 0, mlevel as u32, mipw, miph, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, mippixels_slice);
+    let scheme = lua::tostring(l, 1).unwrap();
+
+    if let Some(old) = crate::lua_manager::remove_resource_loader(&scheme) {
+        lua::L::unref(l, lua::LUA_REGISTRYINDEX, old);
     }
 
-    let t = Texture {
-        //size: req_size,
-        max_u: max_u,
-        max_v: max_v,
-        xy_ratio: xy_ratio,
-        texture: tex,
-    };
+    return 0;
+}
 
-    textures.insert(name.clone(), Arc::new(t));
+/// Returns the name of the metatable of the value at `ind`, if it has one.
+fn metatable_name(l: &lua_State, ind: i32) -> Option<String> {
+    if !lua::getmetatable(l, ind) { return None; }
 
-    return 0;
+    lua::getfield(l, -1, "__name");
+    let name = lua::tostring(l, -1);
+    lua::pop(l, 2);
+
+    return name;
 }
 
 /*** RST
-    .. lua:method:: has(name)
+.. lua:function:: setchannel(list, name)
 
-        Returns ``true`` if this map has a texture named ``name``.
+    Set the render channel of a :lua:class:`dxspritelist`,
+    :lua:class:`dxtraillist` or :lua:class:`dxmarker`.
 
-        :param string name:
+    See :ref:`render-channels` and :lua:func:`capture`.
 
-        :rtype: boolean
+    :param list:
+    :type list: dxspritelist, dxtraillist or dxmarker
+    :param string name:
 
-        .. versionhistory::
-            :0.3.0: Added
+    .. versionhistory::
+        :0.3.0: Added
 */
-unsafe extern "C" fn texturemap_has(l: &lua_State) -> i32 {
+unsafe extern "C" fn set_channel(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 2);
-    let tm = unsafe { checktexturemap(l, 1) };
-    let name = lua::tostring(l, 2).unwrap();
 
-    lua::pushboolean(l, tm.textures.lock().unwrap().contains_key(&name));
+    let channel = lua::tostring(l, 2).unwrap();
 
-    return 1;
+    match metatable_name(l, 1).as_deref() {
+        Some(SPRITELIST_METATABLE_NAME) => {
+            let sl = unsafe { checkspritelist(l, 1) };
+
+            sl.inner.lock().unwrap().channel = channel;
+        },
+        Some(TRAILLIST_METATABLE_NAME) => {
+            let tl = unsafe { checktraillist(l, 1) };
+
+            tl.inner.lock().unwrap().channel = channel;
+        },
+        Some(MARKER_METATABLE_NAME) => {
+            let marker = unsafe { checkmarker(l, 1) };
+
+            if let Some(trails) = &marker.trails {
+                trails.inner.lock().unwrap().channel = channel.clone();
+            }
+
+            marker.sprites.inner.lock().unwrap().channel = channel;
+        },
+        _ => {
+            luaerror!(l, "setchannel: argument 1 must be a spritelist, traillist or marker.");
+        }
+    }
+
+    return 0;
 }
 
 /*** RST
-.. lua:class:: dxspritelist
-*/
+.. lua:function:: setrenderscale(factor)
 
-struct SpriteList {
-    inner: Mutex<SpriteListInner>,
-}
+    Set the resolution sprite lists, trail lists and markers are rendered at,
+    relative to the overlay window.
 
-struct SpriteListInner {
-    vert_buffer: Option<Direct3D12::ID3D12Resource>,
-    vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW,
+    Values less than ``1.0`` render the 3D scene to a smaller render target
+    which is then upscaled, reducing the GPU time spent on marker heavy scenes
+    at the cost of sharpness. The UI is always rendered at full resolution.
 
-    vert_buffer_size: usize,
-    update_vert_buffer: bool,
+    ``factor`` is clamped between ``0.25`` and ``1.0``. The initial value is
+    set by the ``overlay.renderScale`` setting, ``1.0`` by default.
 
-    // Sprites are grouped by texture name because we can render each set that
-    // shares the same texture in a single command.
-    // This is not a HashMap because the order of the textures and data needs
-    // to be consistent and predictable.
-    texture_names: Vec<String>,
-    sprite_data: Vec<Vec<SpriteListSprite>>,
-    sprite_tags: Vec<Vec<i64>>,
-    mouse_test: Vec<Vec<bool>>,
+    :param number factor:
 
-    texture_map: Arc<TextureMap>,
+    .. code-block:: lua
+        :caption: Example
 
-    mouse_hover_tags: Vec<i64>,
+        -- render the scene at 75%
+        dx.setrenderscale(0.75)
 
-    is_map: bool,
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_render_scale(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
 
-    draw: bool,
+    let dx_lua = match get_dx_lua_upvalue(l) {
+        Some(d) => d,
+        None    => return 0,
+    };
+
+    let factor = lua::tonumber(l, 1);
+
+    if factor.is_nan() || factor <= 0.0 {
+        luaerror!(l, "setrenderscale: factor must be greater than 0.");
+        return 0;
+    }
+
+    dx_lua.scene.set_scale(factor as f32);
+
+    crate::overlay::request_redraw();
+
+    return 0;
 }
 
-const SPRITE_MEM_SIZE: usize = std::mem::size_of::<SpriteListSprite>();
+/*** RST
+.. lua:function:: renderscale()
 
-impl SpriteListInner {
-    fn draw(&mut self,
-        frame: &mut dx::SwapChainLock,
-        dx: &Arc<dx::Dx>,
-        world_proj: &lamath::Mat4F,
-        world_view: &lamath::Mat4F,
-        map_proj: &lamath::Mat4F,
-        map_view: &lamath::Mat4F,
-        mapfullscreen: bool,
-        camera: &lamath::Vec3F,
-        mouse_ray: &Option<lamath::Vec3F>,
-        minimapleft: u32,
-        minimaptop: u32,
-        mapw: u32,
-        maph: u32,
-        mouse_map_x: f32,
-        mouse_map_y: f32,
-        mouse_in_map: bool
+    Returns the current render scale, see :lua:func:`setrenderscale`.
 
-    ) {
-        self.mouse_hover_tags.clear();
+    :rtype: number
 
-        if !self.draw { return; }
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn render_scale(l: &lua_State) -> i32 {
+    let dx_lua = match get_dx_lua_upvalue(l) {
+        Some(d) => d,
+        None    => return 0,
+    };
 
-        if !self.is_map && mapfullscreen { return; }
+    lua::pushnumber(l, dx_lua.scene.scale() as f64);
 
-        if self.update_vert_buffer {
-            self.update_vertex_buffer(frame, dx);
-        }
+    return 1;
+}
 
-        if self.vert_buffer.is_none() { return; }
+/*** RST
+.. lua:function:: setworldup(x, y, z)
 
-        if self.is_map {
-            frame.set_root_constant_mat4f(map_view, 0,  0);
-            frame.set_root_constant_mat4f(map_proj, 0, 16);
+    Set the direction that is 'up' in the 3D world.
 
-            if !mapfullscreen {
-                frame.push_viewport(minimapleft as f32, minimaptop as f32, mapw as f32, maph as f32);
-            }
-        } else {
-            frame.set_root_constant_mat4f(world_view, 0,  0);
-            frame.set_root_constant_mat4f(world_proj, 0, 16);
-        }
+    This is used to orient the camera, trails and sprites with a
+    ``billboardmode`` of ``'vertical'``. GW2 uses Y as up, which is the
+    default, and this only needs to be changed for modules rendering scenes
+    that use a different convention. Map lists are not affected.
 
-        frame.set_root_constant_bool (self.is_map   , 0, 35);
+    The vector is normalized and trail lists are rebuilt the next frame.
 
-        frame.set_vertex_buffer(0, &self.vert_buffer_view, self.vert_buffer.as_ref().unwrap());
+    :param number x:
+    :param number y:
+    :param number z:
 
-        let mut inst: u32 = 0;
-        for i in 0..self.texture_names.len() {
-            let tex_name = &self.texture_names[i];
-            let tex: &dx::Texture;
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_world_up(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+
+    let dx_lua = match get_dx_lua_upvalue(l) {
+        Some(d) => d,
+        None    => return 0,
+    };
 
-            let sprite_data = &self.sprite_data[i];
-            let sprite_count = sprite_data.len() as u32;
+    let up = lamath::Vec3F {
+        x: lua::tonumber(l, 1) as f32,
+        y: lua::tonumber(l, 2) as f32,
+        z: lua::tonumber(l, 3) as f32,
+    };
 
-            if sprite_count == 0 { continue; }
+    if up.length() < 0.0001 {
+        luaerror!(l, "setworldup: the up vector can't be zero length.");
+        return 0;
+    }
 
-            let textures = self.texture_map.textures.lock().unwrap();
-            match textures.get(tex_name.as_str()) {
-                Some(t) => tex = &t.texture,
-                _ => {
-                    crate::logging::error!("Invalid texture key: {}", tex_name);
-                    continue;
-                },
-            }
+    *dx_lua.world_up.lock().unwrap() = up.normalize();
 
-            frame.set_texture(0, tex);
+    // trail orientation is baked into their vertex buffers
+    for trail_list in dx_lua.trail_lists.lock().unwrap().iter() {
+        trail_list.inner.lock().unwrap().update_vert_buffer = true;
+    }
 
-            frame.draw_instanced(4, sprite_count, 0, inst);
-            inst += sprite_count;
+    return 0;
+}
 
-            if mouse_ray.is_none() && !self.is_map { continue; }
+/*** RST
+.. lua:function:: capture(channels, callback)
 
-            for s in 0..sprite_data.len() {
-                if !self.mouse_test[i][s] { continue; }
+    Render a separate image that contains only the sprite lists, trail lists,
+    markers and UI elements in the given render channels.
 
-                let tags = self.sprite_tags[i][s];
-                let sprite = &self.sprite_data[i][s];
+    The capture is rendered at the end of the next frame and ``callback`` is
+    called with the result shortly after, once it has been copied from the GPU.
+    The result is a table with the following fields:
 
-                if !self.is_map && !mouse_in_map {
-                    let distsq = (sprite.x - camera.x).powi(2) + (sprite.y - camera.y).powi(2) + (sprite.z - camera.z).powi(2);
+    ====== ==================================================================
+    Field  Description
+    ====== ==================================================================
+    width  The width of the image in pixels.
+    height The height of the image in pixels.
+    data   The pixel data as a string, 4 bytes per pixel in RGBA order with
+           premultiplied alpha. Rows are from top to bottom with no padding.
+    ====== ==================================================================
 
-                    // ray_points_at is fairly resource intensive, so don't do
-                    // it if the sprite is more than 50,000 inches away.
-                    // At that distance, the sprite probably isn't much larger
-                    // than a single pixel anyway.
-                    if distsq >= 2500000000.0 { continue; }
+    :param table channels: A sequence of channel names.
+    :param function callback:
 
-                    if ray_points_at(sprite.x, sprite.y, sprite.z, sprite.size / 2.0, camera, mouse_ray.as_ref().unwrap()) {
-                        self.mouse_hover_tags.push(tags);
-                    }
-                } else if self.is_map && mouse_in_map {
-                    let searchdistsq = (sprite.size / 2.0).powi(2);
+    .. code-block:: lua
+        :caption: Example
 
-                    let mousedistsq = (mouse_map_x - sprite.x).powi(2) + (mouse_map_y - sprite.y).powi(2);
+        local dx = require 'dx'
 
-                    if mousedistsq <= searchdistsq {
-                        self.mouse_hover_tags.push(tags);
-                    }
-                }
+        dx.setchannel(private_sprites, 'private')
+
+        dx.capture({'default'}, function(img)
+            -- img.data does not include private_sprites
+            stream:sendframe(img.width, img.height, img.data)
+        end)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn capture(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 1, lua::LuaType::LUA_TTABLE);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+
+    let dx_lua = match get_dx_lua_upvalue(l) {
+        Some(d) => d,
+        None    => return 0,
+    };
 
+    let mut channels: HashSet<String> = HashSet::new();
+
+    for i in 1..=lua::L::len(l, 1) {
+        lua::geti(l, 1, i as i64);
+
+        match lua::tostring(l, -1) {
+            Some(c) => { channels.insert(c); },
+            None    => {
+                lua::pop(l, 1);
+                luaerror!(l, "capture: channel names must be strings.");
+                return 0;
             }
         }
 
-        if self.is_map && !mapfullscreen { frame.pop_viewport(); }
+        lua::pop(l, 1);
     }
 
-    fn update_vertex_buffer(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>) {
-        let mut new_size = 0;
-        for s in &self.sprite_data {
-            new_size += SPRITE_MEM_SIZE * s.len();
-        }
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
 
-        frame.flush_commands();
+    dx_lua.capture_requests.lock().unwrap().push_back(CaptureRequest {
+        channels: channels,
+        callback: cbi,
+    });
 
-        if new_size == 0 {
-            self.vert_buffer = None;
-            self.vert_buffer_size = new_size;
+    crate::overlay::request_redraw();
 
-            return;
-        } else if self.vert_buffer_size != new_size {
-            let vb = dx.new_vertex_buffer(new_size as u64);
-            crate::dx::object_set_name(&vb, "EG-Overlay D3D12 SpriteList Vertex Buffer");
-            self.vert_buffer_size = new_size;
+    return 0;
+}
 
-            self.vert_buffer_view.BufferLocation = unsafe { vb.GetGPUVirtualAddress() };
-            self.vert_buffer_view.SizeInBytes = new_size as u32;
-            self.vert_buffer_view.StrideInBytes = SPRITE_MEM_SIZE as u32;
+struct CaptureRequest {
+    channels: HashSet<String>,
+    callback: i64,
+}
 
-            self.vert_buffer = Some(vb);
-        }
+/// A capture that has been rendered and copied to a readback buffer.
+struct PendingCapture {
+    buffer: Direct3D12::ID3D12Resource,
+    width: u32,
+    height: u32,
+    row_pitch: u32,
+    callback: i64,
+}
 
-        let upload = dx.new_upload_buffer(self.vert_buffer_size as u64);
-        crate::dx::object_set_name(&upload, "EG-Overlay D3D12 SpriteList Temp. Upload Buffer");
+impl PendingCapture {
+    /// Reads the capture from the readback buffer and queues the callback.
+    ///
+    /// The frame the capture was rendered in must be complete.
+    fn send(self) {
+        let row_size = (self.width * 4) as usize;
 
-        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
-        let rr = Direct3D12::D3D12_RANGE::default();
+        let mut data: Vec<i8> = vec![0; row_size * self.height as usize];
 
-        if unsafe { upload.Map(0, Some(&rr), Some(&mut data)) }.is_err() {
-            panic!("Couldn't map sprite list upload data.");
+        let range = Direct3D12::D3D12_RANGE {
+            Begin: 0,
+            End: self.row_pitch as usize * self.height as usize,
+        };
+
+        let mut mapped: *mut std::ffi::c_void = std::ptr::null_mut();
+
+        if let Err(err) = unsafe { self.buffer.Map(0, Some(&range), Some(&mut mapped)) } {
+            crate::logging::error!("Couldn't map capture readback buffer: {}", err);
+            crate::lua_manager::unref(self.callback);
+            return;
         }
 
-        let mut offset = 0;
-        for sprites in &self.sprite_data {
-            let sprites_size = SPRITE_MEM_SIZE * sprites.len();
-            if sprites_size == 0 { continue; }
+        // the buffer rows are padded to row_pitch
+        for y in 0..self.height as usize {
             unsafe {
-                std::ptr::copy_nonoverlapping(sprites.as_ptr() as *const std::ffi::c_void, data.add(offset), sprites_size);
+                let src = (mapped as *const i8).add(y * self.row_pitch as usize);
+                std::ptr::copy_nonoverlapping(src, data.as_mut_ptr().add(y * row_size), row_size);
             }
-            offset += sprites_size;
         }
 
-        unsafe { upload.Unmap(0, None); }
+        // nothing was written
+        let written = Direct3D12::D3D12_RANGE::default();
 
-        let mut copy = dx.copy_queue();
-        copy.copy_resource(&upload, self.vert_buffer.as_ref().unwrap());
+        unsafe { self.buffer.Unmap(0, Some(&written)); }
 
-        self.update_vert_buffer = false;
+        crate::lua_manager::queue_targeted_event(self.callback, Some(Box::new(CaptureResult {
+            width: self.width,
+            height: self.height,
+            data: data,
+            callback: self.callback,
+        })));
     }
+}
 
-    fn update_matching(&mut self, l: &lua_State) -> i32 {
-        let mut nupdated = 0;
+/// The result of a capture, sent to the capture callback.
+///
+/// The callback reference is freed when this is dropped, after the callback is run.
+struct CaptureResult {
+    width: u32,
+    height: u32,
+    data: Vec<i8>,
+    callback: i64,
+}
 
-        for ti in 0..self.sprite_data.len() {
-            let sprites = &mut self.sprite_data[ti];
-            let tags = &self.sprite_tags[ti];
+impl Drop for CaptureResult {
+    fn drop(&mut self) {
+        crate::lua_manager::unref(self.callback);
+    }
+}
 
-            for si in 0..sprites.len() {
-                let sprite = &mut sprites[si];
-                let tag    = &tags[si];
+impl crate::lua_manager::ToLua for CaptureResult {
+    fn push_to_lua(&self, l: &lua_State) {
+        lua::newtable(l);
 
-                if *tag < 0 { continue; }
+        lua::pushinteger(l, self.width as i64);
+        lua::setfield(l, -2, "width");
 
-                lua::geti(l, lua::LUA_REGISTRYINDEX, *tag);
-                let spritetagsind = lua::gettop(l);
+        lua::pushinteger(l, self.height as i64);
+        lua::setfield(l, -2, "height");
 
-                if tags_match(l, spritetagsind, 2) {
-                    sprite.update_from_lua_table(l, 3);
-                    nupdated += 1;
-                }
-                lua::pop(l, 1);
-            }
+        lua::pushbytes(l, self.data.as_slice());
+        lua::setfield(l, -2, "data");
+    }
+}
 
-        }
+unsafe extern "C" fn open_module(l: &lua_State) -> i32 {
+    let dx_lua_ptr = Weak::into_raw(Arc::downgrade(&DX_LUA.lock().unwrap().as_ref().unwrap().clone()));
 
-        if nupdated > 0 { self.update_vert_buffer = true; }
+    lua::newtable(l);
+    unsafe { lua::pushlightuserdata(l, dx_lua_ptr as *const std::ffi::c_void); }
+    lua::L::setfuncs(l, DX_LUA_FUNCS, 1);
 
-        lua::pushinteger(l, nupdated);
+    return 1;
+}
 
-        return 1;
-    }
+fn get_dx_lua_upvalue(l: &lua_State) -> Option<Arc<DxLua>> {
+    let dx_lua_ptr: *const DxLua = unsafe {
+        std::mem::transmute(lua::touserdata(l, lua::LUA_REGISTRYINDEX -1))
+    };
 
-    fn remove_matching(&mut self, l: &lua_State) -> i32 {
-        let mut nremoved = 0;
+    let dx_lua_weak = ManuallyDrop::new(unsafe { Weak::from_raw(dx_lua_ptr) });
 
-        for ti in 0..self.sprite_data.len() {
-            let sprites    = &mut self.sprite_data[ti];
-            let tags       = &mut self.sprite_tags[ti];
-            let mouse_test = &mut self.mouse_test[ti];
+    dx_lua_weak.upgrade()
+}
 
-            let mut si = 0;
-            while si < sprites.len() {
-                let tag = &tags[si];
 
-                if *tag < 0 {
-                    si += 1;
-                    continue;
-                }
+/*** RST
+Classes
+-------
 
-                lua::geti(l, lua::LUA_REGISTRYINDEX, *tag);
-                let spritetagsind = lua::gettop(l);
+.. lua:class:: dxtexturemap
 
-                if tags_match(l, spritetagsind, 2) {
-                    lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
+    A texture map holds a list of textures that other objects in this module use
+    when being displayed.
+*/
+struct TextureMap {
+    textures: Mutex<HashMap<String, Arc<Texture>>>,
 
-                    sprites.remove(si);
-                    tags.remove(si);
-                    mouse_test.remove(si);
-                    nremoved += 1;
-                } else {
-                    si += 1;
-                }
-                lua::pop(l, 1);
-            }
-        }
+    // incremented each time a texture is added, replaced or removed, see
+    // SpriteListInner::texture_indices_changed
+    generation: atomic::AtomicU64,
+}
 
-        if nremoved > 0 { self.update_vert_buffer = true; }
+impl TextureMap {
+    pub fn get(&self, name: &str) -> Option<Arc<Texture>> {
+        match self.textures.lock().unwrap().get(name) {
+            Some(t) => Some(t.clone()),
+            None    => None,
+        }
+    }
 
-        lua::pushinteger(l, nremoved);
+    /// Records that the textures in this map have changed.
+    fn changed(&self) {
+        self.generation.fetch_add(1, atomic::Ordering::Relaxed);
+    }
 
-        return 1;
+    /// Returns a value that changes each time the textures in this map change.
+    fn generation(&self) -> u64 {
+        self.generation.load(atomic::Ordering::Relaxed)
     }
 }
 
-// repr(C) because this a Vec of these will be directly copied into a vertex
-// buffer
-#[repr(C)]
-struct SpriteListSprite {
-    x: f32,
-    y: f32,
-    z: f32,
-
+struct Texture {
+    //size: u32,
+    width: u32,
+    height: u32,
     max_u: f32,
     max_v: f32,
     xy_ratio: f32,
+    texture: dx::Texture,
+}
 
-    size: f32,
+const TEXTUREMAP_METATABLE_NAME: &str = "dx::lua::TextureMap";
 
-    fade_near: f32,
-    fade_far : f32,
+const TEXTUREMAP_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__gc"      , texturemap_gc,
+    c"clear"     , texturemap_clear,
+    c"add"       , texturemap_add,
+    c"addarray"  , texturemap_add_array,
+    c"has"       , texturemap_has,
+    c"dimensions", texturemap_dimensions,
+    c"remove"    , texturemap_remove,
+    c"update"    , texturemap_update,
+};
 
-    r: f32,
-    g: f32,
-    b: f32,
-    a: f32,
 
-    flags: u32,
+unsafe fn checktexturemap(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<TextureMap>> {
+    let tm_lua_ptr: *mut *const TextureMap = unsafe {
+        std::mem::transmute(lua::L::checkudata(l, ind, TEXTUREMAP_METATABLE_NAME))
+    };
 
-    rotation: lamath::Mat4F,
+    ManuallyDrop::new(unsafe { Arc::from_raw(*tm_lua_ptr) } )
 }
 
-impl SpriteListSprite {
-    fn update_from_lua_table(&mut self, l: &lua_State, table: i32) {
-        if lua::getfield(l, table, "x") != lua::LuaType::LUA_TNIL { self.x = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+unsafe extern "C" fn texturemap_gc(l: &lua_State) -> i32 {
+    let mut tm = unsafe { checktexturemap(l, 1) };
 
-        if lua::getfield(l, table, "y") != lua::LuaType::LUA_TNIL { self.y = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+    unsafe { ManuallyDrop::drop(&mut tm); }
 
-        if lua::getfield(l, table, "z") != lua::LuaType::LUA_TNIL { self.z = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+    return 0;
+}
 
-        if lua::getfield(l, table, "size") != lua::LuaType::LUA_TNIL { self.size = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+/// Pushes a table with the original dimensions of `texture`.
+fn push_texture_dimensions(l: &lua_State, texture: &Texture) {
+    lua::createtable(l, 0, 4);
 
-        if lua::getfield(l, table, "fadenear") != lua::LuaType::LUA_TNIL { self.fade_near = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+    lua::pushinteger(l, texture.width as i64);
+    lua::setfield(l, -2, "width");
 
-        if lua::getfield(l, table, "fadefar") != lua::LuaType::LUA_TNIL { self.fade_far = lua::tonumber(l, -1) as f32; }
-        lua::pop(l, 1);
+    lua::pushinteger(l, texture.height as i64);
+    lua::setfield(l, -2, "height");
 
-        if lua::getfield(l, table, "billboard") != lua::LuaType::LUA_TNIL {
-            let billboard = if lua::toboolean(l, -1) { 1 } else { 0 };
-            self.flags = (self.flags & !0x01) | billboard;
+    lua::pushnumber(l, texture.xy_ratio as f64);
+    lua::setfield(l, -2, "xy_ratio");
+
+    lua::pushinteger(l, texture.texture.depth() as i64);
+    lua::setfield(l, -2, "frames");
+}
+
+/*** RST
+    .. lua:method:: clear()
+
+        Remove all textures from this map.
+
+        .. danger::
+
+            If objects are still referencing textures within this map after this
+            method is called, their draws will not function properly.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_clear(l: &lua_State) -> i32 {
+    let tm = unsafe { checktexturemap(l, 1) };
+
+    tm.textures.lock().unwrap().clear();
+    tm.changed();
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: add(name, data[, mipmaps[, interpolation]])
+
+        Add a texture.
+
+        :param string name: The name of the texture, this will be used to
+            reference it later when adding data to sprite lists and other
+            objects.
+        :param string data: The texture data, or a URI with a scheme that
+            has a loader set with :lua:func:`setresourceloader`.
+        :param boolean mipmaps: Generate mipmaps, default ``true``.
+        :param string interpolation: (Optional) How the image is scaled when
+            generating mipmaps, ``'nearest'``, ``'linear'``, ``'cubic'``, or
+            ``'fant'``. ``'nearest'`` keeps pixel art sharp. Default:
+            ``'fant'``.
+        :returns: A table with the dimensions of the image, see
+            :lua:meth:`dimensions`, or ``nil`` if the texture couldn't be
+            loaded.
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            local dims = textures:add('icon', icondata)
+
+            overlay.logdebug(string.format('icon is %dx%d', dims.width, dims.height))
+
+        .. admonition:: Implementation Detail
+
+            EG-Overlay uses the `Windows Imaging Component <https://learn.microsoft.com/en-us/windows/win32/wic/-wic-lh>`_
+            to load ``data``, so any `format <https://learn.microsoft.com/en-us/windows/win32/wic/native-wic-codecs>`_
+            it supports can be used.
+
+            All textures are loaded as 4 channel BGRA images.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_add(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+
+    if lua::gettop(l) < 3 {
+        lua::pushstring(l, "texturemap:add takes at least 2 arguments");
+        unsafe { lua::error(l); }
+    }
+
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+    let mut data: &[u8] = lua::tobytes(l, 3);
+
+    // data may be a URI for a registered resource loader instead
+    let loaded: Vec<u8>;
+    if let Some(r) = crate::lua_manager::load_resource(l, data) {
+        match r {
+            Ok(d) => {
+                loaded = d;
+                data = &loaded;
+            },
+            Err(err) => {
+                luaerror!(l, "{}", err);
+                return 0;
+            }
         }
-        lua::pop(l, 1);
+    }
 
-        if lua::getfield(l, table, "color") != lua::LuaType::LUA_TNIL {
-            let color = crate::ui::Color::from(lua::tointeger(l, -1));
-            self.r = color.r_f32();
-            self.g = color.g_f32();
-            self.b = color.b_f32();
-            self.a = color.a_f32();
+    let mut mipmaps = true;
+
+    if lua::gettop(l) >= 4 {
+        mipmaps = lua::toboolean(l, 4);
+    }
+
+    let interpolation = match wic_interpolation_from_lua(l, 5) {
+        Some(i) => i,
+        None    => return 0,
+    };
+
+    let mut textures = tm.textures.lock().unwrap();
+
+    if textures.contains_key(&name) {
+        luawarn!(l, "Texture {} already exists in this texturemap, overwriting.", name);
+    }
+
+    let dx_lua = get_dx_lua_upvalue(l).unwrap();
+
+    let wicfactory = match create_wic_factory() {
+        Ok(f)    => f,
+        Err(err) => {
+            luaerror!(l, "{}", err);
+            return 0;
         }
-        lua::pop(l, 1);
+    };
 
-        if lua::getfield(l, table, "rotation") != lua::LuaType::LUA_TNIL {
-            let x: f32;
-            let y: f32;
-            let z: f32;
+    let (bitmap, width, height) = match wic_decode_bitmap(&wicfactory, data) {
+        Ok(b)    => b,
+        Err(err) => {
+            luaerror!(l, "{}", err);
+            return 0;
+        }
+    };
 
-            lua::geti(l, -1, 1); x = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
-            lua::geti(l, -1, 2); y = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
-            lua::geti(l, -1, 3); z = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
+    // How big must we make a texture to hold our image? We'll use square,
+    // power of 2 texture sizes (8, 16, 32, 64, etc.)
+    let mut req_size = 1;
+    while req_size < width || req_size < height { req_size <<= 1; }
 
-            let xr = lamath::Mat4F::rotatex(x.to_radians());
-            let yr = lamath::Mat4F::rotatey(y.to_radians());
-            let zr = lamath::Mat4F::rotatez(z.to_radians());
+    let mipmaplevels = if mipmaps {
+        (req_size as f64).log2().floor() as u16
+    } else { 1 };
 
-            let zy = zr * yr;
-            self.rotation = zy * xr;
+    // texture map textures are always arrays so that sprites can be animated,
+    // a single image is just an array with 1 layer
+    let tex = match dx_lua.dx.new_texture_2d_array(
+        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+        req_size, req_size, 1, mipmaplevels
+    ) {
+        Ok(t) => t,
+        Err(err) => {
+            luaerror!(l, "Couldn't create texture {}: {}", name, err);
+            return 0;
         }
-        lua::pop(l, 1);
+    };
+    tex.set_name(format!("EG-Overlay D3D12 TextureMap Texture: {}", name).as_str());
+
+    if let Err(err) = write_wic_bitmap(&wicfactory, &bitmap, &tex, 0, req_size, mipmaplevels, interpolation) {
+        luaerror!(l, "Couldn't write texture {}: {}", name, err);
+        return 0;
     }
+
+    let t = Texture {
+        //size: req_size,
+        width: width,
+        height: height,
+        max_u: width  as f32 / req_size as f32,
+        max_v: height as f32 / req_size as f32,
+        xy_ratio: width as f32 / height as f32,
+        texture: tex,
+    };
+
+    push_texture_dimensions(l, &t);
+
+    textures.insert(name.clone(), Arc::new(t));
+    tm.changed();
+
+    return 1;
 }
 
-const SPRITELIST_METATABLE_NAME: &str = "dx::lua::SpriteList";
+/*** RST
+    .. lua:method:: addarray(name, frames[, mipmaps[, interpolation]])
 
-const SPRITELIST_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
-    c"__gc"          , spritelist_gc,
-    c"add"           , spritelist_add,
-    c"draw"          , spritelist_draw,
-    c"update"        , spritelist_update,
-    c"remove"        , spritelist_remove,
-    c"clear"         , spritelist_clear,
-    c"mousehovertags", spritelist_mouse_hover_tags,
-};
+        Add a texture made of multiple images, one per layer, that can be used
+        for animated sprites. See the ``frames`` and ``framerate`` fields of
+        :lua:meth:`dxspritelist.add`.
 
-unsafe fn checkspritelist(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<SpriteList>> {
-    let ptr: *mut *const SpriteList = unsafe {
-        std::mem::transmute(lua::L::checkudata(l, ind, SPRITELIST_METATABLE_NAME))
+        All images must be the same size. Trails and other objects that don't
+        support animation will use the first image.
+
+        :param string name: The name of the texture.
+        :param table frames: A sequence of image data or resource URIs, in the
+            same format as :lua:meth:`add`.
+        :param boolean mipmaps: Generate mipmaps, default ``true``.
+        :param string interpolation: (Optional) How the images are scaled when
+            generating mipmaps, see :lua:meth:`add`. Default: ``'fant'``.
+        :returns: A table with the dimensions of the images, see
+            :lua:meth:`dimensions`, or ``nil`` if the texture couldn't be
+            loaded.
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            textures:addarray('pulse', {
+                pack:content('icons/pulse1.png'),
+                pack:content('icons/pulse2.png'),
+                pack:content('icons/pulse3.png'),
+            })
+
+            sprites:add('pulse', { x = 10.0, y = 20.0, z = 30.0, framerate = 6 })
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_add_array(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+
+    let mut mipmaps = true;
+
+    if lua::gettop(l) >= 4 {
+        mipmaps = lua::toboolean(l, 4);
+    }
+
+    let interpolation = match wic_interpolation_from_lua(l, 5) {
+        Some(i) => i,
+        None    => return 0,
     };
 
-    ManuallyDrop::new(unsafe { Arc::from_raw(*ptr) } )
-}
+    let frame_count = lua::L::len(l, 3);
 
-unsafe extern "C" fn spritelist_gc(l: &lua_State) -> i32 {
-    let mut sl = unsafe { checkspritelist(l, 1) };
+    if frame_count == 0 {
+        luaerror!(l, "frames must contain at least 1 image.");
+        return 0;
+    }
 
-    if let Some(dx_lua) = get_dx_lua_upvalue(l) {
-        let mut sprite_lists = dx_lua.sprite_lists.lock().unwrap();
+    if frame_count > u16::MAX as usize {
+        luaerror!(l, "Too many frames: {}", frame_count);
+        return 0;
+    }
 
-        let mut i = 0;
+    let dx_lua = get_dx_lua_upvalue(l).unwrap();
 
-        while i < sprite_lists.len() {
-            if Arc::ptr_eq(&*sl, &sprite_lists[i]) {
-                sprite_lists.remove(i);
-                break;
-            } else {
-                i += 1;
-            }
+    let wicfactory = match create_wic_factory() {
+        Ok(f)    => f,
+        Err(err) => {
+            luaerror!(l, "{}", err);
+            return 0;
         }
-    }
+    };
+
+    let mut bitmaps: Vec<Imaging::IWICBitmap> = Vec::with_capacity(frame_count);
+
+    // WIC reads the image data on demand, so anything returned by a resource
+    // loader needs to live until the texture is written
+    let mut loaded: Vec<Vec<u8>> = Vec::new();
+
+    let mut width: u32 = 0;
+    let mut height: u32 = 0;
+
+    for i in 0..frame_count {
+        if lua::geti(l, 3, i as i64 + 1) != lua::LuaType::LUA_TSTRING {
+            lua::pop(l, 1);
+            luaerror!(l, "frame {} is not a string.", i + 1);
+            return 0;
+        }
+
+        let mut data: &[u8] = lua::tobytes(l, -1);
+
+        if let Some(r) = crate::lua_manager::load_resource(l, data) {
+            match r {
+                Ok(d) => {
+                    loaded.push(d);
+                    data = loaded.last().unwrap();
+                },
+                Err(err) => {
+                    lua::pop(l, 1);
+                    luaerror!(l, "frame {}: {}", i + 1, err);
+                    return 0;
+                }
+            }
+        }
+
+        let decoded = wic_decode_bitmap(&wicfactory, data);
+
+        lua::pop(l, 1);
+
+        let (bitmap, w, h) = match decoded {
+            Ok(b)    => b,
+            Err(err) => {
+                luaerror!(l, "frame {}: {}", i + 1, err);
+                return 0;
+            }
+        };
+
+        if i == 0 {
+            width = w;
+            height = h;
+        } else if w != width || h != height {
+            luaerror!(l, "frame {} is {}x{}, expected {}x{}.", i + 1, w, h, width, height);
+            return 0;
+        }
+
+        bitmaps.push(bitmap);
+    }
+
+    let mut req_size = 1;
+    while req_size < width || req_size < height { req_size <<= 1; }
+
+    let mipmaplevels = if mipmaps {
+        (req_size as f64).log2().floor() as u16
+    } else { 1 };
+
+    let tex = match dx_lua.dx.new_texture_2d_array(
+        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+        req_size, req_size, frame_count as u16, mipmaplevels
+    ) {
+        Ok(t) => t,
+        Err(err) => {
+            luaerror!(l, "Couldn't create texture {}: {}", name, err);
+            return 0;
+        }
+    };
+    tex.set_name(format!("EG-Overlay D3D12 TextureMap Texture Array: {}", name).as_str());
+
+    for (i, bitmap) in bitmaps.iter().enumerate() {
+        if let Err(err) = write_wic_bitmap(&wicfactory, bitmap, &tex, i as u32, req_size, mipmaplevels, interpolation) {
+            luaerror!(l, "Couldn't write frame {} of texture {}: {}", i + 1, name, err);
+            return 0;
+        }
+    }
+
+    let t = Texture {
+        width: width,
+        height: height,
+        max_u: width  as f32 / req_size as f32,
+        max_v: height as f32 / req_size as f32,
+        xy_ratio: width as f32 / height as f32,
+        texture: tex,
+    };
+
+    push_texture_dimensions(l, &t);
+
+    let mut textures = tm.textures.lock().unwrap();
+
+    if textures.contains_key(&name) {
+        luawarn!(l, "Texture {} already exists in this texturemap, overwriting.", name);
+    }
+
+    textures.insert(name.clone(), Arc::new(t));
+    tm.changed();
+
+    return 1;
+}
+
+/// Returns the WIC interpolation mode for the optional `interpolation`
+/// argument at `ind`, or Fant if it isn't given.
+///
+/// A Lua error is raised and [None] is returned if it isn't a known mode.
+fn wic_interpolation_from_lua(l: &lua_State, ind: i32) -> Option<Imaging::WICBitmapInterpolationMode> {
+    if lua::gettop(l) < ind || lua::luatype(l, ind) == lua::LuaType::LUA_TNIL {
+        return Some(Imaging::WICBitmapInterpolationModeFant);
+    }
+
+    let mode = lua::tostring(l, ind).unwrap_or_default();
+
+    match mode.as_str() {
+        "nearest" => Some(Imaging::WICBitmapInterpolationModeNearestNeighbor),
+        "linear"  => Some(Imaging::WICBitmapInterpolationModeLinear),
+        "cubic"   => Some(Imaging::WICBitmapInterpolationModeCubic),
+        "fant"    => Some(Imaging::WICBitmapInterpolationModeFant),
+        _         => {
+            luaerror!(l, "Unknown interpolation '{}', must be 'nearest', 'linear', 'cubic', or 'fant'.", mode);
+            None
+        },
+    }
+}
+
+fn create_wic_factory() -> Result<Imaging::IWICImagingFactory, String> {
+    // We'll use Windows Imaging Component to load the image data in. It's already
+    // present in Windows and can handle any of the formats we should be concerned
+    // with already. It'll also help with creating the mipmaps.
+    match unsafe { Com::CoCreateInstance::<_, Imaging::IWICImagingFactory>(
+        &Imaging::CLSID_WICImagingFactory,
+        None,
+        Com::CLSCTX_INPROC_SERVER
+    ) } {
+        Ok(fac) => Ok(fac),
+        Err(err) => Err(format!("Couldn't create WIC factory: {}", err)),
+    }
+}
+
+/// Encodes `pixels` as a PNG and writes it to `path`.
+///
+/// `pixels` must be `width` x `height` pixels, 4 bytes per pixel in BGRA order
+/// with premultiplied alpha, like [dx::SwapChain::capture_backbuffer]
+/// returns. COM must be initialized on the calling thread.
+pub fn write_png(path: &str, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let wicfactory = create_wic_factory()?;
+
+    let bitmap = unsafe { wicfactory.CreateBitmapFromMemory(
+        width,
+        height,
+        &Imaging::GUID_WICPixelFormat32bppPBGRA,
+        width * 4,
+        pixels
+    ) }.map_err(|err| format!("Couldn't create WIC bitmap: {}", err))?;
+
+    let stream = unsafe { wicfactory.CreateStream() }
+        .map_err(|err| format!("Couldn't create a WIC stream: {}", err))?;
+
+    let mut pathu16: Vec<u16> = path.encode_utf16().collect();
+    pathu16.push(0);
+
+    unsafe { stream.InitializeFromFilename(
+        windows::core::PCWSTR::from_raw(pathu16.as_ptr()),
+        windows::Win32::Foundation::GENERIC_WRITE.0
+    ) }.map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+
+    let encoder = unsafe { wicfactory.CreateEncoder(&Imaging::GUID_ContainerFormatPng, std::ptr::null()) }
+        .map_err(|err| format!("Couldn't create PNG encoder: {}", err))?;
+
+    unsafe { encoder.Initialize(&stream, Imaging::WICBitmapEncoderNoCache) }
+        .map_err(|err| format!("Couldn't initialize PNG encoder: {}", err))?;
+
+    let mut frame: Option<Imaging::IWICBitmapFrameEncode> = None;
+
+    unsafe { encoder.CreateNewFrame(&mut frame, std::ptr::null_mut()) }
+        .map_err(|err| format!("Couldn't create PNG frame: {}", err))?;
+
+    let frame = frame.unwrap();
+
+    unsafe { frame.Initialize(None) }
+        .map_err(|err| format!("Couldn't initialize PNG frame: {}", err))?;
+
+    unsafe { frame.SetSize(width, height) }
+        .map_err(|err| format!("Couldn't set PNG size: {}", err))?;
+
+    // PNG alpha is not premultiplied, WriteSource will convert the pixels to
+    // whatever format the encoder settles on here
+    let mut format = Imaging::GUID_WICPixelFormat32bppBGRA;
+
+    unsafe { frame.SetPixelFormat(&mut format) }
+        .map_err(|err| format!("Couldn't set PNG pixel format: {}", err))?;
+
+    unsafe { frame.WriteSource(&bitmap, std::ptr::null()) }
+        .map_err(|err| format!("Couldn't write PNG pixels: {}", err))?;
+
+    unsafe { frame.Commit() }.map_err(|err| format!("Couldn't commit PNG frame: {}", err))?;
+    unsafe { encoder.Commit() }.map_err(|err| format!("Couldn't write {}: {}", path, err))?;
+
+    return Ok(());
+}
+
+/// Decodes the first frame of the image in `data` into a 32bpp BGRA bitmap.
+///
+/// Returns the bitmap and its width and height.
+fn wic_decode_bitmap(
+    wicfactory: &Imaging::IWICImagingFactory,
+    data: &[u8]
+) -> Result<(Imaging::IWICBitmap, u32, u32), String> {
+    let mut width: u32 = 0;
+    let mut height: u32 = 0;
+
+    // Create a stream to hold the image data that we are feeding in
+    let memstream = unsafe { wicfactory.CreateStream() }
+        .map_err(|err| format!("Couldn't create a WIC stream: {}", err))?;
+
+    unsafe { memstream.InitializeFromMemory(data) }
+        .map_err(|err| format!("Couldn't initialize texture stream: {}", err))?;
+
+    // Create a decoder for the input stream. If this errors with
+    // "Component not found" that usually means the data is invalid or the file
+    // format isn't one WIC can decode.
+    let decoder = unsafe { wicfactory.CreateDecoderFromStream(
+        &memstream,
+        std::ptr::null() as *const _,
+        Imaging::WICDecodeMetadataCacheOnDemand
+    ) }.map_err(|err| format!("Couldn't get image decoder: {}", err))?;
+
+    // Get a frame...most images only have a single frame.
+    let frame = unsafe { decoder.GetFrame(0) }
+        .map_err(|err| format!("Couldn't get image frame: {}", err))?;
+
+    // Create a converter to convert the data from whatever format it happens
+    // to be in to the exact format we want.
+    let converter = unsafe { wicfactory.CreateFormatConverter() }
+        .map_err(|err| format!("Couldn't create image format converter: {}", err))?;
+
+    // Initialize the converter with our input data frame and set the output
+    // format.
+    // BGRA here because RGBA was causing some weird things with B-R swapping
+    // channels in mipmaps. weird
+    unsafe { converter.Initialize(
+        &frame,
+        &Imaging::GUID_WICPixelFormat32bppBGRA,
+        Imaging::WICBitmapDitherTypeNone,
+        None,
+        0.0,
+        Imaging::WICBitmapPaletteTypeCustom
+    ) }.map_err(|err| format!("Couldn't initialize image converter: {}", err))?;
+
+    // Create a bitmap that reads from the output of the converter above. Reading
+    // from this bitmap will be reading converted pixel data.
+    let bitmap = unsafe { wicfactory.CreateBitmapFromSource(&converter, Imaging::WICBitmapCacheOnDemand) }
+        .map_err(|err| format!("Couldn't create WIC bitmap: {}", err))?;
+
+    // We can finally see how big the image is too.
+    unsafe { bitmap.GetSize(&mut width, &mut height) }
+        .map_err(|err| format!("Couldn't get bitmap size: {}", err))?;
+
+    return Ok((bitmap, width, height));
+}
+
+/// Copies `bitmap` to layer `slice` of `tex` and generates `levels` - 1
+/// mipmaps for it, scaled with `interpolation`.
+///
+/// `req_size` is the width and height of `tex`.
+fn write_wic_bitmap(
+    wicfactory: &Imaging::IWICImagingFactory,
+    bitmap: &Imaging::IWICBitmap,
+    tex: &dx::Texture,
+    slice: u32,
+    req_size: u32,
+    levels: u16,
+    interpolation: Imaging::WICBitmapInterpolationMode
+) -> Result<(), String> {
+    let mut width: u32 = 0;
+    let mut height: u32 = 0;
+
+    unsafe { bitmap.GetSize(&mut width, &mut height) }
+        .map_err(|err| format!("Couldn't get bitmap size: {}", err))?;
+
+    let max_u = width  as f32 / req_size as f32;
+    let max_v = height as f32 / req_size as f32;
+
+    // subresources are ordered by mip level within each array slice
+    let subresource = |mlevel: u32| mlevel + (slice * levels as u32);
+
+    // In order to read the image data, we have to define what part we want.
+    // In this case, the entire thing.
+    let lockrect = Imaging::WICRect { X: 0, Y: 0, Width: width as i32, Height: height as i32};
+
+    // Then lock it for reading.
+    let bitmaplock = unsafe { bitmap.Lock(&lockrect, Imaging::WICBitmapLockRead.0 as u32) }
+        .map_err(|err| format!("Couldn't lock bitmap: {}", err))?;
+
+    let mut pixels_len: u32     = 0;
+    let mut pixels    : *mut u8 = std::ptr::null_mut();
+
+    // Now we get a raw pointer and length. Woo!
+    unsafe { bitmaplock.GetDataPointer(&mut pixels_len, &mut pixels) }
+        .map_err(|err| format!("Couldn't get bitmap data pointer: {}", err))?;
+
+    // convert it to a slice for more convenient usage in Rust
+    let pixels_slice: &[u8] = unsafe { std::slice::from_raw_parts(pixels, pixels_len as usize) };
+
+    tex.write_pixels(0, 0, subresource(0), width, height, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, pixels_slice)
+        .map_err(|err| err.to_string())?;
+
+    // At this point we are done with the pixel data, so release the lock.
+    // pixels and pixels_slice are now invalid
+    drop(bitmaplock);
+
+    // Now generate mipmaps
+    for mlevel in 1..levels {
+        let mipsize: u32 = req_size / 2.0f32.powi(mlevel as i32) as u32;
+        let mipw: u32 = (mipsize as f32 * max_u).floor() as u32;
+        let miph: u32 = (mipsize as f32 * max_v).floor() as u32;
+
+        let scaledrect = Imaging::WICRect { X: 0, Y: 0, Width: mipw as i32, Height: miph as i32 };
+
+        // This is much the same as using the converter above, but with a scaler
+        // this time.
+        let scaler = unsafe { wicfactory.CreateBitmapScaler() }
+            .map_err(|err| format!("Couldn't create bitmap scaler: {}", err))?;
+
+        unsafe { scaler.Initialize(
+            bitmap,
+            mipw,
+            miph,
+            interpolation
+        ) }.map_err(|err| format!("Couldn't initialize bitmap scaler: {}", err))?;
+
+        let scaledbitmap = unsafe { wicfactory.CreateBitmapFromSource(&scaler, Imaging::WICBitmapCacheOnDemand) }
+            .map_err(|err| format!("Couldn't create scaled bitmap: {}", err))?;
+
+        let scaledlock = unsafe { scaledbitmap.Lock(&scaledrect, Imaging::WICBitmapLockRead.0 as u32) }
+            .map_err(|err| format!("Couldn't lock scaled bitmap: {}", err))?;
+
+        // raw pixel data, same as above
+        let mut mippixels_len: u32 = 0;
+        let mut mippixels    : *mut u8 = std::ptr::null_mut();
+
+        unsafe { scaledlock.GetDataPointer(&mut mippixels_len, &mut mippixels) }
+            .map_err(|err| format!("Couldn't get mipmap pixels pointer: {}", err))?;
+
+        let mippixels_slice: &[u8] = unsafe { std::slice::from_raw_parts(mippixels, mippixels_len as usize) };
+
+        tex.write_pixels(0, 0, subresource(mlevel as u32), mipw, miph, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, mippixels_slice)
+            .map_err(|err| format!("mipmap {}: {}", mlevel, err))?;
+    }
+
+    return Ok(());
+}
+
+/*** RST
+    .. lua:method:: has(name)
+
+        Returns ``true`` if this map has a texture named ``name``.
+
+        :param string name:
+
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_has(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+
+    lua::pushboolean(l, tm.textures.lock().unwrap().contains_key(&name));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: dimensions(name)
+
+        Returns the dimensions of the texture named ``name`` as a table with
+        the following fields, or ``nil`` if this map doesn't have a texture
+        named ``name``.
+
+        ======== ============================================================
+        Field    Description
+        ======== ============================================================
+        width    The width of the original image, in pixels.
+        height   The height of the original image, in pixels.
+        xy_ratio The ratio of ``width`` to ``height``.
+        frames   The number of images in the texture, see :lua:meth:`addarray`.
+        ======== ============================================================
+
+        :param string name:
+
+        :rtype: table
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_dimensions(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+
+    match tm.textures.lock().unwrap().get(&name) {
+        Some(t) => push_texture_dimensions(l, t),
+        None    => lua::pushnil(l),
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: remove(name)
+
+        Remove the texture named ``name`` from this map, freeing it.
+
+        Returns ``true`` if the texture was removed, or ``false`` if this map
+        did not have a texture named ``name``.
+
+        .. danger::
+
+            If objects are still referencing this texture after this method is
+            called, their draws will not function properly. A warning is logged
+            if any sprite or trail lists still use it.
+
+        :param string name:
+
+        :rtype: boolean
+
+        .. code-block:: lua
+            :caption: Example
+
+            -- the player left the region, free its icons
+            for _, icon in ipairs(regionicons) do
+                textures:remove(icon)
+            end
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_remove(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+
+    // the map isn't locked while the lists are checked below, drawing locks
+    // them in the opposite order
+    let removed = tm.textures.lock().unwrap().remove(&name).is_some();
+
+    if removed { tm.changed(); }
+
+    if removed {
+        if let Some(dx_lua) = get_dx_lua_upvalue(l) {
+            let mut in_use = false;
+
+            for sl in dx_lua.sprite_lists.lock().unwrap().iter() {
+                let inner = sl.inner.lock().unwrap();
+
+                if !Arc::ptr_eq(&inner.texture_map, &*tm) { continue; }
+
+                if let Some(i) = inner.texture_names.iter().position(|n| *n == name) {
+                    if inner.sprite_data[i].len() > 0 { in_use = true; }
+                }
+            }
+
+            for tl in dx_lua.trail_lists.lock().unwrap().iter() {
+                let inner = tl.inner.lock().unwrap();
+
+                if !Arc::ptr_eq(&inner.texture_map, &*tm) { continue; }
+
+                if let Some(i) = inner.texture_names.iter().position(|n| *n == name) {
+                    if inner.trails[i].len() > 0 { in_use = true; }
+                }
+
+                for trails in &inner.trails {
+                    for trail in trails {
+                        if trail.start_texture.as_ref() == Some(&name) || trail.end_texture.as_ref() == Some(&name) {
+                            in_use = true;
+                        }
+                    }
+                }
+            }
+
+            if in_use {
+                luawarn!(l, "Texture {} removed while sprite or trail lists are still using it.", name);
+            }
+        }
+    }
+
+    lua::pushboolean(l, removed);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: update(name, x, y, w, h, pixels[, format])
+
+        Replace a rectangular region of an existing texture with raw pixel
+        data.
+
+        This is much faster than replacing the entire texture with
+        :lua:meth:`add` and is intended for textures that change frequently,
+        such as a heatmap.
+
+        ``pixels`` must be exactly ``w`` x ``h`` x 4 bytes, with rows ordered
+        top to bottom. The region must lie within the original dimensions of
+        the texture.
+
+        ``format`` specifies the channel order of ``pixels`` and can be either
+        ``'bgra'`` (the default) or ``'rgba'``.
+
+        .. note::
+
+            Only the full size texture is updated, mipmaps are not regenerated.
+            Textures that will be updated should be added with ``mipmaps`` set
+            to ``false``.
+
+        :param string name:
+        :param integer x: The left edge of the region, in pixels.
+        :param integer y: The top edge of the region, in pixels.
+        :param integer w: The width of the region, in pixels.
+        :param integer h: The height of the region, in pixels.
+        :param string pixels:
+        :param string format: (Optional)
+
+        .. code-block:: lua
+            :caption: Example
+
+            -- set a 2x2 region at 10,10 to opaque red
+            local red = string.rep(string.char(0, 0, 255, 255), 4)
+            textures:update('heatmap', 10, 10, 2, 2, red)
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn texturemap_update(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    lua::checkarginteger!(l, 3);
+    lua::checkarginteger!(l, 4);
+    lua::checkarginteger!(l, 5);
+    lua::checkarginteger!(l, 6);
+    lua::checkargstring!(l, 7);
+
+    let tm = unsafe { checktexturemap(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+
+    let x = lua::tointeger(l, 3);
+    let y = lua::tointeger(l, 4);
+    let w = lua::tointeger(l, 5);
+    let h = lua::tointeger(l, 6);
+
+    let pixels: &[u8] = lua::tobytes(l, 7);
+
+    let format = if lua::gettop(l) >= 8 && lua::luatype(l, 8) != lua::LuaType::LUA_TNIL {
+        lua::checkargstring!(l, 8);
+        lua::tostring(l, 8).unwrap()
+    } else {
+        String::from("bgra")
+    };
+
+    let texture = match tm.get(&name) {
+        Some(t) => t,
+        None    => {
+            luaerror!(l, "Texture {} not found in texture map.", name);
+            return 0;
+        }
+    };
+
+    if x < 0 || y < 0 || w <= 0 || h <= 0 ||
+       x + w > texture.width as i64 || y + h > texture.height as i64
+    {
+        luaerror!(l, "Region {},{} {}x{} is outside of texture {} ({}x{}).",
+            x, y, w, h, name, texture.width, texture.height);
+        return 0;
+    }
+
+    if pixels.len() as i64 != w * h * 4 {
+        luaerror!(l, "pixels must be {} bytes for a {}x{} region, got {}.", w * h * 4, w, h, pixels.len());
+        return 0;
+    }
+
+    let r = match format.as_str() {
+        "bgra" => {
+            texture.texture.write_pixels(x as u32, y as u32, 0, w as u32, h as u32, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, pixels)
+        },
+        "rgba" => {
+            // textures are always BGRA, so swap the red and blue channels
+            let mut bgra = pixels.to_vec();
+            for p in bgra.chunks_exact_mut(4) { p.swap(0, 2); }
+
+            texture.texture.write_pixels(x as u32, y as u32, 0, w as u32, h as u32, Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM, &bgra)
+        },
+        _ => {
+            luaerror!(l, "format must be 'bgra' or 'rgba'.");
+            return 0;
+        }
+    };
+
+    if let Err(err) = r {
+        luaerror!(l, "Couldn't update texture {}: {}", name, err);
+        return 0;
+    }
+
+    crate::overlay::request_redraw();
+
+    return 0;
+}
+
+/*** RST
+.. lua:class:: dxspritelist
+*/
+
+struct SpriteList {
+    inner: Mutex<SpriteListInner>,
+}
+
+struct SpriteListInner {
+    vert_buffer: Option<Direct3D12::ID3D12Resource>,
+    vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW,
+
+    vert_buffer_size: usize,
+    update_vert_buffer: bool,
+
+    // sprites that have changed in place since the vertex buffer was last
+    // updated, as (texture index, sprite index). when the sprite count hasn't
+    // changed only the range covering these is uploaded, see mark_dirty
+    dirty_sprites: Vec<(usize, usize)>,
+
+    // the number of sprites of each texture in the vertex buffer, which may
+    // not match the sprite data while an update is in progress
+    drawn_counts: Vec<u32>,
+
+    // the number of nested beginupdate calls. the vertex buffer isn't updated
+    // while this is above 0
+    update_depth: u32,
+
+    // Sprites are grouped by texture name because we can render each set that
+    // shares the same texture in a single command.
+    // This is not a HashMap because the order of the textures and data needs
+    // to be consistent and predictable.
+    texture_names: Vec<String>,
+    sprite_data: Vec<Vec<SpriteListSprite>>,
+    sprite_tags: Vec<Vec<i64>>,
+    sprite_userdata: Vec<Vec<i64>>,
+    sprite_handles: Vec<Vec<u64>>,
+    mouse_test: Vec<Vec<bool>>,
+
+    // the SRV heap index of each texture the last time the vertex buffer was
+    // updated. when drawing bindless these are in the vertex buffer, so it
+    // needs to be rebuilt if a texture is replaced
+    texture_indices: Vec<u32>,
+
+    // the texture map generation texture_indices was last checked against
+    texture_generation: u64,
+
+    // handle -> (texture index, sprite index), for addressing a single sprite
+    // without matching tags
+    handles: HashMap<u64, (usize, usize)>,
+    next_handle: u64,
+
+    texture_map: Arc<TextureMap>,
+
+    mouse_hover_tags: Vec<i64>,
+    mouse_hover_data: Vec<i64>,
+
+    // sprites that were under the mouse during the last hit test, as
+    // (tags, userdata) refs, and the time each began being hovered.
+    // these are used to implement the hover delay
+    mouse_hits: Vec<(i64, i64)>,
+    mouse_hover_start: HashMap<(i64, i64), f64>,
+
+    // hit testing is only done every mouse_test_interval frames and, if
+    // mouse_test_on_move is set, only when the mouse or camera has moved
+    mouse_test_interval: u32,
+    mouse_test_on_move: bool,
+    mouse_hover_delay: f64,
+    frames_since_mouse_test: u32,
+    last_mouse_x: i64,
+    last_mouse_y: i64,
+    last_camera: lamath::Vec3F,
+
+    alpha_to_coverage: bool,
+
+    // true if any sprite in the vertex buffer is animated, in which case the
+    // list must be redrawn every frame
+    animated: bool,
+
+    // when clustering, sprites of the same texture within cluster_radius
+    // pixels are drawn as a single sprite. cluster_data holds what is actually
+    // drawn and is rebuilt when the map scale or sprites change
+    cluster: bool,
+    cluster_radius: f32,
+    cluster_scale: f32,
+    cluster_data: Vec<Vec<SpriteListSprite>>,
+    cluster_counts: Vec<Vec<u32>>,
+
+    // when frustum culling, only the sprites that are at least partially in
+    // view are uploaded and drawn. cull_visible holds the indices of those
+    // sprites and cull_data is rebuilt from them when they change
+    frustum_cull: bool,
+    cull_visible: Vec<Vec<usize>>,
+    cull_data: Vec<Vec<SpriteListSprite>>,
+
+    // sprites are positioned in the coordinates of the other location, i.e.
+    // continent coordinates on a world list. converted_data is sprite_data
+    // converted with converted_rects and is what is actually drawn
+    convert: bool,
+    converted_rects: Option<ml::MapRects>,
+    converted_data: Vec<Vec<SpriteListSprite>>,
+
+    // sprites are hidden by things in front of them, when false they are
+    // always drawn on top
+    depth_test: bool,
+
+    is_map: bool,
+
+    // sprites are positioned in pixels from the top left of the screen
+    is_screen: bool,
+
+    draw: bool,
+
+    // the render channel this list is drawn in, see dx.setchannel
+    channel: String,
+}
+
+const SPRITE_MEM_SIZE: usize = std::mem::size_of::<SpriteListSprite>();
+
+impl SpriteListInner {
+    fn new(texture_map: Arc<TextureMap>, is_map: bool) -> SpriteListInner {
+        return SpriteListInner {
+            vert_buffer: None,
+            vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW::default(),
+
+            vert_buffer_size: 0,
+            update_vert_buffer: false,
+
+            dirty_sprites: Vec::new(),
+            drawn_counts: Vec::new(),
+            update_depth: 0,
+
+            texture_names: Vec::new(),
+            sprite_data  : Vec::new(),
+            sprite_tags  : Vec::new(),
+            sprite_userdata: Vec::new(),
+            sprite_handles: Vec::new(),
+            mouse_test   : Vec::new(),
+
+            texture_indices: Vec::new(),
+            texture_generation: 0,
+
+            handles: HashMap::new(),
+            next_handle: 1,
+
+            texture_map: texture_map,
+
+            mouse_hover_tags: Vec::new(),
+            mouse_hover_data: Vec::new(),
+
+            mouse_hits: Vec::new(),
+            mouse_hover_start: HashMap::new(),
+
+            mouse_test_interval: 1,
+            mouse_test_on_move: false,
+            mouse_hover_delay: 0.0,
+            frames_since_mouse_test: 0,
+            last_mouse_x: -1,
+            last_mouse_y: -1,
+            last_camera: lamath::Vec3F { x: 0.0, y: 0.0, z: 0.0 },
+
+            alpha_to_coverage: false,
+
+            animated: false,
+
+            cluster: false,
+            cluster_radius: 0.0,
+            cluster_scale: 0.0,
+            cluster_data: Vec::new(),
+            cluster_counts: Vec::new(),
+
+            frustum_cull: false,
+            cull_visible: Vec::new(),
+            cull_data: Vec::new(),
+
+            convert: false,
+            converted_rects: None,
+            converted_data: Vec::new(),
+
+            depth_test: true,
+
+            is_map: is_map,
+            is_screen: false,
+
+            draw: true,
+
+            channel: String::from(DEFAULT_CHANNEL),
+        };
+    }
+
+    /// Adds a sprite that uses the texture `texname` and returns its handle.
+    ///
+    /// `tags` and `userdata` are registry references, or `-1`.
+    fn push_sprite(&mut self, texname: &str, sprite: SpriteListSprite, tags: i64, userdata: i64, mouse_test: bool) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+
+        let i = match self.texture_names.iter().position(|n| n == texname) {
+            Some(i) => i,
+            None    => {
+                self.texture_names.push(String::from(texname));
+                self.sprite_data.push(Vec::new());
+                self.sprite_tags.push(Vec::new());
+                self.sprite_userdata.push(Vec::new());
+                self.sprite_handles.push(Vec::new());
+                self.mouse_test.push(Vec::new());
+
+                self.texture_names.len() - 1
+            }
+        };
+
+        self.sprite_data[i].push(sprite);
+        self.sprite_tags[i].push(tags);
+        self.sprite_userdata[i].push(userdata);
+        self.sprite_handles[i].push(handle);
+        self.mouse_test[i].push(mouse_test);
+
+        let si = self.sprite_data[i].len() - 1;
+        self.handles.insert(handle, (i, si));
+
+        self.update_vert_buffer = true;
+
+        return handle;
+    }
+
+    fn draw(&mut self,
+        frame: &mut dx::SwapChainLock,
+        dx: &Arc<dx::Dx>,
+        world_proj: &lamath::Mat4F,
+        world_view: &lamath::Mat4F,
+        map_proj: &lamath::Mat4F,
+        map_view: &lamath::Mat4F,
+        mapfullscreen: bool,
+        mapscale: f32,
+        camera: &lamath::Vec3F,
+        mouse_ray: &Option<lamath::Vec3F>,
+        minimapleft: u32,
+        minimaptop: u32,
+        mapw: u32,
+        maph: u32,
+        mouse_x: i64,
+        mouse_y: i64,
+        mouse_map_x: f32,
+        mouse_map_y: f32,
+        mouse_in_map: bool,
+        bindless: bool,
+        map_rects: &Option<ml::MapRects>,
+        capture: bool
+    ) {
+        // a capture only draws the vertex buffer as it was prepared by the
+        // normal pass this frame, see render_lists
+        if capture {
+            if !self.draw || (!self.is_map && mapfullscreen) || (self.convert && map_rects.is_none()) { return; }
+        } else {
+            self.mouse_hover_tags.clear();
+            self.mouse_hover_data.clear();
+
+            if !self.draw || (!self.is_map && mapfullscreen) {
+                self.mouse_hits.clear();
+                self.mouse_hover_start.clear();
+                return;
+            }
+
+            if self.convert {
+                match map_rects {
+                    Some(rects) => {
+                        if self.update_vert_buffer || self.converted_rects != Some(*rects) {
+                            self.update_converted(rects);
+                            self.update_vert_buffer = true;
+                        }
+                    },
+                    None => {
+                        // nothing can be drawn until the rects are set
+                        self.mouse_hits.clear();
+                        self.mouse_hover_start.clear();
+                        return;
+                    },
+                }
+            }
+
+            if bindless && self.texture_indices_changed() {
+                self.update_vert_buffer = true;
+            }
+
+            if self.clustering() && (self.update_vert_buffer || self.cluster_scale != mapscale) {
+                self.update_clusters(mapscale);
+                self.update_vert_buffer = true;
+            }
+
+            if self.culling() {
+                let frustum = lamath::Frustum::from_matrix(&(*world_view * *world_proj));
+                let visible = self.visible_sprites(&frustum);
+
+                let total: usize = self.sprite_data.iter().map(|s| s.len()).sum();
+                let drawn: usize = visible.iter().map(|v| v.len()).sum();
+
+                frame.add_culled((total - drawn) as u32);
+
+                if visible != self.cull_visible {
+                    self.cull_visible = visible;
+                    self.update_vert_buffer = true;
+                }
+            }
+
+            if self.update_depth > 0 {
+                // keep drawing the current vertex buffer until the update ends
+            } else if self.update_vert_buffer {
+                self.update_vertex_buffer(frame, dx);
+            } else if !self.dirty_sprites.is_empty() {
+                self.update_vertex_buffer_range(frame, dx);
+            }
+        }
+
+        if self.vert_buffer.is_none() {
+            if !capture {
+                self.mouse_hits.clear();
+                self.mouse_hover_start.clear();
+            }
+            return;
+        }
+
+        if !capture { self.frames_since_mouse_test += 1; }
+
+        let mouse_moved = mouse_x != self.last_mouse_x ||
+                          mouse_y != self.last_mouse_y ||
+                          *camera != self.last_camera;
+
+        let mouse_test = !capture &&
+                         self.frames_since_mouse_test >= self.mouse_test_interval &&
+                         (!self.mouse_test_on_move || mouse_moved);
+
+        if mouse_test {
+            self.frames_since_mouse_test = 0;
+            self.last_mouse_x = mouse_x;
+            self.last_mouse_y = mouse_y;
+            self.last_camera = *camera;
+            self.mouse_hits.clear();
+        }
+
+        if self.is_screen {
+            frame.set_root_constant_mat4f(&lamath::Mat4F::identity(), 0, 0);
+
+            // the swap chain's ortho projection doesn't account for a reversed
+            // depth buffer, so build one the same way the map projection is
+            if frame.reverse_depth() {
+                let proj = lamath::Mat4F::ortho(
+                    0.0,
+                    frame.render_target_width() as f32,
+                    0.0,
+                    frame.render_target_height() as f32,
+                    1.0,
+                    0.0
+                );
+                frame.set_root_constant_mat4f(&proj, 0, 16);
+            } else {
+                frame.set_root_constant_ortho_proj(0, 16);
+            }
+        } else if self.is_map {
+            frame.set_root_constant_mat4f(map_view, 0,  0);
+            frame.set_root_constant_mat4f(map_proj, 0, 16);
+
+            if !mapfullscreen {
+                frame.push_viewport(minimapleft as f32, minimaptop as f32, mapw as f32, maph as f32);
+            }
+        } else {
+            frame.set_root_constant_mat4f(world_view, 0,  0);
+            frame.set_root_constant_mat4f(world_proj, 0, 16);
+        }
+
+        // 0 = world, 1 = map, 2 = screen, see sprite-list.hlsl
+        let location: u32 = if self.is_screen { 2 } else if self.is_map { 1 } else { 0 };
+        frame.set_root_constant_uint(location, 0, 35);
+
+        frame.set_vertex_buffer(0, &self.vert_buffer_view, self.vert_buffer.as_ref().unwrap());
+
+        // when drawing bindless, consecutive textures are drawn with a single
+        // call, starting at run_start
+        let mut inst: u32 = 0;
+        let mut run_start: u32 = 0;
+        for i in 0..self.texture_names.len() {
+            let tex_name = &self.texture_names[i];
+            let tex: &dx::Texture;
+
+            let sprite_data = &self.sprite_data[i];
+            let sprite_count = sprite_data.len() as u32;
+
+            // the number of sprites actually drawn, which may be less than
+            // sprite_count if they are being clustered or culled
+            let draw_count = self.drawn_counts.get(i).copied().unwrap_or(0);
+
+            if sprite_count == 0 && draw_count == 0 { continue; }
+
+            let textures = self.texture_map.textures.lock().unwrap();
+            match textures.get(tex_name.as_str()) {
+                Some(t) => tex = &t.texture,
+                _ => {
+                    crate::logging::error!("Invalid texture key: {}", tex_name);
+
+                    if bindless {
+                        if inst > run_start { frame.draw_instanced(4, inst - run_start, 0, run_start); }
+                        run_start = inst + draw_count;
+                    }
+
+                    inst += draw_count;
+                    continue;
+                },
+            }
+
+            if bindless {
+                frame.use_texture(tex);
+            } else {
+                frame.set_texture(0, tex);
+                frame.draw_instanced(4, draw_count, 0, inst);
+            }
+            inst += draw_count;
+
+            if !mouse_test { continue; }
+
+            if mouse_ray.is_none() && !self.is_map && !self.is_screen { continue; }
+
+            for s in 0..sprite_data.len() {
+                if !self.mouse_test[i][s] { continue; }
+
+                let tags = self.sprite_tags[i][s];
+                let userdata = self.sprite_userdata[i][s];
+                let sprite = self.positioned()[i][s];
+
+                if self.is_screen {
+                    let half_w = (sprite.size * sprite.xy_ratio) / 2.0;
+                    let half_h = sprite.size / 2.0;
+
+                    if (mouse_x as f32 - sprite.x).abs() <= half_w && (mouse_y as f32 - sprite.y).abs() <= half_h {
+                        self.mouse_hits.push((tags, userdata));
+                    }
+                } else if !self.is_map && !mouse_in_map {
+                    let distsq = (sprite.x - camera.x).powi(2) + (sprite.y - camera.y).powi(2) + (sprite.z - camera.z).powi(2);
+
+                    // ray_points_at is fairly resource intensive, so don't do
+                    // it if the sprite is more than 50,000 inches away.
+                    // At that distance, the sprite probably isn't much larger
+                    // than a single pixel anyway.
+                    if distsq >= 2500000000.0 { continue; }
+
+                    if ray_points_at(sprite.x, sprite.y, sprite.z, sprite.size / 2.0, camera, mouse_ray.as_ref().unwrap()) {
+                        self.mouse_hits.push((tags, userdata));
+                    }
+                } else if self.is_map && mouse_in_map {
+                    let searchdistsq = (sprite.size / 2.0).powi(2);
+
+                    let mousedistsq = (mouse_map_x - sprite.x).powi(2) + (mouse_map_y - sprite.y).powi(2);
+
+                    if mousedistsq <= searchdistsq {
+                        self.mouse_hits.push((tags, userdata));
+                    }
+                }
+
+            }
+        }
+
+        if bindless && inst > run_start {
+            frame.draw_instanced(4, inst - run_start, 0, run_start);
+        }
+
+        if self.is_map && !mapfullscreen { frame.pop_viewport(); }
+
+        if capture { return; }
+
+        // only report sprites that have been hovered longer than the delay
+        let now = crate::overlay::uptime().as_secs_f64();
+
+        self.mouse_hover_start.retain(|t, _| self.mouse_hits.contains(t));
+
+        for hit in &self.mouse_hits {
+            let start = *self.mouse_hover_start.entry(*hit).or_insert(now);
+
+            if now - start >= self.mouse_hover_delay {
+                self.mouse_hover_tags.push(hit.0);
+                self.mouse_hover_data.push(hit.1);
+            }
+        }
+    }
+
+    fn clustering(&self) -> bool {
+        self.cluster && self.is_map
+    }
+
+    fn culling(&self) -> bool {
+        self.frustum_cull && !self.is_map && !self.is_screen
+    }
+
+    /// The sprites as they are positioned for drawing, see `convert`.
+    fn positioned(&self) -> &Vec<Vec<SpriteListSprite>> {
+        if self.convert { &self.converted_data } else { &self.sprite_data }
+    }
+
+    /// Rebuilds `converted_data` from `sprite_data` using `rects`.
+    ///
+    /// World positions are converted from the map X and Z to continent X and
+    /// Y, and continent positions are converted to map X and Z with the
+    /// sprite's Z used as the height.
+    fn update_converted(&mut self, rects: &ml::MapRects) {
+        self.converted_rects = Some(*rects);
+        self.converted_data.clear();
+
+        for sprites in &self.sprite_data {
+            let mut converted = sprites.clone();
+
+            for s in converted.iter_mut() {
+                if self.is_map {
+                    let (cx, cy) = rects.map_to_continent(s.x as f64, s.z as f64);
+
+                    s.x = cx as f32;
+                    s.y = cy as f32;
+                } else {
+                    let (mx, my) = rects.continent_to_map(s.x as f64, s.y as f64);
+
+                    s.y = s.z;
+                    s.x = mx as f32;
+                    s.z = my as f32;
+                }
+            }
+
+            self.converted_data.push(converted);
+        }
+    }
+
+    /// Returns the indices of the sprites of each texture that are at least
+    /// partially inside `frustum`.
+    ///
+    /// Sprites that are pinned to the screen edge are always visible.
+    fn visible_sprites(&self, frustum: &lamath::Frustum) -> Vec<Vec<usize>> {
+        let mut visible: Vec<Vec<usize>> = Vec::with_capacity(self.sprite_data.len());
+
+        for sprites in self.positioned() {
+            let mut v: Vec<usize> = Vec::new();
+
+            for (i, s) in sprites.iter().enumerate() {
+                // a sphere that contains the sprite no matter how it's rotated
+                let radius = (s.size / 2.0) * s.xy_ratio.max(1.0) * std::f32::consts::SQRT_2;
+                let center = lamath::Vec3F { x: s.x, y: s.y, z: s.z };
+
+                if (s.flags & EDGE_INDICATOR) != 0 || frustum.intersects_sphere(&center, radius) {
+                    v.push(i);
+                }
+            }
+
+            visible.push(v);
+        }
+
+        return visible;
+    }
+
+    /// Returns `true` if this list is drawn and has animated sprites.
+    fn animating(&self) -> bool {
+        self.draw && self.animated && self.vert_buffer.is_some()
+    }
+
+    /// Returns `true` if the SRV heap index of any texture in this list is
+    /// different than when the vertex buffer was last updated.
+    ///
+    /// The indices are only looked up again when the texture map has changed.
+    fn texture_indices_changed(&mut self) -> bool {
+        let generation = self.texture_map.generation();
+
+        if generation == self.texture_generation { return false; }
+
+        if self.current_texture_indices() != self.texture_indices { return true; }
+
+        // only textures this list doesn't use changed
+        self.texture_generation = generation;
+
+        return false;
+    }
+
+    /// Returns the SRV heap index of each texture in this list.
+    ///
+    /// Textures that are no longer in the texture map will have an index of 0.
+    fn current_texture_indices(&self) -> Vec<u32> {
+        let textures = self.texture_map.textures.lock().unwrap();
+
+        return self.texture_names.iter()
+            .map(|n| textures.get(n.as_str()).map_or(0, |t| t.texture.srv_index()))
+            .collect();
+    }
+
+    /// Groups the sprites of each texture into clusters.
+    ///
+    /// This divides the map into a grid with cells `cluster_radius` pixels
+    /// wide, at the given map scale, and merges all sprites that fall into a
+    /// cell into a single sprite located at their center.
+    fn update_clusters(&mut self, mapscale: f32) {
+        let cell_size = self.cluster_radius * mapscale;
+
+        self.cluster_data.clear();
+        self.cluster_counts.clear();
+        self.cluster_scale = mapscale;
+
+        let source = if self.convert { &self.converted_data } else { &self.sprite_data };
+
+        for sprites in source {
+            let mut cells: HashMap<(i64, i64), usize> = HashMap::new();
+            let mut clusters: Vec<SpriteListSprite> = Vec::new();
+            let mut counts: Vec<u32> = Vec::new();
+
+            for sprite in sprites {
+                let cell = if cell_size > 0.0 {
+                    ((sprite.x / cell_size).floor() as i64, (sprite.y / cell_size).floor() as i64)
+                } else {
+                    (sprite.x as i64, sprite.y as i64)
+                };
+
+                if let Some(ci) = cells.get(&cell) {
+                    // keep a running average of the position
+                    let c = &mut clusters[*ci];
+                    let n = counts[*ci] as f32;
+
+                    c.x = ((c.x * n) + sprite.x) / (n + 1.0);
+                    c.y = ((c.y * n) + sprite.y) / (n + 1.0);
+
+                    counts[*ci] += 1;
+                } else {
+                    cells.insert(cell, clusters.len());
+                    clusters.push(*sprite);
+                    counts.push(1);
+                }
+            }
+
+            self.cluster_data.push(clusters);
+            self.cluster_counts.push(counts);
+        }
+    }
+
+    fn update_vertex_buffer(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>) {
+        self.texture_generation = self.texture_map.generation();
+        self.texture_indices = self.current_texture_indices();
+
+        for (i, tex_index) in self.texture_indices.iter().enumerate() {
+            for sprite in self.sprite_data[i].iter_mut() { sprite.tex_index = *tex_index; }
+
+            if let Some(converted) = self.converted_data.get_mut(i) {
+                for sprite in converted.iter_mut() { sprite.tex_index = *tex_index; }
+            }
+
+            if let Some(clusters) = self.cluster_data.get_mut(i) {
+                for sprite in clusters.iter_mut() { sprite.tex_index = *tex_index; }
+            }
+        }
+
+        if self.culling() {
+            self.cull_data.clear();
+
+            let positioned = if self.convert { &self.converted_data } else { &self.sprite_data };
+
+            for (i, sprites) in positioned.iter().enumerate() {
+                let visible = self.cull_visible.get(i).map(|v| v.as_slice()).unwrap_or(&[]);
+
+                self.cull_data.push(visible.iter().filter_map(|si| sprites.get(*si).copied()).collect());
+            }
+        }
+
+        let source = if self.clustering() {
+            &self.cluster_data
+        } else if self.culling() {
+            &self.cull_data
+        } else if self.convert {
+            &self.converted_data
+        } else {
+            &self.sprite_data
+        };
+
+        self.animated = source.iter().flatten().any(|s| s.frame_count > 1 && s.fps > 0.0);
+        self.drawn_counts = source.iter().map(|s| s.len() as u32).collect();
+
+        let mut new_size = 0;
+        for s in source {
+            new_size += SPRITE_MEM_SIZE * s.len();
+        }
+
+        frame.flush_commands();
+
+        if new_size == 0 {
+            self.vert_buffer = None;
+            self.vert_buffer_size = new_size;
+
+            return;
+        } else if self.vert_buffer_size != new_size {
+            let vb = dx.new_vertex_buffer(new_size as u64);
+            crate::dx::object_set_name(&vb, "EG-Overlay D3D12 SpriteList Vertex Buffer");
+            self.vert_buffer_size = new_size;
+
+            self.vert_buffer_view.BufferLocation = unsafe { vb.GetGPUVirtualAddress() };
+            self.vert_buffer_view.SizeInBytes = new_size as u32;
+            self.vert_buffer_view.StrideInBytes = SPRITE_MEM_SIZE as u32;
+
+            self.vert_buffer = Some(vb);
+        }
+
+        let upload = dx.new_upload_buffer(self.vert_buffer_size as u64);
+        crate::dx::object_set_name(&upload, "EG-Overlay D3D12 SpriteList Temp. Upload Buffer");
+
+        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+        let rr = Direct3D12::D3D12_RANGE::default();
+
+        if unsafe { upload.Map(0, Some(&rr), Some(&mut data)) }.is_err() {
+            panic!("Couldn't map sprite list upload data.");
+        }
+
+        let mut offset = 0;
+        for sprites in source {
+            let sprites_size = SPRITE_MEM_SIZE * sprites.len();
+            if sprites_size == 0 { continue; }
+            unsafe {
+                std::ptr::copy_nonoverlapping(sprites.as_ptr() as *const std::ffi::c_void, data.add(offset), sprites_size);
+            }
+            offset += sprites_size;
+        }
+
+        unsafe { upload.Unmap(0, None); }
+
+        let mut copy = dx.copy_queue();
+        copy.copy_resource(&upload, self.vert_buffer.as_ref().unwrap());
+
+        self.update_vert_buffer = false;
+        self.dirty_sprites.clear();
+    }
+
+    /// Marks a single sprite as changed.
+    ///
+    /// If the vertex buffer holds [Self::sprite_data] as-is only that sprite
+    /// needs to be uploaded, otherwise the whole buffer is rebuilt.
+    fn mark_dirty(&mut self, ti: usize, si: usize) {
+        if self.update_vert_buffer { return; }
+
+        if self.vert_buffer.is_none() || self.clustering() || self.culling() || self.convert {
+            self.update_vert_buffer = true;
+            return;
+        }
+
+        // a list that isn't being drawn can be updated many times between
+        // uploads, past a point a full rebuild is cheaper anyway
+        if self.dirty_sprites.len() >= self.vert_buffer_size / SPRITE_MEM_SIZE {
+            self.dirty_sprites.clear();
+            self.update_vert_buffer = true;
+            return;
+        }
+
+        self.dirty_sprites.push((ti, si));
+    }
+
+    /// Uploads only the part of the vertex buffer that covers
+    /// [Self::dirty_sprites].
+    ///
+    /// This is only valid when the number of sprites hasn't changed since the
+    /// last full update, see [Self::mark_dirty]. If the texture indices have
+    /// changed the whole buffer is rebuilt instead.
+    fn update_vertex_buffer_range(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>) {
+        // a texture that was replaced or removed since the last full update
+        // changes the index of every sprite that uses it, not only these
+        if self.texture_indices_changed() {
+            self.update_vertex_buffer(frame, dx);
+            return;
+        }
+
+        // sprite positions within the vertex buffer
+        let mut first = usize::MAX;
+        let mut last = 0;
+
+        for (ti, si) in &self.dirty_sprites {
+            let tex_index = self.texture_indices.get(*ti).copied().unwrap_or(0);
+            self.sprite_data[*ti][*si].tex_index = tex_index;
+
+            let pos: usize = self.sprite_data[..*ti].iter().map(|s| s.len()).sum::<usize>() + si;
+
+            first = first.min(pos);
+            last = last.max(pos);
+        }
+
+        self.dirty_sprites.clear();
+
+        let count = last - first + 1;
+        let size = count * SPRITE_MEM_SIZE;
+
+        if (last + 1) * SPRITE_MEM_SIZE > self.vert_buffer_size {
+            // the buffer doesn't match the sprites, this shouldn't happen
+            self.update_vertex_buffer(frame, dx);
+            return;
+        }
+
+        let sprites: Vec<SpriteListSprite> = self.sprite_data.iter()
+            .flatten()
+            .skip(first)
+            .take(count)
+            .copied()
+            .collect();
+
+        self.animated = self.sprite_data.iter().flatten().any(|s| s.frame_count > 1 && s.fps > 0.0);
+
+        frame.flush_commands();
+
+        let upload = dx.new_upload_buffer(size as u64);
+        crate::dx::object_set_name(&upload, "EG-Overlay D3D12 SpriteList Temp. Upload Buffer");
+
+        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+        let rr = Direct3D12::D3D12_RANGE::default();
+
+        if unsafe { upload.Map(0, Some(&rr), Some(&mut data)) }.is_err() {
+            panic!("Couldn't map sprite list upload data.");
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(sprites.as_ptr() as *const std::ffi::c_void, data, size);
+            upload.Unmap(0, None);
+        }
+
+        let mut copy = dx.copy_queue();
+        copy.copy_buffer_region(
+            &upload,
+            self.vert_buffer.as_ref().unwrap(),
+            (first * SPRITE_MEM_SIZE) as u64,
+            size as u64
+        );
+    }
+
+    fn update_matching(&mut self, l: &lua_State) -> i32 {
+        let mut updated: Vec<(usize, usize)> = Vec::new();
+
+        for ti in 0..self.sprite_data.len() {
+            let sprites = &mut self.sprite_data[ti];
+            let tags = &self.sprite_tags[ti];
+            let userdata = &mut self.sprite_userdata[ti];
+
+            for si in 0..sprites.len() {
+                let sprite = &mut sprites[si];
+                let tag    = &tags[si];
+
+                if *tag < 0 { continue; }
+
+                lua::geti(l, lua::LUA_REGISTRYINDEX, *tag);
+                let spritetagsind = lua::gettop(l);
+
+                if tags_match(l, spritetagsind, 2) {
+                    sprite.update_from_lua_table(l, 3);
+
+                    if lua::getfield(l, 3, "userdata") != lua::LuaType::LUA_TNIL {
+                        lua::L::unref(l, lua::LUA_REGISTRYINDEX, userdata[si]);
+                        userdata[si] = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+                    } else {
+                        lua::pop(l, 1);
+                    }
+
+                    updated.push((ti, si));
+                }
+                lua::pop(l, 1);
+            }
+
+        }
+
+        for (ti, si) in &updated {
+            self.mark_dirty(*ti, *si);
+        }
+
+        lua::pushinteger(l, updated.len() as i64);
+
+        return 1;
+    }
+
+    fn remove_matching(&mut self, l: &lua_State) -> i32 {
+        let mut nremoved = 0;
+
+        for ti in 0..self.sprite_data.len() {
+            let sprites    = &mut self.sprite_data[ti];
+            let tags       = &mut self.sprite_tags[ti];
+            let userdata   = &mut self.sprite_userdata[ti];
+            let handles    = &mut self.sprite_handles[ti];
+            let mouse_test = &mut self.mouse_test[ti];
+
+            let mut si = 0;
+            while si < sprites.len() {
+                let tag = &tags[si];
+
+                if *tag < 0 {
+                    si += 1;
+                    continue;
+                }
+
+                lua::geti(l, lua::LUA_REGISTRYINDEX, *tag);
+                let spritetagsind = lua::gettop(l);
+
+                if tags_match(l, spritetagsind, 2) {
+                    lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
+                    lua::L::unref(l, lua::LUA_REGISTRYINDEX, userdata[si]);
+
+                    sprites.remove(si);
+                    tags.remove(si);
+                    userdata.remove(si);
+                    handles.remove(si);
+                    mouse_test.remove(si);
+                    nremoved += 1;
+                } else {
+                    si += 1;
+                }
+                lua::pop(l, 1);
+            }
+        }
+
+        if nremoved > 0 {
+            self.update_vert_buffer = true;
+
+            // the tag and userdata references of removed sprites may be
+            // reused, don't let them show up in mousehovertags
+            self.mouse_hits.clear();
+            self.mouse_hover_start.clear();
+
+            self.rebuild_handles();
+        }
+
+        lua::pushinteger(l, nremoved);
+
+        return 1;
+    }
+
+    /// Rebuilds the handle map after sprites have been moved.
+    fn rebuild_handles(&mut self) {
+        self.handles.clear();
+
+        for ti in 0..self.sprite_handles.len() {
+            for si in 0..self.sprite_handles[ti].len() {
+                let h = self.sprite_handles[ti][si];
+
+                if h > 0 { self.handles.insert(h, (ti, si)); }
+            }
+        }
+    }
+
+    /// Updates the sprite with the given handle from the attributes table at
+    /// index 3. Returns `false` if the handle doesn't exist.
+    fn update_handle(&mut self, l: &lua_State, handle: u64) -> bool {
+        let (ti, si) = match self.handles.get(&handle) {
+            Some(i) => *i,
+            None    => return false,
+        };
+
+        self.sprite_data[ti][si].update_from_lua_table(l, 3);
+
+        if lua::getfield(l, 3, "userdata") != lua::LuaType::LUA_TNIL {
+            lua::L::unref(l, lua::LUA_REGISTRYINDEX, self.sprite_userdata[ti][si]);
+            self.sprite_userdata[ti][si] = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+        } else {
+            lua::pop(l, 1);
+        }
+
+        self.mark_dirty(ti, si);
+
+        return true;
+    }
+
+    /// Removes the sprite with the given handle. Returns `false` if the handle
+    /// doesn't exist.
+    fn remove_handle(&mut self, l: &lua_State, handle: u64) -> bool {
+        let (ti, si) = match self.handles.remove(&handle) {
+            Some(i) => i,
+            None    => return false,
+        };
+
+        lua::L::unref(l, lua::LUA_REGISTRYINDEX, self.sprite_tags[ti][si]);
+        lua::L::unref(l, lua::LUA_REGISTRYINDEX, self.sprite_userdata[ti][si]);
+
+        // swap_remove so only the last sprite moves, keeping this O(1)
+        self.sprite_data[ti].swap_remove(si);
+        self.sprite_tags[ti].swap_remove(si);
+        self.sprite_userdata[ti].swap_remove(si);
+        self.sprite_handles[ti].swap_remove(si);
+        self.mouse_test[ti].swap_remove(si);
+
+        if si < self.sprite_handles[ti].len() {
+            let moved = self.sprite_handles[ti][si];
+
+            if moved > 0 { self.handles.insert(moved, (ti, si)); }
+        }
+
+        self.update_vert_buffer = true;
+
+        self.mouse_hits.clear();
+        self.mouse_hover_start.clear();
+
+        return true;
+    }
+}
+
+// fade curves are stored in bits 1-2 of the sprite flags, see 3dcommon.hlsl
+const FADE_CURVE_SHIFT: u32 = 1;
+const FADE_CURVE_MASK : u32 = 0x06;
+
+// billboard modes, a sprite with neither bit set uses its rotation instead.
+// bit 4 is used by the sprite vertex shader, see EDGE_PINNED in 3dcommon.hlsl
+const BILLBOARD_FULL    : u32 = 0x01;
+const BILLBOARD_VERTICAL: u32 = 0x20;
+const BILLBOARD_MASK    : u32 = BILLBOARD_FULL | BILLBOARD_VERTICAL;
+
+// pin the sprite to the screen edge when it's off-screen, see EDGE_INDICATOR
+// in 3dcommon.hlsl
+const EDGE_INDICATOR: u32 = 0x08;
+const DEFAULT_EDGE_SIZE: f32 = 32.0;
+
+/// Returns the fade curve for the `fadecurve` attribute value at `ind`.
+///
+/// A warning is logged and [None] is returned if it isn't a known curve.
+fn fade_curve_from_lua(l: &lua_State, ind: i32) -> Option<u32> {
+    let curve = lua::tostring(l, ind).unwrap_or_default();
+
+    match curve.as_str() {
+        "linear"     => Some(0),
+        "smoothstep" => Some(1),
+        "quadratic"  => Some(2),
+        _            => {
+            luawarn!(l, "Unknown fadecurve '{}', must be 'linear', 'smoothstep', or 'quadratic'.", curve);
+            None
+        },
+    }
+}
+
+const DEFAULT_SPRITE_FPS: f32 = 10.0;
+
+// repr(C) because this a Vec of these will be directly copied into a vertex
+// buffer
+#[repr(C)]
+#[derive(Clone,Copy)]
+struct SpriteListSprite {
+    x: f32,
+    y: f32,
+    z: f32,
+
+    max_u: f32,
+    max_v: f32,
+    xy_ratio: f32,
+
+    size: f32,
+
+    fade_near: f32,
+    fade_far : f32,
+
+    r: f32,
+    g: f32,
+    b: f32,
+    a: f32,
+
+    flags: u32,
+
+    rotation: lamath::Mat4F,
+
+    // index of the texture in the SRV heap, only used when drawing bindless
+    tex_index: u32,
+
+    // animated sprites cycle through the layers of their texture at fps
+    // frames per second. sprites with a frame_count of 1 or less are static
+    frame_count: u32,
+    fps: f32,
+
+    // arbitrary values passed through to the shaders, the default shaders
+    // ignore these
+    params: [f32; 4],
+
+    // size in pixels when pinned to the screen edge
+    edge_size: f32,
+}
+
+impl SpriteListSprite {
+    /// A billboarded sprite at the origin with default attributes.
+    fn new(texture: &Texture) -> SpriteListSprite {
+        return SpriteListSprite {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+
+            max_u: texture.max_u,
+            max_v: texture.max_v,
+            xy_ratio: texture.xy_ratio,
+
+            size: 80.0,
+
+            fade_near: -1.0,
+            fade_far: -1.0,
+
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+
+            flags: BILLBOARD_FULL,
+
+            rotation: lamath::Mat4F::identity(),
+            tex_index: 0,
+
+            frame_count: texture.texture.depth() as u32,
+            fps: DEFAULT_SPRITE_FPS,
+
+            params: [0.0; 4],
+
+            edge_size: DEFAULT_EDGE_SIZE,
+        };
+    }
+
+    fn update_from_lua_table(&mut self, l: &lua_State, table: i32) {
+        if lua::getfield(l, table, "x") != lua::LuaType::LUA_TNIL { self.x = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "y") != lua::LuaType::LUA_TNIL { self.y = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "z") != lua::LuaType::LUA_TNIL { self.z = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "size") != lua::LuaType::LUA_TNIL { self.size = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "fadenear") != lua::LuaType::LUA_TNIL { self.fade_near = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "fadefar") != lua::LuaType::LUA_TNIL { self.fade_far = lua::tonumber(l, -1) as f32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "billboard") != lua::LuaType::LUA_TNIL {
+            let billboard = if lua::toboolean(l, -1) { BILLBOARD_FULL } else { 0 };
+            self.flags = (self.flags & !BILLBOARD_MASK) | billboard;
+        }
+        lua::pop(l, 1);
+
+        // billboardmode takes precedence over billboard if both are given
+        if lua::getfield(l, table, "billboardmode") != lua::LuaType::LUA_TNIL {
+            let mode = lua::tostring(l, -1).unwrap_or_default();
+
+            let billboard = match mode.as_str() {
+                "full"     => Some(BILLBOARD_FULL),
+                "vertical" => Some(BILLBOARD_VERTICAL),
+                "none"     => Some(0),
+                _          => {
+                    luawarn!(l, "Unknown billboardmode '{}', must be 'full', 'vertical', or 'none'.", mode);
+                    None
+                },
+            };
+
+            if let Some(b) = billboard { self.flags = (self.flags & !BILLBOARD_MASK) | b; }
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "fadecurve") != lua::LuaType::LUA_TNIL {
+            if let Some(curve) = fade_curve_from_lua(l, -1) {
+                self.flags = (self.flags & !FADE_CURVE_MASK) | (curve << FADE_CURVE_SHIFT);
+            }
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "frames") != lua::LuaType::LUA_TNIL { self.frame_count = lua::tointeger(l, -1).max(1) as u32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "framerate") != lua::LuaType::LUA_TNIL { self.fps = (lua::tonumber(l, -1) as f32).max(0.0); }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "edgeindicator") != lua::LuaType::LUA_TNIL {
+            let edge = if lua::toboolean(l, -1) { EDGE_INDICATOR } else { 0 };
+            self.flags = (self.flags & !EDGE_INDICATOR) | edge;
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "edgesize") != lua::LuaType::LUA_TNIL { self.edge_size = (lua::tonumber(l, -1) as f32).max(1.0); }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "params") != lua::LuaType::LUA_TNIL {
+            for i in 0..4 {
+                lua::geti(l, -1, i as i64 + 1);
+                self.params[i] = lua::tonumber(l, -1) as f32;
+                lua::pop(l, 1);
+            }
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "color") != lua::LuaType::LUA_TNIL {
+            let color = crate::ui::Color::from(lua::tointeger(l, -1));
+            self.r = color.r_f32();
+            self.g = color.g_f32();
+            self.b = color.b_f32();
+            self.a = color.a_f32();
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "rotation") != lua::LuaType::LUA_TNIL {
+            let x: f32;
+            let y: f32;
+            let z: f32;
+
+            lua::geti(l, -1, 1); x = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
+            lua::geti(l, -1, 2); y = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
+            lua::geti(l, -1, 3); z = lua::tonumber(l, -1) as f32; lua::pop(l, 1);
+
+            let xr = lamath::Mat4F::rotatex(x.to_radians());
+            let yr = lamath::Mat4F::rotatey(y.to_radians());
+            let zr = lamath::Mat4F::rotatez(z.to_radians());
+
+            let zy = zr * yr;
+            self.rotation = zy * xr;
+        }
+        lua::pop(l, 1);
+    }
+}
+
+const SPRITELIST_METATABLE_NAME: &str = "dx::lua::SpriteList";
+
+const SPRITELIST_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__gc"             , spritelist_gc,
+    c"add"              , spritelist_add,
+    c"draw"             , spritelist_draw,
+    c"update"           , spritelist_update,
+    c"remove"           , spritelist_remove,
+    c"clear"            , spritelist_clear,
+    c"count"            , spritelist_count,
+    c"mousehovertags"   , spritelist_mouse_hover_tags,
+    c"mousehoverdata"   , spritelist_mouse_hover_data,
+    c"mousetestinterval", spritelist_mouse_test_interval,
+    c"mousehoverdelay"  , spritelist_mouse_hover_delay,
+    c"alphatocoverage"  , spritelist_alpha_to_coverage,
+    c"frustumcull"      , spritelist_frustum_cull,
+    c"setcluster"       , spritelist_set_cluster,
+    c"clusters"         , spritelist_clusters,
+    c"updatebyhandle"   , spritelist_update_by_handle,
+    c"removebyhandle"   , spritelist_remove_by_handle,
+    c"beginupdate"      , spritelist_begin_update,
+    c"endupdate"        , spritelist_end_update,
+};
+
+unsafe fn checkspritelist(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<SpriteList>> {
+    let ptr: *mut *const SpriteList = unsafe {
+        std::mem::transmute(lua::L::checkudata(l, ind, SPRITELIST_METATABLE_NAME))
+    };
+
+    ManuallyDrop::new(unsafe { Arc::from_raw(*ptr) } )
+}
+
+unsafe extern "C" fn spritelist_gc(l: &lua_State) -> i32 {
+    let mut sl = unsafe { checkspritelist(l, 1) };
+
+    if let Some(dx_lua) = get_dx_lua_upvalue(l) {
+        let mut sprite_lists = dx_lua.sprite_lists.lock().unwrap();
+
+        let mut i = 0;
+
+        while i < sprite_lists.len() {
+            if Arc::ptr_eq(&*sl, &sprite_lists[i]) {
+                sprite_lists.remove(i);
+                break;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+
+    { // in a block so we can get an immutable reference to inner now and then
+      // a mutable reference when dropping below
+        // sprite lists can only be created from Lua, and by the time they are garbage
+        // collected Lua already knows that there are no other references to it,
+        // so free the other Lua things it references here
+        // TODO: is this still true now that it's a mutex?
+        let inner = sl.inner.lock().unwrap();
+
+        for tags in &inner.sprite_tags {
+            for tag in tags {
+                lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
+            }
+        }
+
+        for userdata in &inner.sprite_userdata {
+            for data in userdata {
+                lua::L::unref(l, lua::LUA_REGISTRYINDEX, *data);
+            }
+        }
+    }
+
+    unsafe { ManuallyDrop::drop(&mut sl); }
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: add(texture, attributes)
+
+        Add a sprite to this list. ``attributes`` must be a table that may have
+        the following fields
+
+        ============= ===================================================================
+        Field         Description
+        ============= ===================================================================
+        x             The sprite's X coordinate in map units. Default: ``0.0``.
+        y             The sprite's Y coordinate in map units. Default: ``0.0``.
+        z             The sprite's Z coordinate in map units. Default: ``0.0``.
+        tags          A table of attributes that can be referenced with update or
+                      remove.
+                      *Note:* the table is referenced, not copied.
+        size          The sprite's size, in map units. Default: ``80``.
+        color         Tint color and opacity, see :ref:`colors`. Default: ``0xFFFFFFFF``.
+        billboard     A boolean indicating if the sprite should always face the
+                      camera. Default: ``true``.
+                      *Note:* ``true`` is the same as a ``billboardmode`` of
+                      ``'full'`` and ``false`` is the same as ``'none'``.
+        billboardmode How the sprite faces the camera, ``'full'``,
+                      ``'vertical'``, or ``'none'``. ``'vertical'`` sprites
+                      only turn to face the camera horizontally and stay
+                      upright, which suits tall markers like pillars or beams.
+                      Default: ``'full'``.
+        rotation      A sequence of 3 numbers, indicating the rotation to be applied
+                      to the sprite along the X, Y, and Z axes, in that order. This
+                      value is only applicable if ``billboardmode`` is ``'none'``.
+        fadenear      The distance in map units from the player that the sprite will
+                      begin to fade to transparent. Default: ``-1.0``.
+                      *Note:* negative values disable distance based fading.
+        fadefar       The distance in map units from the player that the sprite will
+                      become completely transparent. Default: ``-1.0``.
+                      *Note:* negative values disable distance based fading.
+        fadecurve     How the sprite fades between ``fadenear`` and ``fadefar``,
+                      ``'linear'``, ``'smoothstep'``, or ``'quadratic'``.
+                      Default: ``'linear'``.
+        mousetest     A boolean value indicating if the mouse position will be checked
+                      each frame against the position of this sprite.
+        userdata      Any Lua value to associate with this sprite. Unlike ``tags``
+                      this is not used for matching, but it is returned by
+                      :lua:meth:`mousehoverdata`.
+        frames        The number of images to cycle through for animated sprites,
+                      see :lua:meth:`dxtexturemap.addarray`. Default: the number
+                      of images in ``texture``.
+                      *Note:* sprites with 1 frame are not animated.
+        framerate     The number of frames shown per second when animated.
+                      Default: ``10``.
+        params        A sequence of up to 4 numbers that are passed to the sprite
+                      shaders as-is, for custom effects. The built-in shaders
+                      ignore this. Default: ``{0, 0, 0, 0}``.
+        edgeindicator A boolean indicating if the sprite should be pinned to the
+                      edge of the screen when it is off-screen or behind the
+                      camera. See below. Default: ``false``.
+        edgesize      The size of the sprite when it is pinned to the screen
+                      edge, in pixels. Default: ``32``.
+        ============= ===================================================================
+
+        When ``edgeindicator`` is set on a world sprite and it is outside the
+        view, it is drawn at the edge of the screen in the direction of the
+        sprite instead, rotated so that the top of its texture points toward
+        it. An arrow texture that points up works best, add a second sprite at
+        the same position for the arrow if the marker's icon shouldn't be
+        rotated. This has no effect on map sprites.
+
+        :param string texture: The name of the texture, see :lua:meth:`dxtexturemap.add`.
+        :param table attributes: See above.
+        :returns: A handle that can be used with :lua:meth:`updatebyhandle`
+            and :lua:meth:`removebyhandle` to address this sprite directly.
+        :rtype: integer
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_add(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+
+    let sl = unsafe { checkspritelist(l, 1) };
+    let texname = lua::tostring(l, 2).unwrap();
+
+    let mut inner = sl.inner.lock().unwrap();
+
+    let texture: Arc<Texture>;
+
+    match inner.texture_map.get(&texname) {
+        Some(t) => texture = t,
+        None    => {
+            luaerror!(l, "Texture {} not found in texture map.", texname);
+            return 0;
+        }
+    }
+
+    let mut s = SpriteListSprite::new(&texture);
+
+    let mouse_test: bool;
+    if lua::getfield(l, 3, "mousetest") != lua::LuaType::LUA_TNIL {
+        mouse_test = lua::toboolean(l, -1);
+    } else {
+        mouse_test = false;
+    }
+    lua::pop(l, 1);
+
+    s.update_from_lua_table(l, 3);
+
+    let tags_ref = if lua::getfield(l, 3, "tags")!=lua::LuaType::LUA_TNIL {
+        lua::L::ref_(l, lua::LUA_REGISTRYINDEX)
+    } else {
+        lua::pop(l, 1);
+        -1
+    };
+
+    let userdata_ref = if lua::getfield(l, 3, "userdata")!=lua::LuaType::LUA_TNIL {
+        lua::L::ref_(l, lua::LUA_REGISTRYINDEX)
+    } else {
+        lua::pop(l, 1);
+        -1
+    };
+
+    let handle = inner.push_sprite(&texname, s, tags_ref, userdata_ref, mouse_test);
+
+    lua::pushinteger(l, handle as i64);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: draw(value)
+
+        Sets if this spritelist should be drawn.
+
+        :param boolean draw:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_draw(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
+    let val = lua::toboolean(l, 2);
+
+    sl.inner.lock().unwrap().draw = val;
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: beginupdate()
+
+        Begin a batch of changes to this spritelist.
+
+        Adding, updating and removing sprites normally causes the GPU buffer
+        for this list to be rebuilt on the next frame. Between
+        :lua:meth:`beginupdate` and :lua:meth:`endupdate` the list continues
+        to be drawn as it was and the buffer is rebuilt once, after the last
+        :lua:meth:`endupdate`.
+
+        Calls can be nested, the update ends when :lua:meth:`endupdate` has
+        been called once for each :lua:meth:`beginupdate`.
+
+        .. important::
+            Every call must be matched by a call to :lua:meth:`endupdate`,
+            otherwise changes to this list will never be drawn.
+
+        .. code-block:: lua
+            :caption: Example
+
+            sprites:beginupdate()
+
+            for i,poi in ipairs(pois) do
+                sprites:add('poi', {x = poi.x, y = poi.y, z = poi.z})
+            end
+
+            sprites:endupdate()
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_begin_update(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    sl.inner.lock().unwrap().update_depth += 1;
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: endupdate()
+
+        End a batch of changes started with :lua:meth:`beginupdate`.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_end_update(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    let mut inner = sl.inner.lock().unwrap();
+
+    if inner.update_depth == 0 {
+        luawarn!(l, "spritelist:endupdate called without beginupdate.");
+        return 0;
+    }
+
+    inner.update_depth -= 1;
+
+    return 0;
+}
+
+fn tags_match(l: &lua_State, target_tags: i32, query_tags: i32) -> bool {
+    lua::pushnil(l);
+
+    while lua::next(l, query_tags) != 0 {
+        let tagkey = lua::tostring(l, -2).unwrap_or(String::new());
+
+        if lua::getfield(l, target_tags, &tagkey)==lua::LuaType::LUA_TNIL || !lua::compare(l, -1, -2, lua::LUA_OPEQ) {
+            // not a match
+            // pop the current key, value, and the value from the sprites tag
+            // table, which was nil
+            lua::pop(l, 3);
+            return false;
+        }
+
+        lua::pop(l, 2); // pop the sprites tag value and the input value, leaving the key
+    }
+
+    return true;
+}
+
+/*** RST
+    .. lua:method:: update(tags, attributes)
+
+        Update the sprites that have matching tags.
+
+        An empty tags table matches all sprites. A sprite must match all tag
+        values given, if a sprite does not have a value for a tag it will not
+        match.
+
+        Returns the number of sprites updated.
+
+        :param table tags:
+        :param table attributes:
+        :rtype: integer
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_update(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    return sl.inner.lock().unwrap().update_matching(l);
+}
+
+/*** RST
+    .. lua:method:: remove(tags)
+
+        Remove sprites that have matching tags.
+
+        An empty tags table matches all sprites. A sprite must match all tag
+        values given, if a sprite does not have a value for a tag it will not
+        match.
+
+        :param table tags:
+        :returns: The number of sprites removed.
+        :rtype: integer
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_remove(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    return sl.inner.lock().unwrap().remove_matching(l);
+}
+
+/*** RST
+    .. lua:method:: count([texture])
+
+        Returns the number of sprites in this list.
+
+        If ``texture`` is given, only the sprites using that texture are
+        counted, ``0`` if there are none.
+
+        :param string texture: (Optional)
+        :rtype: integer
+
+        .. code-block:: lua
+            :caption: Example
+
+            sprites:remove({ category = 'old' })
+            overlay.logdebug(string.format('%d sprites left.', sprites:count()))
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_count(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    let texture = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargstring!(l, 2);
+        lua::tostring(l, 2)
+    } else {
+        None
+    };
+
+    let inner = sl.inner.lock().unwrap();
+
+    let count: usize = match texture {
+        Some(name) => match inner.texture_names.iter().position(|n| *n == name) {
+            Some(i) => inner.sprite_data[i].len(),
+            None    => 0,
+        },
+        None => inner.sprite_data.iter().map(|d| d.len()).sum(),
+    };
+
+    lua::pushinteger(l, count as i64);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: clear()
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_clear(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
+
+    let mut inner = sl.inner.lock().unwrap();
+
+    for tags in &inner.sprite_tags {
+        for tag in tags {
+            lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
+        }
+    }
+
+    for userdata in &inner.sprite_userdata {
+        for data in userdata {
+            lua::L::unref(l, lua::LUA_REGISTRYINDEX, *data);
+        }
+    }
+
+    inner.texture_names.clear();
+    inner.sprite_data.clear();
+    inner.sprite_tags.clear();
+    inner.sprite_userdata.clear();
+    inner.sprite_handles.clear();
+    inner.handles.clear();
+    inner.cluster_data.clear();
+    inner.cluster_counts.clear();
+    inner.cull_visible.clear();
+    inner.cull_data.clear();
+    inner.converted_data.clear();
+    inner.mouse_test.clear();
+    inner.mouse_hits.clear();
+    inner.mouse_hover_start.clear();
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: updatebyhandle(handle, attributes)
+
+        Update a single sprite using the handle returned by :lua:meth:`add`.
+
+        Unlike :lua:meth:`update`, this does not need to search the list and is
+        suitable for sprites that are moved every frame.
+
+        :param integer handle:
+        :param table attributes: See :lua:meth:`add`.
+        :returns: ``true`` if the sprite was updated, ``false`` if ``handle``
+            is not valid, i.e. the sprite has been removed.
+        :rtype: boolean
+
+        .. code-block:: lua
+            :caption: Example
+
+            local h = sprites:add('dot', { x = 0, y = 0, z = 0 })
+
+            -- later
+            sprites:updatebyhandle(h, { x = pos.x, y = pos.y, z = pos.z })
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_update_by_handle(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+
+    let sl = unsafe { checkspritelist(l, 1) };
+    let handle = lua::tointeger(l, 2);
+
+    let updated = sl.inner.lock().unwrap().update_handle(l, handle as u64);
+
+    lua::pushboolean(l, updated);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: removebyhandle(handle)
+
+        Remove a single sprite using the handle returned by :lua:meth:`add`.
+
+        .. note::
+
+            The last sprite with the same texture takes the place of the removed
+            sprite, so the draw order of sprites may change.
+
+        :param integer handle:
+        :returns: ``true`` if the sprite was removed, ``false`` if ``handle``
+            is not valid.
+        :rtype: boolean
 
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_remove_by_handle(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
 
-    { // in a block so we can get an immutable reference to inner now and then
-      // a mutable reference when dropping below
-        // sprite lists can only be created from Lua, and by the time they are garbage
-        // collected Lua already knows that there are no other references to it,
-        // so free the other Lua things it references here
-        // TODO: is this still true now that it's a mutex?
-        let inner = sl.inner.lock().unwrap();
+    let sl = unsafe { checkspritelist(l, 1) };
+    let handle = lua::tointeger(l, 2);
 
-        for tags in &inner.sprite_tags {
-            for tag in tags {
-                lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
-            }
-        }
-    }
+    let removed = sl.inner.lock().unwrap().remove_handle(l, handle as u64);
 
-    unsafe { ManuallyDrop::drop(&mut sl); }
+    lua::pushboolean(l, removed);
 
-    return 0;
+    return 1;
 }
 
 /*** RST
-    .. lua:method:: add(texture, attributes)
+    .. lua:method:: mousehovertags()
 
-        Add a sprite to this list. ``attributes`` must be a table that may have
-        the following fields
+        Returns a sequence of the tags tables of all sprites with ``mousetest``
+        set that are currently under the mouse cursor.
 
-        ========= ===================================================================
-        Field     Description
-        ========= ===================================================================
-        x         The sprite's X coordinate in map units. Default: ``0.0``.
-        y         The sprite's Y coordinate in map units. Default: ``0.0``.
-        z         The sprite's Z coordinate in map units. Default: ``0.0``.
-        tags      A table of attributes that can be referenced with update or
-                  remove.
-                  *Note:* the table is referenced, not copied.
-        size      The sprite's size, in map units. Default: ``80``.
-        color     Tint color and opacity, see :ref:`colors`. Default: ``0xFFFFFFFF``.
-        billboard A boolean indicating if the sprite should always face the
-                  camera. Default: ``true``.
-        rotation  A sequence of 3 numbers, indicating the rotation to be applied
-                  to the sprite along the X, Y, and Z axes, in that order. This
-                  value is only applicable if ``billboard`` is false.
-        fadenear  The distance in map units from the player that the sprite will
-                  begin to fade to transparent. Default: ``-1.0``.
-                  *Note:* negative values disable distance based fading.
-        fadefar   The distance in map units from the player that the sprite will
-                  become completely transparent. Default: ``-1.0``.
-                  *Note:* negative values disable distance based fading.
-        mousetest A boolean value indicating if the mouse position will be checked
-                  each frame against the position of this sprite.
-        ========= ===================================================================
+        If a hover delay has been set with :lua:meth:`mousehoverdelay`, sprites
+        are only returned once the mouse has been over them for that long.
 
-        :param string texture: The name of the texture, see :lua:meth:`dxtexturemap.add`.
-        :param table attributes: See above.
+        :rtype: table
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_add(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 2);
-    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
-
+unsafe extern "C" fn spritelist_mouse_hover_tags(l: &lua_State) -> i32 {
     let sl = unsafe { checkspritelist(l, 1) };
-    let texname = lua::tostring(l, 2).unwrap();
 
-    let mut inner = sl.inner.lock().unwrap();
+    let inner = sl.inner.lock().unwrap();
 
-    let texture: Arc<Texture>;
+    lua::createtable(l, inner.mouse_hover_tags.len() as i32, 0);
 
-    match inner.texture_map.get(&texname) {
-        Some(t) => texture = t,
-        None    => {
-            luaerror!(l, "Texture {} not found in texture map.", texname);
-            return 0;
+    let mut i = 1;
+    for tags in &inner.mouse_hover_tags {
+        if lua::rawgeti(l, lua::LUA_REGISTRYINDEX, *tags) == lua::LuaType::LUA_TTABLE {
+            // only return tables. if the tags value isn't a table,
+            // the sprite may have been removed between the last frame being drawn and now
+            lua::seti(l, -2, i);
+            i += 1;
+        } else {
+            lua::pop(l, 1);
         }
     }
 
-    let mut ti: Option<usize> = None;
-    for t in 0..inner.texture_names.len() {
-        if texname == inner.texture_names[t] {
-            ti = Some(t);
-            break;
-        }
-    }
+    return 1;
+}
 
-    let mut s = SpriteListSprite {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
+/*** RST
+    .. lua:method:: mousehoverdata()
 
-        max_u: texture.max_u,
-        max_v: texture.max_v,
-        xy_ratio: texture.xy_ratio,
+        Returns a sequence of the ``userdata`` values of all sprites with
+        ``mousetest`` set that are currently under the mouse cursor.
 
-        size: 80.0,
+        Sprites without a ``userdata`` value are not included.
 
-        fade_near: -1.0,
-        fade_far: -1.0,
+        :rtype: table
 
-        r: 1.0,
-        g: 1.0,
-        b: 1.0,
-        a: 1.0,
+        .. code-block:: lua
+            :caption: Example
 
-        flags: 0x01, // billboard
+            sl:add('marker', {x = 0, y = 0, z = 0, mousetest = true, userdata = {
+                name = 'Some Marker',
+                onclick = function() overlay.loginfo('clicked!') end,
+            }})
 
-        rotation: lamath::Mat4F::identity(),
-    };
+            -- later, in a click handler
+            for _, data in ipairs(sl:mousehoverdata()) do
+                data.onclick()
+            end
 
-    let mouse_test: bool;
-    if lua::getfield(l, 3, "mousetest") != lua::LuaType::LUA_TNIL {
-        mouse_test = lua::toboolean(l, -1);
-    } else {
-        mouse_test = false;
-    }
-    lua::pop(l, 1);
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_mouse_hover_data(l: &lua_State) -> i32 {
+    let sl = unsafe { checkspritelist(l, 1) };
 
-    s.update_from_lua_table(l, 3);
+    let inner = sl.inner.lock().unwrap();
 
-    let tags_ref = if lua::getfield(l, 3, "tags")!=lua::LuaType::LUA_TNIL {
-        lua::L::ref_(l, lua::LUA_REGISTRYINDEX)
-    } else {
-        lua::pop(l, 1);
-        -1
-    };
+    lua::createtable(l, inner.mouse_hover_data.len() as i32, 0);
 
-    if let Some(i) = ti {
-        inner.sprite_data[i].push(s);
-        inner.sprite_tags[i].push(tags_ref);
-        inner.mouse_test[i].push(mouse_test);
-    } else {
-        inner.texture_names.push(texname.clone());
-        inner.sprite_data.push(Vec::new());
-        inner.sprite_tags.push(Vec::new());
-        inner.mouse_test.push(Vec::new());
-        inner.sprite_data.last_mut().unwrap().push(s);
-        inner.sprite_tags.last_mut().unwrap().push(tags_ref);
-        inner.mouse_test.last_mut().unwrap().push(mouse_test);
-    }
+    let mut i = 1;
+    for data in &inner.mouse_hover_data {
+        if *data < 0 { continue; }
 
-    inner.update_vert_buffer = true;
+        if lua::rawgeti(l, lua::LUA_REGISTRYINDEX, *data) != lua::LuaType::LUA_TNIL {
+            lua::seti(l, -2, i);
+            i += 1;
+        } else {
+            lua::pop(l, 1);
+        }
+    }
 
-    return 0;
+    return 1;
 }
 
 /*** RST
-    .. lua:method:: draw(value)
+    .. lua:method:: mousetestinterval(interval[, onmove])
 
-        Sets if this spritelist should be drawn.
+        Set how often sprites with ``mousetest`` set are tested against the
+        mouse cursor.
 
-        :param boolean draw:
+        Testing sprites is fairly expensive, so lists that contain many
+        interactive sprites may want to do this less often than every frame.
+
+        :param integer interval: Test every ``interval`` frames. Default: ``1``.
+        :param boolean onmove: (Optional) If ``true``, only test when the mouse
+            or camera has moved since the last test. Default: ``false``.
+
+        .. code-block:: lua
+            :caption: Example
+
+            -- hit test every 3rd frame, and only if the mouse has moved
+            sl:mousetestinterval(3, true)
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_draw(l: &lua_State) -> i32 {
+unsafe extern "C" fn spritelist_mouse_test_interval(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
     let sl = unsafe { checkspritelist(l, 1) };
-    let val = lua::toboolean(l, 2);
 
-    sl.inner.lock().unwrap().draw = val;
+    let interval = lua::tointeger(l, 2);
+
+    if interval < 1 {
+        luaerror!(l, "interval must be 1 or greater.");
+        return 0;
+    }
+
+    let mut inner = sl.inner.lock().unwrap();
+
+    inner.mouse_test_interval = interval as u32;
+
+    if lua::gettop(l) >= 3 {
+        inner.mouse_test_on_move = lua::toboolean(l, 3);
+    }
 
     return 0;
 }
 
-fn tags_match(l: &lua_State, target_tags: i32, query_tags: i32) -> bool {
-    lua::pushnil(l);
+/*** RST
+    .. lua:method:: mousehoverdelay(seconds)
 
-    while lua::next(l, query_tags) != 0 {
-        let tagkey = lua::tostring(l, -2).unwrap_or(String::new());
+        Set how long the mouse must be over a sprite before it is returned from
+        :lua:meth:`mousehovertags`. This can be used to reduce flickering of
+        tooltips, etc.
 
-        if lua::getfield(l, target_tags, &tagkey)==lua::LuaType::LUA_TNIL || !lua::compare(l, -1, -2, lua::LUA_OPEQ) {
-            // not a match
-            // pop the current key, value, and the value from the sprites tag
-            // table, which was nil
-            lua::pop(l, 3);
-            return false;
-        }
+        :param number seconds: Default: ``0``.
 
-        lua::pop(l, 2); // pop the sprites tag value and the input value, leaving the key
-    }
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn spritelist_mouse_hover_delay(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 2);
+    let sl = unsafe { checkspritelist(l, 1) };
 
-    return true;
+    sl.inner.lock().unwrap().mouse_hover_delay = lua::tonumber(l, 2);
+
+    return 0;
 }
 
 /*** RST
-    .. lua:method:: update(tags, attributes)
+    .. lua:method:: alphatocoverage(value)
 
-        Update the sprites that have matching tags.
+        Sets if this spritelist should be drawn using alpha to coverage instead
+        of alpha blending.
 
-        An empty tags table matches all sprites. A sprite must match all tag
-        values given, if a sprite does not have a value for a tag it will not
-        match.
+        Alpha to coverage gives crisp, order independent edges for cutout
+        textures, i.e. foliage or icons with hard alpha edges. However, it does
+        not handle semi-transparent content well; partially transparent pixels
+        will be dithered instead of smoothly blended. Only use this for lists
+        whose textures are fully opaque or fully transparent.
 
-        Returns the number of sprites updated.
+        .. note::
+            Alpha to coverage requires multisampling. When MSAA is not enabled,
+            which is currently always the case, this list will be drawn with
+            normal alpha blending.
 
-        :param table tags:
-        :param table attributes:
-        :rtype: integer
+        :param boolean value: Default: ``false``.
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_update(l: &lua_State) -> i32 {
-    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
-    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+unsafe extern "C" fn spritelist_alpha_to_coverage(l: &lua_State) -> i32 {
     let sl = unsafe { checkspritelist(l, 1) };
+    let val = lua::toboolean(l, 2);
 
-    return sl.inner.lock().unwrap().update_matching(l);
+    sl.inner.lock().unwrap().alpha_to_coverage = val;
+
+    return 0;
 }
 
 /*** RST
-    .. lua:method:: remove(tags)
+    .. lua:method:: frustumcull(value)
 
-        Remove sprites that have matching tags.
+        Sets if sprites that are outside of the view should be skipped.
 
-        An empty tags table matches all sprites. A sprite must match all tag
-        values given, if a sprite does not have a value for a tag it will not
-        match.
+        When enabled, only the sprites that are at least partially in view are
+        uploaded to the GPU and drawn. This can reduce the cost of large world
+        lists where most sprites are off-screen at any one time, but the list
+        is uploaded again each time the set of visible sprites changes. The
+        number of sprites skipped is shown in the performance HUD.
 
-        :param table tags:
-        :returns: The number of sprites removed.
-        :rtype: integer
+        Culling is conservative, so sprites near the edges of the screen may
+        still be drawn. This only applies to world lists.
+
+        :param boolean value: Default: ``false``.
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_remove(l: &lua_State) -> i32 {
-    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
-
+unsafe extern "C" fn spritelist_frustum_cull(l: &lua_State) -> i32 {
     let sl = unsafe { checkspritelist(l, 1) };
+    let val = lua::toboolean(l, 2);
 
-    return sl.inner.lock().unwrap().remove_matching(l);
+    let mut inner = sl.inner.lock().unwrap();
+
+    inner.frustum_cull = val;
+    inner.cull_visible.clear();
+    inner.update_vert_buffer = true;
+
+    return 0;
 }
 
 /*** RST
-    .. lua:method:: clear()
+    .. lua:method:: setcluster(radius, enabled)
+
+        Set if sprites in this list should be clustered.
+
+        When enabled, sprites that use the same texture and are within
+        ``radius`` pixels of each other are drawn as a single sprite at their
+        center. As the map is zoomed in clusters will break apart.
+
+        Clustering is purely visual, it does not change the sprites in this
+        list. :lua:meth:`mousehovertags` and :lua:meth:`mousehoverdata` still
+        report individual sprites.
+
+        .. note::
+            Clustering only applies to lists with a ``'map'`` location.
+
+            Cluster counts are not drawn, see :lua:meth:`clusters`.
+
+        :param number radius: The cluster radius in pixels.
+        :param boolean enabled:
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_clear(l: &lua_State) -> i32 {
+unsafe extern "C" fn spritelist_set_cluster(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 2);
     let sl = unsafe { checkspritelist(l, 1) };
 
+    let radius = lua::tonumber(l, 2) as f32;
+    let enabled = lua::toboolean(l, 3);
+
     let mut inner = sl.inner.lock().unwrap();
 
-    for tags in &inner.sprite_tags {
-        for tag in tags {
-            lua::L::unref(l, lua::LUA_REGISTRYINDEX, *tag);
-        }
+    if !inner.is_map && enabled {
+        luawarn!(l, "Clustering only applies to map sprite lists.");
     }
 
-    inner.texture_names.clear();
-    inner.sprite_data.clear();
-    inner.sprite_tags.clear();
-    inner.mouse_test.clear();
+    inner.cluster = enabled;
+    inner.cluster_radius = radius;
+
+    // rebuild clusters, or revert to the full set of sprites
+    inner.cluster_scale = 0.0;
+    inner.update_vert_buffer = true;
 
     return 0;
 }
 
 /*** RST
-    .. lua:method:: mousehovertags()
+    .. lua:method:: clusters()
+
+        Returns a sequence of the clusters drawn during the last frame.
+
+        Each cluster is a table with ``x``, ``y``, ``count``, and ``texture``
+        fields. This can be used to display the number of sprites in each
+        cluster.
+
+        An empty table is returned if clustering is not enabled.
 
         :rtype: table
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn spritelist_mouse_hover_tags(l: &lua_State) -> i32 {
+unsafe extern "C" fn spritelist_clusters(l: &lua_State) -> i32 {
     let sl = unsafe { checkspritelist(l, 1) };
 
     let inner = sl.inner.lock().unwrap();
 
-    lua::createtable(l, inner.mouse_hover_tags.len() as i32, 0);
+    lua::newtable(l);
+
+    if !inner.clustering() { return 1; }
 
     let mut i = 1;
-    for tags in &inner.mouse_hover_tags {
-        if lua::rawgeti(l, lua::LUA_REGISTRYINDEX, *tags) == lua::LuaType::LUA_TTABLE {
-            // only return tables. if the tags value isn't a table,
-            // the sprite may have been removed between the last frame being drawn and now
+    for ti in 0..inner.cluster_data.len() {
+        for ci in 0..inner.cluster_data[ti].len() {
+            let c = &inner.cluster_data[ti][ci];
+
+            lua::createtable(l, 0, 4);
+
+            lua::pushnumber(l, c.x as f64);
+            lua::setfield(l, -2, "x");
+
+            lua::pushnumber(l, c.y as f64);
+            lua::setfield(l, -2, "y");
+
+            lua::pushinteger(l, inner.cluster_counts[ti][ci] as i64);
+            lua::setfield(l, -2, "count");
+
+            lua::pushstring(l, &inner.texture_names[ti]);
+            lua::setfield(l, -2, "texture");
+
             lua::seti(l, -2, i);
             i += 1;
-        } else {
-            lua::pop(l, 1);
         }
     }
 
@@ -1895,17 +4958,172 @@ struct TrailListInner {
     vert_buffer_size: usize,
     update_vert_buffer: bool,
 
+    // trails whose geometry changed since the last upload, by texture and trail
+    // index. see mark_dirty
+    dirty_trails: Vec<(usize, usize)>,
+
     texture_map: Arc<TextureMap>,
     texture_names: Vec<String>,
 
-    trails: Vec<Vec<TrailListTrail>>,
+    trails: Vec<Vec<TrailListTrail>>,
+
+    // start and end cap sprites, rebuilt along with the vertex buffer and
+    // drawn with the sprite lists
+    caps: SpriteListInner,
+
+    is_map: bool,
+    draw: bool,
+
+    channel: String,
+
+    // tags of the trails with mouse_test set that were under the mouse cursor
+    // during the last frame
+    mouse_hover_tags: Vec<i64>,
+
+    // skip drawing trails whose bounding box is outside the view
+    frustum_cull: bool,
+
+    // the number of nested beginupdate calls, see SpriteListInner
+    update_depth: u32,
+}
+
+impl TrailListInner {
+    fn new(texture_map: Arc<TextureMap>, is_map: bool) -> TrailListInner {
+        return TrailListInner {
+            vert_buffer: None,
+            vert_buffer_view: Direct3D12::D3D12_VERTEX_BUFFER_VIEW::default(),
+
+            vert_buffer_size: 0,
+            update_vert_buffer: false,
+
+            dirty_trails: Vec::new(),
+
+            texture_map: texture_map.clone(),
+
+            texture_names: Vec::new(),
+            trails: Vec::new(),
+
+            caps: SpriteListInner::new(texture_map, is_map),
+
+            is_map: is_map,
+            draw: true,
+
+            channel: String::from(DEFAULT_CHANNEL),
+
+            mouse_hover_tags: Vec::new(),
+
+            frustum_cull: false,
+
+            update_depth: 0,
+        };
+    }
+
+    /// Adds a trail that uses the texture `texname`.
+    fn push_trail(&mut self, texname: &str, trail: TrailListTrail) {
+        match self.texture_names.iter().position(|n| n == texname) {
+            Some(i) => self.trails[i].push(trail),
+            None    => {
+                self.texture_names.push(String::from(texname));
+                self.trails.push(vec![trail]);
+            }
+        }
+
+        self.update_vert_buffer = true;
+    }
+
+    /// Rebuilds the start and end cap sprites from the current trails.
+    fn update_caps(&mut self) {
+        self.caps.texture_names.clear();
+        self.caps.sprite_data.clear();
+        self.caps.sprite_tags.clear();
+        self.caps.sprite_userdata.clear();
+        self.caps.sprite_handles.clear();
+        self.caps.mouse_test.clear();
+
+        for textrails in &self.trails {
+            for trail in textrails {
+                if trail.points.len() < 2 { continue; }
+
+                let caps = [
+                    (&trail.start_texture, trail.points.first().unwrap()),
+                    (&trail.end_texture  , trail.points.last().unwrap()),
+                ];
+
+                for (texname, point) in caps {
+                    let texname = match texname {
+                        Some(n) => n,
+                        None    => continue,
+                    };
+
+                    let texture = match self.texture_map.get(texname) {
+                        Some(t) => t,
+                        None    => {
+                            crate::logging::warn!("Trail cap texture {} not found in texture map.", texname);
+                            continue;
+                        }
+                    };
+
+                    let s = SpriteListSprite {
+                        x: point.x,
+                        y: point.y,
+                        z: point.z,
+
+                        max_u: texture.max_u,
+                        max_v: texture.max_v,
+                        xy_ratio: texture.xy_ratio,
+
+                        size: trail.cap_size,
+
+                        fade_near: trail.fade_near,
+                        fade_far: trail.fade_far,
+
+                        r: trail.color.r_f32(),
+                        g: trail.color.g_f32(),
+                        b: trail.color.b_f32(),
+                        a: trail.color.a_f32(),
+
+                        flags: BILLBOARD_FULL | (trail.fade_curve << FADE_CURVE_SHIFT),
+
+                        rotation: lamath::Mat4F::identity(),
+                        tex_index: 0,
+
+                        frame_count: texture.texture.depth() as u32,
+                        fps: DEFAULT_SPRITE_FPS,
+
+                        params: [0.0; 4],
+
+                        edge_size: DEFAULT_EDGE_SIZE,
+                    };
+
+                    let ti = match self.caps.texture_names.iter().position(|n| n == texname) {
+                        Some(i) => i,
+                        None    => {
+                            self.caps.texture_names.push(texname.clone());
+                            self.caps.sprite_data.push(Vec::new());
+                            self.caps.sprite_tags.push(Vec::new());
+                            self.caps.sprite_userdata.push(Vec::new());
+                            self.caps.sprite_handles.push(Vec::new());
+                            self.caps.mouse_test.push(Vec::new());
+
+                            self.caps.texture_names.len() - 1
+                        }
+                    };
+
+                    self.caps.sprite_data[ti].push(s);
+                    self.caps.sprite_tags[ti].push(-1);
+                    self.caps.sprite_userdata[ti].push(-1);
+                    self.caps.sprite_handles[ti].push(0);
+                    self.caps.mouse_test[ti].push(false);
+                }
+            }
+        }
+
+        self.caps.update_vert_buffer = true;
+    }
 
-    is_map: bool,
-    draw: bool,
-}
+    fn update_vertex_buffer(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>, world_up: &lamath::Vec3F) {
+        self.update_caps();
 
-impl TrailListInner {
-    fn update_vertex_buffer(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>) {
         let mut coords: Vec<Vec<Vec<TrailCoordinate>>> = Vec::new();
 
         let mut new_size: usize = 0;
@@ -1913,7 +5131,7 @@ impl TrailListInner {
             let mut tc: Vec<Vec<TrailCoordinate>> = Vec::new();
 
             for trail in textrails {
-                tc.push(trail.calc_coords(self.is_map));
+                tc.push(trail.calc_coords(self.is_map, world_up));
                 new_size += trail.coord_count as usize * std::mem::size_of::<TrailCoordinate>();
             }
             coords.push(tc);
@@ -1969,6 +5187,119 @@ impl TrailListInner {
         copy.copy_resource(&upload, self.vert_buffer.as_ref().unwrap());
 
         self.update_vert_buffer = false;
+        self.dirty_trails.clear();
+    }
+
+    /// Marks the geometry of a single trail as changed.
+    ///
+    /// If the trail still has the same number of vertices when it is uploaded
+    /// only that trail's part of the vertex buffer is updated, otherwise the
+    /// whole buffer is rebuilt.
+    fn mark_dirty(&mut self, ti: usize, i: usize) {
+        if self.update_vert_buffer { return; }
+
+        if self.vert_buffer.is_none() {
+            self.update_vert_buffer = true;
+            return;
+        }
+
+        if self.dirty_trails.contains(&(ti, i)) { return; }
+
+        // past a point a full rebuild is cheaper
+        let count: usize = self.trails.iter().map(|t| t.len()).sum();
+        if self.dirty_trails.len() >= count / 2 {
+            self.dirty_trails.clear();
+            self.update_vert_buffer = true;
+            return;
+        }
+
+        self.dirty_trails.push((ti, i));
+    }
+
+    /// Uploads only the parts of the vertex buffer that cover
+    /// [Self::dirty_trails].
+    ///
+    /// If any of the trails now have a different number of vertices the whole
+    /// buffer is rebuilt instead.
+    fn update_vertex_buffer_range(&mut self, frame: &mut dx::SwapChainLock, dx: &Arc<dx::Dx>, world_up: &lamath::Vec3F) {
+        let mut dirty = std::mem::take(&mut self.dirty_trails);
+        dirty.sort();
+
+        // (offset in the vertex buffer, coordinates) of each trail
+        let mut updates: Vec<(usize, Vec<TrailCoordinate>)> = Vec::with_capacity(dirty.len());
+
+        let mut offset: usize = 0;
+        let mut next = dirty.iter().peekable();
+        let mut rebuild = false;
+
+        'outer: for (ti, textrails) in self.trails.iter_mut().enumerate() {
+            for (i, trail) in textrails.iter_mut().enumerate() {
+                if next.peek().is_none() { break 'outer; }
+
+                let count = trail.coord_count;
+
+                if next.peek() == Some(&&(ti, i)) {
+                    next.next();
+
+                    let coords = trail.calc_coords(self.is_map, world_up);
+
+                    if trail.coord_count != count || coords.len() != count as usize {
+                        rebuild = true;
+                        break 'outer;
+                    }
+
+                    updates.push((offset, coords));
+                }
+
+                offset += count as usize;
+            }
+        }
+
+        if rebuild {
+            self.update_vertex_buffer(frame, dx, world_up);
+            return;
+        }
+
+        // the caps are placed at the ends of the trails
+        self.update_caps();
+
+        let coord_size = std::mem::size_of::<TrailCoordinate>();
+        let size: usize = updates.iter().map(|(_, c)| c.len() * coord_size).sum();
+
+        if size == 0 { return; }
+
+        frame.flush_commands();
+
+        let upload = dx.new_upload_buffer(size as u64);
+        crate::dx::object_set_name(&upload, "EG-Overlay D3D12 TrailList Temp. Upload Buffer");
+
+        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+        let rr = Direct3D12::D3D12_RANGE::default();
+
+        if unsafe { upload.Map(0, Some(&rr), Some(&mut data)) }.is_err() {
+            panic!("Couldn't map trail upload data.");
+        }
+
+        let mut regions: Vec<(u64, u64, u64)> = Vec::with_capacity(updates.len());
+        let mut src_offset = 0;
+
+        for (dst, coords) in &updates {
+            let csize = coords.len() * coord_size;
+
+            if csize == 0 { continue; }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(coords.as_ptr() as *const std::ffi::c_void, data.add(src_offset), csize);
+            }
+
+            regions.push((src_offset as u64, (dst * coord_size) as u64, csize as u64));
+            src_offset += csize;
+        }
+
+        unsafe { upload.Unmap(0, None); }
+
+        let mut copy = dx.copy_queue();
+        copy.copy_buffer_regions(&upload, self.vert_buffer.as_ref().unwrap(), &regions);
     }
 
     fn remove_matching(&mut self, l: &lua_State) -> i32 {
@@ -1987,6 +5318,7 @@ impl TrailListInner {
 
                 if tags_match(l, trailtags, 2) {
                     lua::L::unref(l, lua::LUA_REGISTRYINDEX, textrails[ti].tags);
+                    lua::L::unref(l, lua::LUA_REGISTRYINDEX, textrails[ti].userdata);
 
                     textrails.remove(ti);
                     nremoved += 1;
@@ -2003,6 +5335,114 @@ impl TrailListInner {
 
         return 1;
     }
+
+    fn update_matching(&mut self, l: &lua_State) -> i32 {
+        let mut nupdated = 0;
+        let mut update_caps = false;
+
+        // trails whose geometry changed
+        let mut changed: Vec<(usize, usize)> = Vec::new();
+
+        for (ti, textrails) in self.trails.iter_mut().enumerate() {
+            for (i, trail) in textrails.iter_mut().enumerate() {
+                if trail.tags < 0 { continue; }
+
+                lua::geti(l, lua::LUA_REGISTRYINDEX, trail.tags);
+                let trailtags = lua::gettop(l);
+
+                if tags_match(l, trailtags, 2) {
+                    if trail.update_from_lua_table(l, 3) {
+                        changed.push((ti, i));
+                    } else if trail.start_texture.is_some() || trail.end_texture.is_some() {
+                        // the caps are tinted and faded with the trail
+                        update_caps = true;
+                    }
+
+                    if lua::getfield(l, 3, "tags") != lua::LuaType::LUA_TNIL {
+                        lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.tags);
+                        trail.tags = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+                    } else {
+                        lua::pop(l, 1);
+                    }
+
+                    if lua::getfield(l, 3, "userdata") != lua::LuaType::LUA_TNIL {
+                        lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.userdata);
+                        trail.userdata = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+                    } else {
+                        lua::pop(l, 1);
+                    }
+
+                    nupdated += 1;
+                }
+                lua::pop(l, 1);
+            }
+        }
+
+        // color and fade are root constants, so only geometry changes need the
+        // vertex buffer to be updated
+        if !changed.is_empty() {
+            for (ti, i) in changed {
+                self.mark_dirty(ti, i);
+            }
+        } else if update_caps {
+            self.update_caps();
+        } else if nupdated > 0 {
+            crate::overlay::request_redraw();
+        }
+
+        lua::pushinteger(l, nupdated);
+
+        return 1;
+    }
+
+    /// Returns if each trail, by texture, is at least partially inside
+    /// `frustum`.
+    fn visible_trails(&mut self, frustum: &lamath::Frustum) -> Vec<Vec<bool>> {
+        let mut visible: Vec<Vec<bool>> = Vec::with_capacity(self.trails.len());
+
+        for textrails in &mut self.trails {
+            visible.push(textrails.iter_mut().map(|t| {
+                let e = t.extents();
+
+                // arrows extend past the edges of the trail
+                let pad = lamath::Vec3F { x: t.size, y: t.size, z: t.size };
+
+                frustum.intersects_aabb(&(e.min - pad), &(e.max + pad))
+            }).collect());
+        }
+
+        return visible;
+    }
+
+    /// Sets `mouse_hover_tags` to the tags of the trails with `mouse_test` set
+    /// that are under the mouse cursor.
+    ///
+    /// World trails are tested against `mouse_ray` and map trails against the
+    /// mouse position on the map, like sprite lists.
+    fn update_mouse_hover(
+        &mut self,
+        camera: &lamath::Vec3F,
+        mouse_ray: &Option<lamath::Vec3F>,
+        mouse_map_x: f32,
+        mouse_map_y: f32,
+        mouse_in_map: bool
+    ) {
+        self.mouse_hover_tags.clear();
+
+        if self.is_map != mouse_in_map { return; }
+
+        if !self.is_map && mouse_ray.is_none() { return; }
+
+        for textrails in &self.trails {
+            for trail in textrails {
+                if !trail.mouse_test || trail.tags < 0 { continue; }
+
+                if trail.mouse_over(camera, mouse_ray.as_ref(), mouse_map_x, mouse_map_y) {
+                    self.mouse_hover_tags.push(trail.tags);
+                }
+            }
+        }
+    }
 }
 
 struct TrailListTrail {
@@ -2012,16 +5452,47 @@ struct TrailListTrail {
 
     fade_near: f32,
     fade_far: f32,
+    fade_curve: u32,
 
     color: crate::ui::Color,
 
+    // per-point colors, used when there is one for each point
+    colors: Vec<crate::ui::Color>,
+
     size: f32,
     wall: bool,
 
+    // direction arrows placed every arrow_spacing units along the trail
+    arrows: bool,
+    arrow_spacing: f32,
+
+    // alpha at the first and last points when fading by age
+    fade_by_age: bool,
+    age_alpha: (f32, f32),
+
+    start_texture: Option<String>,
+    end_texture: Option<String>,
+    cap_size: f32,
+
+    // if the trail is tested against the mouse cursor each frame
+    mouse_test: bool,
+
     tags: i64,
+    userdata: i64,
+
+    // calculated lazily by extents(), reset when points change
+    extents: Option<TrailExtents>,
+}
+
+#[derive(Clone,Copy)]
+struct TrailExtents {
+    length: f32,
+    min: lamath::Vec3F,
+    max: lamath::Vec3F,
 }
 
 #[repr(C)]
+#[derive(Clone,Copy)]
 struct TrailCoordinate {
     x: f32,
     y: f32,
@@ -2029,10 +5500,113 @@ struct TrailCoordinate {
 
     u: f32,
     v: f32,
+
+    // r,g,b,a
+    color: [f32; 4],
+
+    // position along the trail, from 0.0 at the first point to 1.0 at the
+    // last, used to fade by age
+    age: f32,
 }
 
 impl TrailListTrail {
-    fn calc_coords(&mut self, map: bool) -> Vec<TrailCoordinate> {
+    /// A trail with no points and default attributes.
+    fn new() -> TrailListTrail {
+        return TrailListTrail {
+            points: Vec::new(),
+
+            coord_count: 0,
+
+            fade_near: -1.0,
+            fade_far: -1.0,
+            fade_curve: 0,
+
+            color: crate::ui::Color::from(0xFFFFFFFFu32),
+
+            colors: Vec::new(),
+
+            size: 40.0,
+            wall: false,
+
+            arrows: false,
+            arrow_spacing: 250.0,
+
+            fade_by_age: false,
+            age_alpha: (0.0, 1.0),
+
+            start_texture: None,
+            end_texture: None,
+            cap_size: 80.0,
+
+            mouse_test: false,
+
+            tags: -1,
+            userdata: -1,
+
+            extents: None,
+        };
+    }
+
+    /// Returns the total length and axis aligned bounding box of this trail.
+    fn extents(&mut self) -> TrailExtents {
+        if let Some(e) = self.extents { return e; }
+
+        let mut e = TrailExtents {
+            length: 0.0,
+            min: self.points.first().copied().unwrap_or_default(),
+            max: self.points.first().copied().unwrap_or_default(),
+        };
+
+        for i in 1..self.points.len() {
+            let p = &self.points[i];
+
+            e.length += (*p - self.points[i-1]).length();
+
+            e.min.x = e.min.x.min(p.x);
+            e.min.y = e.min.y.min(p.y);
+            e.min.z = e.min.z.min(p.z);
+
+            e.max.x = e.max.x.max(p.x);
+            e.max.y = e.max.y.max(p.y);
+            e.max.z = e.max.z.max(p.z);
+        }
+
+        self.extents = Some(e);
+
+        return e;
+    }
+
+    /// Returns `true` if the mouse is over any segment of this trail.
+    ///
+    /// If `mouse_ray` is given, it is tested against each segment in the world,
+    /// otherwise the mouse position on the map is.
+    fn mouse_over(&self, camera: &lamath::Vec3F, mouse_ray: Option<&lamath::Vec3F>, mouse_map_x: f32, mouse_map_y: f32) -> bool {
+        let radius = self.size / 2.0;
+
+        // these are the same segments calc_coords builds the ribbon around
+        for seg in self.points.windows(2) {
+            let hit = match mouse_ray {
+                Some(ray) => ray_near_segment(&seg[0], &seg[1], radius, camera, ray),
+                None      => point_near_segment_2d(mouse_map_x, mouse_map_y, &seg[0], &seg[1], radius),
+            };
+
+            if hit { return true; }
+        }
+
+        return false;
+    }
+
+    /// The vertex color for point `i`, or white if this trail doesn't have
+    /// per-point colors. This is multiplied by the trail color in the shader.
+    fn point_color(&self, i: usize) -> [f32; 4] {
+        if self.colors.len() != self.points.len() { return [1.0, 1.0, 1.0, 1.0]; }
+
+        let c = &self.colors[i];
+
+        return [c.r_f32(), c.g_f32(), c.b_f32(), c.a_f32()];
+    }
+
+    fn calc_coords(&mut self, map: bool, world_up: &lamath::Vec3F) -> Vec<TrailCoordinate> {
         let mut coords: Vec<TrailCoordinate> = Vec::new();
 
         if self.points.len() == 0 {
@@ -2049,16 +5623,29 @@ impl TrailListTrail {
             // on the map up is Z
             lamath::Vec3F { x: 0.0, y: 0.0, z: 1.0 }
         } else {
-            // otherwise up is Y
-            lamath::Vec3F { x: 0.0, y: 1.0, z: 0.0 }
+            // otherwise up is the world up, Y unless changed with setworldup
+            *world_up
         };
 
+        let mut arrows: Vec<TrailCoordinate> = Vec::new();
+
+        // distance along the trail of the start of the current segment and
+        // the next arrow
+        let mut walked = 0.0f32;
+        let mut next_arrow = self.arrow_spacing;
 
         for i in 0..(self.points.len()-1) {
             // each segment of the trail is made up of 2 points: p1 and p2
             let p1 = &self.points[i];
             let p2 = &self.points[i+1];
 
+            let p1color = self.point_color(i);
+            let p2color = self.point_color(i+1);
+
+            let last = (self.points.len() - 1) as f32;
+            let p1age = i as f32 / last;
+            let p2age = (i + 1) as f32 / last;
+
             /*
                 In order to display a flat 'ribbon' trail, we need 4 points,
                 arranged around p1 and p2.
@@ -2091,6 +5678,27 @@ impl TrailListTrail {
             // side is a vector perpendicular to forward and up
             let mut side = up.crossproduct(&forward).normalize();
 
+            if self.arrows {
+                let seg_len = (*p2 - *p1).length();
+
+                while next_arrow <= walked + seg_len {
+                    let t = next_arrow - walked;
+
+                    let mut color = [0.0f32; 4];
+                    for c in 0..4 {
+                        color[c] = p1color[c] + ((p2color[c] - p1color[c]) * (t / seg_len));
+                    }
+
+                    let age = p1age + ((p2age - p1age) * (t / seg_len));
+
+                    self.push_arrow(&mut arrows, *p1 + forward.mulf(t), &forward, &side, color, age);
+
+                    next_arrow += self.arrow_spacing;
+                }
+
+                walked += seg_len;
+            }
+
             // toside is our vector to b and d
             // and the opposite direction is to a and c
             let mut toside = side.mulf(self.size / 2.0);
@@ -2105,6 +5713,8 @@ impl TrailListTrail {
                     z: p1.z + toside.z,
                     u: 1.0,
                     v: 0.0,
+                    color: p1color,
+                    age: p1age,
                 });
 
                 // a
@@ -2114,6 +5724,8 @@ impl TrailListTrail {
                     z: p1.z - toside.z,
                     u: 0.0,
                     v: 0.0,
+                    color: p1color,
+                    age: p1age,
                 });
             } else {
                 // adjust side and toside to be the mean of the prior side vector
@@ -2146,6 +5758,7 @@ impl TrailListTrail {
             }
 
             let mut section_len = (*p2 - *p1).length();
+            let total_len = section_len;
 
             // If the segment is too long fading won't be calculated properly
             // so insert extra points along forward.
@@ -2174,12 +5787,21 @@ impl TrailListTrail {
 
                     let epv = - (5000.0 / self.size) + coords.last().unwrap().v;
 
+                    // interpolate the color between p1 and p2
+                    let t = len / total_len;
+                    let mut epcolor = [0.0f32; 4];
+                    for c in 0..4 {
+                        epcolor[c] = p1color[c] + ((p2color[c] - p1color[c]) * t);
+                    }
+
                     coords.push(TrailCoordinate {
                         x: p.x + toside.x,
                         y: p.y + toside.y,
                         z: p.z + toside.z,
                         u: 1.0,
                         v: epv,
+                        color: epcolor,
+                        age: p1age + ((p2age - p1age) * t),
                     });
 
                     coords.push(TrailCoordinate {
@@ -2188,6 +5810,8 @@ impl TrailListTrail {
                         z: p.z - toside.z,
                         u: 0.0,
                         v: epv,
+                        color: epcolor,
+                        age: p1age + ((p2age - p1age) * t),
                     });
 
                     section_len -= 5000.0;
@@ -2205,6 +5829,8 @@ impl TrailListTrail {
                 z: p2.z + toside.z,
                 u: 1.0,
                 v: p2v,
+                color: p2color,
+                age: p2age,
             });
 
             // c
@@ -2214,10 +5840,72 @@ impl TrailListTrail {
                 z: p2.z - toside.z,
                 u: 0.0,
                 v: p2v,
+                color: p2color,
+                age: p2age,
             });
         }
 
-        return coords;
+        if arrows.is_empty() { return coords; }
+
+        // The arrows are drawn in the same triangle strip, before the trail so
+        // that they aren't hidden by it, since they are in the same plane.
+        // Each arrow and the trail itself are joined with degenerate triangles
+        // by repeating the last vertex of one and the first vertex of the next.
+        let mut strip: Vec<TrailCoordinate> = Vec::with_capacity(((arrows.len() / 4) * 6) + coords.len());
+
+        for (i, arrow) in arrows.chunks_exact(4).enumerate() {
+            if i > 0 {
+                strip.push(*strip.last().unwrap());
+                strip.push(arrow[0]);
+            }
+
+            strip.extend_from_slice(arrow);
+        }
+
+        strip.push(*strip.last().unwrap());
+        strip.push(coords[0]);
+
+        self.coord_count += strip.len() as u32;
+
+        strip.extend(coords);
+
+        return strip;
+    }
+
+    /// Adds an arrowhead pointing along `forward` at `p` to `arrows`.
+    ///
+    /// Arrows are 4 vertices: the left wing, the notch, the tip, and the right
+    /// wing, in triangle strip order.
+    fn push_arrow(
+        &self,
+        arrows: &mut Vec<TrailCoordinate>,
+        p: lamath::Vec3F,
+        forward: &lamath::Vec3F,
+        side: &lamath::Vec3F,
+        color: [f32; 4],
+        age: f32
+    ) {
+        // the wings extend past the edges of the trail so the arrow is visible
+        let half_len = forward.mulf(self.size / 2.0);
+        let wing = side.mulf(self.size);
+
+        let back = p - half_len;
+        let tip = p + half_len;
+
+        let coord = |v: lamath::Vec3F, u: f32, tv: f32| TrailCoordinate {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            u: u,
+            v: tv,
+            color: color,
+            age: age,
+        };
+
+        arrows.push(coord(back - wing, 0.0,  0.0));
+        arrows.push(coord(p          , 0.5, -0.5));
+        arrows.push(coord(tip        , 0.5, -1.0));
+        arrows.push(coord(back + wing, 1.0,  0.0));
     }
 
     fn update_from_lua_table(&mut self, l: &lua_State, table: i32) -> bool {
@@ -2229,6 +5917,11 @@ impl TrailListTrail {
         if lua::getfield(l, table, "fadefar") != lua::LuaType::LUA_TNIL { self.fade_far = lua::tonumber(l, -1) as f32; }
         lua::pop(l, 1);
 
+        if lua::getfield(l, table, "fadecurve") != lua::LuaType::LUA_TNIL {
+            if let Some(curve) = fade_curve_from_lua(l, -1) { self.fade_curve = curve; }
+        }
+        lua::pop(l, 1);
+
         if lua::getfield(l, table, "color") != lua::LuaType::LUA_TNIL { self.color = ui::Color::from(lua::tonumber(l, -1) as u32); }
         lua::pop(l, 1);
 
@@ -2244,6 +5937,55 @@ impl TrailListTrail {
         }
         lua::pop(l, 1);
 
+        if lua::getfield(l, table, "fadebyage") != lua::LuaType::LUA_TNIL { self.fade_by_age = lua::toboolean(l, -1); }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "mousetest") != lua::LuaType::LUA_TNIL { self.mouse_test = lua::toboolean(l, -1); }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "agealpha") != lua::LuaType::LUA_TNIL {
+            lua::geti(l, -1, 1);
+            lua::geti(l, -2, 2);
+            self.age_alpha = (
+                (lua::tonumber(l, -2) as f32).clamp(0.0, 1.0),
+                (lua::tonumber(l, -1) as f32).clamp(0.0, 1.0),
+            );
+            lua::pop(l, 2);
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "arrows") != lua::LuaType::LUA_TNIL {
+            self.arrows = lua::toboolean(l, -1);
+            update_vert_buffer = true;
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "arrowspacing") != lua::LuaType::LUA_TNIL {
+            // a lower limit keeps a tiny spacing from generating an enormous
+            // number of arrows
+            self.arrow_spacing = (lua::tonumber(l, -1) as f32).max(self.size / 2.0).max(1.0);
+            update_vert_buffer = true;
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "starttexture") != lua::LuaType::LUA_TNIL {
+            self.start_texture = lua::tostring(l, -1);
+            update_vert_buffer = true;
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "endtexture") != lua::LuaType::LUA_TNIL {
+            self.end_texture = lua::tostring(l, -1);
+            update_vert_buffer = true;
+        }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, table, "capsize") != lua::LuaType::LUA_TNIL {
+            self.cap_size = lua::tonumber(l, -1) as f32;
+            update_vert_buffer = true;
+        }
+        lua::pop(l, 1);
+
         if lua::getfield(l, table, "points") != lua::LuaType::LUA_TNIL {
             let points = lua::gettop(l);
             let c = lua::L::len(l, points);
@@ -2255,6 +5997,7 @@ impl TrailListTrail {
             }
 
             self.points.clear();
+            self.extents = None;
 
             for i in 1..(c+1) {
                 lua::geti(l, points, i as i64); // sequence of x,y,z
@@ -2279,6 +6022,26 @@ impl TrailListTrail {
         }
         lua::pop(l, 1);
 
+        if lua::getfield(l, table, "colors") != lua::LuaType::LUA_TNIL {
+            let colors = lua::gettop(l);
+            let c = lua::L::len(l, colors);
+
+            if c != self.points.len() {
+                luaerror!(l, "colors must have the same number of items as points.");
+            } else {
+                self.colors.clear();
+
+                for i in 1..(c+1) {
+                    lua::geti(l, colors, i as i64);
+                    self.colors.push(ui::Color::from(lua::tointeger(l, -1)));
+                    lua::pop(l, 1);
+                }
+
+                update_vert_buffer = true;
+            }
+        }
+        lua::pop(l, 1);
+
         return update_vert_buffer;
     }
 }
@@ -2286,11 +6049,17 @@ impl TrailListTrail {
 const TRAILLIST_METATABLE_NAME: &str = "dx::lua::TrailList";
 
 const TRAILLIST_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
-    c"__gc"  , traillist_gc,
-    c"draw"  , traillist_draw,
-    c"add"   , traillist_add,
-    c"remove", traillist_remove,
-    c"clear" , traillist_clear,
+    c"__gc"          , traillist_gc,
+    c"draw"          , traillist_draw,
+    c"add"           , traillist_add,
+    c"update"        , traillist_update,
+    c"remove"        , traillist_remove,
+    c"clear"         , traillist_clear,
+    c"extents"       , traillist_extents,
+    c"mousehovertags", traillist_mouse_hover_tags,
+    c"frustumcull"   , traillist_frustum_cull,
+    c"beginupdate"   , traillist_begin_update,
+    c"endupdate"     , traillist_end_update,
 };
 
 unsafe fn checktraillist(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<TrailList>> {
@@ -2332,26 +6101,72 @@ unsafe extern "C" fn traillist_gc(l: &lua_State) -> i32 {
                 if trail.tags > 0 {
                     lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.tags);
                 }
+
+                if trail.userdata > 0 {
+                    lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.userdata);
+                }
             }
         }
     }
 
-    unsafe { ManuallyDrop::drop(&mut tl); }
+    unsafe { ManuallyDrop::drop(&mut tl); }
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: draw(value)
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_draw(l: &lua_State) -> i32 {
+    let tl = unsafe { checktraillist(l, 1) };
+    let val = lua::toboolean(l, 2);
+
+    tl.inner.lock().unwrap().draw = val;
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: beginupdate()
+
+        Begin a batch of changes to this traillist. The GPU buffer is not
+        rebuilt until the matching :lua:meth:`endupdate`.
+
+        See :lua:meth:`dxspritelist.beginupdate`.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_begin_update(l: &lua_State) -> i32 {
+    let tl = unsafe { checktraillist(l, 1) };
+
+    tl.inner.lock().unwrap().update_depth += 1;
 
     return 0;
 }
 
 /*** RST
-    .. lua:method:: draw(value)
+    .. lua:method:: endupdate()
+
+        End a batch of changes started with :lua:meth:`beginupdate`.
 
         .. versionhistory::
             :0.3.0: Added
 */
-unsafe extern "C" fn traillist_draw(l: &lua_State) -> i32 {
+unsafe extern "C" fn traillist_end_update(l: &lua_State) -> i32 {
     let tl = unsafe { checktraillist(l, 1) };
-    let val = lua::toboolean(l, 2);
 
-    tl.inner.lock().unwrap().draw = val;
+    let mut inner = tl.inner.lock().unwrap();
+
+    if inner.update_depth == 0 {
+        luawarn!(l, "traillist:endupdate called without beginupdate.");
+        return 0;
+    }
+
+    inner.update_depth -= 1;
 
     return 0;
 }
@@ -2363,23 +6178,62 @@ unsafe extern "C" fn traillist_draw(l: &lua_State) -> i32 {
 
         ``attributes`` must be a table with the following fields:
 
-        ======== ===============================================================
-        Field    Description
-        ======== ===============================================================
-        points   A sequence of sequences, trail points. ie. { {1,1,1}, {2,2,2} }
-        tags     A table of attributes that can be used other methods of this
-                 list to update or remove trails with matching tags.
-                 *Note:* the table is referenced directly, not copied.
-        fadenear A number that indicates how far away from the player a trail
-                 begins to fade to transparent.
-        fadefar  A number that indicates how far away from the player a trail
-                 will become completely transparent.
-        ======== ===============================================================
+        ============ ===========================================================
+        Field        Description
+        ============ ===========================================================
+        points       A sequence of sequences, trail points. ie. { {1,1,1}, {2,2,2} }
+        tags         A table of attributes that can be used other methods of this
+                     list to update or remove trails with matching tags.
+                     *Note:* the table is referenced directly, not copied.
+        fadenear     A number that indicates how far away from the player a trail
+                     begins to fade to transparent.
+        fadefar      A number that indicates how far away from the player a trail
+                     will become completely transparent.
+        fadecurve    How the trail fades between ``fadenear`` and ``fadefar``,
+                     ``'linear'``, ``'smoothstep'``, or ``'quadratic'``.
+                     Default: ``'linear'``.
+        userdata     Any Lua value to associate with this trail. This is not used
+                     for matching.
+        starttexture The name of a texture in the texture map to draw as a
+                     billboarded sprite at the first point of the trail.
+        endtexture   The name of a texture in the texture map to draw as a
+                     billboarded sprite at the last point of the trail, i.e. an
+                     arrowhead or finish marker.
+        capsize      The size of the start and end cap sprites. Default: 80
+        colors       A sequence of colors, one for each point. The trail color
+                     is interpolated between points, creating a gradient. The
+                     trail's ``color`` is still applied as a tint.
+        arrows       A boolean indicating if arrows pointing in the direction of
+                     the trail should be drawn along it. Default: ``false``
+        arrowspacing The distance between arrows, in map units. Default: 250
+        fadebyage    A boolean indicating if the trail should fade from the first
+                     point to the last, i.e. for a position history where older
+                     points fade out. This is applied in addition to distance
+                     fading. Default: ``false``
+        agealpha     A sequence of 2 numbers, the opacity at the first and last
+                     points when ``fadebyage`` is ``true``. Default: ``{0, 1}``
+        mousetest    A boolean value indicating if the mouse position will be
+                     checked against this trail. See :lua:meth:`mousehovertags`.
+                     Default: ``false``
+        ============ ===========================================================
+
+        The start and end caps use the same color and fade distances as the
+        trail and move with it when the trail's points are changed.
 
         :param string texturename: The name of a texture in the texture list
             this trail list references.
         :param table attributes: See above.
 
+        .. code-block:: lua
+            :caption: Example
+
+            trails:add('trail', {
+                points = points,
+                starttexture = 'start',
+                endtexture = 'arrow',
+                capsize = 60,
+            })
+
         .. versionhistory::
             :0.3.0: Added
 */
@@ -2409,28 +6263,7 @@ unsafe extern "C" fn traillist_add(l: &lua_State) -> i32 {
         }
     }
 
-    let mut ti: Option<usize> = None;
-    for t in 0..inner.texture_names.len() {
-        if texname == inner.texture_names[t] {
-            ti = Some(t);
-            break;
-        }
-    }
-
-    let mut t = TrailListTrail {
-        points: Vec::new(),
-
-        coord_count: 0,
-
-        fade_near: -1.0,
-        fade_far: -1.0,
-
-        color: crate::ui::Color::from(0xFFFFFFFFu32),
-
-        size: 40.0,
-        wall: false,
-        tags: -1,
-    };
+    let mut t = TrailListTrail::new();
 
     if lua::getfield(l, 3, "tags")!=lua::LuaType::LUA_TNIL {
         t.tags = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
@@ -2438,21 +6271,58 @@ unsafe extern "C" fn traillist_add(l: &lua_State) -> i32 {
         lua::pop(l, 1);
     }
 
-    t.update_from_lua_table(l, 3);
-
-    if let Some(i) = ti {
-        inner.trails[i].push(t);
+    if lua::getfield(l, 3, "userdata")!=lua::LuaType::LUA_TNIL {
+        t.userdata = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
     } else {
-        inner.texture_names.push(texname.clone());
-        inner.trails.push(Vec::new());
-        inner.trails.last_mut().unwrap().push(t);
+        lua::pop(l, 1);
     }
 
-    inner.update_vert_buffer = true;
+    t.update_from_lua_table(l, 3);
+
+    inner.push_trail(&texname, t);
 
     return 0;
 }
 
+/*** RST
+    .. lua:method:: update(tags, attributes)
+
+        Update the trails that have matching tags.
+
+        An empty tags table matches all trails. A trail must match all tag
+        values given, if a trail does not have a value for a tag it will not
+        match.
+
+        ``attributes`` can contain any of the fields accepted by :lua:meth:`add`.
+        Changing only ``color`` or the fade attributes is inexpensive, changing
+        ``points``, ``size`` or ``wall`` will cause the trail geometry to be
+        rebuilt. If the rebuilt trails have the same number of vertices as
+        before, only their part of the vertex buffer is uploaded, otherwise the
+        whole list is rebuilt.
+
+        Returns the number of trails updated.
+
+        :param table tags:
+        :param table attributes:
+        :rtype: integer
+
+        .. code-block:: lua
+            :caption: Example
+
+            -- dim the sections of the route that have been completed
+            trails:update({ section = 3 }, { color = 0xFFFFFF40 })
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_update(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+    let tl = unsafe { checktraillist(l, 1) };
+
+    return tl.inner.lock().unwrap().update_matching(l);
+}
+
 /*** RST
     .. lua:method:: remove(tags)
 
@@ -2482,11 +6352,381 @@ unsafe extern "C" fn traillist_clear(l: &lua_State) -> i32 {
             if trail.tags > 0 {
                 lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.tags);
             }
+
+            if trail.userdata > 0 {
+                lua::L::unref(l, lua::LUA_REGISTRYINDEX, trail.userdata);
+            }
         }
     }
 
     inner.texture_names.clear();
     inner.trails.clear();
 
+    inner.update_vert_buffer = true;
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: mousehovertags()
+
+        Returns a sequence of the tags tables of all trails with ``mousetest``
+        set that are currently under the mouse cursor.
+
+        A trail is under the cursor if the cursor is within ``size / 2`` of any
+        of its segments. Trails without tags are never returned.
+
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            trails:add('trail', { points = points, tags = { segment = 4 }, mousetest = true })
+
+            -- later
+            for _, tags in ipairs(trails:mousehovertags()) do
+                overlay.loginfo(string.format('Over segment %d', tags.segment))
+            end
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_mouse_hover_tags(l: &lua_State) -> i32 {
+    let tl = unsafe { checktraillist(l, 1) };
+
+    let inner = tl.inner.lock().unwrap();
+
+    lua::createtable(l, inner.mouse_hover_tags.len() as i32, 0);
+
+    let mut i = 1;
+    for tags in &inner.mouse_hover_tags {
+        if lua::rawgeti(l, lua::LUA_REGISTRYINDEX, *tags) == lua::LuaType::LUA_TTABLE {
+            // the trail may have been removed since the last frame
+            lua::seti(l, -2, i);
+            i += 1;
+        } else {
+            lua::pop(l, 1);
+        }
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: frustumcull(value)
+
+        Sets if trails that are entirely outside of the view should be skipped.
+
+        Each trail is tested using its bounding box, see :lua:meth:`extents`,
+        so long trails that wind across the map will rarely be skipped. The
+        number of trails skipped is shown in the performance HUD. This only
+        applies to world lists.
+
+        :param boolean value: Default: ``false``.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_frustum_cull(l: &lua_State) -> i32 {
+    let tl = unsafe { checktraillist(l, 1) };
+    let val = lua::toboolean(l, 2);
+
+    tl.inner.lock().unwrap().frustum_cull = val;
+
+    return 0;
+}
+
+fn push_vec3f(l: &lua_State, v: &lamath::Vec3F) {
+    lua::createtable(l, 3, 0);
+
+    lua::pushnumber(l, v.x as f64);
+    lua::seti(l, -2, 1);
+
+    lua::pushnumber(l, v.y as f64);
+    lua::seti(l, -2, 2);
+
+    lua::pushnumber(l, v.z as f64);
+    lua::seti(l, -2, 3);
+}
+
+/*** RST
+    .. lua:method:: extents(tags)
+
+        Returns the length and bounding box of trails with matching tags.
+
+        The return value is a sequence of tables, one for each matching trail,
+        with the following fields:
+
+        ====== =================================================================
+        Field  Description
+        ====== =================================================================
+        tags   The trail's tags table.
+        length The total length of the trail, in map units.
+        min    The minimum corner of the trail's bounding box, ``{x, y, z}``.
+        max    The maximum corner of the trail's bounding box, ``{x, y, z}``.
+        ====== =================================================================
+
+        These values are calculated the first time they are requested and then
+        cached until the trail's points change.
+
+        :param table tags:
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            for _, e in ipairs(tl:extents({ route = 'jp' })) do
+                local mid = {
+                    (e.min[1] + e.max[1]) / 2,
+                    (e.min[2] + e.max[2]) / 2,
+                    (e.min[3] + e.max[3]) / 2,
+                }
+                -- place a label near mid
+            end
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn traillist_extents(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    let tl = unsafe { checktraillist(l, 1) };
+
+    let mut inner = tl.inner.lock().unwrap();
+
+    lua::newtable(l);
+    let results = lua::gettop(l);
+
+    let mut i = 1;
+    for textrails in &mut inner.trails {
+        for trail in textrails {
+            if trail.tags < 0 { continue; }
+
+            lua::geti(l, lua::LUA_REGISTRYINDEX, trail.tags);
+            let trailtags = lua::gettop(l);
+
+            if !tags_match(l, trailtags, 2) {
+                lua::pop(l, 1);
+                continue;
+            }
+
+            let e = trail.extents();
+
+            lua::createtable(l, 0, 4);
+
+            lua::pushvalue(l, trailtags);
+            lua::setfield(l, -2, "tags");
+
+            lua::pushnumber(l, e.length as f64);
+            lua::setfield(l, -2, "length");
+
+            push_vec3f(l, &e.min);
+            lua::setfield(l, -2, "min");
+
+            push_vec3f(l, &e.max);
+            lua::setfield(l, -2, "max");
+
+            lua::seti(l, results, i);
+            i += 1;
+
+            lua::pop(l, 1); // trail tags
+        }
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:class:: dxmarker
+
+    A single sprite with an optional trail and mouse callbacks. See
+    :lua:func:`marker`.
+*/
+struct Marker {
+    // the marker's sprite is the only sprite in this list, these lists are
+    // drawn along with all others
+    sprites: Arc<SpriteList>,
+    trails: Option<Arc<TrailList>>,
+
+    inner: Mutex<MarkerInner>,
+}
+
+struct MarkerInner {
+    on_hover: i64,
+    on_click: i64,
+
+    hovered: bool,
+}
+
+const MARKER_METATABLE_NAME: &str = "dx::lua::Marker";
+
+const MARKER_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__gc"       , marker_gc,
+    c"setposition", marker_set_position,
+    c"setvisible" , marker_set_visible,
+    c"update"     , marker_update,
+    c"onhover"    , marker_on_hover,
+    c"onclick"    , marker_on_click,
+};
+
+unsafe fn checkmarker(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<Marker>> {
+    let ptr: *mut *const Marker = unsafe {
+        std::mem::transmute(lua::L::checkudata(l, ind, MARKER_METATABLE_NAME))
+    };
+
+    ManuallyDrop::new(unsafe { Arc::from_raw(*ptr) } )
+}
+
+unsafe extern "C" fn marker_gc(l: &lua_State) -> i32 {
+    let mut marker = unsafe { checkmarker(l, 1) };
+
+    if let Some(dx_lua) = get_dx_lua_upvalue(l) {
+        dx_lua.sprite_lists.lock().unwrap().retain(|sl| !Arc::ptr_eq(sl, &marker.sprites));
+
+        if let Some(t) = &marker.trails {
+            dx_lua.trail_lists.lock().unwrap().retain(|tl| !Arc::ptr_eq(tl, t));
+        }
+
+        dx_lua.markers.lock().unwrap().retain(|m| !Arc::ptr_eq(m, &*marker));
+    }
+
+    {
+        let inner = marker.inner.lock().unwrap();
+
+        if inner.on_hover >= 0 { lua::L::unref(l, lua::LUA_REGISTRYINDEX, inner.on_hover); }
+        if inner.on_click >= 0 { lua::L::unref(l, lua::LUA_REGISTRYINDEX, inner.on_click); }
+    }
+
+    unsafe { ManuallyDrop::drop(&mut marker); }
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: setposition(x, y, z)
+
+        Move the marker's sprite. The trail, if any, is not changed.
+
+        :param number x:
+        :param number y:
+        :param number z:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn marker_set_position(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+    lua::checkargnumber!(l, 4);
+
+    let marker = unsafe { checkmarker(l, 1) };
+
+    let mut sl_inner = marker.sprites.inner.lock().unwrap();
+
+    let sprite = &mut sl_inner.sprite_data[0][0];
+    sprite.x = lua::tonumber(l, 2) as f32;
+    sprite.y = lua::tonumber(l, 3) as f32;
+    sprite.z = lua::tonumber(l, 4) as f32;
+
+    sl_inner.mark_dirty(0, 0);
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: setvisible(visible)
+
+        Show or hide the marker and its trail. Hidden markers do not receive
+        hover or click callbacks.
+
+        :param boolean visible:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn marker_set_visible(l: &lua_State) -> i32 {
+    let marker = unsafe { checkmarker(l, 1) };
+    let visible = lua::toboolean(l, 2);
+
+    marker.sprites.inner.lock().unwrap().draw = visible;
+
+    if let Some(t) = &marker.trails {
+        t.inner.lock().unwrap().draw = visible;
+    }
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: update(attributes)
+
+        Update the marker's sprite attributes, see :lua:meth:`dxspritelist.add`.
+
+        :param table attributes:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn marker_update(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+
+    let marker = unsafe { checkmarker(l, 1) };
+
+    let mut sl_inner = marker.sprites.inner.lock().unwrap();
+
+    sl_inner.sprite_data[0][0].update_from_lua_table(l, 2);
+    sl_inner.mark_dirty(0, 0);
+
+    return 0;
+}
+
+/// Replaces the callback in `cb` with the function at index 2, or removes it
+/// if the value is `nil`.
+fn set_marker_callback(l: &lua_State, cb: &mut i64) {
+    if *cb >= 0 {
+        lua::L::unref(l, lua::LUA_REGISTRYINDEX, *cb);
+        *cb = -1;
+    }
+
+    if lua::luatype(l, 2) == lua::LuaType::LUA_TFUNCTION {
+        lua::pushvalue(l, 2);
+        *cb = module_permissions::ref_callback(l);
+    }
+}
+
+/*** RST
+    .. lua:method:: onhover(callback)
+
+        Set the function that is called when the mouse moves over or leaves
+        this marker. ``nil`` removes the callback.
+
+        :param function callback:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn marker_on_hover(l: &lua_State) -> i32 {
+    let marker = unsafe { checkmarker(l, 1) };
+
+    set_marker_callback(l, &mut marker.inner.lock().unwrap().on_hover);
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: onclick(callback)
+
+        Set the function that is called when this marker is left clicked.
+        ``nil`` removes the callback.
+
+        :param function callback:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn marker_on_click(l: &lua_State) -> i32 {
+    let marker = unsafe { checkmarker(l, 1) };
+
+    set_marker_callback(l, &mut marker.inner.lock().unwrap().on_click);
+
     return 0;
 }
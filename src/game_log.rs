@@ -0,0 +1,197 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! Chat and combat log monitoring
+//!
+//! GW2 doesn't write chat or combat logs itself, but other tools can. When
+//! `overlay.gameLog.chatPath` or `overlay.gameLog.combatPath` are set, the
+//! files are tailed on a dedicated thread and each new line is sent to Lua as a
+//! `chat-message` or `combat-event` event.
+//!
+//! Files are read from their end when first opened, so only lines written
+//! while the overlay is running are sent. Files that don't exist yet are
+//! checked again on each poll and a file that shrinks is assumed to have been
+//! truncated or rotated and is read again from the start.
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use std::io::{Read, Seek};
+
+#[allow(unused_imports)]
+use crate::logging::{info,debug,warn,error};
+
+use crate::lua_manager;
+
+static GAMELOG_THREAD: Mutex<Option<std::thread::JoinHandle<()>>> = Mutex::new(None);
+
+static GAMELOG_RUNNING: AtomicBool = AtomicBool::new(true);
+
+// the most that will be read from a single file in one poll, anything beyond
+// this is read on the next poll
+const MAX_READ: u64 = 64 * 1024;
+
+struct TailedFile {
+    path: String,
+    event: &'static str,
+
+    // the position of the next byte to read, None until the file has been
+    // opened for the first time
+    pos: Option<u64>,
+
+    // an incomplete line from the end of the last read
+    partial: Vec<u8>,
+
+    missing_logged: bool,
+}
+
+impl TailedFile {
+    fn new(path: String, event: &'static str) -> TailedFile {
+        return TailedFile {
+            path: path,
+            event: event,
+            pos: None,
+            partial: Vec::new(),
+            missing_logged: false,
+        };
+    }
+
+    fn poll(&mut self) {
+        let mut file = match std::fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(_) => {
+                if !self.missing_logged {
+                    debug!("{} not found, waiting for it to be created.", self.path);
+                    self.missing_logged = true;
+                }
+
+                // if the file is removed it will be read from the beginning
+                // once it is created again
+                if self.pos.is_some() {
+                    self.pos = Some(0);
+                    self.partial.clear();
+                }
+                return;
+            }
+        };
+
+        if self.missing_logged {
+            debug!("Monitoring {}", self.path);
+            self.missing_logged = false;
+        }
+
+        let len = match file.metadata() {
+            Ok(m) => m.len(),
+            Err(err) => {
+                warn!("Couldn't get size of {}: {}", self.path, err);
+                return;
+            }
+        };
+
+        let pos = match self.pos {
+            Some(p) if p <= len => p,
+            Some(_) => {
+                debug!("{} was truncated, reading from the beginning.", self.path);
+                self.partial.clear();
+                0
+            },
+            None => {
+                // start from the end, only new lines are sent
+                self.pos = Some(len);
+                return;
+            },
+        };
+
+        if pos == len { return; }
+
+        let to_read = (len - pos).min(MAX_READ);
+
+        if let Err(err) = file.seek(std::io::SeekFrom::Start(pos)) {
+            warn!("Couldn't seek in {}: {}", self.path, err);
+            return;
+        }
+
+        let mut data: Vec<u8> = Vec::with_capacity(to_read as usize);
+
+        if let Err(err) = file.take(to_read).read_to_end(&mut data) {
+            warn!("Couldn't read {}: {}", self.path, err);
+            return;
+        }
+
+        self.pos = Some(pos + data.len() as u64);
+
+        self.partial.extend_from_slice(&data);
+
+        let last_nl = match self.partial.iter().rposition(|b| *b == b'\n') {
+            Some(p) => p,
+            None    => return,
+        };
+
+        let rest = self.partial.split_off(last_nl + 1);
+        let lines = std::mem::replace(&mut self.partial, rest);
+
+        for line in lines.split(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\r');
+
+            if line.is_empty() { continue; }
+
+            lua_manager::queue_event(self.event, Some(Box::new(line.to_string())));
+        }
+    }
+}
+
+pub fn init() {
+    let settings = crate::overlay::settings();
+
+    let chat_path = settings.get_string("overlay.gameLog.chatPath").unwrap();
+    let combat_path = settings.get_string("overlay.gameLog.combatPath").unwrap();
+    let interval = settings.get_u64("overlay.gameLog.pollInterval").unwrap().max(10);
+
+    let mut files: Vec<TailedFile> = Vec::new();
+
+    if !chat_path.is_empty() { files.push(TailedFile::new(chat_path, "chat-message")); }
+    if !combat_path.is_empty() { files.push(TailedFile::new(combat_path, "combat-event")); }
+
+    if files.is_empty() {
+        debug!("No chat or combat logs configured.");
+        return;
+    }
+
+    let t = std::thread::Builder::new().name("EG-Overlay Game Log Thread".to_string()).spawn(move || {
+        gamelog_thread(files, std::time::Duration::from_millis(interval));
+    }).expect("Couldn't spawn game log thread.");
+
+    *GAMELOG_THREAD.lock().unwrap() = Some(t);
+}
+
+pub fn cleanup() {
+    // the thread is only started if a log is configured
+    let t = match GAMELOG_THREAD.lock().unwrap().take() {
+        Some(t) => t,
+        None    => return,
+    };
+
+    GAMELOG_RUNNING.store(false, Ordering::Relaxed);
+
+    t.thread().unpark();
+    t.join().unwrap();
+}
+
+fn gamelog_thread(mut files: Vec<TailedFile>, interval: std::time::Duration) {
+    debug!("Game log thread starting...");
+
+    for f in &files {
+        info!("Monitoring {} for {} events.", f.path, f.event);
+    }
+
+    while GAMELOG_RUNNING.load(Ordering::Relaxed) {
+        for f in files.iter_mut() {
+            f.poll();
+        }
+
+        std::thread::park_timeout(interval);
+    }
+
+    debug!("Game log thread ending.");
+}
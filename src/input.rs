@@ -13,15 +13,54 @@ use std::sync::Weak;
 
 use windows::Win32::Foundation;
 use windows::Win32::UI::WindowsAndMessaging;
+use windows::Win32::UI::Input;
 use windows::Win32::UI::Input::KeyboardAndMouse;
 use windows::Win32::Graphics::Gdi;
 
+/// How input is captured from the system.
+#[derive(PartialEq, Clone, Copy)]
+pub enum InputMethod {
+    /// Global low level (`WH_MOUSE_LL`/`WH_KEYBOARD_LL`) hooks.
+    ///
+    /// This allows the overlay to consume input so that it isn't sent to the
+    /// target window, i.e. clicks on the overlay UI.
+    Hooks,
+
+    /// Raw input registered on the overlay window.
+    ///
+    /// This is less invasive than global hooks, but input can not be consumed,
+    /// so the target window will still receive everything the overlay does.
+    RawInput,
+}
+
+impl InputMethod {
+    pub fn from_name(method: &str) -> InputMethod {
+        match method {
+            "hooks"    => InputMethod::Hooks,
+            "rawinput" => InputMethod::RawInput,
+            _ => {
+                error!("Unknown input method {}, using hooks.", method);
+                InputMethod::Hooks
+            }
+        }
+    }
+}
+
 /// Global input state.
 ///
 /// Currently just handles to the input hooks.
 pub struct InputManager {
+    method: InputMethod,
+
+    // the keyboard layout identifier detected at startup and the one that is
+    // actually being used, which may be overridden by the user
+    detected_layout: String,
+    layout: String,
+
     mouse: HHookWrapper,
     keyboard: HHookWrapper,
+
+    raw_input_registered: atomic::AtomicBool,
 }
 
 /// A wrapper to store handles to input hooks
@@ -37,6 +76,10 @@ struct MouseState {
     mouse_ldrag_target: bool,
     mouse_rdrag_target: bool,
 
+    // if the UI still receives move events during a left drag that started in
+    // the target, see set_capture_mouse_drag
+    capture_ldrag: bool,
+
     ui: Weak<crate::ui::Ui>,
 }
 
@@ -50,6 +93,8 @@ static MOUSE_STATE: Mutex<MouseState> = Mutex::new(MouseState {
     mouse_ldrag_target: false,
     mouse_rdrag_target: false,
 
+    capture_ldrag: false,
+
     ui: Weak::new(),
 });
 
@@ -57,6 +102,15 @@ static KEYBOARD_STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState {
     ui: Weak::new(),
 });
 
+/// Sets if the UI receives mouse move events while the left button is held
+/// after being pressed over the target (i.e. GW2).
+///
+/// The events are still passed on to the target, this only lets the UI see
+/// them.
+pub fn set_capture_mouse_drag(enabled: bool) {
+    MOUSE_STATE.lock().unwrap().capture_ldrag = enabled;
+}
+
 /// Stores a weak reference to UI for use by mouse and keyboard hooks.
 pub fn set_ui(ui: &Arc<crate::ui::Ui>) {
     MOUSE_STATE.lock().unwrap().ui = Arc::downgrade(&ui);
@@ -66,7 +120,18 @@ pub fn set_ui(ui: &Arc<crate::ui::Ui>) {
 impl InputManager {
 
     /// Initialize the Input Manager.
-    pub fn new() -> Arc<InputManager> {
+    ///
+    /// `method` determines how input is captured when [InputManager::install_hooks]
+    /// is called.
+    ///
+    /// `layout_override` is a keyboard layout identifier, i.e. `00000409`, that
+    /// will be reported instead of the detected layout. This lets users with a
+    /// layout that isn't supported yet acknowledge the mismatch and silence the
+    /// startup error.
+    ///
+    /// The override does not change how keys are translated. Key names always
+    /// follow the US layout, whatever layout is detected or overridden.
+    pub fn new(method: InputMethod, layout_override: Option<String>) -> Arc<InputManager> {
         let mut keyboard_layout = [0u8; 9];
 
         // for reference when I come back to implement different layouts:
@@ -76,22 +141,61 @@ impl InputManager {
                 .expect("Couldn't get keyboard layout nam");
         }
 
-        let kl_str = String::from_utf8_lossy(&keyboard_layout[0..8]);
+        let kl_str = String::from_utf8_lossy(&keyboard_layout[0..8]).into_owned();
 
-        if kl_str != "00000409" {
-            error!("Input assumes US keyboard layout (00000409), {} found. Input keys will not match", kl_str);
+        let layout = match layout_override {
+            Some(o) if o.len() > 0 => {
+                info!("Keyboard layout {} detected, using {} from overlay.keyboardLayout.", kl_str, o);
+                o.to_uppercase()
+            },
+            _ => kl_str.clone(),
+        };
+
+        if layout != "00000409" {
+            error!("Input assumes US keyboard layout (00000409), {} found. Input keys will not match. \
+                    Set overlay.keyboardLayout to 00000409 to acknowledge this and use the US layout anyway.", layout);
         } else {
-            debug!("Using keyboard layout {}", kl_str);
+            debug!("Using keyboard layout {}", layout);
         }
 
         Arc::new(InputManager {
+            method: method,
+
+            detected_layout: kl_str,
+            layout: layout,
+
             mouse: HHookWrapper::new(),
             keyboard: HHookWrapper::new(),
+
+            raw_input_registered: atomic::AtomicBool::new(false),
         })
     }
 
-    /// Install keyboard and mouse hooks
+    /// The keyboard layout identifier detected when the overlay started, i.e.
+    /// `00000409` for US English.
+    pub fn detected_layout(&self) -> &str {
+        &self.detected_layout
+    }
+
+    /// The keyboard layout identifier reported as being in use.
+    ///
+    /// This is the detected layout, unless it was overridden by the
+    /// `overlay.keyboardLayout` setting. Keys are always translated with the US
+    /// layout regardless of this value, see [InputManager::new].
+    pub fn layout(&self) -> &str {
+        &self.layout
+    }
+
+    /// Install keyboard and mouse hooks, or register for raw input, depending
+    /// on the input method.
     pub fn install_hooks(&self) {
+        if self.method == InputMethod::RawInput {
+            self.register_raw_input();
+            return;
+        }
+
+        if !self.mouse.is_empty() { return; }
+
         debug!("Installing input hooks...");
 
         let mouse = unsafe { WindowsAndMessaging::SetWindowsHookExA(
@@ -114,6 +218,11 @@ impl InputManager {
 
     /// Remove keyboard and mouse hooks
     pub fn remove_hooks(&self) {
+        if self.method == InputMethod::RawInput {
+            self.unregister_raw_input();
+            return;
+        }
+
         if !self.mouse.is_empty() {
             debug!("Removing input hooks...");
             unsafe { WindowsAndMessaging::UnhookWindowsHookEx(self.mouse.load()) }
@@ -124,6 +233,156 @@ impl InputManager {
             self.keyboard.clear();
         }
     }
+
+    fn raw_input_devices(flags: Input::RAWINPUTDEVICE_FLAGS, hwnd: Foundation::HWND) -> [Input::RAWINPUTDEVICE; 2] {
+        [
+            // generic desktop, mouse
+            Input::RAWINPUTDEVICE {
+                usUsagePage: 0x01,
+                usUsage: 0x02,
+                dwFlags: flags,
+                hwndTarget: hwnd,
+            },
+            // generic desktop, keyboard
+            Input::RAWINPUTDEVICE {
+                usUsagePage: 0x01,
+                usUsage: 0x06,
+                dwFlags: flags,
+                hwndTarget: hwnd,
+            },
+        ]
+    }
+
+    fn register_raw_input(&self) {
+        if self.raw_input_registered.load(atomic::Ordering::Relaxed) { return; }
+
+        debug!("Registering for raw input...");
+
+        // INPUTSINK so that input is received even though the overlay window
+        // is never the foreground window
+        let devices = Self::raw_input_devices(Input::RIDEV_INPUTSINK, crate::overlay::hwnd());
+
+        unsafe { Input::RegisterRawInputDevices(&devices, std::mem::size_of::<Input::RAWINPUTDEVICE>() as u32) }
+            .expect("Couldn't register raw input devices.");
+
+        self.raw_input_registered.store(true, atomic::Ordering::Relaxed);
+    }
+
+    fn unregister_raw_input(&self) {
+        if !self.raw_input_registered.load(atomic::Ordering::Relaxed) { return; }
+
+        debug!("Removing raw input registration...");
+
+        let devices = Self::raw_input_devices(Input::RIDEV_REMOVE, Foundation::HWND::default());
+
+        unsafe { Input::RegisterRawInputDevices(&devices, std::mem::size_of::<Input::RAWINPUTDEVICE>() as u32) }
+            .expect("Couldn't remove raw input devices.");
+
+        self.raw_input_registered.store(false, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Processes a `WM_INPUT` message sent to the overlay window.
+///
+/// Input received this way can't be consumed, so the results of the UI and Lua
+/// handlers are ignored.
+pub fn process_raw_input(lparam: Foundation::LPARAM) {
+    let mut raw = Input::RAWINPUT::default();
+    let mut size = std::mem::size_of::<Input::RAWINPUT>() as u32;
+
+    let r = unsafe { Input::GetRawInputData(
+        Input::HRAWINPUT(lparam.0 as *mut std::ffi::c_void),
+        Input::RID_INPUT,
+        Some(&mut raw as *mut _ as *mut std::ffi::c_void),
+        &mut size,
+        std::mem::size_of::<Input::RAWINPUTHEADER>() as u32
+    ) };
+
+    if r == u32::MAX {
+        error!("Couldn't get raw input data.");
+        return;
+    }
+
+    crate::overlay::request_redraw();
+
+    if raw.header.dwType == Input::RIM_TYPEKEYBOARD.0 {
+        let kb = unsafe { raw.data.keyboard };
+
+        let down = (kb.Flags as u32 & WindowsAndMessaging::RI_KEY_BREAK) == 0;
+
+        let mods = modifier_keys();
+
+        let event = KeyboardEvent::new(
+            KeyboardAndMouse::VIRTUAL_KEY(kb.VKey),
+            down,
+            mods.alt,
+            mods.shift,
+            mods.ctrl,
+            mods.caps_lock
+        );
+
+        let ui = KEYBOARD_STATE.lock().unwrap().ui.upgrade();
+
+        if let Some(ui) = ui {
+            if !ui.process_keyboard_event(&event) {
+                crate::lua_manager::process_keyboard_event(&event);
+            }
+        }
+    } else if raw.header.dwType == Input::RIM_TYPEMOUSE.0 {
+        let mouse = unsafe { raw.data.mouse };
+        let (flags, data) = unsafe { (
+            mouse.Anonymous.Anonymous.usButtonFlags as u32,
+            mouse.Anonymous.Anonymous.usButtonData,
+        ) };
+
+        let (x, y) = mouse_position();
+
+        let mut events: Vec<MouseEvent> = Vec::new();
+
+        if mouse.lLastX != 0 || mouse.lLastY != 0 {
+            events.push(MouseEvent::Move(MouseGenericEvent { x: x, y: y }));
+        }
+
+        let buttons = [
+            (WindowsAndMessaging::RI_MOUSE_LEFT_BUTTON_DOWN  , MouseButtonEventButton::Left  , true ),
+            (WindowsAndMessaging::RI_MOUSE_LEFT_BUTTON_UP    , MouseButtonEventButton::Left  , false),
+            (WindowsAndMessaging::RI_MOUSE_RIGHT_BUTTON_DOWN , MouseButtonEventButton::Right , true ),
+            (WindowsAndMessaging::RI_MOUSE_RIGHT_BUTTON_UP   , MouseButtonEventButton::Right , false),
+            (WindowsAndMessaging::RI_MOUSE_MIDDLE_BUTTON_DOWN, MouseButtonEventButton::Middle, true ),
+            (WindowsAndMessaging::RI_MOUSE_MIDDLE_BUTTON_UP  , MouseButtonEventButton::Middle, false),
+            (WindowsAndMessaging::RI_MOUSE_BUTTON_4_DOWN     , MouseButtonEventButton::X1    , true ),
+            (WindowsAndMessaging::RI_MOUSE_BUTTON_4_UP       , MouseButtonEventButton::X1    , false),
+            (WindowsAndMessaging::RI_MOUSE_BUTTON_5_DOWN     , MouseButtonEventButton::X2    , true ),
+            (WindowsAndMessaging::RI_MOUSE_BUTTON_5_UP       , MouseButtonEventButton::X2    , false),
+        ];
+
+        for (flag, button, down) in buttons {
+            if flags & flag != 0 {
+                events.push(MouseEvent::Button(MouseButtonEvent { x: x, y: y, button: button, down: down }));
+            }
+        }
+
+        if flags & (WindowsAndMessaging::RI_MOUSE_WHEEL | WindowsAndMessaging::RI_MOUSE_HWHEEL) != 0 {
+            events.push(MouseEvent::Wheel(MouseWheelEvent {
+                x: x,
+                y: y,
+                value: data as i16 as i32 / WindowsAndMessaging::WHEEL_DELTA as i32,
+                horizontal: flags & WindowsAndMessaging::RI_MOUSE_HWHEEL != 0,
+            }));
+        }
+
+        let ui = MOUSE_STATE.lock().unwrap().ui.upgrade();
+
+        if let Some(ui) = ui {
+            for event in &events {
+                if ui.process_mouse_event(event) { continue; }
+
+                if let Some(name) = event.keybind_name() {
+                    crate::lua_manager::process_keybind(&name);
+                }
+            }
+        }
+    }
 }
 
 impl HHookWrapper {
@@ -148,7 +407,41 @@ impl HHookWrapper {
     }
 }
 
-#[derive(PartialEq)]
+/// The state of the modifier keys, see [modifier_keys].
+#[derive(Clone, Copy)]
+pub struct ModifierKeys {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub caps_lock: bool,
+}
+
+/// Returns the current state of the modifier keys.
+pub fn modifier_keys() -> ModifierKeys {
+    // the overlay thread's key state isn't updated since the overlay never
+    // has focus, use the async state instead
+    ModifierKeys {
+        ctrl     : unsafe { KeyboardAndMouse::GetAsyncKeyState(KeyboardAndMouse::VK_CONTROL.0 as i32) as u16 & 0x8000 != 0 },
+        alt      : unsafe { KeyboardAndMouse::GetAsyncKeyState(KeyboardAndMouse::VK_MENU.0    as i32) as u16 & 0x8000 != 0 },
+        shift    : unsafe { KeyboardAndMouse::GetAsyncKeyState(KeyboardAndMouse::VK_SHIFT.0   as i32) as u16 & 0x8000 != 0 },
+        caps_lock: unsafe { KeyboardAndMouse::GetKeyState(KeyboardAndMouse::VK_CAPITAL.0 as i32) & 0x01 == 1 },
+    }
+}
+
+/// Returns the current position of the mouse cursor, relative to the top left
+/// of the overlay.
+pub fn mouse_position() -> (i64, i64) {
+    let mut p = Foundation::POINT::default();
+
+    unsafe {
+        let _ = WindowsAndMessaging::GetCursorPos(&mut p);
+        let _ = Gdi::ScreenToClient(crate::overlay::hwnd(), &mut p);
+    }
+
+    (p.x as i64, p.y as i64)
+}
+
+#[derive(PartialEq, Clone, Copy)]
 pub enum MouseButtonEventButton {
     Left,
     Right,
@@ -278,6 +571,40 @@ impl MouseEvent {
         }
     }
 
+    /// Returns the keybind name of this event, with modifiers, if it can be
+    /// bound, see `overlay.addkeybindhandler`.
+    ///
+    /// Only the X1 and X2 buttons being pressed and the wheel can be bound.
+    /// The left, right and middle buttons are excluded so that they can't be
+    /// taken away from the game.
+    pub fn keybind_name(&self) -> Option<String> {
+        let name = match self {
+            MouseEvent::Button(b) if b.down => match b.button {
+                MouseButtonEventButton::X1 => "mouse-x1",
+                MouseButtonEventButton::X2 => "mouse-x2",
+                _ => return None,
+            },
+            MouseEvent::Wheel(w) => match (w.horizontal, w.value > 0) {
+                _ if w.value == 0 => return None,
+                (false, true ) => "mouse-wheelup",
+                (false, false) => "mouse-wheeldown",
+                (true , true ) => "mouse-wheelright",
+                (true , false) => "mouse-wheelleft",
+            },
+            _ => return None,
+        };
+
+        let mods = modifier_keys();
+
+        Some(format!(
+            "{}{}{}{}",
+            if mods.ctrl  { "ctrl-"  } else { "" },
+            if mods.alt   { "alt-"   } else { "" },
+            if mods.shift { "shift-" } else { "" },
+            name,
+        ))
+    }
+
 
     pub fn from(msg: u32, msll: &WindowsAndMessaging::MSLLHOOKSTRUCT) -> MouseEvent {
         let mut p = Foundation::POINT {
@@ -367,6 +694,9 @@ unsafe extern "system" fn mouse_hook_proc(
         ) };
     }
 
+    // any input may change the UI, so end idle rendering
+    crate::overlay::request_redraw();
+
     let mut state = MOUSE_STATE.lock().unwrap();
 
     if wparam.0 as u32 == WindowsAndMessaging::WM_LBUTTONUP {
@@ -386,7 +716,23 @@ unsafe extern "system" fn mouse_hook_proc(
 
 
     if state.mouse_ldrag_target || state.mouse_rdrag_target {
-        drop(state);
+        if state.capture_ldrag &&
+           !state.mouse_rdrag_target &&
+           wparam.0 as u32 == WindowsAndMessaging::WM_MOUSEMOVE
+        {
+            let event = MouseEvent::from(
+                wparam.0 as u32,
+                unsafe { &*(lparam.0 as *const WindowsAndMessaging::MSLLHOOKSTRUCT) }
+            );
+
+            let ui = state.ui.upgrade().unwrap();
+            drop(state);
+
+            ui.process_mouse_event(&event);
+        } else {
+            drop(state);
+        }
+
         return unsafe { WindowsAndMessaging::CallNextHookEx(None, ncode, wparam, lparam) };
     }
 
@@ -428,6 +774,14 @@ unsafe extern "system" fn mouse_hook_proc(
     // for the state. drop it first here.
     drop(state);
 
+    // the UI didn't want this, extra buttons and the wheel can also be bound
+    // like keys
+    if let Some(name) = event.keybind_name() {
+        if crate::lua_manager::process_keybind(&name) {
+            return Foundation::LRESULT(1);
+        }
+    }
+
     return unsafe { WindowsAndMessaging::CallNextHookEx(
         None,
         ncode,
@@ -458,6 +812,17 @@ impl KeyboardEvent {
 
         let vk = KeyboardAndMouse::VIRTUAL_KEY(kbll.vkCode as u16);
 
+        KeyboardEvent::new(vk, down, alt, shift, ctrl, caps_on)
+    }
+
+    pub fn new(
+        vk: KeyboardAndMouse::VIRTUAL_KEY,
+        down: bool,
+        alt: bool,
+        shift: bool,
+        ctrl: bool,
+        caps_on: bool
+    ) -> KeyboardEvent {
         KeyboardEvent {
             vkey: vk,
             down: down,
@@ -521,6 +886,8 @@ unsafe extern "system" fn keyboard_hook_proc(
         ) };
     }
 
+    crate::overlay::request_redraw();
+
     let event = KeyboardEvent::from(unsafe { &*(lparam.0 as *const WindowsAndMessaging::KBDLLHOOKSTRUCT) });
 
     if KEYBOARD_STATE.lock().unwrap().ui.upgrade().unwrap().process_keyboard_event(&event) ||
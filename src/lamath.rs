@@ -4,6 +4,8 @@
 
 //! Linear algebra (matrix) related math stuff
 
+pub mod lua;
+
 /// A 4x4 matrix of 32-bit floats
 /// arranged in column major format
 ///
@@ -15,7 +17,7 @@
 ///
 /// i4j1  i4j2  i4j3  i4j4
 #[repr(C)]
-#[derive(Default)]
+#[derive(Default,Clone,Copy)]
 pub struct Mat4F {
     i1j1: f32,
     i2j1: f32,
@@ -395,7 +397,7 @@ impl std::ops::Mul<Vec4F> for Mat4F {
 }
 
 #[repr(C)]
-#[derive(Default,Clone,Copy)]
+#[derive(Default,Clone,Copy,PartialEq)]
 pub struct Vec3F {
     pub x: f32,
     pub y: f32,
@@ -464,6 +466,122 @@ impl std::ops::Add for Vec3F {
     }
 }
 
+/// Returns the point where the ray starting at `origin` and going in the
+/// direction `dir` crosses the plane that contains `plane_point` and is
+/// perpendicular to `plane_normal`.
+///
+/// Returns [None] if the ray is parallel to the plane or the plane is behind
+/// `origin`.
+pub fn ray_plane_intersect(origin: &Vec3F, dir: &Vec3F, plane_point: &Vec3F, plane_normal: &Vec3F) -> Option<Vec3F> {
+    let denom = plane_normal.dot(dir);
+
+    if denom.abs() < 1e-6 { return None; }
+
+    let t = plane_normal.dot(&(*plane_point - *origin)) / denom;
+
+    if t < 0.0 { return None; }
+
+    Some(*origin + dir.mulf(t))
+}
+
+/// Returns the point on the line segment from `a` to `b` that is closest to
+/// `p`.
+///
+/// If `a` and `b` are the same point, that point is returned.
+pub fn closest_point_on_segment(p: &Vec3F, a: &Vec3F, b: &Vec3F) -> Vec3F {
+    let ab = *b - *a;
+    let lensq = ab.dot(&ab);
+
+    if lensq == 0.0 { return *a; }
+
+    let t = ((*p - *a).dot(&ab) / lensq).clamp(0.0, 1.0);
+
+    *a + ab.mulf(t)
+}
+
+/// A plane, the points `p` where `normal.dot(p) + d` is 0.
+#[derive(Default,Clone,Copy)]
+pub struct Plane {
+    pub normal: Vec3F,
+    pub d: f32,
+}
+
+impl Plane {
+    /// The signed distance from the plane to `p`, positive on the side
+    /// `normal` points to.
+    pub fn distance(&self, p: &Vec3F) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+/// The planes of a view frustum, with normals pointing inward.
+#[derive(Default,Clone,Copy)]
+pub struct Frustum {
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a combined view and projection matrix,
+    /// i.e. `view * proj`.
+    ///
+    /// This is the method described by Gribb and Hartmann, for a D3D style
+    /// projection where the depth is 0 to 1. It works the same for reversed
+    /// depth since the near and far planes are both still included.
+    pub fn from_matrix(m: &Mat4F) -> Frustum {
+        // columns of m, since points are multiplied as row vectors
+        let c1 = [m.i1j1, m.i2j1, m.i3j1, m.i4j1];
+        let c2 = [m.i1j2, m.i2j2, m.i3j2, m.i4j2];
+        let c3 = [m.i1j3, m.i2j3, m.i3j3, m.i4j3];
+        let c4 = [m.i1j4, m.i2j4, m.i3j4, m.i4j4];
+
+        let plane = |a: [f32; 4], b: [f32; 4], sign: f32| {
+            let p = [a[0] + (b[0] * sign), a[1] + (b[1] * sign), a[2] + (b[2] * sign), a[3] + (b[3] * sign)];
+
+            let normal = Vec3F { x: p[0], y: p[1], z: p[2] };
+            let len = normal.length();
+
+            Plane { normal: normal.mulf(1.0 / len), d: p[3] / len }
+        };
+
+        Frustum {
+            planes: [
+                plane(c4, c1,  1.0), // left
+                plane(c4, c1, -1.0), // right
+                plane(c4, c2,  1.0), // bottom
+                plane(c4, c2, -1.0), // top
+                plane(c3, c3,  0.0), // near (or far, if depth is reversed)
+                plane(c4, c3, -1.0), // far (or near)
+            ],
+        }
+    }
+
+    /// Returns `true` if a sphere at `center` is at least partially inside
+    /// the frustum.
+    pub fn intersects_sphere(&self, center: &Vec3F, radius: f32) -> bool {
+        self.planes.iter().all(|p| p.distance(center) >= -radius)
+    }
+
+    /// Returns `true` if the axis aligned box from `min` to `max` is at least
+    /// partially inside the frustum.
+    ///
+    /// This can return `true` for some boxes that are just outside of the
+    /// corners of the frustum, but it never returns `false` for a box that is
+    /// inside.
+    pub fn intersects_aabb(&self, min: &Vec3F, max: &Vec3F) -> bool {
+        for p in &self.planes {
+            // the corner of the box furthest along the plane normal
+            let corner = Vec3F {
+                x: if p.normal.x >= 0.0 { max.x } else { min.x },
+                y: if p.normal.y >= 0.0 { max.y } else { min.y },
+                z: if p.normal.z >= 0.0 { max.z } else { min.z },
+            };
+
+            if p.distance(&corner) < 0.0 { return false; }
+        }
+
+        true
+    }
+}
 
 #[repr(C)]
 #[derive(Default,Clone,Copy)]
@@ -473,3 +591,57 @@ pub struct Vec4F {
     pub z: f32,
     pub w: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32, z: f32) -> Vec3F {
+        Vec3F { x: x, y: y, z: z }
+    }
+
+    #[test]
+    fn ray_plane_hit() {
+        let hit = ray_plane_intersect(&v(0.0, 10.0, 0.0), &v(0.0, -1.0, 0.0), &v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0));
+
+        assert!(hit == Some(v(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn ray_plane_parallel() {
+        let hit = ray_plane_intersect(&v(0.0, 10.0, 0.0), &v(1.0, 0.0, 0.0), &v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0));
+
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn ray_plane_parallel_in_plane() {
+        let hit = ray_plane_intersect(&v(0.0, 0.0, 0.0), &v(0.0, 0.0, 1.0), &v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0));
+
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn ray_plane_behind() {
+        let hit = ray_plane_intersect(&v(0.0, 10.0, 0.0), &v(0.0, 1.0, 0.0), &v(0.0, 0.0, 0.0), &v(0.0, 1.0, 0.0));
+
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn segment_closest_point() {
+        let a = v(0.0, 0.0, 0.0);
+        let b = v(10.0, 0.0, 0.0);
+
+        assert!(closest_point_on_segment(&v(5.0, 3.0, 0.0), &a, &b) == v(5.0, 0.0, 0.0));
+        assert!(closest_point_on_segment(&v(-5.0, 3.0, 0.0), &a, &b) == a);
+        assert!(closest_point_on_segment(&v(15.0, 3.0, 0.0), &a, &b) == b);
+    }
+
+    #[test]
+    fn segment_degenerate() {
+        let a = v(1.0, 2.0, 3.0);
+
+        assert!(closest_point_on_segment(&v(5.0, 5.0, 5.0), &a, &a) == a);
+    }
+}
@@ -0,0 +1,784 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+/*** RST
+lamath
+======
+
+.. lua:module:: lamath
+
+.. code:: lua
+
+    local lamath = require 'lamath'
+
+The :lua:mod:`lamath` module contains vector and matrix types for 3D math.
+
+These are implemented in Rust and are much faster than doing the same math in
+Lua, which is useful when positioning large numbers of markers or trails.
+
+Both :lua:class:`vec3` and :lua:class:`mat4` are values, none of their methods
+modify them. Instead a new value is returned.
+
+.. code-block:: lua
+    :caption: Example
+
+    local lamath = require 'lamath'
+    local ml = require 'mumble-link'
+
+    local avatar = lamath.vec3(ml.avatarposition())
+    local camera = lamath.vec3(ml.cameraposition())
+
+    -- a point 2 meters in front of the camera, towards the avatar
+    local dir = (avatar - camera):normalize()
+    local point = camera + dir * 2
+
+Functions
+---------
+*/
+use crate::lamath::{Mat4F, Vec3F, Vec4F};
+
+use crate::lua;
+use crate::lua::lua_State;
+use crate::lua::luaL_Reg;
+use crate::lua::luaL_Reg_list;
+
+const VEC3_METATABLE_NAME: &str = "lamath::Vec3F";
+const MAT4_METATABLE_NAME: &str = "lamath::Mat4F";
+
+const LAMATH_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"vec3"        , vec3,
+    c"identity"    , identity,
+    c"translate"   , translate,
+    c"rotatex"     , rotatex,
+    c"rotatey"     , rotatey,
+    c"rotatez"     , rotatez,
+    c"ortho"       , ortho,
+    c"perspective" , perspective,
+    c"camerafacing", camera_facing,
+
+    c"rayplaneintersect"    , ray_plane_intersect,
+    c"closestpointonsegment", closest_point_on_segment,
+};
+
+const VEC3_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__index"   , vec3_index,
+    c"__add"     , vec3_add,
+    c"__sub"     , vec3_sub,
+    c"__mul"     , vec3_mul,
+    c"__div"     , vec3_div,
+    c"__unm"     , vec3_unm,
+    c"__eq"      , vec3_eq,
+    c"__tostring", vec3_tostring,
+    c"dot"       , vec3_dot,
+    c"cross"     , vec3_cross,
+    c"length"    , vec3_length,
+    c"normalize" , vec3_normalize,
+    c"unpack"    , vec3_unpack,
+};
+
+const MAT4_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__mul"      , mat4_mul,
+    c"__tostring" , mat4_tostring,
+    c"inverse"    , mat4_inverse,
+    c"determinate", mat4_determinate,
+    c"transform"  , mat4_transform,
+};
+
+pub fn init() {
+    crate::lua_manager::add_module_opener("lamath", Some(open_module));
+}
+
+unsafe extern "C" fn open_module(l: &lua_State) -> i32 {
+    lua::newtable(l);
+    lua::L::setfuncs(l, LAMATH_FUNCS, 0);
+
+    return 1;
+}
+
+/// Pushes `v` onto the stack as a new Lua vec3.
+pub fn pushvec3(l: &lua_State, v: &Vec3F) {
+    let ptr: *mut Vec3F = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<Vec3F>(), 0))
+    };
+
+    unsafe { ptr.write(*v); }
+
+    if lua::L::newmetatable(l, VEC3_METATABLE_NAME) {
+        lua::L::setfuncs(l, VEC3_FUNCS, 0);
+    }
+    lua::setmetatable(l, -2);
+}
+
+/// Pushes `m` onto the stack as a new Lua mat4.
+pub fn pushmat4(l: &lua_State, m: &Mat4F) {
+    let ptr: *mut Mat4F = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<Mat4F>(), 0))
+    };
+
+    unsafe { ptr.write(*m); }
+
+    if lua::L::newmetatable(l, MAT4_METATABLE_NAME) {
+        lua::pushvalue(l, -1);
+        lua::setfield(l, -2, "__index");
+
+        lua::L::setfuncs(l, MAT4_FUNCS, 0);
+    }
+    lua::setmetatable(l, -2);
+}
+
+/// Returns `true` if the value at `ind` is userdata with the metatable `tname`.
+fn is_udata(l: &lua_State, ind: i32, tname: &str) -> bool {
+    if lua::luatype(l, ind) != lua::LuaType::LUA_TUSERDATA { return false; }
+
+    if !lua::getmetatable(l, ind) { return false; }
+
+    lua::getfield(l, lua::LUA_REGISTRYINDEX, tname);
+    let r = lua::compare(l, -1, -2, lua::LUA_OPEQ);
+    lua::pop(l, 2);
+
+    return r;
+}
+
+/// Returns the vec3 at `ind`, or [None] if it isn't one.
+pub fn tovec3(l: &lua_State, ind: i32) -> Option<Vec3F> {
+    if !is_udata(l, ind, VEC3_METATABLE_NAME) { return None; }
+
+    let ptr = unsafe { lua::touserdata(l, ind) } as *const Vec3F;
+
+    Some(unsafe { *ptr })
+}
+
+/// Returns the vec3 at `ind`, raising a Lua error if it isn't one.
+pub unsafe fn checkvec3(l: &lua_State, ind: i32) -> Vec3F {
+    let ptr: *const Vec3F = unsafe { std::mem::transmute(lua::L::checkudata(l, ind, VEC3_METATABLE_NAME)) };
+
+    unsafe { *ptr }
+}
+
+/// Returns the mat4 at `ind`, or [None] if it isn't one.
+fn tomat4(l: &lua_State, ind: i32) -> Option<Mat4F> {
+    if !is_udata(l, ind, MAT4_METATABLE_NAME) { return None; }
+
+    let ptr = unsafe { lua::touserdata(l, ind) } as *const Mat4F;
+
+    Some(unsafe { *ptr })
+}
+
+unsafe fn checkmat4(l: &lua_State, ind: i32) -> Mat4F {
+    let ptr: *const Mat4F = unsafe { std::mem::transmute(lua::L::checkudata(l, ind, MAT4_METATABLE_NAME)) };
+
+    unsafe { *ptr }
+}
+
+/// Raises a Lua error with `msg`.
+fn raise(l: &lua_State, msg: &str) -> i32 {
+    lua::pushstring(l, msg);
+
+    return unsafe { lua::error(l) };
+}
+
+/*** RST
+.. lua:function:: vec3(x, y, z)
+
+    Create a new :lua:class:`vec3`.
+
+    :param number x:
+    :param number y:
+    :param number z:
+    :rtype: vec3
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn vec3(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+
+    pushvec3(l, &Vec3F {
+        x: lua::tonumber(l, 1) as f32,
+        y: lua::tonumber(l, 2) as f32,
+        z: lua::tonumber(l, 3) as f32,
+    });
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: identity()
+
+    Create an identity :lua:class:`mat4`.
+
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn identity(l: &lua_State) -> i32 {
+    pushmat4(l, &Mat4F::identity());
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: translate(x, y, z)
+
+    Create a translation :lua:class:`mat4`.
+
+    :param number x:
+    :param number y:
+    :param number z:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn translate(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+
+    pushmat4(l, &Mat4F::translate(
+        lua::tonumber(l, 1) as f32,
+        lua::tonumber(l, 2) as f32,
+        lua::tonumber(l, 3) as f32,
+    ));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: rotatex(radians)
+
+    Create a :lua:class:`mat4` that rotates around the X axis.
+
+    :param number radians:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn rotatex(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+
+    pushmat4(l, &Mat4F::rotatex(lua::tonumber(l, 1) as f32));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: rotatey(radians)
+
+    Create a :lua:class:`mat4` that rotates around the Y axis.
+
+    :param number radians:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn rotatey(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+
+    pushmat4(l, &Mat4F::rotatey(lua::tonumber(l, 1) as f32));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: rotatez(radians)
+
+    Create a :lua:class:`mat4` that rotates around the Z axis.
+
+    :param number radians:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn rotatez(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+
+    pushmat4(l, &Mat4F::rotatez(lua::tonumber(l, 1) as f32));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: ortho(left, right, top, bottom, near, far)
+
+    Create an orthographic projection :lua:class:`mat4`.
+
+    :param number left:
+    :param number right:
+    :param number top:
+    :param number bottom:
+    :param number near:
+    :param number far:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn ortho(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+    lua::checkargnumber!(l, 4);
+    lua::checkargnumber!(l, 5);
+    lua::checkargnumber!(l, 6);
+
+    pushmat4(l, &Mat4F::ortho(
+        lua::tonumber(l, 1) as f32,
+        lua::tonumber(l, 2) as f32,
+        lua::tonumber(l, 3) as f32,
+        lua::tonumber(l, 4) as f32,
+        lua::tonumber(l, 5) as f32,
+        lua::tonumber(l, 6) as f32,
+    ));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: perspective(fovy, aspect, near, far)
+
+    Create a left handed perspective projection :lua:class:`mat4`, like the
+    one the overlay uses for the 3D scene.
+
+    :param number fovy: The vertical field of view, in radians.
+    :param number aspect: The aspect ratio, width / height.
+    :param number near:
+    :param number far:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn perspective(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
+    lua::checkargnumber!(l, 4);
+
+    pushmat4(l, &Mat4F::perspective_lh(
+        lua::tonumber(l, 1) as f32,
+        lua::tonumber(l, 2) as f32,
+        lua::tonumber(l, 3) as f32,
+        lua::tonumber(l, 4) as f32,
+    ));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: camerafacing(camera, forward, up)
+
+    Create a view :lua:class:`mat4` for a camera at ``camera`` looking towards
+    ``forward``.
+
+    :param vec3 camera:
+    :param vec3 forward:
+    :param vec3 up:
+    :rtype: mat4
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn camera_facing(l: &lua_State) -> i32 {
+    let camera  = unsafe { checkvec3(l, 1) };
+    let forward = unsafe { checkvec3(l, 2) };
+    let up      = unsafe { checkvec3(l, 3) };
+
+    pushmat4(l, &Mat4F::camera_facing(&camera, &forward, &up));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: rayplaneintersect(origin, direction, planepoint, planenormal)
+
+    Returns the point where a ray crosses a plane.
+
+    The plane contains ``planepoint`` and is perpendicular to ``planenormal``.
+
+    :param vec3 origin: The start of the ray.
+    :param vec3 direction: The direction of the ray.
+    :param vec3 planepoint:
+    :param vec3 planenormal:
+    :returns: The intersection, or ``nil`` if the ray is parallel to the plane
+        or the plane is behind ``origin``.
+    :rtype: vec3
+
+    .. code-block:: lua
+        :caption: Example
+
+        local lamath = require 'lamath'
+
+        -- where does a ray from the camera hit the ground at the avatar's feet?
+        local hit = lamath.rayplaneintersect(
+            camera,
+            lamath.vec3(ml.camerafront()),
+            avatar,
+            lamath.vec3(0, 1, 0)
+        )
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn ray_plane_intersect(l: &lua_State) -> i32 {
+    let origin = unsafe { checkvec3(l, 1) };
+    let dir    = unsafe { checkvec3(l, 2) };
+    let point  = unsafe { checkvec3(l, 3) };
+    let normal = unsafe { checkvec3(l, 4) };
+
+    match crate::lamath::ray_plane_intersect(&origin, &dir, &point, &normal) {
+        Some(p) => pushvec3(l, &p),
+        None    => lua::pushnil(l),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: closestpointonsegment(point, a, b)
+
+    Returns the point on the line segment from ``a`` to ``b`` that is closest
+    to ``point`` and the distance between them.
+
+    This can be used to find how far something is from a trail segment, for
+    example.
+
+    :param vec3 point:
+    :param vec3 a:
+    :param vec3 b:
+    :rtype: vec3, number
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn closest_point_on_segment(l: &lua_State) -> i32 {
+    let p = unsafe { checkvec3(l, 1) };
+    let a = unsafe { checkvec3(l, 2) };
+    let b = unsafe { checkvec3(l, 3) };
+
+    let c = crate::lamath::closest_point_on_segment(&p, &a, &b);
+
+    pushvec3(l, &c);
+    lua::pushnumber(l, (p - c).length() as f64);
+
+    return 2;
+}
+
+/*** RST
+Classes
+-------
+
+.. lua:class:: vec3
+
+    A 3 component vector.
+
+    The components can be read with the ``x``, ``y`` and ``z`` fields.
+
+    Vectors can be added to and subtracted from each other, negated, and
+    multiplied or divided by a number.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local a = lamath.vec3(1, 2, 3)
+        local b = lamath.vec3(4, 5, 6)
+
+        local c = (a + b) * 2 -- 10, 14, 18
+
+        overlay.loginfo(string.format('c.x = %f', c.x))
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_index(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    match lua::tostring(l, 2).as_deref() {
+        Some("x") => lua::pushnumber(l, v.x as f64),
+        Some("y") => lua::pushnumber(l, v.y as f64),
+        Some("z") => lua::pushnumber(l, v.z as f64),
+        Some(k) if !k.contains('\0') => {
+            // methods
+            lua::getmetatable(l, 1);
+            lua::getfield(l, -1, k);
+        },
+        _ => lua::pushnil(l),
+    }
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_add(l: &lua_State) -> i32 {
+    let a = unsafe { checkvec3(l, 1) };
+    let b = unsafe { checkvec3(l, 2) };
+
+    pushvec3(l, &(a + b));
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_sub(l: &lua_State) -> i32 {
+    let a = unsafe { checkvec3(l, 1) };
+    let b = unsafe { checkvec3(l, 2) };
+
+    pushvec3(l, &(a - b));
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_mul(l: &lua_State) -> i32 {
+    // either operand may be the number
+    let (v, n) = match (tovec3(l, 1), tovec3(l, 2)) {
+        (Some(v), None) if lua::luatype(l, 2) == lua::LuaType::LUA_TNUMBER => (v, lua::tonumber(l, 2)),
+        (None, Some(v)) if lua::luatype(l, 1) == lua::LuaType::LUA_TNUMBER => (v, lua::tonumber(l, 1)),
+        _ => return raise(l, "vec3 can only be multiplied by a number, see dot and cross."),
+    };
+
+    pushvec3(l, &v.mulf(n as f32));
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_div(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    if lua::luatype(l, 2) != lua::LuaType::LUA_TNUMBER {
+        return raise(l, "vec3 can only be divided by a number.");
+    }
+
+    pushvec3(l, &v.mulf(1.0 / lua::tonumber(l, 2) as f32));
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_unm(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    pushvec3(l, &v.mulf(-1.0));
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_eq(l: &lua_State) -> i32 {
+    let eq = match (tovec3(l, 1), tovec3(l, 2)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    };
+
+    lua::pushboolean(l, eq);
+
+    return 1;
+}
+
+unsafe extern "C" fn vec3_tostring(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    lua::pushstring(l, &format!("vec3({}, {}, {})", v.x, v.y, v.z));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: dot(other)
+
+        Returns the dot product of this vector and ``other``.
+
+        :param vec3 other:
+        :rtype: number
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_dot(l: &lua_State) -> i32 {
+    let a = unsafe { checkvec3(l, 1) };
+    let b = unsafe { checkvec3(l, 2) };
+
+    lua::pushnumber(l, a.dot(&b) as f64);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: cross(other)
+
+        Returns the cross product of this vector and ``other``.
+
+        :param vec3 other:
+        :rtype: vec3
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_cross(l: &lua_State) -> i32 {
+    let a = unsafe { checkvec3(l, 1) };
+    let b = unsafe { checkvec3(l, 2) };
+
+    pushvec3(l, &a.crossproduct(&b));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: length()
+
+        Returns the length of this vector.
+
+        :rtype: number
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_length(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    lua::pushnumber(l, v.length() as f64);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: normalize()
+
+        Returns a vector with the same direction as this one and a length of 1.
+
+        :rtype: vec3
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_normalize(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    pushvec3(l, &v.normalize());
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: unpack()
+
+        Returns the ``x``, ``y`` and ``z`` components of this vector.
+
+        :rtype: number, number, number
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vec3_unpack(l: &lua_State) -> i32 {
+    let v = unsafe { checkvec3(l, 1) };
+
+    lua::pushnumber(l, v.x as f64);
+    lua::pushnumber(l, v.y as f64);
+    lua::pushnumber(l, v.z as f64);
+
+    return 3;
+}
+
+/*** RST
+.. lua:class:: mat4
+
+    A 4x4 matrix.
+
+    Matrices can be multiplied together. Vectors are transformed as rows, so
+    ``a * b`` is a matrix that applies ``a`` and then ``b``.
+
+    .. code-block:: lua
+        :caption: Example
+
+        -- rotate a quarter turn around Y, then move 10 units up
+        local m = lamath.rotatey(math.pi / 2) * lamath.translate(0, 10, 0)
+
+        local p = m:transform(lamath.vec3(1, 0, 0))
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn mat4_mul(l: &lua_State) -> i32 {
+    let (a, b) = match (tomat4(l, 1), tomat4(l, 2)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return raise(l, "mat4 can only be multiplied by another mat4, see transform."),
+    };
+
+    pushmat4(l, &(a * b));
+
+    return 1;
+}
+
+unsafe extern "C" fn mat4_tostring(l: &lua_State) -> i32 {
+    let _ = unsafe { checkmat4(l, 1) };
+
+    lua::pushstring(l, "mat4");
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: inverse()
+
+        Returns the inverse of this matrix.
+
+        :rtype: mat4
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn mat4_inverse(l: &lua_State) -> i32 {
+    let m = unsafe { checkmat4(l, 1) };
+
+    pushmat4(l, &m.inverse());
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: determinate()
+
+        Returns the determinate of this matrix. A matrix with a determinate
+        of ``0`` has no inverse.
+
+        :rtype: number
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn mat4_determinate(l: &lua_State) -> i32 {
+    let m = unsafe { checkmat4(l, 1) };
+
+    lua::pushnumber(l, m.determinate() as f64);
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: transform(point)
+
+        Returns ``point`` transformed by this matrix.
+
+        The result is divided by ``w``, so this can also be used to project
+        points with a projection matrix.
+
+        :param vec3 point:
+        :rtype: vec3
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn mat4_transform(l: &lua_State) -> i32 {
+    let m = unsafe { checkmat4(l, 1) };
+    let p = unsafe { checkvec3(l, 2) };
+
+    let r = m * Vec4F { x: p.x, y: p.y, z: p.z, w: 1.0 };
+
+    let w = if r.w != 0.0 { r.w } else { 1.0 };
+
+    pushvec3(l, &Vec3F { x: r.x / w, y: r.y / w, z: r.z / w });
+
+    return 1;
+}
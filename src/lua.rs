@@ -439,6 +439,15 @@ pub fn call(state: &lua_State, nargs: i32, nresults: i32) {
     unsafe { api::lua_callk(state, nargs, nresults, 0 as *mut usize, None) };
 }
 
+/// Calls a function, like [call], but allows the called function to yield.
+///
+/// If the called function yields, `k` is called with `ctx` when the coroutine
+/// is resumed instead of returning to the caller. See the Lua manual section
+/// "Handling Yields in C".
+pub fn callk(state: &lua_State, nargs: i32, nresults: i32, ctx: lua_KContext, k: lua_KFunction) {
+    unsafe { api::lua_callk(state, nargs, nresults, ctx, k) };
+}
+
 /// Raises a Lua error, using the value on the top of the stack as the error object.
 ///
 /// This function does a long jump, and therefore never returns.
@@ -461,6 +470,23 @@ pub unsafe fn newuserdatauv(state: &lua_State, size: usize, nuvalue: i32) -> *mu
     unsafe { api::lua_newuserdatauv(state, size, nuvalue) }
 }
 
+/// Pops a value from the stack and sets it as the new `n`-th user value
+/// associated to the full userdata at the given index.
+///
+/// Returns `false` if the userdata does not have that value.
+pub fn setiuservalue(state: &lua_State, index: i32, n: i32) -> bool {
+    unsafe { api::lua_setiuservalue(state, index, n) != 0 }
+}
+
+/// Pushes onto the stack the `n`-th user value associated with the full
+/// userdata at the given index and returns the type of the pushed value.
+///
+/// If the userdata does not have that value, pushes nil and returns
+/// `LUA_TNONE`.
+pub fn getiuservalue(state: &lua_State, index: i32, n: i32) -> LuaType {
+    unsafe { api::lua_getiuservalue(state, index, n) }
+}
+
 /// Set a value's metatable.
 ///
 /// Pops a table or nil from the stack and sets that value as the new metatable
@@ -509,6 +535,46 @@ pub fn remove(state: &lua_State, index: i32) {
     pop(state, 1);
 }
 
+/// Moves the top element into the given valid index, shifting up the elements
+/// above this index to open space.
+pub fn insert(state: &lua_State, index: i32) {
+    unsafe { api::lua_rotate(state, index, 1); }
+}
+
+/// Moves the top element into the given valid index without shifting any
+/// element (therefore replacing the value at that given index), and then pops
+/// the top element.
+pub fn replace(state: &lua_State, index: i32) {
+    unsafe { api::lua_copy(state, -1, index); }
+    pop(state, 1);
+}
+
+/// Converts the acceptable index `index` into an equivalent absolute index
+/// (that is, one that does not depend on the stack size).
+pub fn absindex(state: &lua_State, index: i32) -> i32 {
+    unsafe { api::lua_absindex(state, index) }
+}
+
+/// Converts a value at the given index to a C function. That value must be a C
+/// function, otherwise returns [None].
+pub fn tocfunction(state: &lua_State, index: i32) -> lua_CFunction {
+    unsafe { api::lua_tocfunction(state, index) }
+}
+
+/// Gets information about the `n`-th upvalue of the closure at index
+/// `funcindex`. It pushes the upvalue's value onto the stack and returns its
+/// name, which is empty for C functions.
+///
+/// Returns [None] and pushes nothing when the index `n` is greater than the
+/// number of upvalues.
+pub fn getupvalue(state: &lua_State, funcindex: i32, n: i32) -> Option<String> {
+    let name = unsafe { api::lua_getupvalue(state, funcindex, n) };
+
+    if name.is_null() { return None; }
+
+    Some(unsafe { std::ffi::CStr::from_ptr(name).to_string_lossy().into_owned() })
+}
+
 /// Starts or continues iteration of a Lua table.
 ///
 /// Pops a key from the stack, and pushes a key–value pair from the table at
@@ -541,6 +607,28 @@ pub fn tonumber(state: &lua_State, index: i32) -> f64 {
     unsafe { api::lua_tonumberx(state, index, std::ptr::null_mut()) }
 }
 
+/// Converts the Lua value at the given index to a signed integer.
+///
+/// Returns [None] if the value is not an integer or a string convertible to an
+/// integer.
+pub fn tointegerx(state: &lua_State, index: i32) -> Option<i64> {
+    let mut isnum: i32 = 0;
+    let i = unsafe { api::lua_tointegerx(state, index, &mut isnum) };
+
+    if isnum == 0 { None } else { Some(i) }
+}
+
+/// Converts the Lua value at the given index to a number.
+///
+/// Returns [None] if the value is not a number or a string convertible to a
+/// number.
+pub fn tonumberx(state: &lua_State, index: i32) -> Option<f64> {
+    let mut isnum: i32 = 0;
+    let n = unsafe { api::lua_tonumberx(state, index, &mut isnum) };
+
+    if isnum == 0 { None } else { Some(n) }
+}
+
 /// Returns a full userdata value.
 ///
 /// If the value at the given index is a full userdata, returns its memory-block
@@ -597,6 +685,8 @@ mod api {
         pub fn lua_getinfo(state: &lua_State, what: *const c_char, ar: *mut lua_Debug) -> c_int;
 
         pub fn lua_newuserdatauv(state: &lua_State, size: usize, nuvalue: c_int) -> *mut std::ffi::c_void;
+        pub fn lua_setiuservalue(state: &lua_State, index: c_int, n: c_int) -> c_int;
+        pub fn lua_getiuservalue(state: &lua_State, index: c_int, n: c_int) -> LuaType;
 
         pub fn lua_close(state: &lua_State);
         pub fn lua_closethread(state: &lua_State, from: Option<&lua_State>) -> c_int;
@@ -609,6 +699,10 @@ mod api {
 
         pub fn lua_gettop(state: &lua_State) -> c_int;
         pub fn lua_rotate(state: &lua_State, index: c_int, n: c_int);
+        pub fn lua_copy(state: &lua_State, fromidx: c_int, toidx: c_int);
+        pub fn lua_absindex(state: &lua_State, index: c_int) -> c_int;
+        pub fn lua_tocfunction(state: &lua_State, index: c_int) -> lua_CFunction;
+        pub fn lua_getupvalue(state: &lua_State, funcindex: c_int, n: c_int) -> *const c_char;
 
         pub fn lua_next(state: &lua_State, index: i32) -> c_int;
 
@@ -47,34 +47,65 @@ fn pusharray(l: &lua_State, arr: &Vec<serde_json::Value>) {
     }
 }
 
+// tables nested deeper than this are assumed to be recursive
+const MAX_DEPTH: u32 = 128;
+
+/// Converts the Lua value at `ind` into a JSON value.
+///
+/// Values that can't be represented in JSON are logged and converted to
+/// `null`, see [try_tojson] for a stricter version.
 pub fn tojson(l: &lua_State, ind: i32) -> serde_json::Value {
+    return value_to_json(l, ind, false, 0).unwrap_or(serde_json::Value::Null);
+}
+
+/// Converts the Lua value at `ind` into a JSON value, returning an error if
+/// it or anything within it can't be represented in JSON.
+pub fn try_tojson(l: &lua_State, ind: i32) -> Result<serde_json::Value, String> {
+    return value_to_json(l, ind, true, 0);
+}
+
+/// Returns `err` if `strict`, otherwise logs it and returns `null`.
+fn unsupported(l: &lua_State, strict: bool, err: &str) -> Result<serde_json::Value, String> {
+    if strict { return Err(String::from(err)); }
+
+    luawarn!(l, "{}", err);
+
+    return Ok(serde_json::Value::Null);
+}
+
+fn value_to_json(l: &lua_State, ind: i32, strict: bool, depth: u32) -> Result<serde_json::Value, String> {
     match lua::luatype(l, ind) {
-        lua::LuaType::LUA_TNIL => serde_json::Value::Null,
-        lua::LuaType::LUA_TBOOLEAN => serde_json::Value::Bool(lua::toboolean(l, ind)),
+        lua::LuaType::LUA_TNIL => Ok(serde_json::Value::Null),
+        lua::LuaType::LUA_TBOOLEAN => Ok(serde_json::Value::Bool(lua::toboolean(l, ind))),
         lua::LuaType::LUA_TNUMBER => {
-            let n: serde_json::Number = if lua::isinteger(l, ind) {
+            let n = if lua::isinteger(l, ind) {
                 serde_json::Number::from_i128(lua::tointeger(l, ind) as i128)
-                    .expect("Couldn't convert Lua integer.")
             } else {
                 serde_json::Number::from_f64(lua::tonumber(l, ind))
-                    .expect("Couldn't convert Lua number.")
             };
-            return serde_json::Value::Number(n);
+
+            match n {
+                Some(n) => Ok(serde_json::Value::Number(n)),
+                None    => unsupported(l, strict, "NaN and infinite numbers are not supported for tojson."),
+            }
         },
-        lua::LuaType::LUA_TSTRING => serde_json::Value::String(String::from(lua::tostring(l, ind).unwrap())),
-        lua::LuaType::LUA_TTABLE  => table_to_json(l, ind),
-        lua::LuaType::LUA_TNONE |
-        lua::LuaType::LUA_TLIGHTUSERDATA |
-        lua::LuaType::LUA_TUSERDATA |
-        lua::LuaType::LUA_TFUNCTION |
-        lua::LuaType::LUA_TTHREAD => {
-            luawarn!(l, "Lua type not supported for tojson.");
-            return serde_json::Value::Null;
+        lua::LuaType::LUA_TSTRING => Ok(serde_json::Value::String(String::from(lua::tostring(l, ind).unwrap()))),
+        lua::LuaType::LUA_TTABLE  => {
+            if depth >= MAX_DEPTH {
+                return unsupported(l, strict, "Tables nested too deeply (recursive?) for tojson.");
+            }
+
+            table_to_json(l, ind, strict, depth + 1)
         },
+        lua::LuaType::LUA_TNONE => unsupported(l, strict, "No value given for tojson."),
+        lua::LuaType::LUA_TLIGHTUSERDATA |
+        lua::LuaType::LUA_TUSERDATA => unsupported(l, strict, "Lua userdata is not supported for tojson."),
+        lua::LuaType::LUA_TFUNCTION => unsupported(l, strict, "Lua functions are not supported for tojson."),
+        lua::LuaType::LUA_TTHREAD   => unsupported(l, strict, "Lua threads are not supported for tojson."),
     }
 }
 
-fn table_to_json(l: &lua_State, ind: i32) -> serde_json::Value {
+fn table_to_json(l: &lua_State, ind: i32, strict: bool, depth: u32) -> Result<serde_json::Value, String> {
     if table_is_valid_array(l, ind) {
         let mut arr: Vec<serde_json::Value> = Vec::new();
 
@@ -82,28 +113,44 @@ fn table_to_json(l: &lua_State, ind: i32) -> serde_json::Value {
 
         for i in 1..(len+1) {
             lua::geti(l, ind, i as i64);
-            arr.push(tojson(l, lua::gettop(l)));
+            let val = value_to_json(l, lua::gettop(l), strict, depth);
+            lua::pop(l, 1);
+
+            arr.push(val?);
         }
 
-        return serde_json::Value::Array(arr);
+        return Ok(serde_json::Value::Array(arr));
     } else {
         let mut obj: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
 
         lua::pushnil(l);
 
         while lua::next(l, ind) > 0 {
+            let key_type = lua::luatype(l, -2);
+
+            if strict && key_type != lua::LuaType::LUA_TSTRING && key_type != lua::LuaType::LUA_TNUMBER {
+                lua::pop(l, 2); // value and key
+                return Err(String::from("Table keys must be strings or numbers for tojson."));
+            }
+
             lua::pushvalue(l, -2); // copy the key
 
             let key = lua::tostring(l, -1).unwrap_or(String::new()); // this might convert it to a string
             lua::pop(l, 1); // pop the copy
 
-            let val = tojson(l, lua::gettop(l));
-
-            let _ = obj.insert(key, val);
+            let val = value_to_json(l, lua::gettop(l), strict, depth);
             lua::pop(l, 1); // value
+
+            match val {
+                Ok(v) => { let _ = obj.insert(key, v); },
+                Err(e) => {
+                    lua::pop(l, 1); // key
+                    return Err(e);
+                },
+            }
         }
 
-        return serde_json::Value::Object(obj);
+        return Ok(serde_json::Value::Object(obj));
     }
 }
 
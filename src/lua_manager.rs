@@ -13,7 +13,7 @@ use std::collections::VecDeque;
 
 use std::sync::{Arc, Mutex, atomic};
 
-use std::collections::{HashMap};
+use std::collections::{HashMap, HashSet, BinaryHeap};
 
 
 #[doc(hidden)]
@@ -32,25 +32,102 @@ struct LuaManager {
     events: VecDeque<LuaEvent>,
     targeted_events: VecDeque<TargetedLuaEvent>,
     event_handlers: HashMap<String, Vec<i64>>,
+
+    // event handlers that are removed after they are first called, see
+    // add_lua_event_handler_once
+    once_handlers: HashSet<i64>,
+
     keybind_handlers: HashMap<String, Vec<i64>>,
 
+    // keybind handlers that are registered but skipped, see set_lua_keybind_handler_enabled
+    disabled_keybinds: HashSet<i64>,
+
+    // Lua functions that load resources for a URI scheme, see load_resource
+    resource_loaders: HashMap<String, i64>,
+
     coroutines: VecDeque<LuaCoRoutineThread>,
 
+    // pending timers, ordered by due time. cancelled timers are removed from
+    // timer_callbacks only and skipped when they reach the front of the queue
+    timer_queue: BinaryHeap<ScheduledTimer>,
+    timer_callbacks: HashMap<u64, LuaTimer>,
+    next_timer_id: u64,
+
+    // execution time of event handlers, by module name
+    handler_stats: HashMap<String, HandlerStats>,
+
     unrefs: VecDeque<i64>,
 
     run_thread: Arc<atomic::AtomicBool>,
     thread: Option<std::thread::JoinHandle<()>>,
 }
 
-// keybind event channels
+// keybind event channels, events are sent by their keybind name
 struct KeybindState {
-    event_send: std::sync::mpsc::Sender<crate::input::KeyboardEvent>,
+    event_send: std::sync::mpsc::Sender<String>,
     return_recv: std::sync::mpsc::Receiver<bool>,
 }
 
 struct LuaCoRoutineThread {
     state: &'static lua::lua_State,
     thread_ref: i64,
+
+    // the module and event of the handler that started this coroutine, for
+    // handler timing
+    module: String,
+    event: String,
+}
+
+/// Execution time statistics for the event handlers of a single module.
+///
+/// Times are in milliseconds. Each time a handler is called or a coroutine it
+/// started is resumed counts as a call.
+#[derive(Clone, Default)]
+pub struct HandlerStats {
+    pub calls: u64,
+    pub total_time: f64,
+    pub max_time: f64,
+    pub over_budget: u64,
+}
+
+/// A timer that is waiting to fire.
+///
+/// These are ordered so that the timer that is due first is the greatest,
+/// making the [BinaryHeap] that holds them a min-heap by due time.
+struct ScheduledTimer {
+    due: f64,
+    id: u64,
+}
+
+impl PartialEq for ScheduledTimer {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for ScheduledTimer {}
+
+impl PartialOrd for ScheduledTimer {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledTimer {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // earliest due first, then the timer that was created first
+        other.due.total_cmp(&self.due).then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+/// The shortest interval a repeating timer can have, in seconds.
+pub const MIN_TIMER_INTERVAL: f64 = 0.001;
+
+struct LuaTimer {
+    callback: i64,
+
+    // seconds between each time a repeating timer fires, 0 for one-shot timers
+    interval: f64,
 }
 
 struct LuaEvent {
@@ -118,6 +195,8 @@ pub fn init() {
 
     lua::L::openlibs(l);
 
+    crate::module_permissions::guard_stdlib(l);
+
     // add our embedded module searcher
     match lua::getglobal(l, "package") {
         lua::LuaType::LUA_TTABLE => {},
@@ -145,8 +224,17 @@ pub fn init() {
         targeted_events: VecDeque::new(),
         event_handlers: HashMap::new(),
         keybind_handlers: HashMap::new(),
+        disabled_keybinds: HashSet::new(),
+        once_handlers: HashSet::new(),
+        resource_loaders: HashMap::new(),
         coroutines: VecDeque::new(),
 
+        timer_queue: BinaryHeap::new(),
+        timer_callbacks: HashMap::new(),
+        next_timer_id: 1,
+
+        handler_stats: HashMap::new(),
+
         unrefs: VecDeque::new(),
 
         run_thread: Arc::new(atomic::AtomicBool::new(false)),
@@ -189,6 +277,12 @@ pub fn cleanup() {
     *state = None;
 }
 
+/// Returns `true` if the Lua manager has been initialized and not yet cleaned
+/// up, i.e. events can be queued.
+pub fn is_running() -> bool {
+    LUA_MANAGER.lock().unwrap().is_some()
+}
+
 /// Adds a module opener function.
 ///
 /// `opener` will be called whenever a Lua module of `name` is attempted to be
@@ -242,6 +336,138 @@ pub fn run_file(path: &str) {
     lua::pop(l, 1);
 }
 
+/// A module listed in the `overlay.modules` setting.
+struct ModuleConfig {
+    name: String,
+
+    // modules with a lower priority are loaded first
+    priority: i64,
+
+    // modules that must be loaded before this one
+    load_after: Vec<String>,
+}
+
+/// Returns the order that the modules in `modules` should be loaded.
+///
+/// Each module is loaded after the modules in its `loadafter` list and modules
+/// are otherwise loaded in order of their `priority` and then name, so the
+/// order doesn't depend on the order they appear in the settings file.
+fn resolve_module_order(modules: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    let mut configs: Vec<ModuleConfig> = Vec::new();
+
+    for (name, val) in modules {
+        let priority = val.get("priority").and_then(|p| p.as_i64()).unwrap_or(0);
+
+        let mut load_after: Vec<String> = Vec::new();
+
+        if let Some(after) = val.get("loadafter").and_then(|a| a.as_array()) {
+            for a in after {
+                let a = match a.as_str() {
+                    Some(a) => a,
+                    None    => {
+                        warn!("overlay.modules: loadafter values for {} must be strings.", name);
+                        continue;
+                    }
+                };
+
+                // modules loaded elsewhere, i.e. autoload.lua, will already be
+                // loaded
+                if !modules.contains_key(a) {
+                    debug!("overlay.modules: {} loads after {}, which isn't listed, ignoring.", name, a);
+                    continue;
+                }
+
+                load_after.push(a.to_string());
+            }
+        }
+
+        configs.push(ModuleConfig {
+            name: name.clone(),
+            priority: priority,
+            load_after: load_after,
+        });
+    }
+
+    configs.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.name.cmp(&b.name)));
+
+    let mut order: Vec<String> = Vec::with_capacity(configs.len());
+
+    while configs.len() > 0 {
+        let next = configs.iter().position(|m| m.load_after.iter().all(|a| order.contains(a)));
+
+        let i = match next {
+            Some(i) => i,
+            None    => {
+                let names: Vec<&str> = configs.iter().map(|m| m.name.as_str()).collect();
+
+                warn!("overlay.modules: circular loadafter between {}, loading them by priority.", names.join(", "));
+
+                0
+            }
+        };
+
+        order.push(configs.remove(i).name);
+    }
+
+    return order;
+}
+
+/// Loads the modules listed in the `overlay.modules` setting with `require`,
+/// in the order given by [resolve_module_order].
+///
+/// Unlike [run_file], an error in a module is logged and the remaining modules
+/// are still loaded.
+fn load_configured_modules() {
+    let modules = crate::overlay::settings().get_object("overlay.modules").unwrap_or_default();
+
+    if modules.is_empty() { return; }
+
+    let order = resolve_module_order(&modules);
+
+    info!("Module load order: {}", order.join(", "));
+
+    for name in &order {
+        crate::module_permissions::load_module(name);
+
+        let state_lock = LUA_STATE.lock().unwrap();
+        let l = state_lock.unwrap();
+
+        let thread = lua::newthread(l).expect("Couldn't create Lua thread.");
+
+        lua::getglobal(thread, "require");
+        lua::pushstring(thread, name);
+
+        drop(state_lock);
+
+        let mut nres = 0i32;
+
+        let mut r: i32;
+        let mut nargs = 1;
+        loop {
+            r=lua::resume(thread, None, nargs, &mut nres);
+            nargs = 0;
+
+            if r!=lua::LUA_YIELD { break; }
+
+            while resume_coroutines() { }
+            run_event_queue();
+        }
+
+        if r!=lua::LUA_OK {
+            let err_msg = lua::tostring(thread, -1).unwrap_or_default();
+            lua::L::traceback(l, thread, Some(&err_msg), 0);
+            let traceback = lua::tostring(l, -1).unwrap();
+            error!("Couldn't load module {}: {}", name, traceback);
+            lua::pop(l, 1);
+        }
+
+        lua::closethread(thread, None);
+
+        // pop the thread
+        lua::pop(l, 1);
+    }
+}
+
 /// Something that can be pushed to Lua.
 ///
 /// This might be something simple like a primitive value or complex like full
@@ -264,11 +490,41 @@ pub fn add_lua_event_handler(event: &str, cbi: i64) {
     handlers.push(cbi);
 }
 
+/// Adds an event handler from Lua that is removed after it is called once.
+pub fn add_lua_event_handler_once(event: &str, cbi: i64) {
+    add_lua_event_handler(event, cbi);
+
+    LUA_MANAGER.lock().unwrap().as_mut().unwrap().once_handlers.insert(cbi);
+}
+
+/// Removes a handler added with [add_lua_event_handler_once] just before it is
+/// called.
+///
+/// Returns `false` if the handler was already called or removed, i.e. by an
+/// earlier event in the same batch, and should be skipped.
+fn claim_once_handler(event: &str, cbi: i64) -> bool {
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let lua = lock.as_mut().unwrap();
+
+    if !lua.once_handlers.remove(&cbi) { return false; }
+
+    if let Some(handlers) = lua.event_handlers.get_mut(event) {
+        handlers.retain(|h| *h != cbi);
+    }
+
+    // unrefs are released by cleanup_refs, after the handler has been called
+    lua.unrefs.push_back(cbi);
+
+    true
+}
+
 /// Removes a Lua event handler.
 pub fn remove_lua_event_handler(event: &str, cbi: i64) {
     let mut lock = LUA_MANAGER.lock().unwrap();
     let lua = lock.as_mut().unwrap();
 
+    lua.once_handlers.remove(&cbi);
+
     if !lua.event_handlers.contains_key(event) {
         warn!("{} has no event handlers.", event);
         return;
@@ -313,6 +569,271 @@ pub fn remove_lua_keybind_handler(keybind: &str, cbi: i64) {
             i += 1;
         }
     }
+
+    lua.disabled_keybinds.remove(&cbi);
+}
+
+/// Enables or disables the keybind handler `cbi` without removing it.
+///
+/// Disabled handlers are not called and don't consume key events. Returns
+/// `false` if `cbi` is not a registered keybind handler.
+pub fn set_lua_keybind_handler_enabled(cbi: i64, enabled: bool) -> bool {
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let lua = lock.as_mut().unwrap();
+
+    if !lua.keybind_handlers.values().any(|h| h.contains(&cbi)) { return false; }
+
+    if enabled {
+        lua.disabled_keybinds.remove(&cbi);
+    } else {
+        lua.disabled_keybinds.insert(cbi);
+    }
+
+    return true;
+}
+
+/// Returns the key name, handler ID and enabled state of every registered
+/// keybind handler, ordered by key name.
+pub fn lua_keybind_handlers() -> Vec<(String, i64, bool)> {
+    let lock = LUA_MANAGER.lock().unwrap();
+    let lua = lock.as_ref().unwrap();
+
+    let mut keybinds: Vec<(String, i64, bool)> = Vec::new();
+
+    for (key, handlers) in &lua.keybind_handlers {
+        for cbi in handlers {
+            keybinds.push((key.clone(), *cbi, !lua.disabled_keybinds.contains(cbi)));
+        }
+    }
+
+    keybinds.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    return keybinds;
+}
+
+/// Sets the Lua function that loads resources for URIs with `scheme`.
+///
+/// Returns the reference of the loader that was previously set for `scheme`,
+/// if any, which should be unreferenced by the caller.
+pub fn set_resource_loader(scheme: &str, cbi: i64) -> Option<i64> {
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let lua = lock.as_mut().unwrap();
+
+    lua.resource_loaders.insert(scheme.to_lowercase(), cbi)
+}
+
+/// Removes the resource loader for `scheme`.
+///
+/// Returns the reference of the removed loader, if any.
+pub fn remove_resource_loader(scheme: &str) -> Option<i64> {
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let lua = lock.as_mut().unwrap();
+
+    lua.resource_loaders.remove(&scheme.to_lowercase())
+}
+
+/// Returns the scheme of `uri`, i.e. `pack` for `pack://icons/foo.png`.
+///
+/// Only the first few bytes are checked, so this is cheap to call on
+/// arbitrary data, such as the contents of an image file.
+fn uri_scheme(uri: &[u8]) -> Option<String> {
+    let end = uri.iter().take(33).position(|c| *c == b':')?;
+
+    if end == 0 || !uri[end..].starts_with(b"://") || !uri[0].is_ascii_alphabetic() { return None; }
+
+    let scheme = &uri[..end];
+
+    if !scheme.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'+' || *c == b'-' || *c == b'.') {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(scheme).to_lowercase())
+}
+
+/// Loads the resource at `uri` using the loader registered for its scheme.
+///
+/// Returns [None] if `uri` isn't a URI or there is no loader registered for
+/// its scheme, in which case callers should treat it as raw data.
+///
+/// The loader is called on `l`, which must be the Lua thread.
+pub fn load_resource(l: &lua::lua_State, uri: &[u8]) -> Option<Result<Vec<u8>, String>> {
+    let scheme = uri_scheme(uri)?;
+
+    let cbi = *LUA_MANAGER.lock().unwrap().as_ref().unwrap().resource_loaders.get(&scheme)?;
+
+    let uri = String::from_utf8_lossy(uri).into_owned();
+
+    lua::rawgeti(l, lua::LUA_REGISTRYINDEX, cbi);
+    lua::pushstring(l, &uri);
+
+    if lua::pcall(l, 1, 2, 0).is_err() {
+        let errmsg = lua::tostring(l, -1).unwrap_or_default();
+        lua::pop(l, 1);
+
+        return Some(Err(format!("Error in resource loader for {}: {}", uri, errmsg)));
+    }
+
+    let r = if lua::luatype(l, -2) == lua::LuaType::LUA_TSTRING {
+        Ok(lua::tobytes::<u8>(l, -2).to_vec())
+    } else {
+        let errmsg = lua::tostring(l, -1).unwrap_or(String::from("loader returned nil"));
+
+        Err(format!("Couldn't load {}: {}", uri, errmsg))
+    };
+
+    lua::pop(l, 2);
+
+    return Some(r);
+}
+
+/// Adds a timer that will call the Lua function `cbi` after `delay` seconds.
+///
+/// If `interval` is greater than 0 the timer will repeat every `interval`
+/// seconds, but no more often than [MIN_TIMER_INTERVAL], until it is cancelled.
+/// Returns the ID of the new timer.
+pub fn add_timer(delay: f64, interval: f64, cbi: i64) -> u64 {
+    let due = crate::overlay::uptime().as_secs_f64() + delay.max(0.0);
+
+    let interval = if interval > 0.0 { interval.max(MIN_TIMER_INTERVAL) } else { 0.0 };
+
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let luaman = lock.as_mut().unwrap();
+
+    let id = luaman.next_timer_id;
+    luaman.next_timer_id += 1;
+
+    luaman.timer_callbacks.insert(id, LuaTimer { callback: cbi, interval: interval });
+    luaman.timer_queue.push(ScheduledTimer { due: due, id: id });
+
+    return id;
+}
+
+/// Cancels the timer `id`.
+///
+/// Returns the Lua reference to the timer's callback, which should be
+/// unreferenced by the caller, or [None] if there is no timer `id`.
+pub fn cancel_timer(id: u64) -> Option<i64> {
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let luaman = lock.as_mut().unwrap();
+
+    // the queue entry is skipped once it is due
+    return luaman.timer_callbacks.remove(&id).map(|t| t.callback);
+}
+
+/// Returns the number of seconds until the next timer is due, if there are any.
+fn next_timer_delay() -> Option<f64> {
+    let lock = LUA_MANAGER.lock().unwrap();
+    let luaman = lock.as_ref().unwrap();
+
+    let due = luaman.timer_queue.peek()?.due;
+
+    return Some((due - crate::overlay::uptime().as_secs_f64()).max(0.0));
+}
+
+/// Calls the callbacks of all timers that are due.
+///
+/// Each timer fires at most once per call, even if it is overdue by more than
+/// its interval. Repeating timers are then rescheduled on their original
+/// cadence, skipping any intervals that were missed.
+pub fn run_timers() {
+    let now = crate::overlay::uptime().as_secs_f64();
+
+    let mut due: Vec<(u64, i64, f64)> = Vec::new();
+
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let luaman = lock.as_mut().unwrap();
+
+    while luaman.timer_queue.peek().is_some_and(|t| t.due <= now) {
+        let timer = luaman.timer_queue.pop().unwrap();
+
+        let info = match luaman.timer_callbacks.get(&timer.id) {
+            Some(i) => i,
+            None    => continue, // cancelled
+        };
+
+        due.push((timer.id, info.callback, now - timer.due));
+
+        if info.interval > 0.0 {
+            let missed = ((now - timer.due) / info.interval).floor() + 1.0;
+            let mut next = timer.due + (missed * info.interval);
+
+            // rounding can put the next time at or before now, which would
+            // pop this timer again and never leave this loop
+            if next <= now { next = now + info.interval; }
+
+            luaman.timer_queue.push(ScheduledTimer { due: next, id: timer.id });
+        }
+    }
+
+    // one-shot timers are done, their callbacks are unreferenced after they run
+    let mut finished: Vec<i64> = Vec::new();
+    for (id, cbi, _) in &due {
+        if luaman.timer_callbacks.get(id).is_some_and(|t| t.interval == 0.0) {
+            luaman.timer_callbacks.remove(id);
+            finished.push(*cbi);
+        }
+    }
+
+    drop(lock);
+
+    if due.is_empty() { return; }
+
+    let budget = handler_budget();
+
+    let state_lock = LUA_STATE.lock().unwrap();
+    let lua = state_lock.unwrap();
+
+    for (id, cbi, late) in due {
+        let cothread = lua::newthread(lua).unwrap();
+
+        lua::rawgeti(cothread, lua::LUA_REGISTRYINDEX, cbi);
+        let module = handler_module(cothread);
+        let event_name = format!("timer {}", id);
+
+        // the timer ID and how late it fired, in seconds
+        lua::pushinteger(cothread, id as i64);
+        lua::pushnumber(cothread, late);
+
+        let start = std::time::Instant::now();
+
+        let mut nres = 0;
+        let status = lua::resume(cothread, None, 2, &mut nres);
+
+        record_handler_time(&module, &event_name, start, budget);
+
+        if status == lua::LUA_YIELD {
+            // the callback yielded, save the thread and resume it later
+            if nres > 0 { lua::pop(cothread, nres); }
+
+            let threadi = lua::L::ref_(lua, lua::LUA_REGISTRYINDEX);
+            LUA_MANAGER.lock().unwrap().as_mut().unwrap().coroutines.push_back(LuaCoRoutineThread {
+                state: cothread,
+                thread_ref: threadi,
+                module: module,
+                event: event_name,
+            });
+        } else if status == lua::LUA_OK {
+            if nres > 0 { lua::pop(cothread, nres); }
+
+            // pop the thread
+            lua::pop(lua, 1);
+            lua::closethread(cothread, None);
+        } else {
+            let errmsg = lua::tostring(cothread, -1).unwrap();
+            lua::L::traceback(lua, cothread, Some(&errmsg), 0);
+            let traceback = lua::tostring(lua, -1).unwrap();
+
+            error!("Error occured during lua timer callback ({}): {}", id, traceback);
+            lua::pop(lua, 1); // traceback
+            lua::pop(cothread, 1); // errmsg
+            lua::pop(lua, 1); // thread
+            lua::closethread(cothread, None);
+        }
+    }
+
+    for cbi in finished {
+        lua::L::unref(lua, lua::LUA_REGISTRYINDEX, cbi);
+    }
 }
 
 /// Adds an event to be sent to Lua event handlers
@@ -345,10 +866,23 @@ pub fn process_keyboard_event(keyevent: &crate::input::KeyboardEvent) -> bool {
 
     if !keyevent.down { return false; }
 
+    return process_keybind(&keyevent.full_name());
+}
+
+/// Calls the keybind handlers for `keyname` on the Lua thread and waits for
+/// them to finish.
+///
+/// Returns `true` if a handler consumed the input.
+pub fn process_keybind(keyname: &str) -> bool {
     let lock = LUA_MANAGER.lock().unwrap();
     let luaman = lock.as_ref().unwrap();
 
-    if !luaman.keybind_handlers.contains_key(&keyevent.full_name()) { return false; }
+    // only block on the Lua thread if there is a handler that will be called
+    let enabled = luaman.keybind_handlers.get(keyname).is_some_and(|handlers| {
+        handlers.iter().any(|cbi| !luaman.disabled_keybinds.contains(cbi))
+    });
+
+    if !enabled { return false; }
 
     drop(lock);
 
@@ -360,8 +894,8 @@ pub fn process_keyboard_event(keyevent: &crate::input::KeyboardEvent) -> bool {
 
     while let Ok(_) = state.return_recv.try_recv() { }
 
-    if let Err(_) = state.event_send.send(keyevent.clone()) {
-        error!("Couldn't send keyboard event for Lua keybind: {}", keyevent);
+    if let Err(_) = state.event_send.send(String::from(keyname)) {
+        error!("Couldn't send event for Lua keybind: {}", keyname);
         return false;
     }
 
@@ -369,7 +903,7 @@ pub fn process_keyboard_event(keyevent: &crate::input::KeyboardEvent) -> bool {
         Ok(r) => return r,
         Err(er) => match er {
             std::sync::mpsc::RecvTimeoutError::Timeout => {
-                error!("Timeout while processing keybind for {}", keyevent);
+                error!("Timeout while processing keybind for {}", keyname);
             },
             _ => {},
         },
@@ -378,21 +912,25 @@ pub fn process_keyboard_event(keyevent: &crate::input::KeyboardEvent) -> bool {
     false
 }
 
-fn process_keybinds(keyevent: &crate::input::KeyboardEvent) -> bool {
-    if !keyevent.down { return false; }
+fn process_keybinds(keyname: &str) -> bool {
+    let lock = LUA_MANAGER.lock().unwrap();
+    let luaman = lock.as_ref().unwrap();
 
-    let keybinds = LUA_MANAGER.lock().unwrap().as_ref().unwrap().keybind_handlers.clone();
+    let keybinds = luaman.keybind_handlers.clone();
+    let disabled = luaman.disabled_keybinds.clone();
 
-    let keyname = keyevent.full_name();
+    drop(lock);
 
-    if !keybinds.contains_key(&keyname) { return false; }
+    if !keybinds.contains_key(keyname) { return false; }
 
     let state_lock = LUA_STATE.lock().unwrap();
     let l = state_lock.unwrap();
 
-    for cb in keybinds.get(&keyname).unwrap() {
+    for cb in keybinds.get(keyname).unwrap() {
+        if disabled.contains(cb) { continue; }
+
         lua::rawgeti(l, lua::LUA_REGISTRYINDEX, *cb);
-        lua::pushstring(l, &keyname);
+        lua::pushstring(l, keyname);
 
         match lua::pcall(l, 1, 1, 0) {
             Ok(_) => {
@@ -402,7 +940,7 @@ fn process_keybinds(keyevent: &crate::input::KeyboardEvent) -> bool {
             },
             Err(_) => {
                 let errmsg = lua::tostring(l, -1).unwrap();
-                error!("Error during keybind callback for {}: {}", keyevent, errmsg);
+                error!("Error during keybind callback for {}: {}", keyname, errmsg);
                 lua::pop(l, 1);
             }
         }
@@ -428,6 +966,59 @@ pub fn cleanup_refs() {
     }
 }
 
+/// Returns the name of the module that defined the function on the top of the
+/// stack of `l`.
+fn handler_module(l: &lua::lua_State) -> String {
+    let mut dbg = lua::lua_Debug::default();
+
+    // getinfo pops the function, so push a copy
+    lua::pushvalue(l, -1);
+    if lua::getinfo(l, ">S", &mut dbg).is_err() || dbg.source.is_null() {
+        return String::from("<unknown>");
+    }
+
+    let src = unsafe { std::ffi::CStr::from_ptr(dbg.source).to_string_lossy() };
+
+    return crate::overlay::lua::module_name_from_source(&src);
+}
+
+/// Returns the time budget for a single event handler call, in milliseconds.
+fn handler_budget() -> f64 {
+    crate::overlay::settings().get_f64("overlay.eventHandlerBudget").unwrap()
+}
+
+/// Records the execution time of an event handler in `module`.
+///
+/// A warning is logged if the time exceeds `budget`, since a long running
+/// handler blocks the Lua thread, including keybinds.
+fn record_handler_time(module: &str, event: &str, start: std::time::Instant, budget: f64) {
+    let time = start.elapsed().as_secs_f64() * 1000.0;
+
+    let mut lock = LUA_MANAGER.lock().unwrap();
+    let stats = lock.as_mut().unwrap().handler_stats.entry(String::from(module)).or_default();
+
+    stats.calls += 1;
+    stats.total_time += time;
+    if time > stats.max_time { stats.max_time = time; }
+
+    if budget > 0.0 && time > budget {
+        stats.over_budget += 1;
+
+        drop(lock);
+
+        warn!("Event handler in {} for {} took {:.2}ms, budget is {:.2}ms.", module, event, time, budget);
+    }
+}
+
+/// Returns the event handler execution time statistics for each module.
+pub fn handler_stats() -> Vec<(String, HandlerStats)> {
+    let lock = LUA_MANAGER.lock().unwrap();
+
+    return lock.as_ref().unwrap().handler_stats.iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+}
+
 pub fn run_event_queue() {
     // queue up events for log messages
     while let Some(msg) = LOG_MESSAGES.lock().unwrap().pop_front() {
@@ -446,10 +1037,13 @@ pub fn run_event_queue() {
     // take a copy of the current handlers and run those. handlers may add or
     // remove handlers while they are called
     let handlers = luaman.event_handlers.clone();
+    let once_handlers = luaman.once_handlers.clone();
 
     // handlers may add event handlers, queue events, etc. so unlock the manager
     drop(lock);
 
+    let budget = handler_budget();
+
     // but now we lock the lua thread
     let state_lock = LUA_STATE.lock().unwrap();
     let lua = state_lock.unwrap();
@@ -457,10 +1051,14 @@ pub fn run_event_queue() {
         if !handlers.contains_key(&event.name) { continue; }
 
         for cbi in handlers.get(&event.name).unwrap() {
+            if once_handlers.contains(cbi) && !claim_once_handler(&event.name, *cbi) { continue; }
+
             let cothread = lua::newthread(lua).unwrap();
 
             // push the event handler function
             lua::rawgeti(cothread, lua::LUA_REGISTRYINDEX, *cbi);
+            let module = handler_module(cothread);
+
             // the event name, first parameter
             lua::pushstring(cothread, &event.name);
 
@@ -471,9 +1069,13 @@ pub fn run_event_queue() {
                 lua::pushnil(cothread);
             }
 
+            let start = std::time::Instant::now();
+
             let mut nres = 0;
             let status = lua::resume(cothread, None, 2, &mut nres);
 
+            record_handler_time(&module, &event.name, start, budget);
+
             if status == lua::LUA_YIELD {
                 // the event handler yielded, save the thread and resume it later
                 if nres > 0 { lua::pop(cothread, nres); }
@@ -483,6 +1085,8 @@ pub fn run_event_queue() {
                 LUA_MANAGER.lock().unwrap().as_mut().unwrap().coroutines.push_back(LuaCoRoutineThread {
                     state: cothread,
                     thread_ref: threadi,
+                    module: module,
+                    event: event.name.clone(),
                 });
             } else if status == lua::LUA_OK {
                 // the handler returned normally, close the thread
@@ -526,6 +1130,13 @@ pub fn run_event_queue() {
 
         }
 
+        let module = if lua::luatype(cothread, -1) == lua::LuaType::LUA_TFUNCTION {
+            handler_module(cothread)
+        } else {
+            String::from("<unknown>")
+        };
+        let event_name = format!("targeted event {}", event.target);
+
         // the event data, first parameter
         if let Some(data) = &event.data {
             data.push_to_lua(cothread);
@@ -533,9 +1144,13 @@ pub fn run_event_queue() {
             lua::pushnil(cothread);
         }
 
+        let start = std::time::Instant::now();
+
         let mut nres = 0;
         let status = lua::resume(cothread, None, 1, &mut nres);
 
+        record_handler_time(&module, &event_name, start, budget);
+
         if status == lua::LUA_YIELD {
             // the event handler yielded, save the thread and resume it later
             if nres > 0 { lua::pop(cothread, nres); }
@@ -544,6 +1159,8 @@ pub fn run_event_queue() {
             LUA_MANAGER.lock().unwrap().as_mut().unwrap().coroutines.push_back(LuaCoRoutineThread {
                 state: cothread,
                 thread_ref: threadi,
+                module: module,
+                event: event_name,
             });
         } else if status == lua::LUA_OK {
             // the handler returned normally, close the thread
@@ -575,13 +1192,19 @@ pub fn resume_coroutines() -> bool {
 
     drop(lock);
 
+    let budget = handler_budget();
+
     let state_lock = LUA_STATE.lock().unwrap();
     let lua = state_lock.unwrap();
 
     while let Some(co) = coroutines.pop_front() {
+        let start = std::time::Instant::now();
+
         let mut nres = 0;
         let status = lua::resume(co.state, None, 0, &mut nres);
 
+        record_handler_time(&co.module, &co.event, start, budget);
+
         if status == lua::LUA_YIELD {
             // coroutine yielded again, put it back into the list
             LUA_MANAGER.lock().unwrap().as_mut().unwrap().coroutines.push_back(co);
@@ -609,9 +1232,12 @@ pub fn resume_coroutines() -> bool {
 
 pub fn unref(ind: i64) {
     let mut lock = LUA_MANAGER.lock().unwrap();
-    let luaman = lock.as_mut().unwrap();
 
-    luaman.unrefs.push_back(ind);
+    // objects holding references can outlive the Lua state, i.e. the overlay
+    // settings store. the references were released when the state was closed
+    if let Some(luaman) = lock.as_mut() {
+        luaman.unrefs.push_back(ind);
+    }
 }
 
 impl ToLua for String {
@@ -620,6 +1246,12 @@ impl ToLua for String {
     }
 }
 
+impl ToLua for bool {
+    fn push_to_lua(&self, l: &lua::lua_State) {
+        lua::pushboolean(l, *self);
+    }
+}
+
 pub fn start_thread() {
     debug!("Starting Lua Thread...");
 
@@ -628,7 +1260,7 @@ pub fn start_thread() {
 
     luaman.run_thread.store(true, atomic::Ordering::Relaxed);
 
-    let (event_send, event_recv) = std::sync::mpsc::channel::<crate::input::KeyboardEvent>();
+    let (event_send, event_recv) = std::sync::mpsc::channel::<String>();
     let (ret_send, ret_recv) = std::sync::mpsc::channel::<bool>();
 
     let state = KeybindState {
@@ -662,7 +1294,7 @@ pub fn stop_thread() {
 
 fn lua_thread(
     run_thread: Arc<atomic::AtomicBool>,
-    keyboard_event_recv: std::sync::mpsc::Receiver<crate::input::KeyboardEvent>,
+    keybind_event_recv: std::sync::mpsc::Receiver<String>,
     keybind_return_send: std::sync::mpsc::Sender<bool>
 ) {
     debug!("Begin Lua thread.");
@@ -674,22 +1306,30 @@ fn lua_thread(
     info!("Running lua/autoload.lua...");
     run_file("lua/autoload.lua");
 
+    load_configured_modules();
+
     queue_event("startup", None);
     run_event_queue();
 
     let update_target = overlay.settings().get_f64("overlay.luaUpdateTarget").unwrap();
 
+    let ml = overlay.ml();
+
     debug!("Lua update target time: {}ms or ~{:.0} times per second.", update_target, 1000.0 / update_target);
 
     while run_thread.load(atomic::Ordering::Relaxed) {
         let lua_begin = overlay.uptime().as_secs_f64();
 
-        if let Ok(keyevent) = keyboard_event_recv.try_recv() {
-            keybind_return_send.send(process_keybinds(&keyevent)).unwrap();
+        if let Ok(keyname) = keybind_event_recv.try_recv() {
+            keybind_return_send.send(process_keybinds(&keyname)).unwrap();
         }
 
         cleanup_refs();
+        run_timers();
+        crate::settings::send_throttled_changes();
         resume_coroutines();
+        ml.check_map_change();
+        ml.check_mount_change();
         queue_event("update", None);
         run_event_queue();
 
@@ -717,11 +1357,22 @@ fn lua_thread(
             if !more_coroutines { break; }
         }
 
-        if sleep_time > 0.0 {
-            // sleep the rest of the time, except if a keybound keyboard event comes in
-            if let Ok(keyevent) = keyboard_event_recv.recv_timeout(std::time::Duration::from_secs_f64(sleep_time / 1000.0)) {
-                keybind_return_send.send(process_keybinds(&keyevent)).unwrap();
+        while sleep_time > 0.0 {
+            // sleep the rest of the time, except if a keybound keyboard event
+            // comes in or a timer is due before then
+            let timer_delay = next_timer_delay().map(|d| d * 1000.0).unwrap_or(sleep_time);
+            let wait = sleep_time.min(timer_delay);
+
+            if let Ok(keyname) = keybind_event_recv.recv_timeout(std::time::Duration::from_secs_f64(wait / 1000.0)) {
+                keybind_return_send.send(process_keybinds(&keyname)).unwrap();
+                break;
             }
+
+            run_timers();
+
+            lua_end = overlay.uptime().as_secs_f64();
+            lua_time = (lua_end - lua_begin) * 1000.0;
+            sleep_time = update_target - lua_time;
         }
     }
 
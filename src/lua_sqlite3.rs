@@ -50,13 +50,20 @@ fn get_stmt_err_msg(stmt: &api::sqlite3_stmt) -> String {
     A SQLite3 database connection.
 
     Database connections are created using :lua:func:`overlay.sqlite3open`.
+
+    The connection is closed when it is garbage collected or goes out of scope
+    as a to-be-closed variable.
 */
 const SQLITE3_METATABLE_NAME: &str = "SQLite3";
 
 const SQLITE3_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"__close", sqlite3_close,
+    c"__gc"   , sqlite3_close,
     c"prepare", sqlite3_prepare,
     c"execute", sqlite3_execute,
+
+    c"transaction", sqlite3_transaction,
+    c"busytimeout", sqlite3_busy_timeout,
 };
 
 pub unsafe extern "C" fn sqlite3_open(l: &lua_State) -> i32 {
@@ -97,7 +104,8 @@ unsafe fn checksqlite3<'a>(l: &'a lua_State, ind: i32) -> &'a api::sqlite3 {
     };
 
     if unsafe { *ptr }.is_null() {
-        luaerror!(l, "sqlite3 already finalized.");
+        lua::pushstring(l, "database connection has already been closed.");
+        unsafe { lua::error(l); }
     }
 
     unsafe { &(**ptr) }
@@ -126,14 +134,33 @@ unsafe extern "C" fn sqlite3_close(l: &lua_State) -> i32 {
 /*** RST
     .. lua:method:: prepare(sql)
 
-        Prepare the given ``sql`` statement. An error will be raised if an error
-        occurs during prepartion, otherwise this method returns a new
+        Prepare the given ``sql`` statement and return a new
         :lua:class:`sqlite3stmt`.
 
+        Prepared statements should be used instead of building SQL with string
+        concatenation whenever values come from outside of the module, and to
+        avoid preparing the same statement over and over in a loop. Values are
+        set with :lua:meth:`sqlite3stmt.bind`.
+
         .. note::
             If the supplied ``sql`` statement can not be prepared, an error is
             logged and ``nil`` is returned instead.
 
+        .. code-block:: lua
+            :caption: Example
+
+            local s = db:prepare('SELECT name FROM markers WHERE map = :map')
+
+            s:bind(':map', mapid)
+
+            local row = s:step()
+            while type(row) == 'table' do
+                print(row.name)
+                row = s:step()
+            end
+
+            s:finalize()
+
         :param string sql:
         :rtype: sqlite3stmt
 
@@ -151,8 +178,10 @@ unsafe extern "C" fn sqlite3_prepare(l: &lua_State) -> i32 {
     let r = unsafe { api::sqlite3_prepare_v2(db, sqlstr.as_ptr(), -1, &mut stmt, 0 as *mut *const i8) };
 
     if r==api::SQLITE_OK {
+        // the db is kept as a user value so that it isn't collected before
+        // the statement and so the statement can check if it has been closed
         let lua_stmt: *mut *const api::sqlite3_stmt = unsafe {
-            std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<*const api::sqlite3_stmt>(), 0))
+            std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<*const api::sqlite3_stmt>(), 1))
         };
 
         unsafe { *lua_stmt = stmt; }
@@ -165,6 +194,9 @@ unsafe extern "C" fn sqlite3_prepare(l: &lua_State) -> i32 {
         }
         lua::setmetatable(l, -2);
 
+        lua::pushvalue(l, 1);
+        lua::setiuservalue(l, -2, 1);
+
         return 1;
     }
     let err = get_db_err_msg(db);
@@ -219,25 +251,9 @@ unsafe extern "C" fn sqlite3_execute(l: &lua_State) -> i32 {
         for c in 0i32..colcount as i32 {
             let cname = unsafe { CStr::from_ptr(api::sqlite3_column_name(stmt, c)).to_string_lossy() };
 
-            match unsafe { api::sqlite3_column_type(stmt, c) } {
-                api::SQLITE_INTEGER => lua::pushinteger(l, unsafe { api::sqlite3_column_int64(stmt, c) }),
-                api::SQLITE_FLOAT => lua::pushnumber(l, unsafe { api::sqlite3_column_double(stmt, c) }),
-                api::SQLITE_TEXT => {
-                    let cstr = unsafe { CStr::from_ptr(api::sqlite3_column_text(stmt, c)) };
-                    lua::pushstring(l, &cstr.to_string_lossy());
-                },
-                api::SQLITE_BLOB => {
-                    let len = unsafe { api::sqlite3_column_bytes(stmt, c) };
-                    let bytes_ptr = unsafe { api::sqlite3_column_blob(stmt, c) as *const i8};
-                    let bytes = unsafe { std::slice::from_raw_parts(bytes_ptr, len as usize) };
-                    lua::pushbytes(l, bytes);
-                },
-                api::SQLITE_NULL => lua::pushnil(l),
-                _ => {
-                    luaerror!(l, "Invalid SQLite3 type.");
-                    lua::pop(l,1);
-                    return 0;
-                }
+            if !push_column(l, stmt, c) {
+                lua::pop(l,1);
+                return 0;
             }
 
             lua::setfield(l, -2, &cname);
@@ -255,8 +271,158 @@ unsafe extern "C" fn sqlite3_execute(l: &lua_State) -> i32 {
     return 0;
 }
 
+/// Prepares and steps `sql` until it is done, discarding any results.
+fn exec_sql(db: &api::sqlite3, sql: &str) -> Result<(), String> {
+    let sqlstr = CString::new(sql).unwrap();
+
+    let mut stmt: *const api::sqlite3_stmt = std::ptr::null();
+
+    if unsafe { api::sqlite3_prepare_v2(db, sqlstr.as_ptr(), -1, &mut stmt, 0 as *mut *const i8) } != api::SQLITE_OK {
+        return Err(get_db_err_msg(db));
+    }
+
+    let mut r = unsafe { api::sqlite3_step(stmt) };
+    while r == api::SQLITE_ROW {
+        r = unsafe { api::sqlite3_step(stmt) };
+    }
+
+    let result = if r == api::SQLITE_DONE { Ok(()) } else { Err(get_db_err_msg(db)) };
+
+    unsafe { api::sqlite3_finalize(stmt) };
+
+    return result;
+}
+
+/*** RST
+    .. lua:method:: transaction(func)
+
+        Calls ``func`` inside of a transaction.
+
+        The transaction is committed if ``func`` returns normally. If ``func``
+        raises an error, the transaction is rolled back and the error is raised
+        again.
+
+        Grouping many inserts or updates into a single transaction is much
+        faster than letting each statement commit by itself.
+
+        Transactions can not be nested. Calling this method while a transaction
+        is already in progress on this connection raises an error.
+
+        :param function func:
+        :returns: Any values returned by ``func``.
+
+        .. code-block:: lua
+            :caption: Example
+
+            local s = db:prepare('INSERT INTO markers (map, x, y, z) VALUES (?, ?, ?, ?)')
+
+            db:transaction(function()
+                for i, m in ipairs(markers) do
+                    s:reset()
+                    s:bind(1, m.map)
+                    s:bind(2, m.x)
+                    s:bind(3, m.y)
+                    s:bind(4, m.z)
+                    s:step()
+                end
+            end)
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn sqlite3_transaction(l: &lua_State) -> i32 {
+    let db = unsafe { checksqlite3(l, 1) };
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+
+    if unsafe { api::sqlite3_get_autocommit(db) } == 0 {
+        lua::pushstring(l, "a transaction is already in progress on this database connection.");
+        return unsafe { lua::error(l) };
+    }
+
+    if let Err(err) = exec_sql(db, "BEGIN") {
+        lua::pushstring(l, &format!("couldn't begin transaction: {}", err));
+        return unsafe { lua::error(l) };
+    }
+
+    if lua::gettop(l) > 2 { lua::pop(l, lua::gettop(l) - 2); }
+    lua::pushvalue(l, 2);
+
+    if lua::pcall(l, 0, lua::LUA_MULTRET, 0).is_err() {
+        let ptr: *mut *const api::sqlite3 = unsafe {
+            std::mem::transmute(lua::L::checkudata(l, 1, SQLITE3_METATABLE_NAME))
+        };
+
+        // func may have closed the connection, and the error may have already
+        // ended the transaction
+        if !unsafe { *ptr }.is_null() && unsafe { api::sqlite3_get_autocommit(*ptr) } == 0 {
+            let db = unsafe { &(**ptr) };
+
+            if let Err(err) = exec_sql(db, "ROLLBACK") {
+                luaerror!(l, "Couldn't roll back transaction: {}", err);
+            }
+        }
+
+        // re-raise the original error
+        return unsafe { lua::error(l) };
+    }
+
+    // func may have closed the connection
+    let db = unsafe { checksqlite3(l, 1) };
+
+    if let Err(err) = exec_sql(db, "COMMIT") {
+        if unsafe { api::sqlite3_get_autocommit(db) } == 0 {
+            let _ = exec_sql(db, "ROLLBACK");
+        }
+
+        lua::pushstring(l, &format!("couldn't commit transaction: {}", err));
+        return unsafe { lua::error(l) };
+    }
+
+    return lua::gettop(l) - 2;
+}
+
+/*** RST
+    .. lua:method:: busytimeout(ms)
+
+        Sets how long to wait for a lock on the database before giving up, in
+        milliseconds.
+
+        By default, any statement that needs a lock held by another connection,
+        such as one opened by another module, fails immediately as busy. With a
+        timeout set, SQLite will retry until the lock is available or the
+        timeout expires.
+
+        A value of ``0`` or less turns the timeout off.
+
+        :param integer ms:
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn sqlite3_busy_timeout(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
+    let db = unsafe { checksqlite3(l, 1) };
+    let ms = lua::tointeger(l, 2).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+
+    let r = unsafe { api::sqlite3_busy_timeout(db, ms) };
+
+    if r != api::SQLITE_OK {
+        luaerror!(l, "Couldn't set busy timeout: {}", get_db_err_msg(db));
+    }
+
+    return 0;
+}
+
 /*** RST
 .. lua:class:: sqlite3stmt
+
+    A prepared statement, created by :lua:meth:`sqlite3db.prepare`.
+
+    Statements are finalized when they are garbage collected, but should be
+    finalized with :lua:meth:`finalize` once they are no longer needed.
+
+    Using a statement after its database connection has been closed raises an
+    error.
 */
 
 const STMT_METATABLE_NAME: &str = "SQLite3Statement";
@@ -268,6 +434,7 @@ const STMT_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"reset"   , stmt_reset,
     c"bind"    , stmt_bind,
     c"step"    , stmt_step,
+    c"column"  , stmt_column,
 };
 
 unsafe fn checkstmt<'a>(l: &'a lua_State, ind: i32) -> &'a api::sqlite3_stmt {
@@ -276,7 +443,17 @@ unsafe fn checkstmt<'a>(l: &'a lua_State, ind: i32) -> &'a api::sqlite3_stmt {
     };
 
     if unsafe { *ptr }.is_null() {
-        luaerror!(l, "statement has already been finalized.");
+        lua::pushstring(l, "statement has already been finalized.");
+        unsafe { lua::error(l); }
+    }
+
+    lua::getiuservalue(l, ind, 1);
+    let db: *mut *const api::sqlite3 = unsafe { std::mem::transmute(lua::touserdata(l, -1)) };
+    lua::pop(l, 1);
+
+    if db.is_null() || unsafe { *db }.is_null() {
+        lua::pushstring(l, "statement's database connection has been closed.");
+        unsafe { lua::error(l); }
     }
 
     unsafe { &(**ptr) }
@@ -437,25 +614,9 @@ unsafe extern "C" fn stmt_step(l: &lua_State) -> i32 {
         for c in 0i32..colcount as i32 {
             let cname = unsafe { CStr::from_ptr(api::sqlite3_column_name(stmt, c)).to_string_lossy() };
 
-            match unsafe { api::sqlite3_column_type(stmt, c) } {
-                api::SQLITE_INTEGER => lua::pushinteger(l, unsafe { api::sqlite3_column_int64(stmt, c) }),
-                api::SQLITE_FLOAT => lua::pushnumber(l, unsafe { api::sqlite3_column_double(stmt, c) }),
-                api::SQLITE_TEXT => {
-                    let cstr = unsafe { CStr::from_ptr(api::sqlite3_column_text(stmt, c)) };
-                    lua::pushstring(l, &cstr.to_string_lossy());
-                },
-                api::SQLITE_BLOB => {
-                    let len = unsafe { api::sqlite3_column_bytes(stmt, c) };
-                    let bytes_ptr = unsafe { api::sqlite3_column_blob(stmt, c) as *const i8};
-                    let bytes = unsafe { std::slice::from_raw_parts(bytes_ptr, len as usize) };
-                    lua::pushbytes(l, bytes);
-                },
-                api::SQLITE_NULL => lua::pushnil(l),
-                _ => {
-                    luaerror!(l, "Invalid SQLite3 type.");
-                    lua::pop(l,1);
-                    return 0;
-                }
+            if !push_column(l, stmt, c) {
+                lua::pop(l,1);
+                return 0;
             }
 
             lua::setfield(l, -2, &cname);
@@ -470,6 +631,67 @@ unsafe extern "C" fn stmt_step(l: &lua_State) -> i32 {
     return 0;
 }
 
+/*** RST
+    .. lua:method:: column(index)
+
+        Returns the value of a column in the current result row.
+
+        This is only valid after :lua:meth:`step` has returned a row.
+
+        .. important::
+            Column numbers begin at ``1``.
+
+        :param integer index:
+        :rtype: any
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn stmt_column(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
+    let stmt = unsafe { checkstmt(l, 1) };
+    let c = lua::tointeger(l, 2);
+
+    let colcount = unsafe { api::sqlite3_data_count(stmt) } as i64;
+
+    if c < 1 || c > colcount {
+        luaerror!(l, "Invalid column number: {}", c);
+        return 0;
+    }
+
+    if !push_column(l, stmt, (c - 1) as i32) { return 0; }
+
+    return 1;
+}
+
+/// Pushes the value of column `c` of the current row of `stmt`.
+///
+/// Returns `false` and logs an error if the column type isn't known, in which
+/// case nothing is pushed.
+fn push_column(l: &lua_State, stmt: &api::sqlite3_stmt, c: i32) -> bool {
+    match unsafe { api::sqlite3_column_type(stmt, c) } {
+        api::SQLITE_INTEGER => lua::pushinteger(l, unsafe { api::sqlite3_column_int64(stmt, c) }),
+        api::SQLITE_FLOAT => lua::pushnumber(l, unsafe { api::sqlite3_column_double(stmt, c) }),
+        api::SQLITE_TEXT => {
+            let cstr = unsafe { CStr::from_ptr(api::sqlite3_column_text(stmt, c)) };
+            lua::pushstring(l, &cstr.to_string_lossy());
+        },
+        api::SQLITE_BLOB => {
+            let len = unsafe { api::sqlite3_column_bytes(stmt, c) };
+            let bytes_ptr = unsafe { api::sqlite3_column_blob(stmt, c) as *const i8};
+            let bytes = unsafe { std::slice::from_raw_parts(bytes_ptr, len as usize) };
+            lua::pushbytes(l, bytes);
+        },
+        api::SQLITE_NULL => lua::pushnil(l),
+        _ => {
+            luaerror!(l, "Invalid SQLite3 type.");
+            return false;
+        }
+    }
+
+    return true;
+}
+
 /*** RST
     .. lua:method:: reset()
 
@@ -597,6 +819,8 @@ mod api {
         pub fn sqlite3_close_v2(db: *const sqlite3) -> c_int;
         pub fn sqlite3_errstr(err: c_int) -> *const c_char;
         pub fn sqlite3_errmsg(db: *const sqlite3) -> *const c_char;
+        pub fn sqlite3_get_autocommit(db: *const sqlite3) -> c_int;
+        pub fn sqlite3_busy_timeout(db: *const sqlite3, ms: c_int) -> c_int;
 
         pub fn sqlite3_prepare_v2(
             db: *const sqlite3,
@@ -632,6 +856,7 @@ mod api {
         ) -> c_int;
 
         pub fn sqlite3_column_count(pStmt: *const sqlite3_stmt) -> c_int;
+        pub fn sqlite3_data_count(pStmt: *const sqlite3_stmt) -> c_int;
         pub fn sqlite3_column_bytes(pStmt: *const sqlite3_stmt, col: c_int) -> c_int;
         pub fn sqlite3_column_name(pStmt: *const sqlite3_stmt, n: c_int) -> *const c_char;
         pub fn sqlite3_column_type(pStmt: *const sqlite3_stmt, col: c_int) -> c_int;
@@ -23,6 +23,11 @@ mod ml;
 mod ft;
 mod web_request;
 mod zip;
+mod audio;
+mod speech;
+mod game_log;
+mod chat_code;
+mod module_permissions;
 
 mod version;
 mod githash;
@@ -77,6 +77,96 @@ pub struct MumbleLink {
     gw2_ml: &'static GW2MumbleLinkData,
 
     identity: Mutex<MLIdentityData>,
+
+    map_rects: Mutex<Option<MapRects>>,
+
+    // the map ID last sent in a map-changed event, see check_map_change
+    last_map_id: Mutex<Option<i64>>,
+
+    // the mount index last sent in a mount-changed event, see check_mount_change
+    last_mount: Mutex<u8>,
+}
+
+/// The data for a `map-changed` event.
+struct MapChange {
+    old: Option<i64>,
+    new: i64,
+}
+
+/// The data for a `mount-changed` event, [None] is not mounted.
+struct MountChange {
+    old: Option<&'static str>,
+    new: Option<&'static str>,
+}
+
+impl crate::lua_manager::ToLua for MountChange {
+    fn push_to_lua(&self, l: &crate::lua::lua_State) {
+        crate::lua::createtable(l, 0, 2);
+
+        match self.old {
+            Some(old) => crate::lua::pushstring(l, old),
+            None      => crate::lua::pushnil(l),
+        }
+        crate::lua::setfield(l, -2, "old");
+
+        match self.new {
+            Some(new) => crate::lua::pushstring(l, new),
+            None      => crate::lua::pushnil(l),
+        }
+        crate::lua::setfield(l, -2, "new");
+    }
+}
+
+impl crate::lua_manager::ToLua for MapChange {
+    fn push_to_lua(&self, l: &crate::lua::lua_State) {
+        crate::lua::createtable(l, 0, 2);
+
+        match self.old {
+            Some(old) => crate::lua::pushinteger(l, old),
+            None      => crate::lua::pushnil(l),
+        }
+        crate::lua::setfield(l, -2, "old");
+
+        crate::lua::pushinteger(l, self.new);
+        crate::lua::setfield(l, -2, "new");
+    }
+}
+
+/// The map and continent rectangles of the current map, as returned by the
+/// GW2 API `/v2/maps` endpoint.
+///
+/// Each rectangle is `[[x1, y1], [x2, y2]]`. Map coordinates are in inches with
+/// Y increasing to the north, continent coordinates are in pixels at the
+/// maximum zoom level with Y increasing to the south.
+#[derive(Clone, Copy, PartialEq)]
+pub struct MapRects {
+    pub map_rect: [[f64; 2]; 2],
+    pub continent_rect: [[f64; 2]; 2],
+}
+
+impl MapRects {
+    /// Converts map coordinates to continent coordinates.
+    pub fn map_to_continent(&self, x: f64, y: f64) -> (f64, f64) {
+        let [[mx1, my1], [mx2, my2]] = self.map_rect;
+        let [[cx1, cy1], [cx2, cy2]] = self.continent_rect;
+
+        // map Y is flipped compared to the continent
+        let cx = cx1 + (x - mx1) / (mx2 - mx1) * (cx2 - cx1);
+        let cy = cy1 + (my2 - y) / (my2 - my1) * (cy2 - cy1);
+
+        (cx, cy)
+    }
+
+    /// Converts continent coordinates to map coordinates.
+    pub fn continent_to_map(&self, x: f64, y: f64) -> (f64, f64) {
+        let [[mx1, my1], [mx2, my2]] = self.map_rect;
+        let [[cx1, cy1], [cx2, cy2]] = self.continent_rect;
+
+        let mx = mx1 + (x - cx1) / (cx2 - cx1) * (mx2 - mx1);
+        let my = my2 - (y - cy1) / (cy2 - cy1) * (my2 - my1);
+
+        (mx, my)
+    }
 }
 
 struct MLIdentityData {
@@ -84,6 +174,24 @@ struct MLIdentityData {
     json: serde_json::Value,
 }
 
+/// Returns the name of a mount index, [None] for 0 (not mounted).
+fn mount_name(index: u8) -> Option<&'static str> {
+    Some(match index {
+        0 => return None,
+        1 => "jackal",
+        2 => "griffon",
+        3 => "springer",
+        4 => "skimmer",
+        5 => "raptor",
+        6 => "roller-beetle",
+        7 => "warclaw",
+        8 => "skyscale",
+        9 => "skiff",
+        10 => "seige-turtle",
+        _ => "unknown",
+    })
+}
+
 fn wide_str_to_string(wide: &[u16]) -> String {
     let mut len = wide.len();
 
@@ -137,6 +245,11 @@ impl MumbleLink {
                 tick: 0,
                 json: serde_json::Value::Null,
             }),
+
+            map_rects: Mutex::new(None),
+
+            last_map_id: Mutex::new(None),
+            last_mount: Mutex::new(0),
         });
 
         lua::set_ml(Arc::downgrade(&ml));
@@ -180,6 +293,49 @@ impl MumbleLink {
         &self.gw2_ml.camera_top
     }
 
+    /// Queues a `map-changed` event if the identity map ID is different than
+    /// the last time this was called.
+    ///
+    /// The first valid map ID also sends an event, with no old ID, so modules
+    /// that start while a map is already loaded are notified as well.
+    pub fn check_map_change(&self) {
+        let map_id = match self.identity_map_id() {
+            Some(id) => id,
+            None     => return,
+        };
+
+        let mut last = self.last_map_id.lock().unwrap();
+
+        if *last == Some(map_id) { return; }
+
+        crate::lua_manager::queue_event("map-changed", Some(Box::new(MapChange { old: *last, new: map_id })));
+
+        *last = Some(map_id);
+    }
+
+    /// Queues a `mount-changed` event if the player has mounted, dismounted
+    /// or changed mounts since the last time this was called.
+    ///
+    /// If the player is already mounted the first time this is called an
+    /// event is sent with no old mount.
+    pub fn check_mount_change(&self) {
+        // MumbleLink hasn't been updated by the game yet
+        if self.gw2_ml.tick == 0 { return; }
+
+        let mount = self.context_mount();
+
+        let mut last = self.last_mount.lock().unwrap();
+
+        if *last == mount { return; }
+
+        crate::lua_manager::queue_event("mount-changed", Some(Box::new(MountChange {
+            old: mount_name(*last),
+            new: mount_name(mount),
+        })));
+
+        *last = mount;
+    }
+
     pub fn identity(&self) -> String {
         wide_str_to_string(&self.gw2_ml.identity)
     }
@@ -370,6 +526,16 @@ impl MumbleLink {
         self.gw2_ml.context.map_scale
     }
 
+    /// The current mount index, 0 if the player isn't mounted.
+    pub fn context_mount(&self) -> u8 {
+        self.gw2_ml.context.mount_index
+    }
+
+    /// The name of the current mount, or [None] if the player isn't mounted.
+    pub fn context_mount_name(&self) -> Option<&'static str> {
+        mount_name(self.context_mount())
+    }
+
     pub fn context_ui_state(&self) -> u32 {
         self.gw2_ml.context.ui_state
     }
@@ -393,6 +559,74 @@ impl MumbleLink {
     pub fn context_compass_rotation(&self) -> f32 {
         self.gw2_ml.context.compass_rotation
     }
+
+    /// Returns the scale of the game UI for the current interface size.
+    ///
+    /// Returns [None] if the identity isn't available yet.
+    pub fn ui_scale(&self) -> Option<f32> {
+        match self.identity_uisz()? {
+            0 => Some(0.9),  // small
+            2 => Some(1.11), // large
+            3 => Some(1.22), // larger
+            _ => Some(1.0),  // normal
+        }
+    }
+
+    /// Returns the position and size of the compass (minimap) on a screen of
+    /// `width` x `height` pixels as `(left, top, width, height)`.
+    ///
+    /// Returns [None] if the identity isn't available yet.
+    pub fn compass_rect(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let uisz = self.identity_uisz()?;
+
+        let compassw = self.context_compass_width() as u32;
+        let compassh = self.context_compass_height() as u32;
+
+        let left = width.saturating_sub(compassw);
+
+        if (self.context_ui_state() & UI_STATE_COMPASS_TOP_RIGHT) > 0 {
+            return Some((left, 0, compassw, compassh));
+        }
+
+        // the compass sits just above the bottom edge, how far depends on the
+        // interface size
+        let bottom_margin = match uisz {
+            0 => 33, // small
+            1 => 35, // normal
+            2 => 42, // large
+            3 => 45, // larger
+            _ => 35,
+        };
+
+        let top = height.saturating_sub(bottom_margin + compassh);
+
+        return Some((left, top, compassw, compassh));
+    }
+
+    /// Sets the map and continent rectangles used by [MumbleLink::map_to_continent]
+    /// and [MumbleLink::continent_to_map], or clears them if `rects` is [None].
+    pub fn set_map_rects(&self, rects: Option<MapRects>) {
+        *self.map_rects.lock().unwrap() = rects;
+    }
+
+    /// The map and continent rectangles set by [MumbleLink::set_map_rects].
+    pub fn map_rects(&self) -> Option<MapRects> {
+        *self.map_rects.lock().unwrap()
+    }
+
+    /// Converts map coordinates to continent coordinates.
+    ///
+    /// Returns [None] if the map rectangles have not been set.
+    pub fn map_to_continent(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        Some(self.map_rects()?.map_to_continent(x, y))
+    }
+
+    /// Converts continent coordinates to map coordinates.
+    ///
+    /// Returns [None] if the map rectangles have not been set.
+    pub fn continent_to_map(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        Some(self.map_rects()?.continent_to_map(x, y))
+    }
 }
 
 impl Drop for MumbleLink {
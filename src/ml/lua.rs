@@ -22,6 +22,7 @@ use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
 use crate::lua::luaL_Reg_list;
+use crate::overlay::lua::luaerror;
 
 use std::sync::{Arc,Weak,Mutex};
 use std::mem::ManuallyDrop;
@@ -48,6 +49,9 @@ const ML_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"cameraposition"        , camera_position,
     c"camerafront"           , camera_front,
     c"cameratop"             , camera_top,
+    c"setmaprects"           , set_map_rects,
+    c"maptocontinent"        , map_to_continent,
+    c"continenttomap"        , continent_to_map,
 };
 
 const ID_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
@@ -122,6 +126,29 @@ fn get_ml_upvalue(l: &lua_State) -> Arc<crate::ml::MumbleLink> {
     ml_weak.upgrade().unwrap()
 }
 
+/*** RST
+Coordinate Systems
+~~~~~~~~~~~~~~~~~~
+
+GW2 uses several coordinate systems:
+
+- MumbleLink positions, such as :lua:func:`avatarposition`, are in meters
+  relative to the center of the current map, with Y being elevation.
+- Map coordinates are in inches, with Y increasing to the north. These are
+  the same units as the ``map_rect`` field of the GW2 API ``/v2/maps``
+  endpoint. A MumbleLink X, Z position can be converted to map coordinates by
+  multiplying by ``39.3701``.
+- Continent coordinates are in pixels at the maximum zoom level of the world
+  map, with Y increasing to the south. These are used for the world map
+  tiles and for most positions returned by the GW2 API.
+
+Converting between map and continent coordinates requires the ``map_rect``
+and ``continent_rect`` of the current map, which the overlay does not fetch
+itself. A module must set these with :lua:func:`setmaprects` before
+:lua:func:`maptocontinent` or :lua:func:`continenttomap` will work.
+
+*/
+
 /*** RST
 .. lua:function:: version()
 
@@ -156,22 +183,59 @@ unsafe extern "C" fn tick(l: &lua_State) -> i32 {
     return 1;
 }
 
+// inches per meter, MumbleLink positions are in meters but the map
+// coordinates used by the GW2 API and dx sprite and trail lists are in inches
+const INCHES_PER_METER: f64 = 39.3701;
+
+// pushes the x, y, z of a position, converted to the units given in the
+// optional argument at units_arg
+fn push_position(l: &lua_State, pos: &crate::lamath::Vec3F, units_arg: i32) -> i32 {
+    let scale = if lua::gettop(l) >= units_arg && lua::luatype(l, units_arg) != lua::LuaType::LUA_TNIL {
+        match lua::tostring(l, units_arg).as_deref() {
+            Some("meters") => 1.0,
+            Some("inches") => INCHES_PER_METER,
+            _ => {
+                luaerror!(l, "units must be 'meters' or 'inches'.");
+                return 0;
+            }
+        }
+    } else {
+        1.0
+    };
+
+    lua::pushnumber(l, pos.x as f64 * scale);
+    lua::pushnumber(l, pos.y as f64 * scale);
+    lua::pushnumber(l, pos.z as f64 * scale);
+
+    return 3;
+}
+
 /*** RST
-.. lua:function:: avatarposition()
+.. lua:function:: avatarposition([units])
 
     The player's current position in the game world in GW2.
 
     .. note::
 
-        This is the position in map units using meters and is represented in a
-        rendering fashion. X is east/west, Y is elevation (up/down), and Z is
+        This is the position in map units and is represented in a rendering
+        fashion. X is east/west, Y is elevation (up/down), and Z is
         north/south.
 
+    ``units`` can be ``'meters'``, the units MumbleLink uses, or
+    ``'inches'``, which matches map coordinates from the GW2 API and the
+    positions used by world :lua:class:`dxspritelist` and
+    :lua:class:`dxtraillist` objects.
+
     .. code-block:: lua
         :caption: Example
 
         x, y, z = ml.avatarposition()
 
+        -- place a sprite at the player's feet
+        local sx, sy, sz = ml.avatarposition('inches')
+
+    :param string units: (Optional) ``'meters'`` or ``'inches'``. Default:
+        ``'meters'``.
     :returns: 3 numbers.
 
     .. versionhistory::
@@ -180,13 +244,7 @@ unsafe extern "C" fn tick(l: &lua_State) -> i32 {
 unsafe extern "C" fn avatar_position(l: &lua_State) -> i32 {
     let ml = get_ml_upvalue(l);
 
-    let ap = ml.avatar_position();
-
-    lua::pushnumber(l, ap.x as f64);
-    lua::pushnumber(l, ap.y as f64);
-    lua::pushnumber(l, ap.z as f64);
-
-    return 3;
+    return push_position(l, ml.avatar_position(), 1);
 }
 
 /*** RST
@@ -257,21 +315,19 @@ unsafe extern "C" fn name(l: &lua_State) -> i32 {
 }
 
 /*** RST
-.. lua:function:: cameraposition()
+.. lua:function:: cameraposition([units])
 
     The current camera position in the game world in GW2.
 
-    .. note::
-
-        This is the position in map units using meters and is represented in a
-        rendering fashion. X is east/west, Y is elevation (up/down), and Z is
-        north/south.
+    See :lua:func:`avatarposition` for the coordinates and ``units``.
 
     .. code-block:: lua
         :caption: Example
 
         x, y, z = ml.cameraposition()
 
+    :param string units: (Optional) ``'meters'`` or ``'inches'``. Default:
+        ``'meters'``.
     :returns: 3 numbers.
 
     .. versionhistory::
@@ -280,18 +336,15 @@ unsafe extern "C" fn name(l: &lua_State) -> i32 {
 unsafe extern "C" fn camera_position(l: &lua_State) -> i32 {
     let ml = get_ml_upvalue(l);
 
-    let p = ml.camera_position();
-
-    lua::pushnumber(l, p.x as f64);
-    lua::pushnumber(l, p.y as f64);
-    lua::pushnumber(l, p.z as f64);
-
-    return 3;
+    return push_position(l, ml.camera_position(), 1);
 }
 
 /*** RST
 .. lua:function:: camerafront()
 
+    A unit vector pointing in the direction the camera is facing. This is a
+    direction, so it is the same in meters or inches.
+
     :returns: 3 numbers.
 
     .. versionhistory::
@@ -329,6 +382,175 @@ unsafe extern "C" fn camera_top(l: &lua_State) -> i32 {
     return 3;
 }
 
+// reads a rectangle in the form {{x1, y1}, {x2, y2}} from the table at ind
+fn get_rect(l: &lua_State, ind: i32) -> Option<[[f64; 2]; 2]> {
+    if lua::luatype(l, ind) != lua::LuaType::LUA_TTABLE { return None; }
+
+    let mut rect = [[0.0; 2]; 2];
+
+    for p in 0..2 {
+        if lua::geti(l, ind, p as i64 + 1) != lua::LuaType::LUA_TTABLE {
+            lua::pop(l, 1);
+            return None;
+        }
+
+        for c in 0..2 {
+            if lua::geti(l, -1, c as i64 + 1) != lua::LuaType::LUA_TNUMBER {
+                lua::pop(l, 2);
+                return None;
+            }
+            rect[p][c] = lua::tonumber(l, -1);
+            lua::pop(l, 1);
+        }
+
+        lua::pop(l, 1);
+    }
+
+    // a rectangle without any width or height would cause divide by zero
+    // errors during conversions
+    if rect[0][0] == rect[1][0] || rect[0][1] == rect[1][1] { return None; }
+
+    Some(rect)
+}
+
+/*** RST
+.. lua:function:: setmaprects(maprect, continentrect)
+
+    Set the map and continent rectangles of the current map, used by
+    :lua:func:`maptocontinent` and :lua:func:`continenttomap`.
+
+    Both rectangles are in the form ``{{x1, y1}, {x2, y2}}``, the same as the
+    ``map_rect`` and ``continent_rect`` fields returned by the GW2 API
+    ``/v2/maps`` endpoint.
+
+    The rectangles are not cleared when the map changes, modules should update
+    them on map changes. Calling this function with no arguments clears the
+    rectangles.
+
+    :param table maprect:
+    :param table continentrect:
+
+    .. code-block:: lua
+        :caption: Example
+
+        -- map is the result of /v2/maps?id=<map id>
+        ml.setmaprects(map.map_rect, map.continent_rect)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_map_rects(l: &lua_State) -> i32 {
+    let ml = get_ml_upvalue(l);
+
+    if lua::gettop(l) == 0 {
+        ml.set_map_rects(None);
+        return 0;
+    }
+
+    let map_rect = match get_rect(l, 1) {
+        Some(r) => r,
+        None    => {
+            luaerror!(l, "maprect must be a table in the form {{{{x1, y1}}, {{x2, y2}}}}.");
+            return 0;
+        }
+    };
+
+    let continent_rect = match get_rect(l, 2) {
+        Some(r) => r,
+        None    => {
+            luaerror!(l, "continentrect must be a table in the form {{{{x1, y1}}, {{x2, y2}}}}.");
+            return 0;
+        }
+    };
+
+    ml.set_map_rects(Some(crate::ml::MapRects {
+        map_rect: map_rect,
+        continent_rect: continent_rect,
+    }));
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: maptocontinent(x, y)
+
+    Convert map coordinates to continent coordinates.
+
+    If the map rectangles have not been set with :lua:func:`setmaprects` an
+    error is logged and ``nil`` is returned.
+
+    :param number x:
+    :param number y:
+    :returns: 2 numbers.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local x, _, z = ml.avatarposition()
+        local cx, cy = ml.maptocontinent(x * 39.3701, z * 39.3701)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn map_to_continent(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+
+    let ml = get_ml_upvalue(l);
+
+    match ml.map_to_continent(lua::tonumber(l, 1), lua::tonumber(l, 2)) {
+        Some((x, y)) => {
+            lua::pushnumber(l, x);
+            lua::pushnumber(l, y);
+
+            return 2;
+        },
+        None => {
+            luaerror!(l, "Map rectangles have not been set, see setmaprects.");
+            lua::pushnil(l);
+
+            return 1;
+        }
+    }
+}
+
+/*** RST
+.. lua:function:: continenttomap(x, y)
+
+    Convert continent coordinates to map coordinates.
+
+    If the map rectangles have not been set with :lua:func:`setmaprects` an
+    error is logged and ``nil`` is returned.
+
+    :param number x:
+    :param number y:
+    :returns: 2 numbers.
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn continent_to_map(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargnumber!(l, 2);
+
+    let ml = get_ml_upvalue(l);
+
+    match ml.continent_to_map(lua::tonumber(l, 1), lua::tonumber(l, 2)) {
+        Some((x, y)) => {
+            lua::pushnumber(l, x);
+            lua::pushnumber(l, y);
+
+            return 2;
+        },
+        None => {
+            luaerror!(l, "Map rectangles have not been set, see setmaprects.");
+            lua::pushnil(l);
+
+            return 1;
+        }
+    }
+}
+
 /*** RST
 
 Identity
@@ -347,6 +569,18 @@ For example:
 
     local charname = ml.identity.name()
 
+Until the game has written valid identity data, such as before a character is
+loaded, these functions return ``nil``, so the result should be checked before
+it is used:
+
+.. code-block:: lua
+
+    local mapid = ml.identity.mapid()
+
+    if mapid then
+        loadmarkers(mapid)
+    end
+
 .. important::
     The :lua:mod:`mumble-link` module will only parse the identity JSON once per
     :lua:func:`tick`, however module authors should still take care to only call
@@ -1189,7 +1423,7 @@ unsafe extern "C" fn context_process_id(l: &lua_State) -> i32 {
 unsafe extern "C" fn context_mount(l: &lua_State) -> i32 {
     let ml = get_ml_upvalue(l);
 
-    lua::pushinteger(l, ml.gw2_ml.context.mount_index as i64);
+    lua::pushinteger(l, ml.context_mount() as i64);
 
     return 1;
 }
@@ -1213,26 +1447,16 @@ unsafe extern "C" fn context_mount(l: &lua_State) -> i32 {
 
     :rtype: string
 
+    .. seealso::
+        The :overlay:event:`mount-changed` event is sent when this changes.
+
     .. versionhistory::
         :0.3.0: Added
 */
 unsafe extern "C" fn context_mount_name(l: &lua_State) -> i32 {
     let ml = get_ml_upvalue(l);
 
-    lua::pushstring(l, match ml.gw2_ml.context.mount_index {
-        0 => "none",
-        1 => "jackal",
-        2 => "griffon",
-        3 => "springer",
-        4 => "skimmer",
-        5 => "raptor",
-        6 => "roller-beetle",
-        7 => "warclaw",
-        8 => "skyscale",
-        9 => "skiff",
-        10 => "seige-turtle",
-        _ => "unknown",
-    });
+    lua::pushstring(l, ml.context_mount_name().unwrap_or("none"));
 
     return 1;
 }
@@ -0,0 +1,540 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! Module permissions
+//!
+//! Modules listed in the `overlay.modules` setting are restricted to the
+//! capabilities they declare in a manifest and that the user has granted.
+//! Modules loaded any other way, i.e. from `autoload.lua`, are trusted and
+//! are not restricted.
+//!
+//! A module's manifest is a JSON file next to the module, either
+//! `lua/<module>/manifest.json` for modules with an `init.lua` or
+//! `lua/<module>.manifest.json` otherwise, containing a `permissions` list.
+//! The user is asked to grant any permissions that haven't been granted or
+//! denied yet when the module is first loaded and the result is stored in the
+//! `overlay.modulePermissions` setting.
+//!
+//! Bindings that need a permission call [check], which walks the Lua call
+//! stack so a restricted module can't use a trusted module to make the call
+//! for it. Callbacks registered by a restricted module are wrapped so that
+//! they are still attributed to that module when they are run later with
+//! nothing else on the stack, see [ref_callback].
+//!
+//! The file and process functions of the Lua `io` and `os` libraries,
+//! `loadfile` and `dofile` are wrapped to require [Permission::Files] as well.
+//! The `debug` library, `package.loadlib` and loading C modules with `require`
+//! are only available to trusted modules, see [guard_stdlib].
+use std::sync::Mutex;
+
+use std::collections::HashMap;
+
+#[allow(unused_imports)]
+use crate::logging::{info,debug,warn,error};
+
+use crate::lua;
+use crate::lua::lua_State;
+
+use windows::Win32::UI::WindowsAndMessaging;
+
+/// A capability that restricted modules must be granted to use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    /// Web requests.
+    Web,
+
+    /// Reading and writing files, databases and zip archives.
+    Files,
+
+    /// Reading the Windows registry.
+    Registry,
+}
+
+const PERMISSIONS: [Permission; 3] = [
+    Permission::Web,
+    Permission::Files,
+    Permission::Registry,
+];
+
+impl Permission {
+    /// The name used in manifests and settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::Web      => "web",
+            Permission::Files    => "files",
+            Permission::Registry => "registry",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Permission> {
+        PERMISSIONS.iter().find(|p| p.name() == name).copied()
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Permission::Web      => "Make web requests",
+            Permission::Files    => "Read and write files",
+            Permission::Registry => "Read the Windows registry",
+        }
+    }
+}
+
+// the granted permissions of each restricted module, by module name
+static RESTRICTED: Mutex<Option<HashMap<String, Vec<Permission>>>> = Mutex::new(None);
+
+/// Reads the manifest of the restricted module `name` and asks the user to
+/// grant any permissions that haven't been granted or denied yet.
+///
+/// This must be called before the module is loaded, otherwise it is trusted.
+pub fn load_module(name: &str) {
+    let requested = read_manifest(name);
+
+    let mut all = crate::overlay::settings().get_object("overlay.modulePermissions").unwrap_or_default();
+
+    let mut grants = all.get(name).and_then(|g| g.as_object()).cloned().unwrap_or_default();
+
+    let new: Vec<Permission> = requested.iter().filter(|p| !grants.contains_key(p.name())).copied().collect();
+
+    if new.len() > 0 {
+        let granted = prompt(name, &new);
+
+        for p in &new {
+            grants.insert(p.name().to_string(), serde_json::Value::Bool(granted));
+        }
+
+        all.insert(name.to_string(), serde_json::Value::Object(grants.clone()));
+        crate::overlay::settings().set("overlay.modulePermissions", all);
+    }
+
+    // only permissions that are both requested and granted are usable, a
+    // module can't use a permission that was removed from its manifest
+    let granted: Vec<Permission> = requested.into_iter()
+        .filter(|p| grants.get(p.name()).and_then(|g| g.as_bool()).unwrap_or(false))
+        .collect();
+
+    info!("Module {} permissions: {}", name, granted.iter().map(|p| p.name()).collect::<Vec<_>>().join(", "));
+
+    RESTRICTED.lock().unwrap().get_or_insert_with(HashMap::new).insert(name.to_string(), granted);
+}
+
+fn read_manifest(name: &str) -> Vec<Permission> {
+    let mut base = std::env::current_dir().unwrap();
+    base.push("lua");
+    for part in name.split('.') { base.push(part); }
+
+    let mut dir_manifest = base.clone();
+    dir_manifest.push("manifest.json");
+
+    let file_manifest = base.with_file_name(format!("{}.manifest.json", name.rsplit('.').next().unwrap()));
+
+    let path = if dir_manifest.exists() { dir_manifest } else { file_manifest };
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(_) => {
+            debug!("No manifest for module {}, it will have no permissions.", name);
+            return Vec::new();
+        }
+    };
+
+    let manifest: serde_json::Value = match serde_json::from_str(&text) {
+        Ok(m) => m,
+        Err(err) => {
+            warn!("Couldn't parse {}: {}", path.display(), err);
+            return Vec::new();
+        }
+    };
+
+    let mut permissions: Vec<Permission> = Vec::new();
+
+    for p in manifest.get("permissions").and_then(|p| p.as_array()).map(|p| p.as_slice()).unwrap_or_default() {
+        match p.as_str().and_then(Permission::from_name) {
+            Some(p) => if !permissions.contains(&p) { permissions.push(p) },
+            None    => warn!("{}: unknown permission {}", path.display(), p),
+        }
+    }
+
+    return permissions;
+}
+
+// asks the user to grant all of permissions, returns true if they did
+fn prompt(name: &str, permissions: &[Permission]) -> bool {
+    let list: Vec<String> = permissions.iter().map(|p| format!("  - {}", p.description())).collect();
+
+    let text = format!(
+        "The module '{}' is requesting permission to:\n\n{}\n\nAllow this module to do these things?\n\n\
+         This can be changed later in the overlay.modulePermissions setting.",
+        name,
+        list.join("\n")
+    );
+
+    let textu16: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+
+    let r = unsafe {
+        WindowsAndMessaging::MessageBoxW(
+            None,
+            windows::core::PCWSTR::from_raw(textu16.as_ptr()),
+            windows::core::w!("EG-Overlay Module Permissions"),
+            WindowsAndMessaging::MB_YESNO | WindowsAndMessaging::MB_ICONQUESTION |
+            WindowsAndMessaging::MB_TOPMOST | WindowsAndMessaging::MB_SETFOREGROUND
+        )
+    };
+
+    return r == WindowsAndMessaging::IDYES;
+}
+
+// returns the restricted module that module_name belongs to, if any
+fn restricted_module<'a>(restricted: &'a HashMap<String, Vec<Permission>>, module_name: &str) -> Option<(&'a String, &'a Vec<Permission>)> {
+    restricted.iter().find(|(name, _)| {
+        module_name == name.as_str() ||
+        module_name.strip_prefix(name.as_str()).is_some_and(|r| r.starts_with('.'))
+    })
+}
+
+/// Returns `true` if the Lua code that called the current function is allowed
+/// to use `permission`.
+///
+/// Every Lua function on the call stack is checked, if any belong to a
+/// restricted module that wasn't granted `permission` this logs an error and
+/// returns `false`. Callbacks registered with [ref_callback] count as part of
+/// the module that registered them.
+pub fn check(l: &lua_State, permission: Permission) -> bool {
+    match denied_module(l, |granted| granted.contains(&permission)) {
+        Some(module) => {
+            error!("Module {} was denied the {} permission.", module, permission.name());
+            false
+        },
+        None => true,
+    }
+}
+
+/// Returns `true` if there are no restricted modules on the Lua call stack.
+///
+/// This is used for functions that change permissions or overlay settings,
+/// which restricted modules must not be able to call.
+pub fn check_trusted(l: &lua_State) -> bool {
+    match denied_module(l, |_| false) {
+        Some(module) => {
+            error!("Module {} is restricted and can't change overlay settings or module permissions.", module);
+            false
+        },
+        None => true,
+    }
+}
+
+// the standard library functions that access files or run programs. an empty
+// library name is a global function
+const GUARDED_STDLIB: [(&str, &str); 11] = [
+    ("io", "open"),
+    ("io", "lines"),
+    ("io", "popen"),
+    ("io", "input"),
+    ("io", "output"),
+    ("os", "remove"),
+    ("os", "rename"),
+    ("os", "execute"),
+    ("os", "tmpname"),
+    ("", "loadfile"),
+    ("", "dofile"),
+];
+
+// the functions of the debug library that restricted modules can use, all
+// others can read or change upvalues, locals, the registry or metatables and
+// would give access to the unguarded functions
+const ALLOWED_DEBUG: [&str; 2] = ["traceback", "getinfo"];
+
+/// Replaces the Lua standard library functions that access files or run
+/// programs with versions that require [Permission::Files].
+///
+/// This must be called after the standard libraries are opened.
+pub fn guard_stdlib(l: &lua_State) {
+    for (lib, func) in GUARDED_STDLIB {
+        if lib.is_empty() {
+            lua::getglobal(l, func);
+            lua::pushcclosure(l, Some(guarded_file_function), 1);
+            lua::setglobal(l, func);
+            continue;
+        }
+
+        if lua::getglobal(l, lib) != lua::LuaType::LUA_TTABLE {
+            lua::pop(l, 1);
+            continue;
+        }
+
+        // the original function is the only upvalue
+        lua::getfield(l, -1, func);
+        lua::pushcclosure(l, Some(guarded_file_function), 1);
+        lua::setfield(l, -2, func);
+
+        lua::pop(l, 1);
+    }
+
+    // everything but ALLOWED_DEBUG in the debug library
+    if lua::getglobal(l, "debug") == lua::LuaType::LUA_TTABLE {
+        let mut names: Vec<String> = Vec::new();
+
+        lua::pushnil(l);
+        while lua::next(l, -2) != 0 {
+            if lua::luatype(l, -2) == lua::LuaType::LUA_TSTRING {
+                names.push(lua::tostring(l, -2).unwrap());
+            }
+            lua::pop(l, 1);
+        }
+
+        for name in names.iter().filter(|n| !ALLOWED_DEBUG.contains(&n.as_str())) {
+            guard_trusted_field(l, "debug", name);
+        }
+    }
+    lua::pop(l, 1);
+
+    if lua::getglobal(l, "package") == lua::LuaType::LUA_TTABLE {
+        guard_trusted_field(l, "package", "loadlib");
+
+        // the C and all-in-one searchers, the preload and Lua searchers are
+        // left alone
+        if lua::getfield(l, -1, "searchers") == lua::LuaType::LUA_TTABLE {
+            for i in 3..=4 {
+                if lua::geti(l, -1, i) != lua::LuaType::LUA_TFUNCTION {
+                    lua::pop(l, 1);
+                    continue;
+                }
+
+                lua::pushcclosure(l, Some(guarded_c_searcher), 1);
+                lua::seti(l, -2, i);
+            }
+        }
+        lua::pop(l, 1);
+    }
+    lua::pop(l, 1);
+
+    lua::getglobal(l, "load");
+    lua::pushcclosure(l, Some(guarded_load), 1);
+    lua::setglobal(l, "load");
+
+    if lua::getglobal(l, "coroutine") == lua::LuaType::LUA_TTABLE {
+        for func in ["create", "wrap"] {
+            lua::getfield(l, -1, func);
+            lua::pushcclosure(l, Some(owned_coroutine_function), 1);
+            lua::setfield(l, -2, func);
+        }
+    }
+    lua::pop(l, 1);
+}
+
+// replaces func in the table on the top of the stack with a version that only
+// trusted modules can call
+fn guard_trusted_field(l: &lua_State, lib: &str, func: &str) {
+    lua::getfield(l, -1, func);
+    lua::pushstring(l, &format!("{}.{}", lib, func));
+    lua::pushcclosure(l, Some(guarded_trusted_function), 2);
+    lua::setfield(l, -2, func);
+}
+
+// calls upvalue 1 with all arguments, returning all results
+fn call_upvalue(l: &lua_State) -> i32 {
+    let nargs = lua::gettop(l);
+
+    lua::pushvalue(l, lua::LUA_REGISTRYINDEX - 1); // up value 1
+    lua::insert(l, 1);
+
+    lua::call(l, nargs, lua::LUA_MULTRET);
+
+    return lua::gettop(l);
+}
+
+unsafe extern "C" fn guarded_trusted_function(l: &lua_State) -> i32 {
+    if let Some(module) = caller_module(l) {
+        let name = lua::tostring(l, lua::LUA_REGISTRYINDEX - 2).unwrap_or_default();
+        error!("Module {} is restricted and can't use {}.", module, name);
+        return 0;
+    }
+
+    return call_upvalue(l);
+}
+
+// a package.searchers entry that loads C modules. restricted modules get the
+// same result as if the module wasn't found
+unsafe extern "C" fn guarded_c_searcher(l: &lua_State) -> i32 {
+    if let Some(module) = caller_module(l) {
+        lua::pushstring(l, &format!("\n\tC modules can't be loaded by restricted module {}", module));
+        return 1;
+    }
+
+    return call_upvalue(l);
+}
+
+// load, but chunks loaded by restricted modules are always named after the
+// module and can only be text. otherwise a chunk could claim to be from a
+// trusted module, either with its name or with the source saved in a binary
+// chunk
+unsafe extern "C" fn guarded_load(l: &lua_State) -> i32 {
+    if let Some(module) = caller_module(l) {
+        while lua::gettop(l) < 3 { lua::pushnil(l); }
+
+        lua::pushstring(l, &format!("={}", module));
+        lua::replace(l, 2);
+
+        lua::pushstring(l, "t");
+        lua::replace(l, 3);
+    }
+
+    return call_upvalue(l);
+}
+
+// coroutine.create and coroutine.wrap, the function run by the coroutine is
+// owned by the calling module like a callback. the coroutine's stack doesn't
+// include the caller
+unsafe extern "C" fn owned_coroutine_function(l: &lua_State) -> i32 {
+    if lua::gettop(l) >= 1 { own_function(l, 1); }
+
+    return call_upvalue(l);
+}
+
+/// Pops the value on the top of the stack and returns a reference to it in the
+/// registry, like `luaL_ref`.
+///
+/// This should be used for all Lua callbacks. If the value is a function and
+/// it is being registered by a restricted module, a wrapper function is
+/// referenced instead. The wrapper keeps the module on the call stack while the
+/// callback runs, so that [check] attributes it to the module even if the
+/// callback is a function the module didn't define, i.e. `os.execute`.
+pub fn ref_callback(l: &lua_State) -> i64 {
+    own_function(l, -1);
+
+    lua::L::ref_(l, lua::LUA_REGISTRYINDEX)
+}
+
+// replaces the function at ind with one owned by the calling restricted
+// module, if there is one
+fn own_function(l: &lua_State, ind: i32) {
+    if lua::luatype(l, ind) != lua::LuaType::LUA_TFUNCTION { return; }
+
+    let module = match caller_module(l) {
+        Some(m) => m,
+        None    => return,
+    };
+
+    let ind = lua::absindex(l, ind);
+
+    lua::pushvalue(l, ind);
+    lua::pushstring(l, &module);
+    lua::pushcclosure(l, Some(owned_function), 2);
+    lua::replace(l, ind);
+}
+
+unsafe extern "C" fn owned_function(l: &lua_State) -> i32 {
+    let nargs = lua::gettop(l);
+
+    lua::pushvalue(l, lua::LUA_REGISTRYINDEX - 1); // up value 1
+    lua::insert(l, 1);
+
+    // callbacks are allowed to yield
+    lua::callk(l, nargs, lua::LUA_MULTRET, std::ptr::null(), Some(owned_function_k));
+
+    return unsafe { owned_function_k(l, lua::LUA_OK, std::ptr::null()) };
+}
+
+unsafe extern "C" fn owned_function_k(l: &lua_State, _status: i32, _ctx: lua::lua_KContext) -> i32 {
+    return lua::gettop(l);
+}
+
+unsafe extern "C" fn guarded_file_function(l: &lua_State) -> i32 {
+    if !check(l, Permission::Files) { return 0; }
+
+    return call_upvalue(l);
+}
+
+// returns the module names of each function on the call stack, innermost
+// first. functions owned by a module, see own_function, are that module
+fn stack_modules(l: &lua_State) -> Vec<String> {
+    let mut modules: Vec<String> = Vec::new();
+
+    let mut level = 0;
+    let mut dbg = lua::lua_Debug::default();
+
+    while lua::getstack(l, level, &mut dbg).is_ok() {
+        level += 1;
+
+        // f pushes the function
+        if lua::getinfo(l, "Sf", &mut dbg).is_err() { continue; }
+
+        if lua::tocfunction(l, -1) == Some(owned_function as unsafe extern "C" fn(&lua_State) -> i32) {
+            if lua::getupvalue(l, -1, 2).is_some() {
+                if let Some(owner) = lua::tostring(l, -1) { modules.push(owner); }
+                lua::pop(l, 1);
+            }
+        } else if !dbg.source.is_null() {
+            let src = unsafe { std::ffi::CStr::from_ptr(dbg.source).to_string_lossy() };
+            modules.push(crate::overlay::lua::module_name_from_source(&src));
+        }
+
+        lua::pop(l, 1);
+    }
+
+    modules
+}
+
+// returns the innermost restricted module on the call stack
+fn caller_module(l: &lua_State) -> Option<String> {
+    let modules = stack_modules(l);
+
+    let lock = RESTRICTED.lock().unwrap();
+    let restricted = lock.as_ref()?;
+
+    modules.iter().find_map(|m| restricted_module(restricted, m).map(|(name, _)| name.clone()))
+}
+
+// returns the first restricted module on the stack that allowed returns false for
+fn denied_module(l: &lua_State, allowed: impl Fn(&Vec<Permission>) -> bool) -> Option<String> {
+    let modules = stack_modules(l);
+
+    let lock = RESTRICTED.lock().unwrap();
+    let restricted = lock.as_ref()?;
+
+    for module_name in modules {
+        if let Some((name, granted)) = restricted_module(restricted, &module_name) {
+            if !allowed(granted) { return Some(name.clone()); }
+        }
+    }
+
+    None
+}
+
+/// Sets whether the restricted module `name` is granted `permission`.
+///
+/// The change is saved to the `overlay.modulePermissions` setting and applies
+/// immediately, but only to permissions the module requested in its manifest.
+pub fn set_granted(name: &str, permission: Permission, granted: bool) {
+    let mut all = crate::overlay::settings().get_object("overlay.modulePermissions").unwrap_or_default();
+
+    let mut grants = all.get(name).and_then(|g| g.as_object()).cloned().unwrap_or_default();
+    grants.insert(permission.name().to_string(), serde_json::Value::Bool(granted));
+
+    all.insert(name.to_string(), serde_json::Value::Object(grants));
+    crate::overlay::settings().set("overlay.modulePermissions", all);
+
+    let mut lock = RESTRICTED.lock().unwrap();
+
+    if let Some(perms) = lock.as_mut().and_then(|r| r.get_mut(name)) {
+        perms.retain(|p| *p != permission);
+
+        if granted && read_manifest(name).contains(&permission) { perms.push(permission); }
+    }
+}
+
+/// Returns the stored permissions of all modules, as `(module, permission,
+/// granted)`.
+pub fn stored() -> Vec<(String, String, bool)> {
+    let all = crate::overlay::settings().get_object("overlay.modulePermissions").unwrap_or_default();
+
+    let mut r: Vec<(String, String, bool)> = Vec::new();
+
+    for (module, grants) in &all {
+        for (perm, granted) in grants.as_object().into_iter().flatten() {
+            r.push((module.clone(), perm.clone(), granted.as_bool().unwrap_or(false)));
+        }
+    }
+
+    return r;
+}
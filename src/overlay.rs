@@ -20,6 +20,8 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic;
 
+use std::collections::VecDeque;
+
 use std::time;
 
 use windows::core::PCSTR;
@@ -57,6 +59,19 @@ pub struct EgOverlay {
 
     frame_count: atomic::AtomicU64,
 
+    // idle rendering, see render_thread
+    idle_fps: atomic::AtomicU32,
+    redraw: atomic::AtomicBool,
+
+    // the most recent frames that took too long, see frame_hitches
+    frame_hitches: Mutex<VecDeque<FrameHitch>>,
+
+    // the time between recent frames, see frame_time and fps
+    frame_timing: Mutex<FrameTiming>,
+
+    // paths to save screenshots of the next frame to, see request_screenshot
+    screenshots: Mutex<Vec<String>>,
+
     mods: Mutex<OverlayModules>,
 
     settings: Arc<settings::SettingsStore>,
@@ -181,6 +196,27 @@ pub fn init() {
     overlay_settings.set_default_value("overlay.frameTargetTime",  32.0);
     overlay_settings.set_default_value("overlay.luaUpdateTarget",  32.0);
     overlay_settings.set_default_value("overlay.fgWinCheckTime" , 250.0);
+    overlay_settings.set_default_value("overlay.inputMethod"    , "hooks");
+    overlay_settings.set_default_value("overlay.soundVolume"    , 1.0);
+    overlay_settings.set_default_value("overlay.reverseDepth"   , false);
+    overlay_settings.set_default_value("overlay.renderScale"    , 1.0);
+    overlay_settings.set_default_value("overlay.bindlessTextures", true);
+    overlay_settings.set_default_value("overlay.eventHandlerBudget", 10.0);
+    overlay_settings.set_default_value("overlay.keyboardLayout" , "");
+    overlay_settings.set_default_value("overlay.idleFPS"        , 5);
+    overlay_settings.set_default_value("overlay.opacity"        , 1.0);
+    overlay_settings.set_default_value("overlay.frameHitchThreshold", 2.0);
+    overlay_settings.set_default_value("overlay.webRequestWorkers", 4);
+    overlay_settings.set_default_value("overlay.captureMouseDrag", false);
+    overlay_settings.set_default_value("overlay.gameLog.chatPath", "");
+    overlay_settings.set_default_value("overlay.gameLog.combatPath", "");
+    overlay_settings.set_default_value("overlay.gameLog.pollInterval", 250);
+    overlay_settings.set_default_value("overlay.modules", serde_json::Map::new());
+    overlay_settings.set_default_value("overlay.modulePermissions", serde_json::Map::new());
+
+    let input_method = input::InputMethod::from_name(&overlay_settings.get_string("overlay.inputMethod").unwrap());
+    let keyboard_layout = overlay_settings.get_string("overlay.keyboardLayout");
+    let idle_fps = overlay_settings.get_u64("overlay.idleFPS").unwrap() as u32;
 
     let overlay = EgOverlay {
         hwnd: atomic::AtomicUsize::new(0),
@@ -193,10 +229,19 @@ pub fn init() {
 
         frame_count: atomic::AtomicU64::new(0),
 
+        idle_fps: atomic::AtomicU32::new(idle_fps),
+        redraw: atomic::AtomicBool::new(true),
+
+        frame_hitches: Mutex::new(VecDeque::new()),
+
+        frame_timing: Mutex::new(FrameTiming::new()),
+
+        screenshots: Mutex::new(Vec::new()),
+
         settings: overlay_settings,
         start_time: start_time,
 
-        input: input::InputManager::new(),
+        input: input::InputManager::new(input_method, keyboard_layout),
 
         mods: Mutex::new(OverlayModules {
             dx: None,
@@ -226,7 +271,10 @@ pub fn init() {
 
     crate::lua_shell::init();
     crate::lua_path::init();
+    crate::lamath::lua::init();
     crate::web_request::init();
+    crate::speech::init();
+    crate::game_log::init();
 
     if o.script.is_some() {
         // scripts might still want mumble-link
@@ -241,11 +289,18 @@ pub fn init() {
 
     // don't keep mods locked, Ui::new needs dx, etc.
     o.mods.lock().unwrap().dx = Some(dx::Dx::new());
+
+    let opacity = o.settings.get_f64("overlay.opacity").unwrap().clamp(0.0, 1.0);
+    if opacity < 1.0 {
+        info!("Overlay opacity: {:.2}", opacity);
+        dx().set_opacity(opacity as f32);
+    }
     o.mods.lock().unwrap().ui = Some(ui::Ui::new());
     o.mods.lock().unwrap().ml = Some(ml::MumbleLink::new());
 
     // input needs a reference to UI now that it's up
     input::set_ui(&ui());
+    input::set_capture_mouse_drag(o.settings.get_bool("overlay.captureMouseDrag").unwrap());
 }
 
 fn register_win_class() {
@@ -376,6 +431,8 @@ pub fn run() {
         Shell::Shell_NotifyIconA(Shell::NIM_SETVERSION, &nid).expect("Failed to set shell icon version.");
     }
 
+    utils::add_clipboard_listener(overlay.hwnd());
+
     overlay.running.store(true, atomic::Ordering::Relaxed);
 
     debug!("Starting render thread...");
@@ -561,6 +618,25 @@ impl EgOverlay {
         return self.settings.clone();
     }
 
+    fn add_frame_hitch(&self, hitch: FrameHitch) {
+        debug!(
+            "Frame hitch: {:.2}ms (gpu {:.2}ms, dx {:.2}ms, ui {:.2}ms, capture {:.2}ms, present {:.2}ms), mostly {}.",
+            hitch.duration,
+            hitch.gpu_time,
+            hitch.dx_time,
+            hitch.ui_time,
+            hitch.capture_time,
+            hitch.present_time,
+            hitch.phase(),
+        );
+
+        let mut hitches = self.frame_hitches.lock().unwrap();
+
+        if hitches.len() >= MAX_FRAME_HITCHES { hitches.pop_front(); }
+
+        hitches.push_back(hitch);
+    }
+
     pub fn dx(&self) -> Arc<dx::Dx> {
         self.mods.lock().unwrap().dx.as_ref().unwrap().clone()
     }
@@ -574,12 +650,9 @@ impl EgOverlay {
         self.mods.lock().unwrap().ml.as_ref().unwrap().clone()
     }
 
-    /*
-
     pub fn input(&self) -> Arc<input::InputManager> {
         self.input.clone()
     }
-    */
 }
 
 pub fn restart() {
@@ -602,6 +675,8 @@ pub fn exit() {
 
 pub fn cleanup() {
     crate::web_request::cleanup();
+    crate::speech::cleanup();
+    crate::game_log::cleanup();
 
     lua_manager::cleanup();
 
@@ -669,8 +744,19 @@ unsafe extern "system" fn overlay_wnd_proc(
             WindowsAndMessaging::DestroyWindow(hwnd).unwrap();
         },
         WindowsAndMessaging::WM_DESTROY => unsafe {
+            utils::remove_clipboard_listener(hwnd);
             WindowsAndMessaging::PostQuitMessage(0);
         },
+        WindowsAndMessaging::WM_CLIPBOARDUPDATE => {
+            utils::clipboard_changed();
+        },
+        WindowsAndMessaging::WM_INPUT => {
+            input::process_raw_input(lparam);
+
+            // DefWindowProc must still be called for WM_INPUT so the system
+            // can clean up
+            return unsafe { WindowsAndMessaging::DefWindowProcA(hwnd, msg, wparam, lparam) };
+        },
         WindowsAndMessaging::WM_SIZE => {
             let overlay = OVERLAY.lock().unwrap();
             if overlay.is_none() { return Foundation::LRESULT(0); }
@@ -730,6 +816,129 @@ unsafe extern "system" fn overlay_wnd_proc(
     return Foundation::LRESULT(0);
 }
 
+// seconds without changes before rendering drops to the idle frame rate
+const IDLE_DELAY: f64 = 1.0;
+
+// the number of frame hitches kept for frame_hitches
+const MAX_FRAME_HITCHES: usize = 32;
+
+/// A frame that took longer than the frame hitch threshold to render.
+///
+/// All times are in milliseconds, except for `time`.
+#[derive(Clone)]
+pub struct FrameHitch {
+    /// The overlay uptime when the frame started, in seconds.
+    pub time: f64,
+
+    /// The total time spent on the frame.
+    pub duration: f64,
+
+    /// Time spent waiting for the GPU to finish the previous frame.
+    pub gpu_time: f64,
+
+    /// Time spent rendering sprite and trail lists.
+    pub dx_time: f64,
+
+    /// Time spent rendering the UI.
+    pub ui_time: f64,
+
+    /// Time spent rendering captures, see `dx.capture`.
+    pub capture_time: f64,
+
+    /// Time spent submitting and presenting the frame.
+    pub present_time: f64,
+}
+
+impl FrameHitch {
+    /// Returns the name of the phase that took the longest.
+    pub fn phase(&self) -> &'static str {
+        let phases = [
+            ("gpu"    , self.gpu_time),
+            ("dx"     , self.dx_time),
+            ("ui"     , self.ui_time),
+            ("capture", self.capture_time),
+            ("present", self.present_time),
+        ];
+
+        let mut longest = phases[0];
+
+        for p in phases {
+            if p.1 > longest.1 { longest = p; }
+        }
+
+        return longest.0;
+    }
+}
+
+// the number of frames frame rate is averaged over
+const FRAME_TIMING_WINDOW: usize = 60;
+
+/// The time between the most recent rendered frames.
+struct FrameTiming {
+    // milliseconds between each frame and the one before it, a ring buffer
+    // starting at next
+    times: [f64; FRAME_TIMING_WINDOW],
+    next: usize,
+    count: usize,
+
+    // the uptime when the last frame began, in seconds
+    last_begin: Option<f64>,
+}
+
+impl FrameTiming {
+    fn new() -> FrameTiming {
+        return FrameTiming {
+            times: [0.0; FRAME_TIMING_WINDOW],
+            next: 0,
+            count: 0,
+            last_begin: None,
+        };
+    }
+
+    /// Records a frame that began at `begin` seconds of uptime.
+    fn record(&mut self, begin: f64) {
+        if let Some(last) = self.last_begin {
+            self.times[self.next] = (begin - last) * 1000.0;
+            self.next = (self.next + 1) % FRAME_TIMING_WINDOW;
+            self.count = (self.count + 1).min(FRAME_TIMING_WINDOW);
+        }
+
+        self.last_begin = Some(begin);
+    }
+
+    /// The time between the last two frames, in milliseconds.
+    fn last(&self) -> f64 {
+        if self.count == 0 { return 0.0; }
+
+        return self.times[(self.next + FRAME_TIMING_WINDOW - 1) % FRAME_TIMING_WINDOW];
+    }
+
+    /// The average frames per second over the window.
+    fn fps(&self) -> f64 {
+        let total: f64 = self.times[..self.count].iter().sum();
+
+        if total <= 0.0 { return 0.0; }
+
+        return (self.count as f64 * 1000.0) / total;
+    }
+}
+
+/// The MumbleLink values that affect what is drawn in the 3D scene and map.
+type SceneState = (crate::lamath::Vec3F, crate::lamath::Vec3F, crate::lamath::Vec3F, u32, f32, f32, f32, f32);
+
+fn scene_state(ml: &ml::MumbleLink) -> SceneState {
+    (
+        *ml.avatar_position(),
+        *ml.camera_position(),
+        *ml.camera_front(),
+        ml.context_ui_state(),
+        ml.context_map_center_x(),
+        ml.context_map_center_y(),
+        ml.context_map_scale(),
+        ml.context_compass_rotation(),
+    )
+}
+
 fn render_thread(overlay: Arc<EgOverlay>) {
     debug!("Begin render thread.");
 
@@ -753,33 +962,106 @@ fn render_thread(overlay: Arc<EgOverlay>) {
 
     debug!("Frame target time: {}ms (~{:.0} FPS).", frame_target, 1000.0 / frame_target);
 
+    // frames that take longer than this are logged and kept for frame_hitches
+    let hitch_threshold = frame_target * overlay.settings.get_f64("overlay.frameHitchThreshold").unwrap();
+
     let ui = ui();
 
     let odx = overlay.dx();
+    let ml = overlay.ml();
+
+    dx::lua::init(&odx, &ml, &ui);
 
-    dx::lua::init(&odx, &overlay.ml(), &ui);
+    // when nothing has changed for IDLE_DELAY seconds, frames are only rendered
+    // at idle_fps until something changes again
+    let mut last_scene = scene_state(&ml);
+    let mut last_change = 0.0;
+    let mut last_frame = 0.0;
 
     while overlay.running.load(atomic::Ordering::Relaxed) {
         if overlay.visible.load(atomic::Ordering::Relaxed) {
             if overlay.do_resize.load(atomic::Ordering::Relaxed) {
                 odx.resize_swapchain(overlay.hwnd());
                 overlay.do_resize.store(false, atomic::Ordering::Relaxed);
+                overlay.redraw.store(true, atomic::Ordering::Relaxed);
             }
 
             let frame_begin = overlay.uptime().as_secs_f64();
 
-            if let Some(mut frame) = odx.start_frame() {
-                dx::lua::render(&mut frame);
-                ui.draw(&mut frame);
-                frame.end_frame();
+            let scene = scene_state(&ml);
 
-                overlay.frame_count.fetch_add(1, atomic::Ordering::Relaxed);
+            if overlay.redraw.swap(false, atomic::Ordering::Relaxed) ||
+               scene != last_scene ||
+               dx::lua::needs_redraw() ||
+               ui.is_dirty()
+            {
+                last_change = frame_begin;
+                last_scene = scene;
+            }
+
+            let idle_fps = overlay.idle_fps.load(atomic::Ordering::Relaxed);
+            let idle = idle_fps > 0 && frame_begin - last_change >= IDLE_DELAY;
+
+            // the time at the end of each phase of the frame: gpu, dx, ui,
+            // capture, present
+            let mut phase_ends: Option<[f64;5]> = None;
+
+            if !idle || frame_begin - last_frame >= 1.0 / idle_fps as f64 {
+                if let Some(mut frame) = odx.start_frame() {
+                    let gpu_end = overlay.uptime().as_secs_f64();
+
+                    dx::lua::render(&mut frame);
+                    let dx_end = overlay.uptime().as_secs_f64();
+
+                    ui.draw(&mut frame);
+                    let ui_end = overlay.uptime().as_secs_f64();
+
+                    dx::lua::render_capture(&mut frame);
+                    ui.perf.draw(&mut frame, &ui);
+
+                    let screenshots = std::mem::take(&mut *overlay.screenshots.lock().unwrap());
+
+                    if !screenshots.is_empty() {
+                        let width = frame.render_target_width();
+                        let height = frame.render_target_height();
+                        let pixels = frame.capture_backbuffer();
+
+                        save_screenshots(screenshots, width, height, pixels);
+                    }
+                    let capture_end = overlay.uptime().as_secs_f64();
+
+                    frame.end_frame();
+                    let present_end = overlay.uptime().as_secs_f64();
+
+                    phase_ends = Some([gpu_end, dx_end, ui_end, capture_end, present_end]);
+
+                    overlay.frame_count.fetch_add(1, atomic::Ordering::Relaxed);
+                    overlay.frame_timing.lock().unwrap().record(frame_begin);
+                }
+
+                last_frame = frame_begin;
             }
 
             let frame_end = overlay.uptime().as_secs_f64();
             let frame_time = (frame_end - frame_begin) * 1000.0;
             let sleep_time = frame_target - frame_time;
 
+            if let Some(ends) = phase_ends {
+                ui.perf.record_frame(frame_begin, frame_time);
+
+                if hitch_threshold > 0.0 && frame_time > hitch_threshold {
+                    overlay.add_frame_hitch(FrameHitch {
+                        time: frame_begin,
+                        duration: frame_time,
+                        gpu_time    : (ends[0] - frame_begin) * 1000.0,
+                        dx_time     : (ends[1] - ends[0]) * 1000.0,
+                        ui_time     : (ends[2] - ends[1]) * 1000.0,
+                        capture_time: (ends[3] - ends[2]) * 1000.0,
+                        present_time: (ends[4] - ends[3]) * 1000.0,
+                    });
+                }
+            }
+
             // if we have extra time, sleep
             if sleep_time > 0.0 {
                 std::thread::sleep(std::time::Duration::from_secs_f64(sleep_time / 1000.0));
@@ -808,11 +1090,9 @@ pub fn ui() -> Arc<ui::Ui> {
     OVERLAY.lock().unwrap().as_ref().unwrap().ui()
 }
 
-/*
 pub fn ml() -> Arc<ml::MumbleLink> {
     OVERLAY.lock().unwrap().as_ref().unwrap().ml()
 }
-*/
 
 pub fn settings() -> Arc<settings::SettingsStore> {
     OVERLAY.lock().unwrap().as_ref().unwrap().settings()
@@ -826,13 +1106,85 @@ pub fn uptime() -> time::Duration {
     OVERLAY.lock().unwrap().as_ref().unwrap().uptime()
 }
 
-/*
 pub fn input() -> Arc<input::InputManager> {
     OVERLAY.lock().unwrap().as_ref().unwrap().input()
 }
-*/
+
+/// Sets the frame rate used when nothing is changing, `0` disables idle
+/// rendering.
+pub fn set_idle_fps(fps: u32) {
+    OVERLAY.lock().unwrap().as_ref().unwrap().idle_fps.store(fps, atomic::Ordering::Relaxed);
+}
+
+/// Sets the opacity of the entire overlay and saves it to the
+/// `overlay.opacity` setting.
+///
+/// `opacity` is clamped to `0.0` to `1.0`.
+pub fn set_opacity(opacity: f64) {
+    let opacity = opacity.clamp(0.0, 1.0);
+
+    settings().set("overlay.opacity", opacity);
+    dx().set_opacity(opacity as f32);
+}
+
+/// Ends idle rendering, if active, so that the next frame is rendered at the
+/// full frame rate.
+///
+/// This should be called whenever something changes that will be drawn, but
+/// the render thread can't detect, i.e. input or animation.
+pub fn request_redraw() {
+    if let Some(o) = OVERLAY.lock().unwrap().as_ref() {
+        o.redraw.store(true, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Saves a PNG of the next rendered frame to `path`.
+///
+/// The image is written on a separate thread after the frame is rendered.
+pub fn request_screenshot(path: &str) {
+    OVERLAY.lock().unwrap().as_ref().unwrap().screenshots.lock().unwrap().push(path.to_string());
+
+    request_redraw();
+}
+
+// encodes and writes screenshots on a separate thread so the render thread
+// isn't held up
+fn save_screenshots(paths: Vec<String>, width: u32, height: u32, pixels: Vec<u8>) {
+    let r = std::thread::Builder::new().name("EG-Overlay Screenshot Thread".to_string()).spawn(move || {
+        utils::init_com_for_thread();
+
+        for path in &paths {
+            match dx::lua::write_png(path, width, height, &pixels) {
+                Ok(_)    => info!("Saved {}x{} screenshot to {}", width, height, path),
+                Err(err) => error!("Couldn't save screenshot to {}: {}", path, err),
+            }
+        }
+
+        utils::uninit_com_for_thread();
+    });
+
+    if let Err(err) = r {
+        error!("Couldn't spawn screenshot thread: {}", err);
+    }
+}
+
+/// Returns the most recent frames that took longer than the
+/// `overlay.frameHitchThreshold` setting to render, oldest first.
+pub fn frame_hitches() -> Vec<FrameHitch> {
+    OVERLAY.lock().unwrap().as_ref().unwrap().frame_hitches.lock().unwrap().iter().cloned().collect()
+}
 
 pub fn frame_count() -> u64 {
     OVERLAY.lock().unwrap().as_ref().unwrap().frame_count.load(atomic::Ordering::Relaxed)
 }
 
+/// The time between the last two rendered frames, in milliseconds.
+pub fn frame_time() -> f64 {
+    OVERLAY.lock().unwrap().as_ref().unwrap().frame_timing.lock().unwrap().last()
+}
+
+/// The frames per second, averaged over the last 60 frames.
+pub fn fps() -> f64 {
+    OVERLAY.lock().unwrap().as_ref().unwrap().frame_timing.lock().unwrap().fps()
+}
+
@@ -23,9 +23,14 @@ use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
 use crate::lua::luaL_Reg_list;
 use crate::lua_manager;
+use crate::chat_code::ChatCode;
+use crate::module_permissions::{self, Permission};
 
 use xml::reader::XmlEvent;
 
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 // A Lua value that is passed to queueevent
 // On the Rust side we store a reference  to the Lua value and then
 // use it to push the value back onto the Lua stack when the event is being
@@ -60,40 +65,102 @@ impl lua_manager::ToLua for LuaEventData {
 
 const OVERLAY_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"time"                , time,
+    c"settimer"            , set_timer,
+    c"canceltimer"         , cancel_timer,
+    c"settimeout"          , set_timeout,
+    c"setinterval"         , set_interval,
+    c"cleartimer"          , cancel_timer,
     c"logdebug"            , log_debug,
     c"loginfo"             , log_info,
     c"logwarn"             , log_warn,
     c"logerror"            , log_error,
     c"addeventhandler"     , add_event_handler,
+    c"addeventhandleronce" , add_event_handler_once,
     c"removeeventhandler"  , remove_event_handler,
     c"addkeybindhandler"   , add_keybind_handler,
     c"removekeybindhandler", remove_keybind_handler,
+    c"setkeybindenabled"   , set_keybind_enabled,
+    c"keybinds"            , keybinds,
     c"settings"            , settings,
     c"memusage"            , memusage,
     c"videomemusage"       , videomemusage,
+    c"gpuinfo"             , gpuinfo,
     c"framecount"          , frame_count,
+    c"frametime"           , frame_time,
+    c"fps"                 , fps,
+    c"setidlefps"          , set_idle_fps,
+    c"requestredraw"       , request_redraw,
+    c"setopacity"          , set_opacity,
+    c"setcompositioneffect", set_composition_effect,
+    c"anchor"              , anchor,
+    c"setanchor"           , set_anchor,
     c"processtime"         , process_time,
+    c"handlerstats"        , handler_stats,
+    c"framehitches"        , frame_hitches,
+    c"showperf"            , show_perf,
+    c"screenshot"          , screenshot,
     c"queueevent"          , queue_event,
     c"datafolder"          , data_folder,
+    c"paths"               , paths,
+    c"readfile"            , read_file,
+    c"writefile"           , write_file,
+    c"fileexists"          , file_exists,
+    c"deletefile"          , delete_file,
+    c"renamefile"          , rename_file,
     c"overlaysettings"     , overlay_settings,
 
     c"restart"             , restart,
 
+    c"modulepermissions"   , get_module_permissions,
+    c"setmodulepermission" , set_module_permission,
+
     c"versionstring"       , version_string,
 
     c"clipboardtext"       , clipboard_text,
+    c"openurl"             , open_url,
+    c"keyboardlayout"      , keyboard_layout,
+    c"mousepos"            , mouse_pos,
+    c"capturemousedrag"    , capture_mouse_drag,
+    c"modifierkeys"        , modifier_keys,
+
+    c"getenv"              , get_env,
+    c"readregistry"        , read_registry,
+
+    c"playsound"           , play_sound,
+    c"stopsound"           , stop_sound,
+    c"speak"               , speak,
+    c"stopspeaking"        , stop_speaking,
 
     c"sqlite3open"         , sqlite3_open,
 
     c"webrequest"          , web_request,
+    c"downloadfile"        , download_file,
+    c"cancelwebrequest"    , cancel_web_request,
+    c"webqueuedepth"       , web_queue_depth,
 
     c"parsejson"           , parse_json,
+    c"tojson"              , to_json,
 
     c"openzip"             , open_zip,
+    c"mountzip"            , mount_zip,
+    c"createzip"           , create_zip,
 
     c"parsexml"            , parse_xml,
 
+    c"parsetrl"            , parse_trl,
+
     c"splitstring"         , split_string,
+
+    c"tonum"               , to_num,
+    c"toint"               , to_int,
+
+    c"parsechatcode"       , parse_chat_code,
+    c"makechatcode"        , make_chat_code,
+
+    c"parsecolor"          , parse_color,
+    c"formatcolor"         , format_color,
+
+    c"rng"                 , rng,
 };
 
 pub unsafe extern "C" fn open_module(l: &lua_State) -> i32 {
@@ -128,6 +195,208 @@ unsafe extern "C" fn time(l: &lua_State) -> i32 {
     return 1;
 }
 
+/*** RST
+.. lua:function:: settimer(delay, callback[, interval])
+
+    Call ``callback`` after ``delay`` seconds.
+
+    If ``interval`` is given, the timer will repeat every ``interval`` seconds
+    until it is cancelled with :lua:func:`canceltimer`. Intervals shorter than
+    1 millisecond are treated as 1 millisecond.
+
+    ``callback`` is called with two arguments: the timer ID and the number of
+    seconds the timer fired after it was due. Timers are checked between
+    ``update`` events, so they are much more accurate than comparing
+    :lua:func:`time` during each update.
+
+    If the overlay is delayed long enough that a repeating timer misses more
+    than one interval, it will only fire once and then continue on its
+    original schedule.
+
+    :param number delay: Seconds until the timer fires.
+    :param function callback:
+    :param number interval: (Optional) Seconds between each repeat, must be
+        greater than 0.
+    :returns: A timer ID that can be used with :lua:func:`canceltimer`, or
+        ``nil`` if ``interval`` is not valid.
+    :rtype: integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local function breakbar(id, late)
+            overlay.loginfo(string.format('Break bar! (%.3fs late)', late))
+        end
+
+        -- every 15 seconds, starting in 10 seconds
+        local timer = overlay.settimer(10, breakbar, 15)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_timer(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+
+    let delay = lua::tonumber(l, 1);
+
+    let interval = if lua::gettop(l) >= 3 && lua::luatype(l, 3) != lua::LuaType::LUA_TNIL {
+        lua::checkargnumber!(l, 3);
+        let interval = lua::tonumber(l, 3);
+
+        if !interval.is_finite() || interval <= 0.0 {
+            luaerror!(l, "settimer: interval must be greater than 0.");
+            lua::pushnil(l);
+            return 1;
+        }
+
+        interval
+    } else {
+        0.0
+    };
+
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
+
+    let id = lua_manager::add_timer(delay, interval, cbi);
+
+    lua::pushinteger(l, id as i64);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: canceltimer(id)
+
+    Cancel a timer created by :lua:func:`settimer`.
+
+    :param integer id:
+    :returns: ``true`` if the timer was cancelled, ``false`` if it had already
+        fired or was already cancelled.
+    :rtype: boolean
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn cancel_timer(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
+
+    let id = lua::tointeger(l, 1);
+
+    match lua_manager::cancel_timer(id as u64) {
+        Some(cbi) => {
+            lua::L::unref(l, lua::LUA_REGISTRYINDEX, cbi);
+            lua::pushboolean(l, true);
+        },
+        None => lua::pushboolean(l, false),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: settimeout(seconds, callback)
+
+    Call ``callback`` once after ``seconds``.
+
+    This is the same as ``overlay.settimer(seconds, callback)``, see
+    :lua:func:`settimer`.
+
+    :param number seconds:
+    :param function callback:
+    :returns: A timer ID that can be used with :lua:func:`cleartimer`.
+    :rtype: integer
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_timeout(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+
+    let delay = lua::tonumber(l, 1);
+
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
+
+    let id = lua_manager::add_timer(delay, 0.0, cbi);
+
+    lua::pushinteger(l, id as i64);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: setinterval(seconds, callback)
+
+    Call ``callback`` every ``seconds`` until the timer is cancelled with
+    :lua:func:`cleartimer`.
+
+    This is the same as ``overlay.settimer(seconds, callback, seconds)``, see
+    :lua:func:`settimer`.
+
+    :param number seconds: Must be at least ``0.001``, 1 millisecond.
+    :param function callback:
+    :returns: A timer ID that can be used with :lua:func:`cleartimer`, or
+        ``nil`` if ``seconds`` is not valid.
+    :rtype: integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local ticks = 0
+        local timer
+
+        timer = overlay.setinterval(1, function()
+            ticks = ticks + 1
+
+            if ticks == 10 then overlay.cleartimer(timer) end
+        end)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_interval(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+
+    let interval = lua::tonumber(l, 1);
+
+    if !interval.is_finite() || interval < lua_manager::MIN_TIMER_INTERVAL {
+        luaerror!(l, "setinterval: seconds must be at least {}.", lua_manager::MIN_TIMER_INTERVAL);
+        lua::pushnil(l);
+        return 1;
+    }
+
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
+
+    let id = lua_manager::add_timer(interval, interval, cbi);
+
+    lua::pushinteger(l, id as i64);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: cleartimer(id)
+
+    Cancel a timer created by :lua:func:`settimeout`, :lua:func:`setinterval`
+    or :lua:func:`settimer`. This is the same as :lua:func:`canceltimer`.
+
+    :param integer id:
+    :returns: ``true`` if the timer was cancelled, ``false`` if it had already
+        fired or was already cancelled.
+    :rtype: boolean
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+
 pub fn get_lua_source(l: &lua_State) -> String {
     let mut dbg = lua::lua_Debug::default();
 
@@ -140,8 +409,15 @@ pub fn get_lua_source(l: &lua_State) -> String {
 }
 
 pub fn get_module_name(l: &lua_State) -> String {
+    return module_name_from_source(&get_lua_source(l));
+}
 
-    let src = get_lua_source(l);
+/// Returns a module name, i.e. `mymodule.submodule`, from a Lua chunk source.
+///
+/// If the source isn't a file within the `lua/` directory the source is
+/// returned as-is.
+pub fn module_name_from_source(src: &str) -> String {
+    let src = String::from(src);
 
     if !src.starts_with("@") && !src.starts_with("=") { return src; }
 
@@ -320,15 +596,13 @@ unsafe extern "C" fn log_error(l: &lua_State) -> i32 {
 
         local overlay = require 'overlay'
 
-        -- run on_update_once on only the first update event
-        local update_event_handler = 0
-
-        local function on_update_once(event, data)
+        overlay.addeventhandler('update', function(event, data)
             overlay.loginfo("Update event")
-            overlay.removeeventhandler(update_event_handler)
-        end
+        end)
 
-        update_event_handler = overlay.addeventhandler('update', on_update_once)
+    .. tip::
+        Use :lua:func:`addeventhandleronce` for handlers that should only be
+        called for the first event.
 
     .. versionhistory::
         :0.3.0: Added
@@ -339,7 +613,7 @@ unsafe extern "C" fn add_event_handler(l: &lua_State) -> i32 {
     let event = lua::tostring(l, 1).unwrap();
 
     lua::pushvalue(l, -1);
-    let cbi = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let cbi = module_permissions::ref_callback(l);
 
     lua_manager::add_lua_event_handler(&event, cbi);
 
@@ -348,6 +622,46 @@ unsafe extern "C" fn add_event_handler(l: &lua_State) -> i32 {
     return 1;
 }
 
+/*** RST
+.. lua:function:: addeventhandleronce(event, handler)
+
+    Add an event handler that is called only for the next ``event`` and then
+    removed.
+
+    :param string event: Event type
+    :param function handler: Function to be called on the given event
+    :returns: A callback ID that can be used with :lua:func:`removeeventhandler`
+        to remove the handler before it is called.
+    :rtype: integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        -- only log the first update event
+        overlay.addeventhandleronce('update', function(event, data)
+            overlay.loginfo("First update event")
+        end)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn add_event_handler_once(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+    let event = lua::tostring(l, 1).unwrap();
+
+    lua::pushvalue(l, 2);
+    let cbi = module_permissions::ref_callback(l);
+
+    lua_manager::add_lua_event_handler_once(&event, cbi);
+
+    lua::pushinteger(l, cbi);
+
+    return 1;
+}
+
 /*** RST
 .. lua:function:: removeeventhandler(event, cbi)
 
@@ -384,6 +698,24 @@ unsafe extern "C" fn remove_event_handler(l: &lua_State) -> i32 {
     ``shift-a``, ``ctrl-alt-e``, or ``f``. Individual modifier keys can be bound
     by specifying them directly, ie ``lctrl`` or ``alt-lctrl``.
 
+    The extra mouse buttons and the mouse wheel can also be bound, with or
+    without modifiers, for example ``mouse-x1`` or ``ctrl-mouse-wheelup``:
+
+    ==================== ==========================================
+    Name                 Input
+    ==================== ==========================================
+    ``mouse-x1``         Mouse button 4 (usually 'back')
+    ``mouse-x2``         Mouse button 5 (usually 'forward')
+    ``mouse-wheelup``    Mouse wheel scrolled up
+    ``mouse-wheeldown``  Mouse wheel scrolled down
+    ``mouse-wheelleft``  Mouse wheel tilted left
+    ``mouse-wheelright`` Mouse wheel tilted right
+    ==================== ==========================================
+
+    The left, right and middle mouse buttons can not be bound, so that a module
+    can't break mouse input to the game. Mouse keybinds are only called when
+    the mouse isn't over an overlay UI element.
+
     The handler function will be called every time the corresponding key is
     pressed.
 
@@ -420,6 +752,9 @@ unsafe extern "C" fn remove_event_handler(l: &lua_State) -> i32 {
         -- run onkey everytime ctrl-shift-e is pressed, consuming the event
         overlay.addkeybindhandler('ctrl-shift-e', onkey)
 
+        -- and when the 'back' mouse button is pressed
+        overlay.addkeybindhandler('mouse-x1', onkey)
+
     .. versionhistory::
         :0.3.0: Added
 */
@@ -429,7 +764,7 @@ unsafe extern "C" fn add_keybind_handler(l: &lua_State) -> i32 {
     let keyname = lua::tostring(l, 1).unwrap();
 
     lua::pushvalue(l, -1);
-    let cbi = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let cbi = module_permissions::ref_callback(l);
 
     lua_manager::add_lua_keybind_handler(&keyname, cbi);
 
@@ -461,17 +796,104 @@ unsafe extern "C" fn remove_keybind_handler(l: &lua_State) -> i32 {
     return 0;
 }
 
+/*** RST
+.. lua:function:: setkeybindenabled(cbi, enabled)
+
+    Enable or disable a keybind handler without removing it.
+
+    Disabled handlers are not called and do not consume key events, which are
+    then sent to other handlers and GW2 as if the handler did not exist.
+
+    :param integer cbi: The ID returned by :lua:func:`addkeybindhandler`.
+    :param boolean enabled:
+    :rtype: boolean
+    :returns: ``false`` if ``cbi`` is not a registered keybind handler.
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_keybind_enabled(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TBOOLEAN);
+
+    let cbi = lua::tointeger(l, 1);
+    let enabled = lua::toboolean(l, 2);
+
+    lua::pushboolean(l, lua_manager::set_lua_keybind_handler_enabled(cbi, enabled));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: keybinds()
+
+    Returns a sequence of all registered keybind handlers, sorted by key name.
+    Each is a table with the following fields:
+
+    ======= ===================================================================
+    Field   Description
+    ======= ===================================================================
+    keyname The key name the handler is bound to.
+    cbi     The ID returned by :lua:func:`addkeybindhandler`.
+    enabled ``false`` if the handler was disabled with
+            :lua:func:`setkeybindenabled`.
+    ======= ===================================================================
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        for _, kb in ipairs(overlay.keybinds()) do
+            overlay.loginfo(string.format('%s (%d): %s', kb.keyname, kb.cbi, kb.enabled))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn keybinds(l: &lua_State) -> i32 {
+    let keybinds = lua_manager::lua_keybind_handlers();
+
+    lua::createtable(l, keybinds.len() as i32, 0);
+
+    for (i, (keyname, cbi, enabled)) in keybinds.iter().enumerate() {
+        lua::createtable(l, 0, 3);
+
+        lua::pushstring(l, keyname);
+        lua::setfield(l, -2, "keyname");
+
+        lua::pushinteger(l, *cbi);
+        lua::setfield(l, -2, "cbi");
+
+        lua::pushboolean(l, *enabled);
+        lua::setfield(l, -2, "enabled");
+
+        lua::seti(l, -2, i as i64 + 1);
+    }
+
+    return 1;
+}
+
 /*** RST
 .. lua:function:: settings(name)
 
     Create a :lua:class:`settingsstore` named ``name``.
 
-    This function should be used by modules to create a settings store.
+    This function should be used by modules to create a settings store. Each
+    call with the same ``name`` returns the same store, so handlers added with
+    :lua:meth:`settingsstore.onchange` are called for changes made through any
+    of them.
 
     .. seealso::
 
         The :lua:class:`settingsstore` class.
 
+    ``name`` is used as the file name in the ``settings`` folder and can't
+    contain ``/``, ``\``, ``:`` or ``..``. Restricted modules can't open the
+    overlay's own ``eg-overlay`` settings, see :ref:`module-permissions`.
+
     :param string name: The name of the settings store, typically the name of the module.
     :rtype: settingsstore
 
@@ -482,7 +904,24 @@ unsafe extern "C" fn settings(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 1);
     let name = lua::tostring(l, 1).unwrap();
 
-    let settings = crate::settings::SettingsStore::new(&name);
+    // the name is used as a file name in settings/, it can't be a path
+    if name.is_empty() || name.contains(['/', '\\', ':']) || name.contains("..") {
+        lua::pushstring(l, format!("invalid settings store name: {}", name).as_str());
+        unsafe { lua::error(l) };
+    }
+
+    let settings = if name == "eg-overlay" {
+        // otherwise a restricted module could open a second store on the
+        // overlay's settings and grant itself permissions
+        if !module_permissions::check_trusted(l) {
+            lua::pushstring(l, "restricted modules can't open the eg-overlay settings store");
+            unsafe { lua::error(l) };
+        }
+
+        crate::overlay::settings()
+    } else {
+        crate::settings::SettingsStore::open(&name)
+    };
 
     crate::settings::lua::pushsettings(l, settings);
 
@@ -578,6 +1017,62 @@ unsafe extern "C" fn videomemusage(l: &lua_State) -> i32 {
     return 1;
 }
 
+/*** RST
+.. lua:function:: gpuinfo()
+
+    Returns information about the GPU the overlay is rendering with.
+
+    The returned table has the following fields:
+
+    ======== ==================================================================
+    Field    Description
+    ======== ==================================================================
+    name     The adapter description, i.e. the GPU model.
+    vendorid The PCI vendor ID.
+    deviceid The PCI device ID.
+    budget   The video memory budget, in bytes, when the overlay started.
+    used     The overlay's current video memory usage, in bytes. This is the
+             same as :lua:func:`videomemusage`.
+    ======== ==================================================================
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local gpu = overlay.gpuinfo()
+
+        overlay.loginfo(string.format('%s: %.2f / %.2f MiB', gpu.name, gpu.used / 1024.0 / 1024.0, gpu.budget / 1024.0 / 1024.0))
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn gpuinfo(l: &lua_State) -> i32 {
+    let dx = crate::overlay::dx();
+    let (vendor_id, device_id) = dx.adapter_ids();
+
+    lua::newtable(l);
+
+    lua::pushstring(l, &dx.adapter_description());
+    lua::setfield(l, -2, "name");
+
+    lua::pushinteger(l, vendor_id as i64);
+    lua::setfield(l, -2, "vendorid");
+
+    lua::pushinteger(l, device_id as i64);
+    lua::setfield(l, -2, "deviceid");
+
+    lua::pushinteger(l, dx.memory_budget() as i64);
+    lua::setfield(l, -2, "budget");
+
+    lua::pushinteger(l, dx.get_video_mem_used() as i64);
+    lua::setfield(l, -2, "used");
+
+    return 1;
+}
+
 /*** RST
 .. lua:function:: framecount()
 
@@ -594,706 +1089,3369 @@ unsafe extern "C" fn frame_count(l: &lua_State) -> i32 {
     return 1;
 }
 
-macro_rules! filetime_to_u64 {
-    ($a:ident) => {{
-        ($a.dwHighDateTime as u64) << 32 | ($a.dwLowDateTime as u64)
-    }}
+/*** RST
+.. lua:function:: frametime()
+
+    Returns the time between the last two frames rendered by the overlay, in
+    milliseconds.
+
+    .. note::
+
+        While idle the overlay only renders at the idle FPS, see
+        :lua:func:`setidlefps`, so this will be much higher.
+
+    :rtype: number
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn frame_time(l: &lua_State) -> i32 {
+    lua::pushnumber(l, crate::overlay::frame_time());
+
+    return 1;
 }
 
 /*** RST
-.. lua:function:: processtime()
+.. lua:function:: fps()
 
-    Returns a table containing the CPU time and uptime of the overlay process.
+    Returns the overlay's frame rate, averaged over the last 60 frames.
 
-    This can be used to calculate the overlay's CPU usage. The table has the fields
-    described below:
+    :rtype: number
 
-    +------------------+----------------------------------------------------------+
-    | Field            | Description                                              |
-    +==================+==========================================================+
-    | processtimetotal | The total time the overlay has been running.             |
-    +------------------+----------------------------------------------------------+
-    | usertime         | The total time spent executing code within the overlay.  |
-    +------------------+----------------------------------------------------------+
-    | kerneltime       | The total time spent executing system code for the       |
-    |                  | overlay, not including idle time.                        |
-    +------------------+----------------------------------------------------------+
-    | systemusertime   | The total time spent executing all application code on   |
-    |                  | the system.                                              |
-    +------------------+----------------------------------------------------------+
-    | systemkerneltime | The total time spent executing system code on the system |
-    |                  | including idle time.                                     |
-    +------------------+----------------------------------------------------------+
+    .. code-block:: lua
+        :caption: Example
 
-    :rtype: table
+        local overlay = require 'overlay'
+
+        overlay.loginfo(string.format('%.1f FPS (%.2fms)', overlay.fps(), overlay.frametime()))
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn process_time(l: &lua_State) -> i32 {
-    use std::time::Duration;
-
-    use windows::Win32::Foundation::FILETIME;
-    use windows::Win32::System::Threading::{GetProcessTimes, GetCurrentProcess, GetSystemTimes};
-    use windows::Win32::System::Time::SystemTimeToFileTime;
-    use windows::Win32::System::SystemInformation::GetSystemTime;
+unsafe extern "C" fn fps(l: &lua_State) -> i32 {
+    lua::pushnumber(l, crate::overlay::fps());
 
-    // times for the user process
-    let mut ft_create = FILETIME::default();
-    let mut ft_exit   = FILETIME::default();
-    let mut ft_kernel = FILETIME::default();
-    let mut ft_user   = FILETIME::default();
+    return 1;
+}
 
-    // times for the system
-    //let mut ft_sys_idle   = FILETIME::default();
-    let mut ft_sys_user   = FILETIME::default();
-    let mut ft_sys_kernel = FILETIME::default();
+macro_rules! filetime_to_u64 {
+    ($a:ident) => {{
+        ($a.dwHighDateTime as u64) << 32 | ($a.dwLowDateTime as u64)
+    }}
+}
 
-    unsafe { GetProcessTimes(GetCurrentProcess(), &mut ft_create, &mut ft_exit, &mut ft_kernel, &mut ft_user).unwrap() };
-    unsafe { GetSystemTimes(None, Some(&mut ft_sys_user), Some(&mut ft_sys_kernel)).unwrap() };
+/*** RST
+.. lua:function:: setidlefps(fps)
 
-    // the current time
-    let mut ft_now = FILETIME::default();
-    let st_now = unsafe { GetSystemTime() };
+    Set the frame rate the overlay renders at while idle.
 
-    unsafe { SystemTimeToFileTime(&st_now, &mut ft_now).unwrap() };
+    When nothing that is drawn has changed for a second, i.e. the player and
+    camera haven't moved, there's been no input, and no sprite or trail lists
+    have been modified, the overlay drops to this frame rate until something
+    changes.
 
-    let total  = Duration::from_nanos((filetime_to_u64!(ft_now) - filetime_to_u64!(ft_create)) * 100);
-    let kernel = Duration::from_nanos(filetime_to_u64!(ft_kernel) * 100);
-    let user   = Duration::from_nanos(filetime_to_u64!(ft_user) * 100);
+    The initial value is set by the ``overlay.idleFPS`` setting, ``5`` by
+    default. A value of ``0`` disables idle rendering.
 
-    let sys_user   = Duration::from_nanos(filetime_to_u64!(ft_sys_user) * 100);
-    let sys_kernel = Duration::from_nanos(filetime_to_u64!(ft_sys_kernel) * 100);
+    :param integer fps:
 
-    lua::newtable(l);
+    .. seealso::
 
-    lua::pushnumber(l, total.as_millis() as f64);
-    lua::setfield(l, -2, "processtimetotal");
+        :lua:func:`requestredraw`
 
-    lua::pushnumber(l, user.as_millis() as f64);
-    lua::setfield(l, -2, "usertime");
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_idle_fps(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
 
-    lua::pushnumber(l, kernel.as_millis() as f64);
-    lua::setfield(l, -2, "kerneltime");
+    let fps = lua::tointeger(l, 1);
 
-    lua::pushnumber(l, sys_user.as_millis() as f64);
-    lua::setfield(l, -2, "systemusertime");
+    if fps < 0 {
+        luaerror!(l, "fps must be 0 or greater.");
+        return 0;
+    }
 
-    lua::pushnumber(l, sys_kernel.as_millis() as f64);
-    lua::setfield(l, -2, "systemkerneltime");
+    crate::overlay::set_idle_fps(fps as u32);
 
-    return 1;
+    return 0;
 }
 
 /*** RST
-.. lua:function:: queueevent(event[, data])
-
-    Add a new event to the event queue.
+.. lua:function:: requestredraw()
 
-    .. note::
-
-        Events added with this function will be run before the next frame is
-        rendered.
-
-    .. warning::
-
-        Module authors should take care to use unique event names. It is
-        possible to queue any event with this function, however if proper data
-        is not supplied event handlers may behave in unexpected ways.
+    End idle rendering, if active, and render at the full frame rate.
 
-    :param string event: Event name
-    :param data: (Optional) Event data. This can be any Lua value.
+    Changes to sprite and trail lists are detected automatically, but modules
+    that animate UI elements or otherwise change what is drawn without input
+    should call this each update while animating.
 
     .. code-block:: lua
         :caption: Example
 
         local overlay = require 'overlay'
 
-        local eventdata = {
-            foo = 'bar',
-            baz = true,
-        }
-
-        overlay.queueevent('my-module-event', eventdata)
+        overlay.addeventhandler('update', function()
+            if animating then
+                overlay.requestredraw()
+            end
+        end)
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn queue_event(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 1);
-    let event = lua::tostring(l, 1).unwrap();
-
-    if lua::gettop(l)==1 {
-        lua_manager::queue_event(&event, None);
-    } else {
-        let data = LuaEventData::new(l, 2);
-        lua_manager::queue_event(&event, Some(data));
-    }
+unsafe extern "C" fn request_redraw(_l: &lua_State) -> i32 {
+    crate::overlay::request_redraw();
 
     return 0;
 }
 
 /*** RST
-.. lua:function:: datafolder(name)
+.. lua:function:: setopacity(value)
 
-    Returns the full path to the data folder for the given module.
+    Set the opacity of the entire overlay, everything drawn by the overlay is
+    dimmed at once.
 
-    Modules should store any data other than settings in this folder. The folder
-    will be created by this function if it does not already exist.
+    ``value`` is from ``0.0``, completely transparent, to ``1.0``, fully
+    opaque. Values outside that range are clamped.
 
-    :param string name: The name of the module and corresponding folder.
-    :rtype: string
+    The value is saved in the ``overlay.opacity`` setting and restored the
+    next time the overlay starts.
+
+    :param number value:
 
     .. code-block:: lua
         :caption: Example
 
         local overlay = require 'overlay'
 
-        local modulefolder = overlay.datafolder('my-module')
-
-        local f = io.open(modulefolder .. '/data.txt')
+        -- ghost mode
+        overlay.setopacity(0.4)
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn data_folder(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 1);
-    let name = lua::tostring(l, 1).unwrap();
-
-    let mut path = std::env::current_exe().unwrap();
-
-    path.pop();
-    path.push("data");
-    path.push(name);
+unsafe extern "C" fn set_opacity(l: &lua_State) -> i32 {
+    lua::checkargnumber!(l, 1);
 
-    if let Err(err) = std::fs::create_dir_all(&path) {
-        luaerror!(l, "Couldn't create data directory: {}", err);
-        return 0;
-    }
+    crate::overlay::set_opacity(lua::tonumber(l, 1));
 
-    lua::pushstring(l, path.to_str().unwrap());
-
-    return 1;
+    return 0;
 }
 
 /*** RST
-.. lua:function:: overlaysettings()
-
-    Return the :lua:class:`settingsstore` for the overlay.
-
-    This contains core settings for the overlay and UI.
-
-    :rtype: settingsstore
+.. lua:function:: setcompositioneffect(kind[, params])
+
+    Apply an effect to the entire overlay.
+
+    Like :lua:func:`setopacity`, effects are applied by the system compositor
+    (DirectComposition) to everything the overlay draws. Effects only apply to
+    the overlay's own content, the game behind the overlay can't be blurred or
+    otherwise changed.
+
+    Only one effect is active at a time, setting an effect replaces the
+    previous one. ``fade`` is the exception, it changes the overlay's opacity
+    and does not replace or remove other effects.
+
+    ``kind`` is one of the following, ``params`` is a table with the listed
+    fields, all of which are optional:
+
+    +------------+----------------------------------------------------------+
+    | kind       | Description and params                                   |
+    +============+==========================================================+
+    | none       | Remove the current effect.                               |
+    +------------+----------------------------------------------------------+
+    | blur       | A gaussian blur.                                         |
+    |            |                                                          |
+    |            | ``radius``: the standard deviation in pixels, default    |
+    |            | ``4.0``.                                                 |
+    +------------+----------------------------------------------------------+
+    | saturation | Desaturate colors.                                       |
+    |            |                                                          |
+    |            | ``value``: from ``0.0``, grayscale (default), to ``1.0``,|
+    |            | unchanged.                                               |
+    +------------+----------------------------------------------------------+
+    | shadow     | A shadow around everything drawn, which can make text    |
+    |            | easier to read over bright scenes.                       |
+    |            |                                                          |
+    |            | ``radius``: the size of the shadow, default ``4.0``.     |
+    |            |                                                          |
+    |            | ``color``: the :ref:`color <colors>` of the shadow,      |
+    |            | default ``0x000000FF``.                                  |
+    +------------+----------------------------------------------------------+
+    | fade       | Animate the overlay's opacity.                           |
+    |            |                                                          |
+    |            | ``opacity``: the final opacity, default ``1.0``.         |
+    |            |                                                          |
+    |            | ``duration``: the length of the fade in seconds, default |
+    |            | ``0.25``.                                                |
+    +------------+----------------------------------------------------------+
 
-    .. versionhistory::
-        :0.3.0: Added
-*/
-unsafe extern "C" fn overlay_settings(l: &lua_State) -> i32 {
-    let settings = crate::overlay::settings();
+    .. important::
 
-    crate::settings::lua::pushsettings(l, settings);
+        ``blur`` and ``shadow`` are rendered by the compositor each time the
+        overlay is composited, not just when it changes, and their cost
+        increases with the radius and the size of the overlay. On lower end
+        GPUs a large radius can noticeably affect the game's frame rate.
+        ``saturation`` and ``fade`` are inexpensive.
 
-    return 1;
-}
+    :param string kind:
+    :param table params: (Optional)
 
-/*** RST
-.. lua:function:: restart()
+    .. code-block:: lua
+        :caption: Example
 
-    Restart the overlay.
+        local overlay = require 'overlay'
 
-    .. danger::
+        overlay.setcompositioneffect('shadow', { radius = 2.0, color = 0x000000C0 })
 
-        This will shut down the overlay and restart it immediately.
+        -- fade out over half a second
+        overlay.setcompositioneffect('fade', { opacity = 0.0, duration = 0.5 })
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn restart(_l: &lua_State) -> i32 {
-    crate::overlay::restart();
+unsafe extern "C" fn set_composition_effect(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
 
-    return 0;
-}
+    let kind = lua::tostring(l, 1).unwrap();
 
-/*** RST
-.. lua:function:: versionstring()
+    let has_params = lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL;
 
-    Returns a string containing the EG-Overlay version.
+    if has_params {
+        lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    }
 
-    For example: ``"0.3.0-dev"``.
+    let number_param = |name: &str, default: f64| -> f64 {
+        if !has_params { return default; }
 
-    :rtype: string
+        let v = if lua::getfield(l, 2, name) != lua::LuaType::LUA_TNIL { lua::tonumber(l, -1) } else { default };
+        lua::pop(l, 1);
 
-    .. versionhistory::
-        :0.3.0: Added
-*/
-unsafe extern "C" fn version_string(l: &lua_State) -> i32 {
-    lua::pushstring(l, crate::version::VERSION_STR);
+        v
+    };
 
-    return 1;
+    let effect = match kind.as_str() {
+        "none"       => crate::dx::CompositionEffect::None,
+        "blur"       => crate::dx::CompositionEffect::Blur { radius: number_param("radius", 4.0) as f32 },
+        "saturation" => crate::dx::CompositionEffect::Saturation { value: number_param("value", 0.0) as f32 },
+        "shadow"     => crate::dx::CompositionEffect::Shadow {
+            radius: number_param("radius", 4.0) as f32,
+            color: crate::ui::Color::from(number_param("color", 0x000000FF as f64) as u32),
+        },
+        "fade"       => crate::dx::CompositionEffect::Fade {
+            opacity: number_param("opacity", 1.0) as f32,
+            duration: number_param("duration", 0.25),
+        },
+        _            => {
+            luaerror!(l, "Unknown composition effect: {}", kind);
+            return 0;
+        }
+    };
+
+    crate::overlay::dx().set_composition_effect(&effect);
+
+    return 0;
 }
 
+/// A module defined anchor, see setanchor.
+struct Anchor {
+    x: f64,
+    y: f64,
+    offset_x: f64,
+    offset_y: f64,
+}
 
-/*** RST
-.. lua:function:: clipboardtext([text])
+static ANCHORS: Mutex<Option<HashMap<String, Anchor>>> = Mutex::new(None);
 
-    Set or return the text on the clipboard.
+// the approximate height of the skill bar at the normal interface size
+const SKILLBAR_HEIGHT: f64 = 110.0;
 
-    :param string text: (Optional)
+/*** RST
+.. lua:function:: anchor(name)
+
+    Returns the screen position of a named anchor point, in pixels.
+
+    Anchors are computed from the current size of the overlay and the game's
+    interface size, so UI positioned relative to them stays in the same place
+    relative to the game's UI across resolutions and interface sizes.
+
+    The following anchors are built in:
+
+    ================================================ ===========================
+    Name                                             Position
+    ================================================ ===========================
+    top-left, top, top-right                         The corners and middle of
+                                                     the top edge.
+    left, center, right                              The middle of the left
+                                                     edge, screen and right edge.
+    bottom-left, bottom, bottom-right                The corners and middle of
+                                                     the bottom edge.
+    compass                                          The top left corner of the
+                                                     compass (minimap). The
+                                                     width and height of the
+                                                     compass are also returned.
+    skillbar                                         The approximate top center
+                                                     of the skill bar.
+    ================================================ ===========================
+
+    Anchors added with :lua:func:`setanchor` are also available.
+
+    Returns ``nil`` if the anchor doesn't exist, or if it depends on
+    MumbleLink data that isn't available yet, i.e. ``compass`` before the
+    player is in game.
+
+    :param string name:
+    :return: x, y
+    :rtype: number
 
     .. code-block:: lua
         :caption: Example
 
         local overlay = require 'overlay'
 
-        -- get the text from the clipboard
-        local t = overlay.clipboardtext()
-
-        overlay.loginfo(string.format("Got text from clipboard: %s", t))
+        local x, y = overlay.anchor('skillbar')
 
-        -- set the text on the clipboard
-        overlay.clipboardtext("Hello world from EG-Overlay!")
+        -- just above the skill bar
+        win:position(math.floor(x - win:width() / 2), math.floor(y - win:height()))
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn clipboard_text(l: &lua_State) -> i32 {
-    if lua::gettop(l) >= 1 {
-        if let Some(text) = lua::tostring(l, 1) {
-            crate::utils::set_clipboard_text(&text);
-        } else {
-            luaerror!(l, "clipboardtext argument #1 must be a string.");
-        }
-        return 0;
-    } else {
-        if let Some(text) = crate::utils::get_clipboard_text() {
-            lua::pushstring(l, &text);
-        } else {
+unsafe extern "C" fn anchor(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+
+    let name = lua::tostring(l, 1).unwrap();
+
+    let (width, height) = crate::overlay::ui().get_last_ui_size();
+    let (w, h) = (width as f64, height as f64);
+
+    let ml = crate::overlay::ml();
+
+    let ui_scale = ml.ui_scale().unwrap_or(1.0) as f64;
+
+    let pos: Option<(f64, f64)> = match name.as_str() {
+        "top-left"     => Some((0.0      , 0.0      )),
+        "top"          => Some((w / 2.0  , 0.0      )),
+        "top-right"    => Some((w        , 0.0      )),
+        "left"         => Some((0.0      , h / 2.0  )),
+        "center"       => Some((w / 2.0  , h / 2.0  )),
+        "right"        => Some((w        , h / 2.0  )),
+        "bottom-left"  => Some((0.0      , h        )),
+        "bottom"       => Some((w / 2.0  , h        )),
+        "bottom-right" => Some((w        , h        )),
+        "skillbar"     => Some((w / 2.0  , h - (SKILLBAR_HEIGHT * ui_scale))),
+        "compass"      => {
+            match ml.compass_rect(width, height) {
+                Some((left, top, cw, ch)) => {
+                    lua::pushnumber(l, left as f64);
+                    lua::pushnumber(l, top as f64);
+                    lua::pushnumber(l, cw as f64);
+                    lua::pushnumber(l, ch as f64);
+
+                    return 4;
+                },
+                None => None,
+            }
+        },
+        _ => {
+            match ANCHORS.lock().unwrap().as_ref().and_then(|a| a.get(&name)) {
+                Some(a) => Some((
+                    (a.x * w) + (a.offset_x * ui_scale),
+                    (a.y * h) + (a.offset_y * ui_scale),
+                )),
+                None => None,
+            }
+        },
+    };
+
+    match pos {
+        Some((x, y)) => {
+            lua::pushnumber(l, x);
+            lua::pushnumber(l, y);
+
+            return 2;
+        },
+        None => {
             lua::pushnil(l);
-        }
 
-        return 1;
+            return 1;
+        },
     }
 }
 
 /*** RST
-.. lua:function:: sqlite3open(db)
+.. lua:function:: setanchor(name[, x, y[, offsetx, offsety]])
 
-    Open or create a SQLite3 database.
+    Add or remove an anchor that can be retrieved with :lua:func:`anchor`.
 
-    .. seealso::
-        The :lua:class:`sqlite3db` class.
+    ``x`` and ``y`` are fractions of the overlay's size, i.e. ``0.5, 0.5`` is
+    the center of the screen. ``offsetx`` and ``offsety`` are in pixels at the
+    normal interface size and are scaled with the game's interface size.
 
-    :param string db: The SQLite3 database path or connection string.
+    If only ``name`` is given, the anchor is removed. Anchors are shared by
+    all modules and the built in anchors can't be replaced.
 
-    :rtype: sqlite3db
+    :param string name:
+    :param number x: (Optional)
+    :param number y: (Optional)
+    :param number offsetx: (Optional) Default ``0``.
+    :param number offsety: (Optional) Default ``0``.
 
     .. code-block:: lua
         :caption: Example
 
         local overlay = require 'overlay'
 
-        local db = overlay.open('some-data.db')
+        -- 200 pixels left of the top center, below the target frame
+        overlay.setanchor('mymodule.targetinfo', 0.5, 0.0, -200, 120)
 
     .. versionhistory::
-        :0.3.0:
+        :0.3.0: Added
 */
-unsafe extern "C" fn sqlite3_open(l: &lua_State) -> i32 {
-    unsafe { crate::lua_sqlite3::sqlite3_open(l) }
-}
-
-/*** RST
-.. lua:function:: webrequest(url, headers, query_params, callback)
+unsafe extern "C" fn set_anchor(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
 
-    Queue a web request to the given URL.
+    let name = lua::tostring(l, 1).unwrap();
 
-    Requests are completed asynchronously, with the results provided to ``callback``.
+    match name.as_str() {
+        "top-left" | "top" | "top-right" |
+        "left" | "center" | "right" |
+        "bottom-left" | "bottom" | "bottom-right" |
+        "compass" | "skillbar" => {
+            luaerror!(l, "{} is a built in anchor and can't be replaced.", name);
+            return 0;
+        },
+        _ => {},
+    }
 
-    :param string url: The full URL. Query parameters can be excluded if they are
-        supplied in ``query_params``.
-    :param table headers: A list of headers to add to the request.
-    :param table query_params: A list of query parameters to add to the URL.
-    :param function callback: A function that will be called when the request is
-        completed. This function will be called with 3 arguments: the response body
-        or ``nil`` if the request failed, the HTTP status code, and a table
-        containing the response headers.
+    let mut anchors = ANCHORS.lock().unwrap();
+    let anchors = anchors.get_or_insert_with(HashMap::new);
 
-    .. note::
-        Web requests are currently assumed to be HTTP(S).
+    if lua::gettop(l) == 1 {
+        anchors.remove(&name);
+        return 0;
+    }
 
-    .. warning::
-        Do not mix ``query_params`` with parameters supplied in ``url``. This
-        function does not check if ``url`` already contains parameters, it simply
-        appends ``query_params`` if it contains values.
+    lua::checkargnumber!(l, 2);
+    lua::checkargnumber!(l, 3);
 
-    .. important::
-        All web requests are logged, with the path to the Lua source and line number
-        of the ``webrequest`` call.
+    let offset_x = if lua::gettop(l) >= 4 { lua::checkargnumber!(l, 4); lua::tonumber(l, 4) } else { 0.0 };
+    let offset_y = if lua::gettop(l) >= 5 { lua::checkargnumber!(l, 5); lua::tonumber(l, 5) } else { 0.0 };
 
-        The author of EG-Overlay believes that users
-        should be able to easily understand when and where web requests are sent.
+    anchors.insert(name, Anchor {
+        x: lua::tonumber(l, 2),
+        y: lua::tonumber(l, 3),
+        offset_x: offset_x,
+        offset_y: offset_y,
+    });
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: processtime()
+
+    Returns a table containing the CPU time and uptime of the overlay process.
+
+    This can be used to calculate the overlay's CPU usage. The table has the fields
+    described below:
+
+    +------------------+----------------------------------------------------------+
+    | Field            | Description                                              |
+    +==================+==========================================================+
+    | processtimetotal | The total time the overlay has been running.             |
+    +------------------+----------------------------------------------------------+
+    | usertime         | The total time spent executing code within the overlay.  |
+    +------------------+----------------------------------------------------------+
+    | kerneltime       | The total time spent executing system code for the       |
+    |                  | overlay, not including idle time.                        |
+    +------------------+----------------------------------------------------------+
+    | systemusertime   | The total time spent executing all application code on   |
+    |                  | the system.                                              |
+    +------------------+----------------------------------------------------------+
+    | systemkerneltime | The total time spent executing system code on the system |
+    |                  | including idle time.                                     |
+    +------------------+----------------------------------------------------------+
+
+    :rtype: table
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn process_time(l: &lua_State) -> i32 {
+    use std::time::Duration;
+
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::System::Threading::{GetProcessTimes, GetCurrentProcess, GetSystemTimes};
+    use windows::Win32::System::Time::SystemTimeToFileTime;
+    use windows::Win32::System::SystemInformation::GetSystemTime;
+
+    // times for the user process
+    let mut ft_create = FILETIME::default();
+    let mut ft_exit   = FILETIME::default();
+    let mut ft_kernel = FILETIME::default();
+    let mut ft_user   = FILETIME::default();
+
+    // times for the system
+    //let mut ft_sys_idle   = FILETIME::default();
+    let mut ft_sys_user   = FILETIME::default();
+    let mut ft_sys_kernel = FILETIME::default();
+
+    unsafe { GetProcessTimes(GetCurrentProcess(), &mut ft_create, &mut ft_exit, &mut ft_kernel, &mut ft_user).unwrap() };
+    unsafe { GetSystemTimes(None, Some(&mut ft_sys_user), Some(&mut ft_sys_kernel)).unwrap() };
+
+    // the current time
+    let mut ft_now = FILETIME::default();
+    let st_now = unsafe { GetSystemTime() };
+
+    unsafe { SystemTimeToFileTime(&st_now, &mut ft_now).unwrap() };
+
+    let total  = Duration::from_nanos((filetime_to_u64!(ft_now) - filetime_to_u64!(ft_create)) * 100);
+    let kernel = Duration::from_nanos(filetime_to_u64!(ft_kernel) * 100);
+    let user   = Duration::from_nanos(filetime_to_u64!(ft_user) * 100);
+
+    let sys_user   = Duration::from_nanos(filetime_to_u64!(ft_sys_user) * 100);
+    let sys_kernel = Duration::from_nanos(filetime_to_u64!(ft_sys_kernel) * 100);
+
+    lua::newtable(l);
+
+    lua::pushnumber(l, total.as_millis() as f64);
+    lua::setfield(l, -2, "processtimetotal");
+
+    lua::pushnumber(l, user.as_millis() as f64);
+    lua::setfield(l, -2, "usertime");
+
+    lua::pushnumber(l, kernel.as_millis() as f64);
+    lua::setfield(l, -2, "kerneltime");
+
+    lua::pushnumber(l, sys_user.as_millis() as f64);
+    lua::setfield(l, -2, "systemusertime");
+
+    lua::pushnumber(l, sys_kernel.as_millis() as f64);
+    lua::setfield(l, -2, "systemkerneltime");
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: handlerstats()
+
+    Returns a table containing the execution time of event handlers, by
+    module name.
+
+    Each time an event handler is called, or a coroutine started by an event
+    handler is resumed, the time it takes is recorded. If that time exceeds the
+    ``overlay.eventHandlerBudget`` setting (10 milliseconds by default) a
+    warning is logged that includes the module and event.
+
+    Each value in the returned table is a table with the fields below:
+
+    +------------+---------------------------------------------------------+
+    | Field      | Description                                             |
+    +============+=========================================================+
+    | calls      | The number of handler calls and coroutine resumes.      |
+    +------------+---------------------------------------------------------+
+    | totaltime  | The total time spent in handlers, in milliseconds.      |
+    +------------+---------------------------------------------------------+
+    | maxtime    | The longest single call, in milliseconds.               |
+    +------------+---------------------------------------------------------+
+    | overbudget | The number of calls that exceeded the budget.           |
+    +------------+---------------------------------------------------------+
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        for name, stats in pairs(overlay.handlerstats()) do
+            overlay.loginfo(string.format('%s: %.2fms average', name, stats.totaltime / stats.calls))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn handler_stats(l: &lua_State) -> i32 {
+    lua::newtable(l);
+
+    for (module, stats) in lua_manager::handler_stats() {
+        lua::newtable(l);
+
+        lua::pushinteger(l, stats.calls as i64);
+        lua::setfield(l, -2, "calls");
+
+        lua::pushnumber(l, stats.total_time);
+        lua::setfield(l, -2, "totaltime");
+
+        lua::pushnumber(l, stats.max_time);
+        lua::setfield(l, -2, "maxtime");
+
+        lua::pushinteger(l, stats.over_budget as i64);
+        lua::setfield(l, -2, "overbudget");
+
+        lua::setfield(l, -2, &module);
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: framehitches()
+
+    Returns a sequence of the most recent frames that took too long to render,
+    oldest first.
+
+    A frame is a hitch when it takes longer than the ``overlay.frameTargetTime``
+    setting multiplied by the ``overlay.frameHitchThreshold`` setting (``2.0``
+    by default). A threshold of ``0`` disables hitch detection. Each hitch is
+    also logged at the debug level. Up to 32 hitches are kept.
+
+    Each value is a table with the fields below, all times are in milliseconds:
+
+    +----------+-----------------------------------------------------------+
+    | Field    | Description                                               |
+    +==========+===========================================================+
+    | time     | The overlay uptime when the frame started, in seconds.    |
+    +----------+-----------------------------------------------------------+
+    | duration | The total time spent on the frame.                        |
+    +----------+-----------------------------------------------------------+
+    | phase    | The name of the phase below that took the longest.        |
+    +----------+-----------------------------------------------------------+
+    | gpu      | Time spent waiting for the GPU to finish the last frame.  |
+    +----------+-----------------------------------------------------------+
+    | dx       | Time spent rendering :lua:mod:`dx` sprites and trails.    |
+    +----------+-----------------------------------------------------------+
+    | ui       | Time spent rendering the UI.                              |
+    +----------+-----------------------------------------------------------+
+    | capture  | Time spent rendering :lua:func:`dx.capture` images.       |
+    +----------+-----------------------------------------------------------+
+    | present  | Time spent submitting and presenting the frame.           |
+    +----------+-----------------------------------------------------------+
+
+    .. note::
+
+        Event handlers run on a separate thread and are not included in frame
+        times, see :lua:func:`handlerstats`.
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        for _, h in ipairs(overlay.framehitches()) do
+            overlay.loginfo(string.format('%.2fms frame, mostly %s', h.duration, h.phase))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn frame_hitches(l: &lua_State) -> i32 {
+    let hitches = crate::overlay::frame_hitches();
+
+    lua::createtable(l, hitches.len() as i32, 0);
+
+    for (i, hitch) in hitches.iter().enumerate() {
+        lua::createtable(l, 0, 8);
+
+        lua::pushnumber(l, hitch.time);
+        lua::setfield(l, -2, "time");
+
+        lua::pushnumber(l, hitch.duration);
+        lua::setfield(l, -2, "duration");
+
+        lua::pushstring(l, hitch.phase());
+        lua::setfield(l, -2, "phase");
+
+        lua::pushnumber(l, hitch.gpu_time);
+        lua::setfield(l, -2, "gpu");
+
+        lua::pushnumber(l, hitch.dx_time);
+        lua::setfield(l, -2, "dx");
+
+        lua::pushnumber(l, hitch.ui_time);
+        lua::setfield(l, -2, "ui");
+
+        lua::pushnumber(l, hitch.capture_time);
+        lua::setfield(l, -2, "capture");
+
+        lua::pushnumber(l, hitch.present_time);
+        lua::setfield(l, -2, "present");
+
+        lua::seti(l, -2, i as i64 + 1);
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: showperf(show)
+
+    Show or hide the built-in performance HUD.
+
+    The HUD is drawn by the overlay itself in the top right corner and shows:
+
+    - FPS over the last second
+    - The last and longest frame times, with a graph of recent frame times.
+      Frames longer than the frame target time are shown in red.
+    - Video memory usage
+    - The number of draw calls made in the previous frame
+    - The number of sprites and trails skipped by frustum culling in the
+      previous frame, see :lua:meth:`dxspritelist.frustumcull`
+
+    The HUD can also be toggled with the hotkey in the ``overlay.ui.perfHotkey``
+    setting, ``ctrl-shift-f12`` by default. Set it to an empty string to
+    disable the hotkey.
+
+    .. note::
+
+        When nothing on screen is changing the overlay only renders at the
+        idle FPS, see :lua:func:`setidlefps`. The HUD is not updated between
+        these frames.
+
+    :param boolean show:
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        overlay.showperf(true)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn show_perf(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 1, lua::LuaType::LUA_TBOOLEAN);
+
+    crate::overlay::ui().perf.set_visible(lua::toboolean(l, 1));
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: screenshot(path)
+
+    Save an image of everything the overlay draws to a PNG file.
+
+    The image is taken from the next frame and written shortly after, so the
+    file will not exist yet when this function returns. Success or failure is
+    logged. It contains only the overlay, not the game, and areas where nothing
+    is drawn are transparent.
+
+    This is useful for bug reports or for generating thumbnails.
+
+    :param string path: The file to write, relative paths are relative to the
+        overlay's working directory. An existing file is overwritten.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        overlay.screenshot(overlay.datafolder('my-module') .. '/capture.png')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn screenshot(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+
+    let path = lua::tostring(l, 1).unwrap();
+
+    crate::overlay::request_screenshot(&path);
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: queueevent(event[, data])
+
+    Add a new event to the event queue.
+
+    .. note::
+
+        Events added with this function will be run before the next frame is
+        rendered.
+
+    .. warning::
+
+        Module authors should take care to use unique event names. It is
+        possible to queue any event with this function, however if proper data
+        is not supplied event handlers may behave in unexpected ways.
+
+    :param string event: Event name
+    :param data: (Optional) Event data. This can be any Lua value.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local eventdata = {
+            foo = 'bar',
+            baz = true,
+        }
+
+        overlay.queueevent('my-module-event', eventdata)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn queue_event(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let event = lua::tostring(l, 1).unwrap();
+
+    if lua::gettop(l)==1 {
+        lua_manager::queue_event(&event, None);
+    } else {
+        let data = LuaEventData::new(l, 2);
+        lua_manager::queue_event(&event, Some(data));
+    }
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: datafolder(name)
+
+    Returns the full path to the data folder for the given module.
+
+    Modules should store any data other than settings in this folder. The folder
+    will be created by this function if it does not already exist.
+
+    :param string name: The name of the module and corresponding folder.
+    :rtype: string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local modulefolder = overlay.datafolder('my-module')
+
+        local f = io.open(modulefolder .. '/data.txt')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn data_folder(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let name = lua::tostring(l, 1).unwrap();
+
+    let mut path = std::env::current_exe().unwrap();
+
+    path.pop();
+    path.push("data");
+    path.push(name);
+
+    if let Err(err) = std::fs::create_dir_all(&path) {
+        luaerror!(l, "Couldn't create data directory: {}", err);
+        return 0;
+    }
+
+    lua::pushstring(l, path.to_str().unwrap());
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: paths()
+
+    Returns a table containing the full paths to the folders used by the overlay.
+
+    ========= ==================================================================
+    Field     Description
+    ========= ==================================================================
+    install   The folder containing the overlay executable.
+    data      The root data folder, which contains each module's
+              :lua:func:`datafolder`.
+    config    The folder containing settings files, see :lua:func:`settings`.
+    logs      The folder containing ``eg-overlay.log``.
+    ========= ==================================================================
+
+    The ``data`` and ``config`` folders will be created by this function if
+    they do not already exist.
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local paths = overlay.paths()
+
+        overlay.loginfo(string.format('Settings are stored in %s', paths.config))
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn paths(l: &lua_State) -> i32 {
+    let mut install = std::env::current_exe().unwrap();
+    install.pop();
+
+    let mut data = install.clone();
+    data.push("data");
+
+    // settings and the log file are relative to the working directory
+    let logs = std::env::current_dir().unwrap();
+
+    let mut config = logs.clone();
+    config.push("settings");
+
+    for dir in [&data, &config] {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            luaerror!(l, "Couldn't create {}: {}", dir.display(), err);
+            return 0;
+        }
+    }
+
+    lua::createtable(l, 0, 4);
+
+    lua::pushstring(l, install.to_str().unwrap());
+    lua::setfield(l, -2, "install");
+
+    lua::pushstring(l, data.to_str().unwrap());
+    lua::setfield(l, -2, "data");
+
+    lua::pushstring(l, config.to_str().unwrap());
+    lua::setfield(l, -2, "config");
+
+    lua::pushstring(l, logs.to_str().unwrap());
+    lua::setfield(l, -2, "logs");
+
+    return 1;
+}
+
+/// Reads a boolean `name` from the options table at `ind`, if present.
+///
+/// If `path` ends in `.gz`, the default is `true`.
+fn file_gzip_option(l: &lua_State, ind: i32, name: &str, path: &str) -> bool {
+    let mut gzip = path.to_lowercase().ends_with(".gz");
+
+    if lua::gettop(l) >= ind && lua::luatype(l, ind) != lua::LuaType::LUA_TNIL {
+        if lua::getfield(l, ind, name) != lua::LuaType::LUA_TNIL { gzip = lua::toboolean(l, -1); }
+        lua::pop(l, 1);
+    }
+
+    return gzip;
+}
+
+/*** RST
+.. lua:function:: readfile(path[, options])
+
+    Read the entire contents of the file at ``path``.
+
+    The contents are returned as-is, including any nulls, so this can be used to
+    read binary files.
+
+    If ``path`` ends in ``.gz`` or the ``decompress`` option is ``true`` the
+    file will be decompressed with gzip after being read.
+
+    ``options`` is an optional table with the following fields:
+
+    ========== =================================================================
+    Field      Description
+    ========== =================================================================
+    decompress ``true`` to gunzip the data, ``false`` to return it as-is.
+               Defaults to ``true`` for paths ending in ``.gz``.
+    ========== =================================================================
+
+    :param string path:
+    :param table options: (Optional)
+    :return: The file contents, or ``nil`` if the file could not be read or
+        decompressed.
+    :rtype: string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local data = overlay.readfile(overlay.datafolder('my-module') .. '/cache.json.gz')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn read_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    }
+
+    let decompress = file_gzip_option(l, 2, "decompress", &path);
+
+    let mut data = match std::fs::read(&path) {
+        Ok(d) => d,
+        Err(err) => {
+            luaerror!(l, "Couldn't read {}: {}", path, err);
+            return 0;
+        }
+    };
+
+    if decompress {
+        data = match crate::utils::gzip_decompress(&data) {
+            Ok(d) => d,
+            Err(err) => {
+                luaerror!(l, "Couldn't decompress {}: {}", path, err);
+                return 0;
+            }
+        };
+    }
+
+    let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const i8, data.len()) };
+    lua::pushbytes(l, bytes);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: writefile(path, data[, options])
+
+    Write ``data`` to the file at ``path``, replacing any existing contents.
+
+    ``data`` may contain binary data, including nulls. The file is written
+    atomically: the data is written to a temporary file that then replaces
+    ``path``, so ``path`` will never contain partially written data.
+
+    If ``path`` ends in ``.gz`` or the ``compress`` option is ``true`` the data
+    will be compressed with gzip before being written.
+
+    ``options`` is an optional table with the following fields:
+
+    ======== ===================================================================
+    Field    Description
+    ======== ===================================================================
+    compress ``true`` to gzip the data, ``false`` to write it as-is. Defaults
+             to ``true`` for paths ending in ``.gz``.
+    ======== ===================================================================
+
+    :param string path:
+    :param string data:
+    :param table options: (Optional)
+    :return: ``true`` if the file was written.
+    :rtype: boolean
+
+    .. code-block:: lua
+        :caption: Example
+
+        local path = overlay.datafolder('my-module') .. '/cache.json'
+
+        overlay.writefile(path, data, { compress = true })
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn write_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    lua::checkargstring!(l, 2);
+
+    let path = lua::tostring(l, 1).unwrap();
+    let data = lua::tobytes::<u8>(l, 2);
+
+    if lua::gettop(l) >= 3 && lua::luatype(l, 3) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+    }
+
+    let compress = file_gzip_option(l, 3, "compress", &path);
+
+    let r = if compress {
+        match crate::utils::gzip_compress(data) {
+            Ok(d)    => crate::utils::write_file_atomic(&path, &d),
+            Err(err) => {
+                luaerror!(l, "Couldn't compress data for {}: {}", path, err);
+                lua::pushboolean(l, false);
+                return 1;
+            }
+        }
+    } else {
+        crate::utils::write_file_atomic(&path, data)
+    };
+
+    if let Err(err) = r {
+        luaerror!(l, "Couldn't write {}: {}", path, err);
+        lua::pushboolean(l, false);
+        return 1;
+    }
+
+    lua::pushboolean(l, true);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: fileexists(path)
+
+    Returns ``true`` if ``path`` is an existing file.
+
+    :param string path:
+    :rtype: boolean
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn file_exists(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    lua::pushboolean(l, std::path::Path::new(&path).is_file());
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: deletefile(path)
+
+    Delete the file at ``path``.
+
+    :param string path:
+    :return: ``true`` if the file was deleted.
+    :rtype: boolean
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn delete_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    if let Err(err) = std::fs::remove_file(&path) {
+        luaerror!(l, "Couldn't delete {}: {}", path, err);
+        lua::pushboolean(l, false);
+        return 1;
+    }
+
+    lua::pushboolean(l, true);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: renamefile(from, to)
+
+    Rename or move the file at ``from`` to ``to``, replacing ``to`` if it
+    already exists.
+
+    :param string from:
+    :param string to:
+    :return: ``true`` if the file was renamed.
+    :rtype: boolean
+
+    .. code-block:: lua
+        :caption: Example
+
+        local folder = overlay.datafolder('my-module')
+
+        overlay.renamefile(folder .. '/cache.json', folder .. '/cache.old.json')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn rename_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    lua::checkargstring!(l, 2);
+
+    let from = lua::tostring(l, 1).unwrap();
+    let to = lua::tostring(l, 2).unwrap();
+
+    if let Err(err) = std::fs::rename(&from, &to) {
+        luaerror!(l, "Couldn't rename {} to {}: {}", from, to, err);
+        lua::pushboolean(l, false);
+        return 1;
+    }
+
+    lua::pushboolean(l, true);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: overlaysettings()
+
+    Return the :lua:class:`settingsstore` for the overlay.
+
+    This contains core settings for the overlay and UI.
+
+    :rtype: settingsstore
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn overlay_settings(l: &lua_State) -> i32 {
+    let settings = crate::overlay::settings();
+
+    crate::settings::lua::pushsettings(l, settings);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: restart()
+
+    Restart the overlay.
+
+    .. danger::
+
+        This will shut down the overlay and restart it immediately.
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn restart(_l: &lua_State) -> i32 {
+    crate::overlay::restart();
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: modulepermissions()
+
+    Returns the permissions that have been granted or denied to modules listed
+    in the ``overlay.modules`` setting. See :ref:`module-permissions`.
+
+    The result is a table with a key for each module, each of which is a table
+    of permission names and a boolean indicating if the permission is granted.
+
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        for module, perms in pairs(overlay.modulepermissions()) do
+            for perm, granted in pairs(perms) do
+                overlay.loginfo(string.format('%s %s: %s', module, perm, granted))
+            end
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn get_module_permissions(l: &lua_State) -> i32 {
+    lua::newtable(l);
+
+    for (module, perm, granted) in module_permissions::stored() {
+        if lua::getfield(l, -1, &module) == lua::LuaType::LUA_TNIL {
+            lua::pop(l, 1);
+            lua::newtable(l);
+            lua::pushvalue(l, -1);
+            lua::setfield(l, -3, &module);
+        }
+
+        lua::pushboolean(l, granted);
+        lua::setfield(l, -2, &perm);
+
+        lua::pop(l, 1);
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: setmodulepermission(module, permission, granted)
+
+    Grant or revoke a permission for a module listed in the ``overlay.modules``
+    setting. The change is saved and takes effect immediately.
+
+    Restricted modules can't call this function.
+
+    :param string module: The module name.
+    :param string permission: ``'web'``, ``'files'`` or ``'registry'``.
+    :param boolean granted:
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn set_module_permission(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    lua::checkargstring!(l, 2);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TBOOLEAN);
+
+    if !module_permissions::check_trusted(l) { return 0; }
+
+    let module = lua::tostring(l, 1).unwrap();
+    let name = lua::tostring(l, 2).unwrap();
+    let granted = lua::toboolean(l, 3);
+
+    let permission = match Permission::from_name(&name) {
+        Some(p) => p,
+        None    => {
+            luaerror!(l, "setmodulepermission: unknown permission '{}'.", name);
+            return 0;
+        }
+    };
+
+    module_permissions::set_granted(&module, permission, granted);
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: versionstring()
+
+    Returns a string containing the EG-Overlay version.
+
+    For example: ``"0.3.0-dev"``.
+
+    :rtype: string
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn version_string(l: &lua_State) -> i32 {
+    lua::pushstring(l, crate::version::VERSION_STR);
+
+    return 1;
+}
+
+
+/*** RST
+.. lua:function:: clipboardtext([text])
+
+    Set or return the text on the clipboard.
+
+    :param string text: (Optional)
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        -- get the text from the clipboard
+        local t = overlay.clipboardtext()
+
+        overlay.loginfo(string.format("Got text from clipboard: %s", t))
+
+        -- set the text on the clipboard
+        overlay.clipboardtext("Hello world from EG-Overlay!")
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn clipboard_text(l: &lua_State) -> i32 {
+    if lua::gettop(l) >= 1 {
+        if let Some(text) = lua::tostring(l, 1) {
+            crate::utils::set_clipboard_text(&text);
+        } else {
+            luaerror!(l, "clipboardtext argument #1 must be a string.");
+        }
+        return 0;
+    } else {
+        if let Some(text) = crate::utils::get_clipboard_text() {
+            lua::pushstring(l, &text);
+        } else {
+            lua::pushnil(l);
+        }
+
+        return 1;
+    }
+}
+
+/*** RST
+.. lua:function:: openurl(url)
+
+    Open ``url`` in the user's default web browser.
+
+    Only ``http`` and ``https`` URLs can be opened, any other URL will raise
+    an error. The URL and the module that opened it are logged.
+
+    Restricted modules need the ``web`` permission, see
+    :ref:`module-permissions`.
+
+    :param string url:
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        overlay.openurl('https://wiki.guildwars2.com/wiki/Main_Page')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn open_url(l: &lua_State) -> i32 {
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    lua::checkargstring!(l, 1);
+
+    if !module_permissions::check(l, Permission::Web) { return 0; }
+
+    let url = lua::tostring(l, 1).unwrap();
+    let lower = url.trim().to_lowercase();
+
+    if !lower.starts_with("http://") && !lower.starts_with("https://") {
+        lua::pushstring(l, "openurl: only http and https URLs can be opened.");
+        return unsafe { lua::error(l) };
+    }
+
+    crate::logging::info!("{}: opening URL {}", web_request_source(l), url);
+
+    let urlu16: Vec<u16> = url.trim().encode_utf16().chain(std::iter::once(0)).collect();
+
+    // ShellExecute returns a value greater than 32 on success
+    let r = unsafe { ShellExecuteW(
+        None,
+        windows::core::w!("open"),
+        windows::core::PCWSTR(urlu16.as_ptr()),
+        None,
+        None,
+        SW_SHOWNORMAL
+    )};
+
+    if r.0 as usize <= 32 {
+        luaerror!(l, "Couldn't open URL {}: {}", url, r.0 as usize);
+    }
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: mousepos()
+
+    Returns the current position of the mouse cursor, in pixels from the top
+    left of the overlay.
+
+    :returns: A table with ``x`` and ``y`` fields.
+    :rtype: table
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn mouse_pos(l: &lua_State) -> i32 {
+    let (x, y) = crate::input::mouse_position();
+
+    lua::createtable(l, 0, 2);
+
+    lua::pushinteger(l, x);
+    lua::setfield(l, -2, "x");
+
+    lua::pushinteger(l, y);
+    lua::setfield(l, -2, "y");
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: capturemousedrag(enabled)
+
+    Set if the UI continues to receive mouse move events while the left mouse
+    button is held after being pressed over the game.
+
+    Normally once a left or right drag starts over the game, for example to
+    move the camera, all mouse events go only to the game until the button is
+    released. When this is enabled, move events during a left drag are also
+    sent to the UI. They are still passed on to the game, so camera control is
+    not affected.
+
+    The default is set by the ``overlay.captureMouseDrag`` setting, which is
+    ``false`` by default. This function does not change the setting.
+
+    :param boolean enabled:
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn capture_mouse_drag(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 1, lua::LuaType::LUA_TBOOLEAN);
+
+    crate::input::set_capture_mouse_drag(lua::toboolean(l, 1));
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: modifierkeys()
+
+    Returns the current state of the modifier keys.
+
+    :returns: A table with ``ctrl``, ``alt``, ``shift`` and ``caps`` boolean
+        fields. ``caps`` is ``true`` if caps lock is on.
+    :rtype: table
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        if overlay.modifierkeys().shift then
+            -- show more details
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn modifier_keys(l: &lua_State) -> i32 {
+    let mods = crate::input::modifier_keys();
+
+    lua::createtable(l, 0, 4);
+
+    lua::pushboolean(l, mods.ctrl);
+    lua::setfield(l, -2, "ctrl");
+
+    lua::pushboolean(l, mods.alt);
+    lua::setfield(l, -2, "alt");
+
+    lua::pushboolean(l, mods.shift);
+    lua::setfield(l, -2, "shift");
+
+    lua::pushboolean(l, mods.caps_lock);
+    lua::setfield(l, -2, "caps");
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: keyboardlayout()
+
+    Returns the keyboard layout identifier detected when the overlay started
+    and the identifier reported as being in use.
+
+    Layout identifiers are 8 character hexadecimal strings, for example
+    ``00000409`` is US English. Users can set the ``overlay.keyboardLayout``
+    overlay setting to override the reported layout.
+
+    .. important::
+        Key names are always translated using the US layout. Overriding the
+        layout only changes the identifier returned here and silences the
+        startup error, it does not change how keys are translated. On other
+        layouts some keybinds and key event names may not match the keys
+        printed on the keyboard.
+
+    :returns: The detected layout and the layout in use.
+    :rtype: string, string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local detected, used = overlay.keyboardlayout()
+
+        if used ~= '00000409' then
+            overlay.logwarn(string.format('Keyboard layout %s is not supported.', used))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn keyboard_layout(l: &lua_State) -> i32 {
+    let input = crate::overlay::input();
+
+    lua::pushstring(l, input.detected_layout());
+    lua::pushstring(l, input.layout());
+
+    return 2;
+}
+
+/*** RST
+.. lua:function:: getenv(name)
+
+    Return the value of the environment variable ``name``, or ``nil`` if it is
+    not set.
+
+    :param string name:
+    :rtype: string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local appdata = overlay.getenv('APPDATA')
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn get_env(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let name = lua::tostring(l, 1).unwrap();
+
+    match std::env::var(&name) {
+        Ok(val) => lua::pushstring(l, &val),
+        Err(_)  => lua::pushnil(l),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: readregistry(hive, key, value)
+
+    Read a value from the Windows registry.
+
+    Only reading is supported, modules can not modify the registry.
+
+    ``hive`` must be one of ``'HKEY_LOCAL_MACHINE'``, ``'HKEY_CURRENT_USER'``,
+    or ``'HKEY_CLASSES_ROOT'``, or their abbreviations ``'HKLM'``, ``'HKCU'``,
+    and ``'HKCR'``.
+
+    The value returned depends on the type of the registry value:
+
+    ============================= ==============================================
+    Registry Type                 Lua Type
+    ============================= ==============================================
+    ``REG_SZ``, ``REG_EXPAND_SZ`` string. Environment variables are not expanded.
+    ``REG_MULTI_SZ``              A sequence of strings.
+    ``REG_DWORD``, ``REG_QWORD``  integer
+    Other                         string, containing the raw bytes of the value.
+    ============================= ==============================================
+
+    If the key or value does not exist, ``nil`` is returned.
+
+    :param string hive:
+    :param string key: The path of the key, i.e. ``'SOFTWARE\\ArenaNet\\Guild Wars 2'``
+    :param string value: The name of the value, or an empty string for the
+        key's default value.
+    :rtype: any
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local gw2path = overlay.readregistry(
+            'HKLM',
+            'SOFTWARE\\ArenaNet\\Guild Wars 2',
+            'Path'
+        )
+
+        if gw2path then
+            overlay.loginfo(string.format('GW2 is installed at %s', gw2path))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn read_registry(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Registry) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    lua::checkargstring!(l, 2);
+    lua::checkargstring!(l, 3);
+
+    let hive  = lua::tostring(l, 1).unwrap();
+    let key   = lua::tostring(l, 2).unwrap();
+    let value = lua::tostring(l, 3).unwrap();
+
+    match crate::utils::read_registry_value(&hive, &key, &value) {
+        Some(crate::utils::RegistryValue::String(s))   => lua::pushstring(l, &s),
+        Some(crate::utils::RegistryValue::Integer(i))  => lua::pushinteger(l, i as i64),
+        Some(crate::utils::RegistryValue::Binary(b))   => {
+            let bytes: Vec<i8> = b.iter().map(|b| *b as i8).collect();
+            lua::pushbytes(l, &bytes);
+        },
+        Some(crate::utils::RegistryValue::MultiString(strings)) => {
+            lua::createtable(l, strings.len() as i32, 0);
+
+            let mut i = 1;
+            for s in strings {
+                lua::pushstring(l, &s);
+                lua::seti(l, -2, i);
+                i += 1;
+            }
+        },
+        None => lua::pushnil(l),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: playsound(sound[, volume])
+
+    Play a sound.
+
+    ``sound`` can either be the path to a WAV file or a string containing the
+    contents of a WAV file, i.e. the body of a :lua:func:`webrequest`. Only
+    uncompressed PCM or floating point WAV files are supported.
+
+    The sound is played in the background, this function returns immediately.
+    Multiple sounds can be played at the same time.
+
+    The final volume is ``volume`` multiplied by the ``overlay.soundVolume``
+    overlay setting.
+
+    Restricted modules need the ``files`` permission to play a sound from a
+    path, see :ref:`module-permissions`.
+
+    :param string sound:
+    :param number volume: (Optional) The volume, from ``0.0`` to ``1.0``.
+        Default: ``1.0``
+    :returns: An integer handle that can be passed to :lua:func:`stopsound`,
+        or ``nil`` if the sound could not be played.
+    :rtype: integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local snd = overlay.playsound(overlay.datafolder('timers') .. '\\alert.wav', 0.5)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn play_sound(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+
+    let volume = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargnumber!(l, 2);
+        lua::tonumber(l, 2) as f32
+    } else {
+        1.0
+    };
+
+    let master = crate::overlay::settings().get_f64("overlay.soundVolume").unwrap_or(1.0) as f32;
+
+    let sound: &[u8] = lua::tobytes(l, 1);
+
+    let data: Vec<u8> = if sound.starts_with(b"RIFF") {
+        sound.to_vec()
+    } else {
+        if !module_permissions::check(l, Permission::Files) {
+            lua::pushnil(l);
+            return 1;
+        }
+
+        let path = String::from_utf8_lossy(sound).to_string();
+
+        match std::fs::read(&path) {
+            Ok(d) => d,
+            Err(err) => {
+                luawarn!(l, "Couldn't read {}: {}", path, err);
+                lua::pushnil(l);
+                return 1;
+            }
+        }
+    };
+
+    match crate::audio::play(&data, volume * master) {
+        Ok(id) => lua::pushinteger(l, id as i64),
+        Err(err) => {
+            luawarn!(l, "Couldn't play sound: {}", err);
+            lua::pushnil(l);
+        }
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: stopsound(handle)
+
+    Stop a sound started with :lua:func:`playsound`.
+
+    :param integer handle:
+    :returns: ``true`` if the sound was still playing.
+    :rtype: boolean
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn stop_sound(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
+
+    let id = lua::tointeger(l, 1);
+
+    lua::pushboolean(l, crate::audio::stop(id as u64));
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: speak(text[, options])
+
+    Speak ``text`` using the Windows text-to-speech voice.
+
+    Speech is queued and spoken in the background, this function returns
+    immediately.
+
+    ``options`` may be a table with the following fields:
+
+    ========= ==================================================================
+    Field     Description
+    ========= ==================================================================
+    rate      The speaking rate, from ``-10`` (slowest) to ``10`` (fastest).
+              Default: ``0``
+    volume    The volume, from ``0`` to ``100``. Default: ``100``
+    interrupt If ``true``, stop any speech in progress or queued before
+              speaking ``text``. Default: ``false``
+    ========= ==================================================================
+
+    :param string text:
+    :param table options: (Optional)
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        overlay.speak('Boss in 10 seconds', { rate = 2, interrupt = true })
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn speak(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+
+    let text = lua::tostring(l, 1).unwrap();
+
+    let mut rate: i32 = 0;
+    let mut volume: u16 = 100;
+    let mut interrupt = false;
+
+    if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+
+        if lua::getfield(l, 2, "rate") != lua::LuaType::LUA_TNIL { rate = lua::tointeger(l, -1) as i32; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, 2, "volume") != lua::LuaType::LUA_TNIL { volume = lua::tointeger(l, -1).clamp(0, 100) as u16; }
+        lua::pop(l, 1);
+
+        if lua::getfield(l, 2, "interrupt") != lua::LuaType::LUA_TNIL { interrupt = lua::toboolean(l, -1); }
+        lua::pop(l, 1);
+    }
+
+    crate::speech::speak(&text, rate, volume, interrupt);
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: stopspeaking()
+
+    Stop any speech started by :lua:func:`speak`, including speech that is
+    queued but hasn't started yet.
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn stop_speaking(_l: &lua_State) -> i32 {
+    crate::speech::stop();
+
+    return 0;
+}
+
+/*** RST
+.. lua:function:: sqlite3open(db)
+
+    Open or create a SQLite3 database.
+
+    .. seealso::
+        The :lua:class:`sqlite3db` class.
+
+    :param string db: The SQLite3 database path or connection string.
+
+    :rtype: sqlite3db
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local db = overlay.open('some-data.db')
+
+    .. versionhistory::
+        :0.3.0:
+*/
+unsafe extern "C" fn sqlite3_open(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    unsafe { crate::lua_sqlite3::sqlite3_open(l) }
+}
+
+/*** RST
+.. lua:function:: webrequest(url, headers, query_params, callback[, onchunk])
+                  webrequest(url, options, callback[, onchunk])
+
+    Queue a web request to the given URL.
+
+    Requests are completed asynchronously, with the results provided to ``callback``.
+
+    The first form always performs a ``GET`` request. The second form takes
+    an ``options`` table instead of ``headers`` and ``query_params``, which
+    can contain the following fields, all optional:
+
+    ======= ===============================================================
+    Field   Description
+    ======= ===============================================================
+    method  ``'GET'``, ``'POST'``, ``'PUT'`` or ``'DELETE'``. Default:
+            ``'GET'``.
+    body    A string sent as the request body.
+    headers A table of headers, the same as ``headers`` above.
+    query   A table of query parameters, the same as ``query_params``
+            above.
+    timeout The number of seconds the request can take, including reading
+            the response body. By default there is no timeout.
+    ======= ===============================================================
+
+    ``callback`` is called with a single table containing the following
+    fields:
+
+    ======= ===============================================================
+    Field   Description
+    ======= ===============================================================
+    id      The request ID, the same as returned by this function.
+    status  The HTTP status code, or ``0`` if the request failed.
+    body    The response body, or ``nil`` if the request failed or
+            ``onchunk`` was given.
+    headers A table of response headers.
+    error   ``nil`` if the request completed, otherwise ``'timeout'``,
+            ``'cancelled'`` or a description of the failure.
+    ======= ===============================================================
+
+    Up to ``overlay.webRequestWorkers`` requests, 4 by default, are performed
+    at once. Any others are queued and started in order as earlier requests
+    complete, see :lua:func:`webqueuedepth`.
+
+    :param string url: The full URL. Query parameters can be excluded if they are
+        supplied in ``query_params``.
+    :param table headers: A list of headers to add to the request.
+    :param table query_params: A list of query parameters to add to the URL.
+    :param function callback: A function that will be called when the request is
+        completed, see above.
+    :param function onchunk: (Optional) A function that will be called with each
+        chunk of the response body as it is received. If this is given the body
+        is not buffered and ``callback`` will receive a ``nil`` body, signaling
+        only that the request has completed.
+    :returns: An integer request ID, see :lua:func:`cancelwebrequest`.
+
+    .. note::
+        Web requests are currently assumed to be HTTP(S).
+
+    .. warning::
+        Do not mix ``query_params`` with parameters supplied in ``url``. This
+        function does not check if ``url`` already contains parameters, it simply
+        appends ``query_params`` if it contains values.
+
+    .. important::
+        All web requests are logged, with the path to the Lua source and line number
+        of the ``webrequest`` call.
+
+        The author of EG-Overlay believes that users
+        should be able to easily understand when and where web requests are sent.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local function on_response(resp)
+            overlay.loginfo(string.format('Got %d response from server.', resp.status))
+            if resp.body then
+                overlay.loginfo(string.format('Response body:\n%s', resp.body))
+            end
+        end
+
+        local request_headers = {}
+        request_headers['X-Special-Header'] = 'SomeValue'
+
+        local params = {}
+        params['query_param'] = 1
+
+        overlay.webrequest('https://some.url/path/etc', request_headers, params, on_response)
+
+    .. code-block:: lua
+        :caption: Streaming Example
+
+        local received = 0
+
+        local function on_chunk(data)
+            received = received + #data
+        end
+
+        local function on_done(resp)
+            overlay.loginfo(string.format('Received %d bytes.', received))
+        end
+
+        overlay.webrequest('https://some.url/large/file', {}, {}, on_done, on_chunk)
+
+    .. code-block:: lua
+        :caption: POST Example
+
+        local options = {
+            method = 'POST',
+            headers = { ['Content-Type'] = 'application/json' },
+            body = '{"event":"test"}',
+            timeout = 10,
+        }
+
+        overlay.webrequest('https://some.url/api/events', options, on_response)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn web_request(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Web) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+
+    // webrequest(url, options, callback[, onchunk]) or
+    // webrequest(url, headers, query_params, callback[, onchunk])
+    let options_form = lua::luatype(l, 3) == lua::LuaType::LUA_TFUNCTION;
+
+    let callback_ind = if options_form { 3 } else { 4 };
+
+    if !options_form { lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE); }
+    lua::checkargtype!(l, callback_ind, lua::LuaType::LUA_TFUNCTION);
+
+    if lua::gettop(l) >= callback_ind + 1 && lua::luatype(l, callback_ind + 1) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, callback_ind + 1, lua::LuaType::LUA_TFUNCTION);
+    }
+
+    let url = lua::tostring(l, 1).unwrap();
+
+    let mut options = crate::web_request::RequestOptions::default();
+
+    if options_form {
+        match lua::getfield(l, 2, "method") {
+            lua::LuaType::LUA_TNIL => {},
+            lua::LuaType::LUA_TSTRING => {
+                let name = lua::tostring(l, -1).unwrap();
+
+                options.method = match crate::web_request::Method::from_name(&name) {
+                    Some(m) => m,
+                    None    => {
+                        lua::pop(l, 1);
+                        luaerror!(l, "Unsupported method: {}", name);
+                        return 0;
+                    }
+                };
+            },
+            _ => {
+                lua::pop(l, 1);
+                luaerror!(l, "method must be a string.");
+                return 0;
+            }
+        }
+        lua::pop(l, 1);
+
+        match lua::getfield(l, 2, "body") {
+            lua::LuaType::LUA_TNIL => {},
+            lua::LuaType::LUA_TSTRING => options.body = Some(lua::tobytes::<u8>(l, -1).to_vec()),
+            _ => {
+                lua::pop(l, 1);
+                luaerror!(l, "body must be a string.");
+                return 0;
+            }
+        }
+        lua::pop(l, 1);
+
+        match lua::getfield(l, 2, "timeout") {
+            lua::LuaType::LUA_TNIL => {},
+            lua::LuaType::LUA_TNUMBER if lua::tonumber(l, -1) > 0.0 => {
+                options.timeout = Some(std::time::Duration::from_secs_f64(lua::tonumber(l, -1)));
+            },
+            _ => {
+                lua::pop(l, 1);
+                luaerror!(l, "timeout must be a number greater than 0.");
+                return 0;
+            }
+        }
+        lua::pop(l, 1);
+
+        options.headers = match web_request_field_pairs(l, "headers", "Header") {
+            Some(h) => h,
+            None    => return 0,
+        };
+
+        options.query_params = match web_request_field_pairs(l, "query", "Query parameter") {
+            Some(p) => p,
+            None    => return 0,
+        };
+    } else {
+        options.headers = match web_request_pairs(l, 2, "Header") {
+            Some(h) => h,
+            None    => return 0,
+        };
+
+        options.query_params = match web_request_pairs(l, 3, "Query parameter") {
+            Some(p) => p,
+            None    => return 0,
+        };
+    }
+
+    lua::pushvalue(l, callback_ind);
+    let callback = module_permissions::ref_callback(l);
+
+    let chunk_callback = if lua::luatype(l, callback_ind + 1) == lua::LuaType::LUA_TFUNCTION {
+        lua::pushvalue(l, callback_ind + 1);
+        Some(module_permissions::ref_callback(l))
+    } else {
+        None
+    };
+
+    let source = web_request_source(l);
+
+    let id = crate::web_request::queue_request(&url, options, callback, chunk_callback, &source);
+
+    lua::pushinteger(l, id as i64);
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: cancelwebrequest(id)
+
+    Cancel a web request started with :lua:func:`webrequest`.
+
+    A request that hasn't started yet won't be sent. A request that is in
+    progress is stopped before reading the next part of the response. The
+    request's callback is still called, with an ``error`` of ``'cancelled'``.
+
+    :param integer id: The ID returned by :lua:func:`webrequest`.
+    :returns: ``true`` if the request was cancelled, ``false`` if it had
+        already completed.
+    :rtype: boolean
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local id = overlay.webrequest('https://some.url/slow', {}, {}, function(resp)
+            if resp.error == 'cancelled' then
+                overlay.loginfo('Request cancelled.')
+            end
+        end)
+
+        overlay.cancelwebrequest(id)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn cancel_web_request(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
+
+    let id = lua::tointeger(l, 1);
+
+    lua::pushboolean(l, id > 0 && crate::web_request::cancel_request(id as u64));
+
+    return 1;
+}
+
+// the Lua source and line that called the current function, all web requests
+// are logged with this
+fn web_request_source(l: &lua_State) -> String {
+    let mut dbg = lua::lua_Debug::default();
+
+    lua::getstack(l, 1, &mut dbg).unwrap();
+    lua::getinfo(l, "Sl", &mut dbg).unwrap();
+
+    let src = unsafe { std::ffi::CStr::from_ptr(dbg.source).to_str().unwrap() };
+
+    format!("{}@{}", src, dbg.currentline)
+}
+
+/*** RST
+.. lua:function:: downloadfile(url, headers, query_params, destpath, callback)
+
+    Download the response body of a ``GET`` request to a file.
+
+    The body is written to ``destpath`` as it is received instead of being
+    held in memory, which makes this better suited than
+    :lua:func:`webrequest` for large files such as marker packs. Any missing
+    directories in ``destpath`` are created. The body is written to a
+    temporary file first and ``destpath`` is only replaced if the request
+    succeeds.
+
+    Downloads are queued and logged the same as :lua:func:`webrequest` and can
+    be cancelled with :lua:func:`cancelwebrequest`.
+
+    :param string url: The full URL.
+    :param table headers: A table of headers to add to the request.
+    :param table query_params: A table of query parameters to add to the URL.
+    :param string destpath: The file to write the body to.
+    :param function callback: Called when the download completes with 3
+        arguments: ``true`` if the request succeeded with a 2xx or 3xx status
+        and the file was written, the HTTP status code, and a table of the
+        response headers. Unlike :lua:func:`webrequest` callbacks, this
+        function can't yield.
+    :returns: An integer request ID.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local dest = overlay.datafolder('my-module') .. '/markers.zip'
+
+        overlay.downloadfile('https://some.url/markers.zip', {}, {}, dest, function(success, code, hdrs)
+            if success then
+                overlay.loginfo('Download complete.')
+            else
+                overlay.logerror(string.format('Download failed: %d', code))
+            end
+        end)
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn download_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Web) { return 0; }
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
+    lua::checkargstring!(l, 4);
+    lua::checkargtype!(l, 5, lua::LuaType::LUA_TFUNCTION);
+
+    let url = lua::tostring(l, 1).unwrap();
+
+    let mut options = crate::web_request::RequestOptions::default();
+
+    options.headers = match web_request_pairs(l, 2, "Header") {
+        Some(h) => h,
+        None    => return 0,
+    };
+
+    options.query_params = match web_request_pairs(l, 3, "Query parameter") {
+        Some(p) => p,
+        None    => return 0,
+    };
+
+    options.download_path = Some(lua::tostring(l, 4).unwrap());
+
+    // the callback is called with the response table, which is unpacked for
+    // the actual callback by download_file_callback
+    lua::pushvalue(l, 5);
+    lua::pushcclosure(l, Some(download_file_callback), 1);
+    let callback = module_permissions::ref_callback(l);
+
+    let source = web_request_source(l);
+
+    let id = crate::web_request::queue_request(&url, options, callback, None, &source);
+
+    lua::pushinteger(l, id as i64);
+
+    return 1;
+}
+
+unsafe extern "C" fn download_file_callback(l: &lua_State) -> i32 {
+    lua::getfield(l, 1, "status");
+    let status = lua::tointeger(l, -1);
+    lua::pop(l, 1);
+
+    let failed = lua::getfield(l, 1, "error") != lua::LuaType::LUA_TNIL;
+    lua::pop(l, 1);
+
+    lua::pushvalue(l, lua::LUA_REGISTRYINDEX - 1); // up value 1, the callback
+    lua::pushboolean(l, !failed && status >= 200 && status < 400);
+    lua::pushinteger(l, status);
+    lua::getfield(l, 1, "headers");
+
+    lua::call(l, 3, 0);
+
+    return 0;
+}
+
+// reads the string keys and values of the table at ind, what is used in errors
+fn web_request_pairs(l: &lua_State, ind: i32, what: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+
+    lua::pushnil(l);
+    while lua::next(l, ind) != 0 {
+        if lua::luatype(l, -2) != lua::LuaType::LUA_TSTRING {
+            lua::pop(l, 2);
+            luaerror!(l, "{} keys must be strings.", what);
+            return None;
+        }
+
+        let key = String::from(lua::tostring(l, -2).unwrap());
+        let val = String::from(lua::tostring(l, -1).unwrap());
+        pairs.push((key, val));
+
+        lua::pop(l, 1);
+    }
+
+    Some(pairs)
+}
+
+// same as web_request_pairs, for the table in field of the options table
+fn web_request_field_pairs(l: &lua_State, field: &str, what: &str) -> Option<Vec<(String, String)>> {
+    let pairs = match lua::getfield(l, 2, field) {
+        lua::LuaType::LUA_TNIL   => Some(Vec::new()),
+        lua::LuaType::LUA_TTABLE => web_request_pairs(l, lua::gettop(l), what),
+        _ => {
+            luaerror!(l, "{} must be a table.", field);
+            None
+        }
+    };
+
+    lua::pop(l, 1);
+
+    pairs
+}
+
+/*** RST
+.. lua:function:: webqueuedepth()
+
+    Returns the number of web requests waiting to be started and the number
+    currently in progress.
+
+    Requests wait when all of the web request workers are busy, see
+    :lua:func:`webrequest`.
+
+    :rtype: integer, integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local queued, active = overlay.webqueuedepth()
+
+        if queued > 20 then
+            -- back off before queuing more
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn web_queue_depth(l: &lua_State) -> i32 {
+    let (queued, active) = crate::web_request::queue_depth();
+
+    lua::pushinteger(l, queued as i64);
+    lua::pushinteger(l, active as i64);
+
+    return 2;
+}
+
+/*** RST
+.. lua:function:: parsejson(JSON)
+
+    Parse a JSON string into a Lua value.
+
+    This function returns ``nil`` if the string can not be parsed.
+
+    :param string JSON:
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        -- objects are parsed into tables
+        local obj = overlay.parsejson('{"test": 1234}')
+        overlay.loginfo(string.format("test: %d", obj.test))
+
+        -- arrays are parsed into sequences
+        local arr = overlay.parsejson('["hello world", 1234]')
+        for i,val in ipairs(arr) do
+            overlay.loginfo(string.format("%d: %s", i, val))
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn parse_json(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let json_str = lua::tostring(l, 1).unwrap();
+
+    match &serde_json::from_str(&json_str) {
+        Ok(val) => crate::lua_json::pushjson(l, val),
+        Err(err) => {
+            luaerror!(l, "Couldn't parse JSON value: {}", err);
+            return 0;
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: tojson(value[, pretty])
+
+    Serialize a Lua value into a JSON string.
+
+    Tables with consecutive integer keys starting at 1 are serialized as
+    arrays, all other tables are serialized as objects. Functions, userdata,
+    threads and NaN or infinite numbers can not be serialized and will raise
+    an error.
+
+    :param value: The value to serialize.
+    :param boolean pretty: (Optional) If ``true`` the JSON will be indented.
+    :rtype: string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local json = overlay.tojson({name = 'test', values = {1, 2, 3}})
+        -- {"name":"test","values":[1,2,3]}
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn to_json(l: &lua_State) -> i32 {
+    let pretty = lua::gettop(l) >= 2 && lua::toboolean(l, 2);
+
+    let val = match crate::lua_json::try_tojson(l, 1) {
+        Ok(v) => v,
+        Err(err) => {
+            lua::pushstring(l, &err);
+            return unsafe { lua::error(l) };
+        },
+    };
+
+    let json = if pretty { serde_json::to_string_pretty(&val) } else { serde_json::to_string(&val) };
+
+    match json {
+        Ok(s) => lua::pushstring(l, &s),
+        Err(err) => {
+            lua::pushstring(l, &format!("Couldn't serialize JSON value: {}", err));
+            return unsafe { lua::error(l) };
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: openzip(path)
+
+    Open the zip file at the given path and return a :lua:class:`zipfile`.
+
+    .. seealso::
+        The :lua:class:`zipfile` class.
+
+    .. note::
+        If an error occurs while opening/reading the zip file, this function
+        will log an error and return ``nil``.
+
+    :rtype: zipfile
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn open_zip(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    match crate::zip::open_zip(&path) {
+        Ok(zip) => crate::zip::lua::pushzipfile(l, zip),
+        Err(err) => {
+            luaerror!(l, "Couldn't open zip file: {}", err);
+            lua::pushnil(l);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: mountzip(path)
+
+    Open the zip file at the given path as a read-only file system and return
+    a :lua:class:`zipvfs`.
+
+    Unlike :lua:func:`openzip`, files read from the returned object are cached,
+    which makes it well suited to marker packs and other archives where the
+    same files are read many times.
+
+    .. note::
+        If an error occurs while opening/reading the zip file, this function
+        will log an error and return ``nil``.
+
+    :rtype: zipvfs
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn mount_zip(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    match crate::zip::mount_zip(&path) {
+        Ok(vfs) => crate::zip::lua::pushzipvfs(l, vfs),
+        Err(err) => {
+            luaerror!(l, "Couldn't mount zip file: {}", err);
+            lua::pushnil(l);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: createzip(path)
+
+    Create a new zip file at the given path and return a
+    :lua:class:`zipwriter`. If a file already exists at ``path`` it is
+    replaced.
+
+    .. seealso::
+        The :lua:class:`zipwriter` class.
+
+    .. note::
+        If the file can't be created, this function will log an error and
+        return ``nil``.
+
+    :rtype: zipwriter
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn create_zip(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 1);
+    let path = lua::tostring(l, 1).unwrap();
+
+    match crate::zip::create_zip(&path) {
+        Ok(writer) => crate::zip::lua::pushzipwriter(l, writer),
+        Err(err) => {
+            luaerror!(l, "Couldn't create zip file: {}", err);
+            lua::pushnil(l);
+        },
+    }
+
+    return 1;
+}
+
+fn push_ownedname(l: &lua_State, name: &xml::name::OwnedName) {
+    lua::createtable(l, 0, 3);
+
+    lua::pushstring(l, &name.local_name);
+    lua::setfield(l, -2, "local_name");
+
+    match &name.namespace {
+        Some(ns) => lua::pushstring(l, &ns),
+        None     => lua::pushnil(l),
+    }
+    lua::setfield(l, -2, "namespace");
+
+    match &name.prefix {
+        Some(p) => lua::pushstring(l, &p),
+        None    => lua::pushnil(l),
+    }
+    lua::setfield(l, -2, "prefix");
+}
+
+fn push_startdoc(l: &lua_State) {
+    lua::pushstring(l, "start-document");
+
+    lua::pushnil(l);
+}
+
+fn push_enddoc(l: &lua_State) {
+    lua::pushstring(l, "end-document");
+
+    lua::pushnil(l);
+}
+
+fn push_startelement(l: &lua_State, name: &xml::name::OwnedName, attributes: &Vec<xml::attribute::OwnedAttribute>) {
+    lua::pushstring(l, "start-element");
+
+    lua::createtable(l, 0, 2); // data
+
+    push_ownedname(l, name);
+    lua::setfield(l, -2, "name");
+
+    let mut ai = 1;
+    lua::createtable(l, attributes.len() as i32, 0);
+    for oa in attributes {
+        lua::createtable(l, 0, 2);
+
+        push_ownedname(l, &oa.name);
+        lua::setfield(l, -2, "name");
+
+        lua::pushstring(l, &oa.value);
+        lua::setfield(l, -2, "value");
+
+        lua::seti(l, -2, ai);
+
+        ai += 1;
+    }
+    lua::setfield(l, -2, "attributes");
+}
+
+fn push_endelement(l: &lua_State, name: &xml::name::OwnedName) {
+    lua::pushstring(l, "end-element");
+
+    push_ownedname(l, name);
+}
+
+fn push_stringevent(l: &lua_State, event: &str, data: &str) {
+    lua::pushstring(l, event);
+    lua::pushstring(l, data);
+}
+
+/*** RST
+.. lua:function:: parsexml(xml, eventcallback)
+
+    Attempt to parse the given XML string in an event driven manner (i.e. SAX).
+
+    ``eventcallback`` will be called on each event, corresponding to various elements
+    of the XML document. ``eventcallback`` will be called with 2 arguments:
+    the name of the event and the event data (see below).
+
+    This function will only return once the document is fully parsed, returning
+    ``true`` on success and ``false`` on any failure.
+
+    .. note::
+
+        Raising an error during the callback will cause parsing to fail and this
+        function to return ``false``.
+
+    :param string xml:
+    :param function eventcallback:
+    :rtype: boolean
+
+    **Event Types**
+
+    ================ ==================================================================
+    Event Name       Event Data
+    ================ ==================================================================
+    start-document   ``nil``
+    end-document     ``nil``
+    start-element    A Lua table. See below for fields.
+    end-element      A Lua table containing the following fields:
+
+                     - ``local_name``: the element name without prefixes or namespaces
+                     - ``namespace``: the element name namespace
+                     - ``prefix``: the element name prefix
+
+    cdata            A string containing the CDATA.
+    comment          A string containing the comment.
+    characters       A string containing the text.
+    whitespace       A string containing whitespace characters.
+    ================ ==================================================================
+
+
+    **start-element data fields:**
+
+    ========== =================================================================
+    Field      Description
+    ========== =================================================================
+    name       A Lua table containing the following fields:
+
+               - ``local_name``: the element name without prefixes or namespaces
+               - ``namespace``: the element name namespace
+               - ``prefix``: the element name prefix
+
+    attributes A Lua sequence of tables for each attribute, each with ``name``
+               and ``value`` fields
+    ========== =================================================================
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn parse_xml(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
+    let xml = lua::tostring(l, 1).unwrap();
+
+    let reader = xml::EventReader::new(xml.as_bytes());
+
+    for e in reader {
+        match e {
+            Ok(event) => {
+                lua::pushvalue(l, 2); // the callback function
+                match event {
+                    XmlEvent::StartDocument{..}                  => push_startdoc(l),
+                    XmlEvent::EndDocument                        => push_enddoc(l),
+                    XmlEvent::StartElement{name, attributes, ..} => push_startelement(l, &name, &attributes),
+                    XmlEvent::EndElement{name}                   => push_endelement(l, &name),
+                    XmlEvent::CData(cdata)                       => push_stringevent(l, "cdata", &cdata),
+                    XmlEvent::Comment(comment)                   => push_stringevent(l, "comment", &comment),
+                    XmlEvent::Characters(char)                   => push_stringevent(l, "characters", &char),
+                    XmlEvent::Whitespace(ws)                     => push_stringevent(l, "whitespace", &ws),
+                    XmlEvent::ProcessingInstruction{..}          => {},
+                }
+
+                if let Err(_err) = lua::pcall(l, 2, 0, 0) {
+                    let msg = lua::tostring(l, -1).unwrap_or(String::from("<invalid error message>"));
+                    luaerror!(l, "Error while running xml event callback: {}", msg);
+                    lua::pop(l, 1);
+
+                    lua::pushboolean(l, false);
+
+                    return 1;
+                }
+            },
+            Err(e) => {
+                luaerror!(l, "Error while parsing xml: {}", e);
+
+                lua::pushboolean(l, false);
+                return 1;
+            },
+        }
+    }
+
+    lua::pushboolean(l, true);
+    return 1;
+}
+
+/// Parses a TacO/BlishHUD `.trl` trail.
+///
+/// The file is a little-endian 32 bit version, 32 bit map ID and then a
+/// sequence of x, y, z 32 bit floats until the end of the file.
+///
+/// The points are in meters and a point at 0, 0, 0 separates segments. The
+/// segments are returned with the points converted to inches, segments with
+/// less than 2 points are dropped.
+fn parse_trl_data(data: &[u8]) -> Result<(u32, Vec<Vec<[f32; 3]>>), String> {
+    if data.len() < 8 {
+        return Err(format!("Data is too short for a trl header ({} bytes).", data.len()));
+    }
+
+    let version = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    if version != 0 {
+        return Err(format!("Unsupported trl version: {}", version));
+    }
+
+    let map_id = u32::from_le_bytes(data[4..8].try_into().unwrap());
+
+    let point_data = &data[8..];
+
+    if point_data.len() % 12 != 0 {
+        return Err(format!("Point data is not a multiple of 12 bytes ({} bytes).", point_data.len()));
+    }
+
+    let points: Vec<[f32; 3]> = point_data.chunks_exact(12).map(|p| {
+        [
+            f32::from_le_bytes(p[0..4].try_into().unwrap()),
+            f32::from_le_bytes(p[4..8].try_into().unwrap()),
+            f32::from_le_bytes(p[8..12].try_into().unwrap()),
+        ]
+    }).collect();
+
+    if points.iter().any(|p| p.iter().any(|c| !c.is_finite())) {
+        return Err(String::from("Point data contains invalid values."));
+    }
+
+    let segments: Vec<Vec<[f32; 3]>> = points
+        .split(|p| *p == [0.0, 0.0, 0.0])
+        .filter(|s| s.len() > 1)
+        .map(|s| s.iter().map(|p| p.map(|c| c * 39.3701)).collect())
+        .collect();
+
+    return Ok((map_id, segments));
+}
+
+/*** RST
+.. lua:function:: parsetrl(data)
+
+    Parse a TacO/BlishHUD ``.trl`` binary trail.
+
+    Returns the map ID of the trail and a sequence of segments. Each segment is
+    a sequence of points, each a sequence of x, y, z coordinates in inches.
+    A segment can be given directly to :lua:meth:`dxtraillist.add`.
+
+    ``.trl`` files store points in meters, with a point at ``0, 0, 0``
+    separating segments. Segments with less than 2 points are ignored.
+
+    If ``data`` is not a valid trail an error is logged and ``nil`` is
+    returned.
+
+    :param string data: The contents of a ``.trl`` file.
+    :rtype: integer, sequence
 
     .. code-block:: lua
         :caption: Example
 
-        local overlay = require 'overlay'
+        local mapid, segments = overlay.parsetrl(pack:content('trails/example.trl'))
 
-        local function on_response(body, code, hdrs)
-            overlay.loginfo(string.format('Got %d response from server.', code))
-            if body then
-                overlay.loginfo(string.format('Response body:\n%s', body))
+        if mapid then
+            for _, points in ipairs(segments) do
+                trails:add('trailtexture', { points = points })
             end
         end
 
-        local request_headers = {}
-        request_headers['X-Special-Header'] = 'SomeValue'
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn parse_trl(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+    let data = lua::tobytes::<u8>(l, 1);
+
+    match parse_trl_data(data) {
+        Ok((map_id, segments)) => {
+            lua::pushinteger(l, map_id as i64);
+
+            lua::createtable(l, segments.len() as i32, 0);
+            for (si, points) in segments.iter().enumerate() {
+                lua::createtable(l, points.len() as i32, 0);
+                for (i, p) in points.iter().enumerate() {
+                    lua::createtable(l, 3, 0);
+                    for (c, v) in p.iter().enumerate() {
+                        lua::pushnumber(l, *v as f64);
+                        lua::seti(l, -2, c as i64 + 1);
+                    }
+                    lua::seti(l, -2, i as i64 + 1);
+                }
+                lua::seti(l, -2, si as i64 + 1);
+            }
 
-        local params = {}
-        params['query_param'] = 1
+            return 2;
+        },
+        Err(err) => {
+            luaerror!(l, "Couldn't parse trl data: {}", err);
+            lua::pushnil(l);
 
-        overlay.webrequest('https://some.url/path/etc', request_headers, params, on_response)
+            return 1;
+        },
+    }
+}
+
+/*** RST
+.. lua:function:: splitstring(str, pat)
+
+    Return a sequence of substrings of ``str`` separated by ``pat``.
+
+    :param string str:
+    :param string pat:
+
+    :rtype: sequence
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn web_request(l: &lua_State) -> i32 {
+unsafe extern "C" fn split_string(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 1);
-    lua::checkargtype!(l, 2, lua::LuaType::LUA_TTABLE);
-    lua::checkargtype!(l, 3, lua::LuaType::LUA_TTABLE);
-    lua::checkargtype!(l, 4, lua::LuaType::LUA_TFUNCTION);
+    lua::checkargstring!(l, 2);
 
-    let url = lua::tostring(l, 1).unwrap();
+    let st = lua::tostring(l, 1).unwrap();
+    let pat = lua::tostring(l, 2).unwrap();
+
+    lua::newtable(l);
+    let mut ti = 1;
+    for t in st.split(&pat) {
+        lua::pushstring(l, &t);
+        lua::seti(l, -2, ti);
+        ti += 1;
+    }
+
+    return 1;
+}
 
-    lua::pushvalue(l, 4);
-    let callback = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+/*** RST
+.. lua:function:: tonum(value[, default])
 
-    let mut hdrs: Vec<(String, String)> = Vec::new();
+    Convert ``value`` to a number, or return ``default`` if it can't be.
 
-    lua::pushnil(l);
-    while lua::next(l, 2) != 0 {
-        if lua::luatype(l, -2) != lua::LuaType::LUA_TSTRING {
-            lua::pop(l, 2);
-            luaerror!(l, "Header keys must be strings.");
-            return 0;
+    Numbers and strings that contain a number are converted, any other value,
+    including ``nil`` and NaN, results in ``default``.
+
+    This is a safe way to use values that may be ``nil``, such as data from
+    MumbleLink or web APIs, in places that require a number like
+    ``string.format``.
+
+    :param value: Any Lua value.
+    :param number default: (Optional) The value returned if ``value`` can't be
+        converted, ``0`` if omitted.
+
+    :rtype: number
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        -- never errors, even if data.count is missing
+        local text = string.format('%.1f', overlay.tonum(data.count))
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn to_num(l: &lua_State) -> i32 {
+    let default = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargnumber!(l, 2);
+        lua::tonumber(l, 2)
+    } else {
+        0.0
+    };
+
+    match lua::tonumberx(l, 1) {
+        Some(n) if !n.is_nan() => lua::pushnumber(l, n),
+        _                      => lua::pushnumber(l, default),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: toint(value[, default])
+
+    Convert ``value`` to an integer, or return ``default`` if it can't be.
+
+    This is the same as :lua:func:`tonum`, except that the result is always an
+    integer. Non-integer values are truncated toward zero and values that are
+    out of the range of an integer, including infinity, result in ``default``.
+
+    :param value: Any Lua value.
+    :param integer default: (Optional) The value returned if ``value`` can't be
+        converted, ``0`` if omitted.
+
+    :rtype: integer
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        overlay.toint('42')      -- 42
+        overlay.toint(3.7)       -- 3
+        overlay.toint(nil, -1)   -- -1
+        overlay.toint({}, -1)    -- -1
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn to_int(l: &lua_State) -> i32 {
+    let default = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkarginteger!(l, 2);
+        lua::tointeger(l, 2)
+    } else {
+        0
+    };
+
+    if let Some(i) = lua::tointegerx(l, 1) {
+        lua::pushinteger(l, i);
+        return 1;
+    }
+
+    // floats and float strings that aren't whole numbers
+    match lua::tonumberx(l, 1) {
+        Some(n) if n.is_finite() && n.trunc() >= i64::MIN as f64 && n.trunc() < i64::MAX as f64 => {
+            lua::pushinteger(l, n.trunc() as i64);
+        },
+        _ => lua::pushinteger(l, default),
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:function:: parsechatcode(code)
+
+    Decode a GW2 chat code, like the ones the game copies for waypoints and
+    items.
+
+    The result is a table with a ``kind`` field and other fields depending on
+    the kind:
+
+    =========== ===============================================================
+    Kind        Fields
+    =========== ===============================================================
+    coin        ``amount``, in copper.
+    item        ``id``, ``count``, ``skin`` (optional) and ``upgrades``, a
+                sequence of 0 to 2 upgrade item IDs.
+    objective   ``id`` and ``mapid`` of a WvW objective.
+    waypoint    ``id``. Points of interest and vistas are also this kind.
+    npctext     ``id``
+    skill       ``id``
+    trait       ``id``
+    recipe      ``id``
+    skin        ``id``
+    outfit      ``id``
+    achievement ``id``
+    =========== ===============================================================
+
+    Build templates, PvP games and user links are not supported.
+
+    :param string code: The chat code, including the ``[&`` and ``]``.
+    :returns: A table, or ``nil`` if ``code`` is malformed or not a supported
+        kind.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local link = overlay.parsechatcode('[&BDgAAAA=]')
+
+        if link and link.kind == 'waypoint' then
+            overlay.loginfo('Waypoint ' .. link.id)
+        end
+
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn parse_chat_code(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 1);
+
+    let code = match ChatCode::parse(&lua::tostring(l, 1).unwrap()) {
+        Some(c) => c,
+        None    => {
+            lua::pushnil(l);
+            return 1;
         }
+    };
 
-        let key = String::from(lua::tostring(l, -2).unwrap());
-        let val = String::from(lua::tostring(l, -1).unwrap());
-        hdrs.push((key, val));
+    lua::newtable(l);
 
-        lua::pop(l, 1);
+    lua::pushstring(l, code.kind());
+    lua::setfield(l, -2, "kind");
+
+    match code {
+        ChatCode::Coin { amount } => {
+            lua::pushinteger(l, amount as i64);
+            lua::setfield(l, -2, "amount");
+        },
+        ChatCode::Item { id, count, skin, upgrades } => {
+            lua::pushinteger(l, id as i64);
+            lua::setfield(l, -2, "id");
+
+            lua::pushinteger(l, count as i64);
+            lua::setfield(l, -2, "count");
+
+            if let Some(s) = skin {
+                lua::pushinteger(l, s as i64);
+                lua::setfield(l, -2, "skin");
+            }
+
+            lua::newtable(l);
+            for (i, u) in upgrades.iter().enumerate() {
+                lua::pushinteger(l, *u as i64);
+                lua::seti(l, -2, i as i64 + 1);
+            }
+            lua::setfield(l, -2, "upgrades");
+        },
+        ChatCode::Objective { id, map_id } => {
+            lua::pushinteger(l, id as i64);
+            lua::setfield(l, -2, "id");
+
+            lua::pushinteger(l, map_id as i64);
+            lua::setfield(l, -2, "mapid");
+        },
+        ChatCode::Id { id, .. } => {
+            lua::pushinteger(l, id as i64);
+            lua::setfield(l, -2, "id");
+        },
     }
 
-    let mut params: Vec<(String, String)> = Vec::new();
+    return 1;
+}
+
+/*** RST
+.. lua:function:: makechatcode(link)
+
+    Create a GW2 chat code that can be pasted into the game's chat.
+
+    :param table link: A table in the same form :lua:func:`parsechatcode`
+        returns. For items, ``count`` defaults to ``1`` and ``skin`` and
+        ``upgrades`` are optional.
+    :rtype: string
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
 
-    lua::pushnil(l);
-    while lua::next(l, 3) != 0 {
-        if lua::luatype(l, -2) != lua::LuaType::LUA_TSTRING {
-            lua::pop(l, 2);
-            luaerror!(l, "Query parameter keys must be strings.");
-            return 0;
-        }
+        overlay.clipboardtext(overlay.makechatcode({ kind = 'waypoint', id = 56 }))
 
-        let key = String::from(lua::tostring(l, -2).unwrap());
-        let val = String::from(lua::tostring(l, -1).unwrap());
-        params.push((key, val));
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn make_chat_code(l: &lua_State) -> i32 {
+    lua::checkargtype!(l, 1, lua::LuaType::LUA_TTABLE);
 
+    // returns an integer field, or default if it isn't set
+    let field = |name: &str, default: Option<i64>| -> Option<i64> {
+        let r = if lua::getfield(l, 1, name) == lua::LuaType::LUA_TNIL {
+            default
+        } else {
+            lua::tointegerx(l, -1)
+        };
         lua::pop(l, 1);
-    }
 
-    let mut dbg = lua::lua_Debug::default();
+        r
+    };
 
-    lua::getstack(l, 1, &mut dbg).unwrap();
-    lua::getinfo(l, "Sl", &mut dbg).unwrap();
+    lua::getfield(l, 1, "kind");
+    let kind = lua::tostring(l, -1).unwrap_or_default();
+    lua::pop(l, 1);
 
-    let src = unsafe { std::ffi::CStr::from_ptr(dbg.source).to_str().unwrap() };
+    let code = match kind.as_str() {
+        "coin" => field("amount", None).map(|a| ChatCode::Coin { amount: a as u32 }),
+        "item" => {
+            let mut upgrades: Vec<u32> = Vec::new();
 
-    let source = format!("{}@{}", src, dbg.currentline);
+            if lua::getfield(l, 1, "upgrades") == lua::LuaType::LUA_TTABLE {
+                for i in 1..=lua::L::len(l, -1).min(2) {
+                    lua::geti(l, -1, i as i64);
+                    upgrades.push(lua::tointeger(l, -1) as u32);
+                    lua::pop(l, 1);
+                }
+            }
+            lua::pop(l, 1);
+
+            match (field("id", None), field("count", Some(1))) {
+                (Some(id), Some(count)) => Some(ChatCode::Item {
+                    id: id as u32,
+                    count: count.clamp(1, 255) as u8,
+                    skin: field("skin", None).map(|s| s as u32),
+                    upgrades: upgrades,
+                }),
+                _ => None,
+            }
+        },
+        "objective" => match (field("id", None), field("mapid", None)) {
+            (Some(id), Some(map_id)) => Some(ChatCode::Objective { id: id as u32, map_id: map_id as u32 }),
+            _                        => None,
+        },
+        _ => match ChatCode::id(&kind, 0) {
+            Some(_) => field("id", None).and_then(|id| ChatCode::id(&kind, id as u32)),
+            None    => {
+                luaerror!(l, "makechatcode: unknown kind '{}'.", kind);
+                return 0;
+            }
+        },
+    };
 
-    crate::web_request::queue_request(&url, hdrs, params, callback, &source);
+    match code {
+        Some(c) => lua::pushstring(l, &c.encode()),
+        None    => {
+            luaerror!(l, "makechatcode: missing or invalid fields for a {} link.", kind);
+            return 0;
+        }
+    }
 
-    return 0;
+    return 1;
 }
 
+
 /*** RST
-.. lua:function:: parsejson(JSON)
+.. lua:function:: parsecolor(text)
 
-    Parse a JSON string into a Lua value.
+    Parse a color from a string, such as one entered in a setting.
 
-    This function returns ``nil`` if the string can not be parsed.
+    ``text`` can be a hex color in the form ``#RGB``, ``#RRGGBB`` or
+    ``#RRGGBBAA``, or one of the basic CSS color names: ``transparent``,
+    ``black``, ``silver``, ``gray``, ``white``, ``maroon``, ``red``,
+    ``purple``, ``fuchsia``, ``green``, ``lime``, ``olive``, ``yellow``,
+    ``navy``, ``blue``, ``teal`` or ``aqua``. Colors without an alpha are fully
+    opaque.
 
-    :param string JSON:
+    The result is a packed ``0xRRGGBBAA`` integer, the same as the colors used
+    by the UI and :lua:mod:`dx` sprite and trail lists.
+
+    :param string text:
+    :returns: An integer, or ``nil`` if ``text`` isn't a valid color.
 
     .. code-block:: lua
         :caption: Example
 
         local overlay = require 'overlay'
 
-        -- objects are parsed into tables
-        local obj = overlay.parsejson('{"test": 1234}')
-        overlay.loginfo(string.format("test: %d", obj.test))
-
-        -- arrays are parsed into sequences
-        local arr = overlay.parsejson('["hello world", 1234]')
-        for i,val in ipairs(arr) do
-            overlay.loginfo(string.format("%d: %s", i, val))
-        end
+        overlay.parsecolor('#F80')      -- 0xFF8800FF
+        overlay.parsecolor('#00FF0080') -- 0x00FF0080
+        overlay.parsecolor('teal')      -- 0x008080FF
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn parse_json(l: &lua_State) -> i32 {
+unsafe extern "C" fn parse_color(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 1);
-    let json_str = lua::tostring(l, 1).unwrap();
 
-    match &serde_json::from_str(&json_str) {
-        Ok(val) => crate::lua_json::pushjson(l, val),
-        Err(err) => {
-            luaerror!(l, "Couldn't parse JSON value: {}", err);
-            return 0;
-        },
+    let text = lua::tostring(l, 1).unwrap();
+
+    match crate::ui::Color::parse(&text) {
+        Some(c) => lua::pushinteger(l, c.into()),
+        None    => lua::pushnil(l),
     }
 
     return 1;
 }
 
 /*** RST
-.. lua:function:: openzip(path)
+.. lua:function:: formatcolor(color)
 
-    Open the zip file at the given path and return a :lua:class:`zipfile`.
+    Format a packed ``0xRRGGBBAA`` color as a ``#RRGGBBAA`` string.
 
-    .. seealso::
-        The :lua:class:`zipfile` class.
+    :param integer color:
+    :rtype: string
 
-    .. note::
-        If an error occurs while opening/reading the zip file, this function
-        will log an error and return ``nil``.
+    .. code-block:: lua
+        :caption: Example
 
-    :rtype: zipfile
+        local overlay = require 'overlay'
+
+        overlay.formatcolor(0xFF8800FF) -- '#FF8800FF'
 
     .. versionhistory::
         :0.3.0: Added
 */
-unsafe extern "C" fn open_zip(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 1);
-    let path = lua::tostring(l, 1).unwrap();
+unsafe extern "C" fn format_color(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 1);
 
-    match crate::zip::open_zip(&path) {
-        Ok(zip) => crate::zip::lua::pushzipfile(l, zip),
-        Err(err) => {
-            luaerror!(l, "Couldn't open zip file: {}", err);
-            lua::pushnil(l);
-        },
-    }
+    let color = crate::ui::Color::from(lua::tointeger(l, 1));
+
+    lua::pushstring(l, &color.to_hex());
 
     return 1;
 }
 
-fn push_ownedname(l: &lua_State, name: &xml::name::OwnedName) {
-    lua::createtable(l, 0, 3);
-
-    lua::pushstring(l, &name.local_name);
-    lua::setfield(l, -2, "local_name");
-
-    match &name.namespace {
-        Some(ns) => lua::pushstring(l, &ns),
-        None     => lua::pushnil(l),
-    }
-    lua::setfield(l, -2, "namespace");
+/*** RST
+.. lua:function:: rng([seed])
 
-    match &name.prefix {
-        Some(p) => lua::pushstring(l, &p),
-        None    => lua::pushnil(l),
-    }
-    lua::setfield(l, -2, "prefix");
-}
+    Create a new :lua:class:`rng`.
 
-fn push_startdoc(l: &lua_State) {
-    lua::pushstring(l, "start-document");
+    Unlike ``math.random``, the sequence of values produced for a given
+    ``seed`` is well defined and will be the same across overlay and Lua
+    versions.
 
-    lua::pushnil(l);
-}
+    :param integer seed: (Optional) If omitted, a seed based on the current
+        time is used.
 
-fn push_enddoc(l: &lua_State) {
-    lua::pushstring(l, "end-document");
+    :rtype: rng
 
-    lua::pushnil(l);
-}
+    .. code-block:: lua
+        :caption: Example
 
-fn push_startelement(l: &lua_State, name: &xml::name::OwnedName, attributes: &Vec<xml::attribute::OwnedAttribute>) {
-    lua::pushstring(l, "start-element");
+        local overlay = require 'overlay'
 
-    lua::createtable(l, 0, 2); // data
+        local rng = overlay.rng(1234)
 
-    push_ownedname(l, name);
-    lua::setfield(l, -2, "name");
+        local roll = rng:range(1, 6)
 
-    let mut ai = 1;
-    lua::createtable(l, attributes.len() as i32, 0);
-    for oa in attributes {
-        lua::createtable(l, 0, 2);
+    .. versionhistory::
+        :0.3.0: Added
+*/
+unsafe extern "C" fn rng(l: &lua_State) -> i32 {
+    let seed: u64 = if lua::gettop(l) >= 1 && lua::luatype(l, 1) != lua::LuaType::LUA_TNIL {
+        lua::checkarginteger!(l, 1);
+        lua::tointeger(l, 1) as u64
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    };
 
-        push_ownedname(l, &oa.name);
-        lua::setfield(l, -2, "name");
+    let rng_ptr: *mut crate::utils::Rng = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<crate::utils::Rng>(), 0))
+    };
 
-        lua::pushstring(l, &oa.value);
-        lua::setfield(l, -2, "value");
+    unsafe { rng_ptr.write(crate::utils::Rng::new(seed)); }
 
-        lua::seti(l, -2, ai);
+    if lua::L::newmetatable(l, RNG_METATABLE_NAME) {
+        lua::pushvalue(l, -1);
+        lua::setfield(l, -2, "__index");
 
-        ai += 1;
+        lua::L::setfuncs(l, RNG_FUNCS, 0);
     }
-    lua::setfield(l, -2, "attributes");
-}
-
-fn push_endelement(l: &lua_State, name: &xml::name::OwnedName) {
-    lua::pushstring(l, "end-element");
+    lua::setmetatable(l, -2);
 
-    push_ownedname(l, name);
-}
-
-fn push_stringevent(l: &lua_State, event: &str, data: &str) {
-    lua::pushstring(l, event);
-    lua::pushstring(l, data);
+    return 1;
 }
 
 /*** RST
-.. lua:function:: parsexml(xml, eventcallback)
-
-    Attempt to parse the given XML string in an event driven manner (i.e. SAX).
-
-    ``eventcallback`` will be called on each event, corresponding to various elements
-    of the XML document. ``eventcallback`` will be called with 2 arguments:
-    the name of the event and the event data (see below).
+Classes
+-------
 
-    This function will only return once the document is fully parsed, returning
-    ``true`` on success and ``false`` on any failure.
+.. lua:class:: rng
 
-    .. note::
+    A seedable pseudo-random number generator, see :lua:func:`rng`.
+*/
+const RNG_METATABLE_NAME: &str = "overlay::lua::Rng";
 
-        Raising an error during the callback will cause parsing to fail and this
-        function to return ``false``.
+const RNG_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"next"     , rng_next,
+    c"nextfloat", rng_next_float,
+    c"range"    , rng_range,
+};
 
-    :param string xml:
-    :param function eventcallback:
-    :rtype: boolean
+// the Rng is plain data stored directly in the userdata, so there is no __gc
+unsafe fn checkrng(l: &lua_State, ind: i32) -> &mut crate::utils::Rng {
+    unsafe { &mut *(lua::L::checkudata(l, ind, RNG_METATABLE_NAME) as *mut crate::utils::Rng) }
+}
 
-    **Event Types**
+/*** RST
+    .. lua:method:: next()
 
-    ================ ==================================================================
-    Event Name       Event Data
-    ================ ==================================================================
-    start-document   ``nil``
-    end-document     ``nil``
-    start-element    A Lua table. See below for fields.
-    end-element      A Lua table containing the following fields:
+        Return the next integer in the sequence. This can be any 64 bit integer,
+        including negative values.
 
-                     - ``local_name``: the element name without prefixes or namespaces
-                     - ``namespace``: the element name namespace
-                     - ``prefix``: the element name prefix
+        :rtype: integer
 
-    cdata            A string containing the CDATA.
-    comment          A string containing the comment.
-    characters       A string containing the text.
-    whitespace       A string containing whitespace characters.
-    ================ ==================================================================
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn rng_next(l: &lua_State) -> i32 {
+    let rng = unsafe { checkrng(l, 1) };
 
+    lua::pushinteger(l, rng.next_u64() as i64);
 
-    **start-element data fields:**
+    return 1;
+}
 
-    ========== =================================================================
-    Field      Description
-    ========== =================================================================
-    name       A Lua table containing the following fields:
+/*** RST
+    .. lua:method:: nextfloat()
 
-               - ``local_name``: the element name without prefixes or namespaces
-               - ``namespace``: the element name namespace
-               - ``prefix``: the element name prefix
+        Return the next number in the sequence, between ``0.0`` (inclusive)
+        and ``1.0`` (exclusive).
 
-    attributes A Lua sequence of tables for each attribute, each with ``name``
-               and ``value`` fields
-    ========== =================================================================
+        :rtype: number
 
-    .. versionhistory::
-        :0.3.0: Added
+        .. versionhistory::
+            :0.3.0: Added
 */
-unsafe extern "C" fn parse_xml(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 1);
-    lua::checkargtype!(l, 2, lua::LuaType::LUA_TFUNCTION);
-    let xml = lua::tostring(l, 1).unwrap();
-
-    let reader = xml::EventReader::new(xml.as_bytes());
-
-    for e in reader {
-        match e {
-            Ok(event) => {
-                lua::pushvalue(l, 2); // the callback function
-                match event {
-                    XmlEvent::StartDocument{..}                  => push_startdoc(l),
-                    XmlEvent::EndDocument                        => push_enddoc(l),
-                    XmlEvent::StartElement{name, attributes, ..} => push_startelement(l, &name, &attributes),
-                    XmlEvent::EndElement{name}                   => push_endelement(l, &name),
-                    XmlEvent::CData(cdata)                       => push_stringevent(l, "cdata", &cdata),
-                    XmlEvent::Comment(comment)                   => push_stringevent(l, "comment", &comment),
-                    XmlEvent::Characters(char)                   => push_stringevent(l, "characters", &char),
-                    XmlEvent::Whitespace(ws)                     => push_stringevent(l, "whitespace", &ws),
-                    XmlEvent::ProcessingInstruction{..}          => {},
-                }
-
-                if let Err(_err) = lua::pcall(l, 2, 0, 0) {
-                    let msg = lua::tostring(l, -1).unwrap_or(String::from("<invalid error message>"));
-                    luaerror!(l, "Error while running xml event callback: {}", msg);
-                    lua::pop(l, 1);
-
-                    lua::pushboolean(l, false);
-
-                    return 1;
-                }
-            },
-            Err(e) => {
-                luaerror!(l, "Error while parsing xml: {}", e);
+unsafe extern "C" fn rng_next_float(l: &lua_State) -> i32 {
+    let rng = unsafe { checkrng(l, 1) };
 
-                lua::pushboolean(l, false);
-                return 1;
-            },
-        }
-    }
+    lua::pushnumber(l, rng.next_f64());
 
-    lua::pushboolean(l, true);
     return 1;
 }
 
 /*** RST
-.. lua:function:: splitstring(str, pat)
-
-    Return a sequence of substrings of ``str`` separated by ``pat``.
+    .. lua:method:: range(min, max)
 
-    :param string str:
-    :param string pat:
+        Return the next integer in the sequence between ``min`` and ``max``,
+        inclusive.
 
-    :rtype: sequence
+        :param integer min:
+        :param integer max:
+        :rtype: integer
 
-    .. versionhistory::
-        :0.3.0: Added
+        .. versionhistory::
+            :0.3.0: Added
 */
-unsafe extern "C" fn split_string(l: &lua_State) -> i32 {
-    lua::checkargstring!(l, 1);
-    lua::checkargstring!(l, 2);
+unsafe extern "C" fn rng_range(l: &lua_State) -> i32 {
+    lua::checkarginteger!(l, 2);
+    lua::checkarginteger!(l, 3);
 
-    let st = lua::tostring(l, 1).unwrap();
-    let pat = lua::tostring(l, 2).unwrap();
+    let rng = unsafe { checkrng(l, 1) };
 
-    lua::newtable(l);
-    let mut ti = 1;
-    for t in st.split(&pat) {
-        lua::pushstring(l, &t);
-        lua::seti(l, -2, ti);
-        ti += 1;
+    let min = lua::tointeger(l, 2);
+    let max = lua::tointeger(l, 3);
+
+    if min > max {
+        luaerror!(l, "range: min must be less than or equal to max.");
+        return 0;
     }
 
+    lua::pushinteger(l, rng.range(min, max));
+
     return 1;
 }
 
-
 /*** RST
 .. include:: /docs/_include/overlayevents.rst
 */
@@ -20,12 +20,80 @@ use std::path;
 
 /// A collection of settings, backed by a JSON file.
 pub struct SettingsStore {
+    name: String,
+
     save_on_set: atomic::AtomicBool,
 
     file_path: path::PathBuf,
 
     data: Mutex<serde_json::Value>,
     defaults: Mutex<HashMap<String, serde_json::Value>>,
+
+    change_handlers: Mutex<Vec<ChangeHandler>>,
+}
+
+/// A Lua callback for changes to a single key, see [SettingsStore::add_change_handler].
+struct ChangeHandler {
+    key: String,
+    callback: i64,
+
+    // the minimum time between calls, in seconds. 0 calls on every change
+    interval: f64,
+
+    // the overlay uptime before which further changes are coalesced
+    next_allowed: f64,
+}
+
+/// A change that was coalesced and will be sent by [send_throttled_changes].
+struct ThrottledChange {
+    callback: i64,
+    due: f64,
+    value: serde_json::Value,
+}
+
+/// The data for a `setting-changed` event.
+struct SettingChange {
+    store: String,
+    key: String,
+    value: serde_json::Value,
+}
+
+impl crate::lua_manager::ToLua for SettingChange {
+    fn push_to_lua(&self, l: &crate::lua::lua_State) {
+        crate::lua::createtable(l, 0, 3);
+
+        crate::lua::pushstring(l, &self.store);
+        crate::lua::setfield(l, -2, "store");
+
+        crate::lua::pushstring(l, &self.key);
+        crate::lua::setfield(l, -2, "key");
+
+        crate::lua_json::pushjson(l, &self.value);
+        crate::lua::setfield(l, -2, "value");
+    }
+}
+
+static THROTTLED_CHANGES: Mutex<Vec<ThrottledChange>> = Mutex::new(Vec::new());
+
+// the stores opened with SettingsStore::open, by name
+static STORES: Mutex<Option<HashMap<String, Arc<SettingsStore>>>> = Mutex::new(None);
+
+/// Sends the latest value of coalesced changes whose interval has passed to
+/// their Lua callbacks.
+///
+/// This is called from the Lua thread each update.
+pub fn send_throttled_changes() {
+    let now = crate::overlay::uptime().as_secs_f64();
+
+    let mut throttled = THROTTLED_CHANGES.lock().unwrap();
+
+    throttled.retain(|c| {
+        if c.due > now { return true; }
+
+        crate::lua_manager::queue_targeted_event(c.callback, Some(Box::new(c.value.clone())));
+
+        false
+    });
 }
 
 /// Returns the value from the JSON object based on a path.
@@ -75,6 +143,21 @@ fn create_values_for_path(data: &mut serde_json::Value, path: &str) {
     }
 }
 
+/// Appends the full path of every non-object value in `data` to `keys`.
+fn collect_keys(data: &serde_json::Value, prefix: &str, keys: &mut Vec<String>) {
+    if let Some(obj) = data.as_object() {
+        for (k, v) in obj {
+            let path = if prefix.len()==0 { k.clone() } else { format!("{}.{}", prefix, k) };
+
+            if v.is_object() && v.as_object().unwrap().len() > 0 {
+                collect_keys(v, &path, keys);
+            } else {
+                keys.push(path);
+            }
+        }
+    }
+}
+
 impl SettingsStore {
     /// Creates a new [SettingsStore].
     ///
@@ -110,13 +193,29 @@ impl SettingsStore {
         }
 
         return Arc::new(SettingsStore {
+            name: String::from(name),
             save_on_set: atomic::AtomicBool::new(true),
             file_path: file_path,
             data: Mutex::new(data),
             defaults: Mutex::new(HashMap::new()),
+            change_handlers: Mutex::new(Vec::new()),
         });
     }
 
+    /// Returns the [SettingsStore] named `name`, creating it with
+    /// [SettingsStore::new] the first time it is opened.
+    ///
+    /// Every caller gets the same store, so change handlers are called for
+    /// changes made by any of them.
+    pub fn open(name: &str) -> Arc<SettingsStore> {
+        let mut stores = STORES.lock().unwrap();
+
+        return stores.get_or_insert_with(HashMap::new)
+            .entry(name.to_string())
+            .or_insert_with(|| SettingsStore::new(name))
+            .clone();
+    }
+
     /// Saves the settings in this store to the backing JSON file.
     pub fn save(&self) {
         let data = self.data.lock().unwrap();
@@ -137,10 +236,20 @@ impl SettingsStore {
         self.defaults.lock().unwrap().insert(String::from(key), serde_json::json!(value));
     }
 
+    /// The name of this store, as given to [SettingsStore::new].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Sets the `value` of `key`.
     ///
-    /// If `key` already exists the existing value is discarded.
+    /// If `key` already exists the existing value is discarded. Change
+    /// handlers and the `setting-changed` event are only notified if the value
+    /// of `key`, including any default, is different than before.
     pub fn set<T: serde::Serialize>(&self, key: &str, value: T) {
+        let value = serde_json::json!(value);
+        let old_value = self.get(key);
+
         let mut data = self.data.lock().unwrap();
 
         let key_parts: Vec<&str> = key.split('.').collect();
@@ -162,15 +271,26 @@ impl SettingsStore {
             }
         }
 
-        parent[value_key] = serde_json::json!(value);
+        // already stored, nothing to save or notify
+        if parent.get(value_key) == Some(&value) { return; }
+
+        parent[value_key] = value.clone();
 
         drop(data);
 
         if self.save_on_set.load(atomic::Ordering::Relaxed) {
             self.save();
         }
+
+        if old_value.as_ref() != Some(&value) {
+            self.notify_change(key);
+        }
     }
 
+    /// Removes the value for `key`.
+    ///
+    /// Returns `true` if a value was removed. The removal is saved to the
+    /// backing JSON file if `save_on_set` is enabled.
     pub fn remove(&self, key: &str) -> bool {
         let mut data = self.data.lock().unwrap();
 
@@ -194,7 +314,8 @@ impl SettingsStore {
         }
 
         if let Some(parentobj) = parent.as_object_mut() {
-            let ret: bool = if let Some(_) = parentobj.remove(value_key) { true  } else { false };
+            let removed = parentobj.remove(value_key);
+            let ret = removed.is_some();
 
             drop(data);
 
@@ -202,12 +323,100 @@ impl SettingsStore {
                 self.save();
             }
 
+            // the value may have been the same as the default
+            if ret && removed != self.get(key) { self.notify_change(key); }
+
             return ret;
         }
 
         false
     }
 
+    /// Adds a Lua function that will be called with the new value of `key`
+    /// each time it is set or removed.
+    ///
+    /// If `interval` is greater than 0, the callback is called at most once
+    /// every `interval` seconds. Changes made within that time are coalesced
+    /// and the callback is called once more with the latest value when the
+    /// interval has passed. Otherwise it is called for every change.
+    ///
+    /// `callback` is unreferenced when this store is dropped.
+    pub fn add_change_handler(&self, key: &str, callback: i64, interval: f64) {
+        self.change_handlers.lock().unwrap().push(ChangeHandler {
+            key: String::from(key),
+            callback: callback,
+            interval: interval.max(0.0),
+            next_allowed: 0.0,
+        });
+    }
+
+    /// Queues a `setting-changed` event and calls any change handlers for
+    /// `key` with its current value.
+    fn notify_change(&self, key: &str) {
+        let value = self.get(key).unwrap_or(serde_json::Value::Null);
+
+        if crate::lua_manager::is_running() {
+            crate::lua_manager::queue_event("setting-changed", Some(Box::new(SettingChange {
+                store: self.name.clone(),
+                key: String::from(key),
+                value: value.clone(),
+            })));
+        }
+
+        let mut handlers = self.change_handlers.lock().unwrap();
+
+        if !handlers.iter().any(|h| h.key == key) { return; }
+
+        let now = crate::overlay::uptime().as_secs_f64();
+
+        let mut throttled = THROTTLED_CHANGES.lock().unwrap();
+
+        for h in handlers.iter_mut().filter(|h| h.key == key) {
+            if h.interval == 0.0 || now >= h.next_allowed {
+                // a pending change would be older than this one
+                throttled.retain(|c| c.callback != h.callback);
+
+                crate::lua_manager::queue_targeted_event(h.callback, Some(Box::new(value.clone())));
+                h.next_allowed = now + h.interval;
+            } else if let Some(c) = throttled.iter_mut().find(|c| c.callback == h.callback) {
+                c.value = value.clone();
+            } else {
+                throttled.push(ThrottledChange { callback: h.callback, due: h.next_allowed, value: value.clone() });
+                h.next_allowed += h.interval;
+            }
+        }
+    }
+
+    /// Returns the top level keys of this store.
+    ///
+    /// If `recursive` is `true`, nested objects are walked instead and each
+    /// non-object value is returned as a dotted path, i.e. `levelone.two`.
+    /// Default values are not included.
+    pub fn keys(&self, recursive: bool) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+
+        if recursive {
+            let mut keys: Vec<String> = Vec::new();
+
+            collect_keys(&data, "", &mut keys);
+
+            return keys;
+        }
+
+        match data.as_object() {
+            Some(obj) => obj.keys().cloned().collect(),
+            None      => Vec::new(),
+        }
+    }
+
+    /// Returns `true` if `key` has a value, either set or a default.
+    ///
+    /// A `null` value is treated as no value, the same as [SettingsStore::get]
+    /// from Lua.
+    pub fn has(&self, key: &str) -> bool {
+        self.get(key).is_some_and(|v| !v.is_null())
+    }
+
     pub fn get(&self, key: &str) -> Option<serde_json::Value> {
         let mut data = self.data.lock().unwrap();
 
@@ -375,3 +584,18 @@ impl SettingsStore {
         }
     }
 }
+
+impl Drop for SettingsStore {
+    fn drop(&mut self) {
+        let handlers = self.change_handlers.lock().unwrap();
+
+        if handlers.is_empty() { return; }
+
+        let mut throttled = THROTTLED_CHANGES.lock().unwrap();
+
+        for h in handlers.iter() {
+            throttled.retain(|c| c.callback != h.callback);
+            crate::lua_manager::unref(h.callback);
+        }
+    }
+}
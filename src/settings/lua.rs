@@ -18,6 +18,8 @@ use crate::logging::{debug, info, warn, error};
 use std::sync::Arc;
 use std::mem::ManuallyDrop;
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
@@ -35,6 +37,10 @@ const SETTINGS_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"get"       , get,
     c"set"       , set,
     c"remove"    , remove,
+    c"delete"    , remove,
+    c"keys"      , keys,
+    c"has"       , has,
+    c"onchange"  , onchange,
 };
 
 
@@ -66,6 +72,12 @@ pub unsafe fn checksettings(l: &lua_State, ind: i32) -> ManuallyDrop<Arc<Setting
     ManuallyDrop::new(unsafe { Arc::from_raw(*checksettings_ptr(l, ind)) })
 }
 
+// restricted modules can't change the overlay's settings, otherwise they
+// could grant themselves permissions. see module_permissions
+fn can_modify(l: &lua_State, s: &Arc<SettingsStore>) -> bool {
+    !Arc::ptr_eq(s, &crate::overlay::settings()) || crate::module_permissions::check_trusted(l)
+}
+
 unsafe extern "C" fn __gc(l: &lua_State) -> i32 {
     let settings_ptr = unsafe { checksettings_ptr(l, 1) };
 
@@ -89,6 +101,9 @@ unsafe extern "C" fn __gc(l: &lua_State) -> i32 {
 unsafe extern "C" fn set_default(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 2);
     let s = unsafe { checksettings(l, 1) };
+
+    if !can_modify(l, &s) { return 0; }
+
     let key = lua::tostring(l, 2).unwrap();
 
     let val = lua_json::tojson(l, 3);
@@ -99,9 +114,21 @@ unsafe extern "C" fn set_default(l: &lua_State) -> i32 {
 }
 
 /*** RST
-    .. lua:method:: get(key)
+    .. lua:method:: get(key[, default])
+
+        Nested values can be accessed with a dotted path, i.e. ``'window.x'``.
 
-        :returns: The value for the given key, or nil if no value exists.
+        If ``key`` has no value and no default set with :lua:meth:`setdefault`,
+        or the value is ``null``, ``default`` is returned instead. ``default`` is not saved to the store.
+
+        :param string key:
+        :param default: (Optional)
+        :returns: The value for the given key, or ``default`` if no value exists.
+
+        .. code-block:: lua
+            :caption: Example
+
+            local size = settings:get('window.size', 12)
 
         .. versionhistory::
             :0.3.0: Added
@@ -111,15 +138,40 @@ unsafe extern "C" fn get(l: &lua_State) -> i32 {
     let s = unsafe { checksettings(l, 1) };
     let key = lua::tostring(l, 2).unwrap();
 
-    if let Some(val) = s.get(&key) {
-        lua_json::pushjson(l, &val);
-    } else {
-        lua::pushnil(l);
+    match s.get(&key) {
+        Some(val) if !val.is_null() => lua_json::pushjson(l, &val),
+        _ => {
+            if lua::gettop(l) >= 3 { lua::pushvalue(l, 3); }
+            else                   { lua::pushnil(l);      }
+        },
     }
 
     return 1;
 }
 
+/*** RST
+    .. lua:method:: has(key)
+
+        Returns ``true`` if ``key`` has a value, including a default set with
+        :lua:meth:`setdefault`. A ``null`` value is treated as no value, the
+        same as :lua:meth:`get`.
+
+        :param string key:
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn has(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let s = unsafe { checksettings(l, 1) };
+    let key = lua::tostring(l, 2).unwrap();
+
+    lua::pushboolean(l, s.has(&key));
+
+    return 1;
+}
+
 /*** RST
     .. lua:method:: set(key, value)
 
@@ -129,6 +181,9 @@ unsafe extern "C" fn get(l: &lua_State) -> i32 {
 unsafe extern "C" fn set(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 2);
     let s = unsafe { checksettings(l, 1) };
+
+    if !can_modify(l, &s) { return 0; }
+
     let key = lua::tostring(l, 2).unwrap();
 
     let val = lua_json::tojson(l, 3);
@@ -141,6 +196,23 @@ unsafe extern "C" fn set(l: &lua_State) -> i32 {
 /*** RST
     .. lua:method:: remove(key)
 
+        Remove the value for ``key``. The change is saved to the settings file
+        immediately.
+
+        :rtype: boolean
+        :returns: ``true`` if a value was removed.
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+
+/*** RST
+    .. lua:method:: delete(key)
+
+        An alias for :lua:meth:`remove`.
+
+        :rtype: boolean
+
         .. versionhistory::
             :0.3.0: Added
 */
@@ -148,9 +220,116 @@ unsafe extern "C" fn remove(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 2);
 
     let s = unsafe { checksettings(l, 1) };
+
+    if !can_modify(l, &s) { return 0; }
+
     let key = lua::tostring(l, 2).unwrap();
 
-    s.remove(&key);
+    lua::pushboolean(l, s.remove(&key));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: keys([recursive])
+
+        Returns a sequence of the top level keys that have a value set in this
+        store. Nested values are not listed separately, ``'window'`` is
+        returned but not ``'window.x'``.
+
+        If ``recursive`` is ``true``, the full path of every nested value is
+        returned instead, i.e. ``'window.x'`` and ``'window.y'`` but not
+        ``'window'``.
+
+        Default values set with :lua:meth:`setdefault` are not included.
+
+        :param boolean recursive: (Optional) Default: ``false``
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            -- clear all settings for this module
+            for _, key in ipairs(settings:keys()) do
+                settings:delete(key)
+            end
+
+        .. versionhistory::
+            :0.3.0: Added, returning full key paths. Changed to return top level
+                keys unless ``recursive`` is ``true``.
+*/
+unsafe extern "C" fn keys(l: &lua_State) -> i32 {
+    let s = unsafe { checksettings(l, 1) };
+
+    let recursive = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargtype!(l, 2, lua::LuaType::LUA_TBOOLEAN);
+        lua::toboolean(l, 2)
+    } else {
+        false
+    };
+
+    let keys = s.keys(recursive);
+
+    lua::createtable(l, keys.len() as i32, 0);
+
+    for (i, k) in keys.iter().enumerate() {
+        lua::pushstring(l, k);
+        lua::seti(l, -2, i as i64 + 1);
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: onchange(key, callback[, interval])
+
+        Call ``callback`` with the new value of ``key`` each time it is set or
+        removed through this store. Setting a value that is the same as the
+        current value does not call ``callback``.
+
+        To be notified of changes to any key, use the
+        :overlay:event:`setting-changed` event instead.
+
+        By default ``callback`` is called for every change. If ``interval`` is
+        given, ``callback`` is called at most once every ``interval`` seconds.
+        Changes made within that time are coalesced and ``callback`` is then
+        called once with the latest value. This is useful for settings that are
+        changed rapidly, i.e. from a text entry or slider, where recalculating
+        on every change would be wasteful.
+
+        :param string key:
+        :param function callback:
+        :param number interval: (Optional) The minimum time between calls, in
+            seconds. ``0`` or ``nil`` calls ``callback`` for every change.
+
+        .. code-block:: lua
+            :caption: Example
+
+            settings:onchange('filter', function(value)
+                rebuildlist(value)
+            end, 0.25)
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn onchange(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    lua::checkargtype!(l, 3, lua::LuaType::LUA_TFUNCTION);
+
+    let s = unsafe { checksettings(l, 1) };
+    let key = lua::tostring(l, 2).unwrap();
+
+    let interval = if lua::gettop(l) >= 4 && lua::luatype(l, 4) != lua::LuaType::LUA_TNIL {
+        lua::checkargnumber!(l, 4);
+        lua::tonumber(l, 4)
+    } else {
+        0.0
+    };
+
+    lua::pushvalue(l, 3);
+    let cbi = module_permissions::ref_callback(l);
+
+    s.add_change_handler(&key, cbi, interval);
 
     return 0;
 }
@@ -3,6 +3,21 @@
 // SPDX-License-Identifier: MIT
 #pragma once
 #define BILLBOARD (1u)
+// sprite flags bit 5, only rotate around the world up axis to face the camera
+#define BILLBOARD_VERTICAL (32u)
+
+// sprite flags bits 1-2, and the trail fade_curve constant
+#define FADE_CURVE_MASK  (6u)
+#define FADE_CURVE_SHIFT (1u)
+
+#define FADE_CURVE_LINEAR     (0u)
+#define FADE_CURVE_SMOOTHSTEP (1u)
+#define FADE_CURVE_QUADRATIC  (2u)
+
+// sprite flags bit 3, pin the sprite to the screen edge when it's off-screen
+#define EDGE_INDICATOR (8u)
+// set by the sprite vertex shader on sprites that are pinned to the edge
+#define EDGE_PINNED    (16u)
 
 // Calculate the alpha based on distance given near and far thresholds.
 // Distances less than near will be 1.0, more than far will be 0.0, and linear
@@ -15,6 +30,17 @@ float distance_fade_alpha(float near, float far, float dist) {
     return 1.0 - ((dist - near) / (far - near));
 }
 
+// Shape a linear fade alpha from distance_fade_alpha with one of the
+// FADE_CURVE_* curves.
+float fade_curve_alpha(float alpha, uint curve) {
+    switch (curve) {
+    case FADE_CURVE_SMOOTHSTEP: return smoothstep(0.0, 1.0, alpha);
+    // stays opaque longer then drops off quickly near fade_far
+    case FADE_CURVE_QUADRATIC:  return 1.0 - ((1.0 - alpha) * (1.0 - alpha));
+    default:                    return alpha;
+    }
+}
+
 #ifdef PIXEL_SHADER
 
 // Discard this fragment if it's within the area where the minimap is
@@ -2,8 +2,19 @@
 // Copyright (c) 2025 Taylor Talkington
 // SPDX-License-Identifier: MIT
 #define ROOTSIG "RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT),"\
-                "RootConstants(num32BitConstants=48, b0),"\
+                "RootConstants(num32BitConstants=51, b0),"\
                 "DescriptorTable(SRV(t0), VISIBILITY=SHADER_VISIBILITY_PIXEL),"\
                 "StaticSampler(s0,"\
                 "    visibility=SHADER_VISIBILITY_PIXEL"\
                 ")"
+
+// Same as ROOTSIG, with an additional table that covers the entire SRV heap so
+// shaders can index textures directly. Only used on resource binding tier 2+.
+#define ROOTSIG_BINDLESS "RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT),"\
+                         "RootConstants(num32BitConstants=51, b0),"\
+                         "DescriptorTable(SRV(t0), VISIBILITY=SHADER_VISIBILITY_PIXEL),"\
+                         "DescriptorTable(SRV(t0, space=1, numDescriptors=unbounded, flags=DESCRIPTORS_VOLATILE),"\
+                         "    VISIBILITY=SHADER_VISIBILITY_PIXEL),"\
+                         "StaticSampler(s0,"\
+                         "    visibility=SHADER_VISIBILITY_PIXEL"\
+                         ")"
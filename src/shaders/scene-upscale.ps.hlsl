@@ -0,0 +1,18 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+#include "image.hlsl"
+
+// the 3D scene, rendered at a lower resolution than the render target
+Texture2D    scene      : register(t0);
+SamplerState texsampler : register(s0);
+
+float4 main(PSInput input) : SV_Target {
+    // the sampler wraps, keep samples off the far edges
+    float2 size;
+    scene.GetDimensions(size.x, size.y);
+    float2 uv = clamp(input.texuv, 0.5 / size, 1.0 - 0.5 / size);
+
+    // colors in the scene are already premultiplied, so they can be used as-is
+    return scene.Sample(texsampler, uv) * color.a;
+}
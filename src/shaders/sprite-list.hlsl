@@ -7,11 +7,15 @@
 //  0  16  float4x4  view
 // 16  16  float4x4  proj
 // 32   3  float3    player_pos
-// 35   1  uint      ismap
+// 35   1  uint      ismap, 0 = world, 1 = map, 2 = screen
 // 36   3  float3    camera_pos
 // 39   1  float     map_left
 // 40   1  float     map_bottom
 // 41   1  float     map_height
+// 42   1  float     time
+// 43   1  float     screen_width
+// 44   1  float     screen_height
+// 45   3  float3    world_up
 
 cbuffer constants : register(b0) {
     float4x4 view;
@@ -26,6 +30,11 @@ cbuffer constants : register(b0) {
 
     float    map_top;
     float    map_height;
+    float    time;
+    float    screen_width;
+
+    float    screen_height;
+    float3   world_up;
 };
 
 struct PSInput {
@@ -33,6 +42,10 @@ struct PSInput {
     float2 texuv           : TEXUV;
     float4 color           : COLOR;
     uint   flags           : FLAGS;
+    nointerpolation uint tex_index : TEX_INDEX;
+    nointerpolation uint frame     : FRAME;
+    // per-sprite values for custom shaders, unused by the built-in shaders
+    nointerpolation float4 params  : PARAMS;
     float  fade_alpha      : FADE_ALPHA;
     float  fade_dist       : FADE_DIST;
     float  cam_player_dist : CAM_PLAYER_DIST;
@@ -6,23 +6,36 @@
 #define PIXEL_SHADER
 #include "3dcommon.hlsl"
 
-Texture2D    texture : register(t0);
+#ifdef BINDLESS
+// the entire SRV heap, indexed by tex_index
+Texture2DArray textures[] : register(t0, space1);
+#else
+Texture2DArray texture : register(t0);
+#endif
 SamplerState texsampler : register(s0);
 
 float4 main(PSInput input) :SV_Target {
 
+    float fade_alpha = fade_curve_alpha(input.fade_alpha, (input.flags & FADE_CURVE_MASK) >> FADE_CURVE_SHIFT);
+
     if (ismap==0) {
         discard_if_in_map(input.position, map_left, map_top, map_height);
-        if (input.fade_alpha < 0.01) discard;
+        if (fade_alpha < 0.01) discard;
     }
 
-    float4 texcolor = texture.Sample(texsampler, input.texuv);
+#ifdef BINDLESS
+    float4 texcolor = textures[NonUniformResourceIndex(input.tex_index)].Sample(texsampler, float3(input.texuv, input.frame));
+#else
+    float4 texcolor = texture.Sample(texsampler, float3(input.texuv, input.frame));
+#endif
 
     float alpha = texcolor.a * input.color.a;
 
     if (ismap==0) {
-        alpha = min(alpha, input.fade_alpha);
-        if (input.cam_player_dist >= input.vert_cam_dist) {
+        alpha = min(alpha, fade_alpha);
+        if ((input.flags & EDGE_PINNED) > 0) {
+            // pinned sprites aren't dimmed when they are behind the player
+        } else if (input.cam_player_dist >= input.vert_cam_dist) {
             alpha = min(alpha, 0.05);
         } else if (input.vert_cam_dist - input.cam_player_dist <= 36) {
             float adist = input.vert_cam_dist - input.cam_player_dist;
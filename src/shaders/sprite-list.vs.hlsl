@@ -15,8 +15,51 @@ struct VSInput {
     float4   color     : COLOR;
     uint     flags     : FLAGS;
     float4x4 rotation  : ROTATION;
+    uint     tex_index : TEX_INDEX;
+    uint     frames    : FRAMES;
+    float    fps       : FPS;
+    float4   params    : PARAMS;
+    float    edge_size : EDGE_SIZE;
 };
 
+// Returns the clip space position of the corner of an edge indicator, or w = 0
+// if the sprite is on-screen and should be drawn normally.
+//
+// local is the corner of the sprite quad, -0.5 to 0.5, with +y toward the top
+// of the texture.
+float4 edge_indicator_pos(float3 pos, float2 local, float size) {
+    float4 viewpos = mul(float4(pos, 1.0), view);
+    float4 center = mul(viewpos, proj);
+
+    // behind the camera w is negative, which would flip the direction
+    float2 ndc = center.xy / max(abs(center.w), 0.0001);
+
+    if (center.w > 0.0 && all(abs(ndc) <= 1.0)) return float4(0.0, 0.0, 0.0, 0.0);
+
+    float2 half_screen = float2(screen_width, screen_height) / 2.0;
+
+    // the direction to the sprite in pixels from the center of the screen
+    float2 dir = ndc * half_screen;
+    if (length(dir) < 0.0001) dir = float2(0.0, -1.0);
+    dir = normalize(dir);
+
+    // keep the whole indicator on screen
+    float2 limit = max(half_screen - size, 0.0);
+    float scale = min(limit.x / max(abs(dir.x), 0.0001), limit.y / max(abs(dir.y), 0.0001));
+
+    // the top of the texture points toward the sprite
+    float2 up = dir;
+    float2 right = float2(up.y, -up.x);
+
+    float2 px = (dir * scale) + (right * local.x * size) + (up * local.y * size);
+
+    // use the depth the sprite would have if it were straight ahead, so
+    // indicators are still sorted by distance
+    float4 ahead = mul(float4(0.0, 0.0, length(viewpos.xyz), 1.0), proj);
+
+    return float4(px / half_screen, ahead.z / ahead.w, 1.0);
+}
+
 PSInput main(VSInput input, uint vert : SV_VertexID) {
     PSInput output;
 
@@ -55,7 +98,19 @@ PSInput main(VSInput input, uint vert : SV_VertexID) {
     }
 
     if (ismap==0) {
-        if ((input.flags & BILLBOARD) > 0) {
+        if ((input.flags & BILLBOARD_VERTICAL) > 0) {
+            // face the camera horizontally, but stay upright
+            float3 cam_right = float3(view[0].x, view[1].x, view[2].x);
+            float3 right = cam_right - (dot(cam_right, world_up) * world_up);
+
+            // the camera is looking straight up or down
+            if (length(right) < 0.0001) {
+                right = abs(world_up.x) < 0.9 ? float3(1.0, 0.0, 0.0) : float3(0.0, 0.0, 1.0);
+                right = right - (dot(right, world_up) * world_up);
+            }
+
+            vpos = (normalize(right) * vpos.x) + (world_up * vpos.y);
+        } else if ((input.flags & BILLBOARD) > 0) {
             vpos = mul(billboard, vpos);
         } else {
             vpos = mul(float4(vpos, 1.0), input.rotation).xyz;
@@ -63,11 +118,31 @@ PSInput main(VSInput input, uint vert : SV_VertexID) {
     }
 
     output.flags = input.flags;
+    output.tex_index = input.tex_index;
+    output.params = input.params;
+
+    // animated sprites show one layer of their texture at a time
+    if (input.frames > 1) {
+        output.frame = uint(time * input.fps) % input.frames;
+    } else {
+        output.frame = 0;
+    }
 
     float4 adjpos = float4(input.pos + vpos, 1.0);
     float4 viewpos = mul(adjpos, view);
     output.position = mul(viewpos, proj);
 
+    if (ismap==0 && (input.flags & EDGE_INDICATOR) > 0) {
+        // texture v is 0 at the top
+        float2 local = float2(output.texuv.x / input.max_u - 0.5, 0.5 - output.texuv.y / input.max_v);
+        float4 edgepos = edge_indicator_pos(input.pos, local, input.edge_size);
+
+        if (edgepos.w > 0.0) {
+            output.position = edgepos;
+            output.flags |= EDGE_PINNED;
+        }
+    }
+
     output.color = input.color;
 
     output.fade_dist = distance(player_pos, input.pos);
@@ -14,6 +14,9 @@
 // 45  1 float    map_left
 // 46  1 float    map_top
 // 47  1 float    map_height
+// 48  1 uint     fade_curve
+// 49  1 float    age_alpha_start
+// 50  1 float    age_alpha_end
 
 struct PSInput {
     float4 position        : SV_Position;
@@ -22,6 +25,8 @@ struct PSInput {
     float3 trail_pos       : TRAIL_POS;
     float  cam_player_dist : CAM_PLAYER_DIST;
     float  vert_cam_dist   : VERT_CAM_DIST;
+    float4 vert_color      : VERT_COLOR;
+    float  age             : AGE;
 };
 
 cbuffer constants : register(b0) {
@@ -36,4 +41,7 @@ cbuffer constants : register(b0) {
     float    map_left;
     float    map_top;
     float    map_height;
+    uint     fade_curve;
+    float    age_alpha_start;
+    float    age_alpha_end;
 };
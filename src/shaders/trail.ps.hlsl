@@ -5,17 +5,22 @@
 #include "trail.hlsl"
 #include "3dcommon.hlsl"
 
-Texture2D    texture    : register(t0);
+// texture map textures are arrays, trails always use the first layer
+Texture2DArray texture  : register(t0);
 SamplerState texsampler : register(s0);
 
 float4 main(PSInput input) : SV_Target {
     if (inmap==0) discard_if_in_map(input.position, map_left, map_top, map_height);
 
-    float alpha = color.a;
+    // per-point colors are tinted by the trail color
+    float4 tcolor = color * input.vert_color;
+
+    // both age alpha values are 1.0 when not fading by age
+    float alpha = tcolor.a * lerp(age_alpha_start, age_alpha_end, input.age);
 
     if (inmap==0) {
         float fade_dist = distance(player_pos, input.trail_pos);
-        alpha = min(alpha, distance_fade_alpha(fade_near, fade_far, fade_dist));
+        alpha = min(alpha, fade_curve_alpha(distance_fade_alpha(fade_near, fade_far, fade_dist), fade_curve));
         if (alpha < 0.01) discard;
 
         float vertcamdist = distance(camera_pos, input.trail_pos);
@@ -40,11 +45,11 @@ float4 main(PSInput input) : SV_Target {
         */
     }
 
-    float4 texcolor = texture.Sample(texsampler, input.texuv);
+    float4 texcolor = texture.Sample(texsampler, float3(input.texuv, 0));
 
     alpha *= texcolor.a;
 
     if (alpha < 0.01) discard;
 
-    return float4((texcolor.rgb * color.rgb) * alpha, alpha);
+    return float4((texcolor.rgb * tcolor.rgb) * alpha, alpha);
 }
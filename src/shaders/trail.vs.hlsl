@@ -7,6 +7,8 @@
 struct VSInput {
     float3 position : POSITION;
     float2 texuv    : TEXUV;
+    float4 color    : COLOR;
+    float  age      : AGE;
 };
 
 PSInput main(VSInput input) {
@@ -17,6 +19,9 @@ PSInput main(VSInput input) {
     output.position = mul(viewpos, proj);
     output.texuv    = input.texuv;
 
+    output.vert_color = input.color;
+    output.age        = input.age;
+
     output.cam_player_dist = distance(camera_pos, player_pos);
     output.vert_cam_dist   = distance(camera_pos, input.position);
 
@@ -0,0 +1,12 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+#include "image.hlsl"
+
+// the cached UI layer, which is the same size as the render target
+Texture2D layer : register(t0);
+
+float4 main(PSInput input) : SV_Target {
+    // colors in the layer are already premultiplied, so they can be used as-is
+    return layer.Load(int3(input.position.xy, 0)) * color.a;
+}
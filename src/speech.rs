@@ -0,0 +1,141 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! Text-to-speech using SAPI
+//!
+//! Speech requests are queued and handed off to a SAPI voice on a dedicated
+//! thread so that long utterances don't block the Lua or render threads.
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use std::collections::VecDeque;
+
+#[allow(unused_imports)]
+use crate::logging::{info,debug,warn,error};
+
+use crate::utils;
+
+use windows::Win32::Media::Speech;
+use windows::Win32::System::Com;
+
+static SPEECH_THREAD: Mutex<Option<std::thread::JoinHandle<()>>> = Mutex::new(None);
+
+static SPEECH_RUNNING: AtomicBool = AtomicBool::new(true);
+
+static SPEECH_QUEUE: Mutex<VecDeque<SpeechCommand>> = Mutex::new(VecDeque::new());
+
+enum SpeechCommand {
+    Speak {
+        text: String,
+        rate: i32,
+        volume: u16,
+        interrupt: bool,
+    },
+    Stop,
+}
+
+pub fn init() {
+    let t = std::thread::Builder::new().name("EG-Overlay Speech Thread".to_string()).spawn(move || {
+        speech_thread();
+    }).expect("Couldn't spawn speech thread.");
+
+    *SPEECH_THREAD.lock().unwrap() = Some(t);
+}
+
+pub fn cleanup() {
+    let t = SPEECH_THREAD.lock().unwrap().take().unwrap();
+
+    SPEECH_RUNNING.store(false, Ordering::Relaxed);
+
+    t.thread().unpark();
+    t.join().unwrap();
+}
+
+fn speech_thread() {
+    debug!("Speech thread starting...");
+
+    utils::init_com_for_thread();
+
+    let voice: Option<Speech::ISpVoice> = match unsafe {
+        Com::CoCreateInstance(&Speech::SpVoice, None, Com::CLSCTX_ALL)
+    } {
+        Ok(v) => Some(v),
+        Err(err) => {
+            error!("Couldn't create SAPI voice, text-to-speech will not be available: {}", err);
+            None
+        }
+    };
+
+    while SPEECH_RUNNING.load(Ordering::Relaxed) {
+        while let Some(cmd) = SPEECH_QUEUE.lock().unwrap().pop_front() {
+            if let Some(v) = &voice { perform(v, cmd); }
+        }
+
+        std::thread::park();
+    }
+
+    if let Some(v) = &voice {
+        // don't leave anything speaking after the overlay exits
+        perform(v, SpeechCommand::Stop);
+    }
+
+    drop(voice);
+
+    utils::uninit_com_for_thread();
+
+    debug!("Speech thread ending...");
+}
+
+fn perform(voice: &Speech::ISpVoice, cmd: SpeechCommand) {
+    match cmd {
+        SpeechCommand::Speak { text, rate, volume, interrupt } => {
+            let mut textu16: Vec<u16> = text.encode_utf16().collect();
+            textu16.push(0u16);
+
+            let mut flags = Speech::SPF_ASYNC.0 | Speech::SPF_IS_NOT_XML.0;
+            if interrupt { flags |= Speech::SPF_PURGEBEFORESPEAK.0; }
+
+            unsafe {
+                // rate and volume apply to the voice, so anything already
+                // queued will also change
+                let _ = voice.SetRate(rate);
+                let _ = voice.SetVolume(volume);
+
+                if let Err(err) = voice.Speak(windows::core::PCWSTR::from_raw(textu16.as_ptr()), flags as u32, None) {
+                    error!("Couldn't speak text: {}", err);
+                }
+            }
+        },
+        SpeechCommand::Stop => {
+            let flags = Speech::SPF_ASYNC.0 | Speech::SPF_PURGEBEFORESPEAK.0;
+
+            if let Err(err) = unsafe { voice.Speak(windows::core::PCWSTR::null(), flags as u32, None) } {
+                error!("Couldn't stop speaking: {}", err);
+            }
+        },
+    }
+}
+
+fn queue(cmd: SpeechCommand) {
+    SPEECH_QUEUE.lock().unwrap().push_back(cmd);
+    SPEECH_THREAD.lock().unwrap().as_ref().unwrap().thread().unpark();
+}
+
+/// Queues `text` to be spoken.
+///
+/// `rate` is from -10 to 10, `volume` from 0 to 100. If `interrupt` is true,
+/// anything currently being spoken or queued will be stopped first.
+pub fn speak(text: &str, rate: i32, volume: u16, interrupt: bool) {
+    queue(SpeechCommand::Speak {
+        text: String::from(text),
+        rate: rate.clamp(-10, 10),
+        volume: volume.min(100),
+        interrupt: interrupt,
+    });
+}
+
+/// Stops speaking and discards any queued speech.
+pub fn stop() {
+    queue(SpeechCommand::Stop);
+}
@@ -6,6 +6,7 @@
 
 pub mod font;
 pub mod rect;
+pub mod layer;
 pub mod text;
 pub mod window;
 pub mod uibox;
@@ -15,6 +16,7 @@ pub mod button;
 pub mod scrollview;
 pub mod entry;
 pub mod menu;
+pub mod perf;
 
 pub mod lua;
 
@@ -25,11 +27,13 @@ use crate::input;
 use crate::dx;
 
 use std::sync::Arc;
+use std::sync::Weak;
 use std::sync::Mutex;
 use std::sync::atomic;
 
 use std::collections::VecDeque;
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use std::fs::File;
 use std::io::BufRead;
@@ -85,7 +89,10 @@ impl Element {
     }
 
     pub fn set_x(&self, value: i64) {
-        element_dispatch!(self, set_x, value)
+        if self.get_x() == value { return; }
+
+        element_dispatch!(self, set_x, value);
+        mark_dirty();
     }
 
     pub fn get_y(&self) -> i64 {
@@ -93,7 +100,10 @@ impl Element {
     }
 
     pub fn set_y(&self, value: i64) {
+        if self.get_y() == value { return; }
+
         element_dispatch!(self, set_y, value);
+        mark_dirty();
     }
 
     pub fn get_preferred_width(&self) -> i64 {
@@ -109,7 +119,10 @@ impl Element {
     }
 
     pub fn set_width(&self, value: i64) {
+        if self.get_width() == value { return; }
+
         element_dispatch!(self, set_width, value);
+        mark_dirty();
     }
 
     pub fn get_height(&self) -> i64 {
@@ -117,7 +130,10 @@ impl Element {
     }
 
     pub fn set_height(&self, value: i64) {
+        if self.get_height() == value { return; }
+
         element_dispatch!(self, set_height, value);
+        mark_dirty();
     }
 
     pub fn get_bg_color(&self) -> Color {
@@ -125,7 +141,10 @@ impl Element {
     }
 
     pub fn set_bg_color(&self, value: Color) {
+        if self.get_bg_color() == value { return; }
+
         element_dispatch!(self, set_bg_color, value);
+        mark_dirty();
     }
 
     pub fn on_lost_focus(&self) {
@@ -203,6 +222,19 @@ impl Element {
     }
 }
 
+// see mark_dirty
+static DIRTY: atomic::AtomicBool = atomic::AtomicBool::new(true);
+
+/// Flags the UI as changed so that it will be redrawn during the next frame.
+///
+/// This must be called whenever something changes the appearance of an
+/// element. The element setters, i.e. [Element::set_x], and input events do
+/// this automatically. Changes made directly to an element's state, including
+/// from its Lua methods, must call this.
+pub fn mark_dirty() {
+    DIRTY.store(true, atomic::Ordering::Relaxed);
+}
+
 /// The global state for the UI
 pub struct Ui {
     top_level_elements: Mutex<VecDeque<Arc<Element>>>,
@@ -216,6 +248,9 @@ pub struct Ui {
     mouse_over_element: Mutex<Option<InputElement>>,
     mouse_capture_element: Mutex<Option<InputElement>>,
 
+    // the button that started a drag capture, see capture_mouse_drag
+    mouse_capture_button: Mutex<Option<input::MouseButtonEventButton>>,
+
     focus_element: Mutex<Option<Arc<Element>>>,
 
     last_mouse_x: atomic::AtomicI64,
@@ -223,6 +258,18 @@ pub struct Ui {
 
     last_ui_size: Mutex<(u32, u32)>,
 
+    // when the layer is used elements are only drawn when the UI is dirty, see
+    // mark_dirty
+    layer: Mutex<Option<layer::Layer>>,
+
+    // draw calls made by the UI during the last frame and by the last time the
+    // elements were drawn, see draw_stats
+    draw_calls: atomic::AtomicU32,
+    element_draw_calls: atomic::AtomicU32,
+
+    // top level elements that aren't in the default render channel
+    element_channels: Mutex<Vec<(Weak<Element>, String)>>,
+
     // fonts must be declared before the font manager so that they are dropped
     // first.
     pub regular_font: Arc<font::Font>,
@@ -234,6 +281,7 @@ pub struct Ui {
 
     pub font_manager: font::FontManager,
     pub rect: rect::Rect,
+    pub perf: perf::PerfHud,
 }
 
 fn get_default_font(font_manager: &font::FontManager, key: &str) -> Arc<font::Font> {
@@ -288,6 +336,8 @@ impl Ui {
 
         o_settings.set_default_value("overlay.ui.font.gammaCorrection", 1.4);
 
+        o_settings.set_default_value("overlay.ui.cacheLayer", true);
+
         o_settings.set_default_value("overlay.ui.font.regular.path"     , "fonts/Inter.ttf");
         o_settings.set_default_value("overlay.ui.font.regular.size"     ,  12);
         o_settings.set_default_value("overlay.ui.font.regular.vars", serde_json::json!({
@@ -322,6 +372,13 @@ impl Ui {
         let mono_font = get_default_font(&font_man, "mono");
         let icon_font = get_default_font(&font_man, "icon");
 
+        let layer = if o_settings.get_bool("overlay.ui.cacheLayer").unwrap() {
+            Some(layer::Layer::new())
+        } else {
+            info!("UI layer caching disabled, the UI will be drawn every frame.");
+            None
+        };
+
         let ui = Ui {
             top_level_elements: Mutex::new(VecDeque::new()),
             input_elements: Mutex::new(VecDeque::new()),
@@ -329,6 +386,8 @@ impl Ui {
             mouse_over_element: Mutex::new(None),
             mouse_capture_element: Mutex::new(None),
 
+            mouse_capture_button: Mutex::new(None),
+
             focus_element: Mutex::new(None),
 
             last_mouse_x: atomic::AtomicI64::new(0),
@@ -336,8 +395,16 @@ impl Ui {
 
             last_ui_size: Mutex::new((0, 0)),
 
+            layer: Mutex::new(layer),
+
+            draw_calls: atomic::AtomicU32::new(0),
+            element_draw_calls: atomic::AtomicU32::new(0),
+
+            element_channels: Mutex::new(Vec::new()),
+
             font_manager: font_man,
             rect: rect::Rect::new(),
+            perf: perf::PerfHud::new(),
 
             regular_font: reg_font,
             italic_font: ital_font,
@@ -365,6 +432,8 @@ impl Ui {
         }
 
         top_level.push_back(element.clone());
+
+        self.mark_dirty();
     }
 
     pub fn remove_top_level_element(&self, element: &Arc<Element>) {
@@ -383,6 +452,7 @@ impl Ui {
 
         if let Some(i) = ind {
             let _ = top_level.remove(i);
+            self.mark_dirty();
         }/* else {
             warn!("Element was not top level.");
         }*/
@@ -408,6 +478,8 @@ impl Ui {
         }
 
         top_level.push_back(element.clone());
+
+        self.mark_dirty();
     }
 
     pub fn add_input_element(&self, element: &Arc<Element>, offset_x: i64, offset_y: i64, scissor: Foundation::RECT) {
@@ -434,8 +506,88 @@ impl Ui {
         });
     }
 
+    /// Captures the mouse for a drag started by pressing `button`.
+    ///
+    /// Unlike [Ui::set_mouse_capture], `element` receives every mouse event
+    /// until `button` is released, no matter where the cursor is, and no other
+    /// elements will receive events. This is the equivalent of `SetCapture`.
+    ///
+    /// The capture is released automatically after the button up event is sent
+    /// to `element`. If the button up is missed, i.e. if the input hook timed
+    /// out, the capture is released the next time the button is pressed and a
+    /// button up event is sent to `element` first so that the drag can end.
+    pub fn capture_mouse_drag(
+        &self,
+        element: &Arc<Element>,
+        offset_x: i64,
+        offset_y: i64,
+        scissor: Foundation::RECT,
+        button: input::MouseButtonEventButton
+    ) {
+        if self.mouse_capture_element.lock().unwrap().is_some() { return; }
+
+        self.set_mouse_capture(element, offset_x, offset_y, scissor);
+
+        *self.mouse_capture_button.lock().unwrap() = Some(button);
+    }
+
     pub fn clear_mouse_capture(&self) {
         *self.mouse_capture_element.lock().unwrap() = None;
+        *self.mouse_capture_button.lock().unwrap() = None;
+    }
+
+    /// Sends `event` to the element that has a drag capture.
+    ///
+    /// Returns `None` if the drag ended because of a missed button up and the
+    /// event should be processed normally.
+    fn process_drag_mouse_event(&self, event: &input::MouseEvent, button: input::MouseButtonEventButton) -> Option<bool> {
+        let mce = match self.mouse_capture_element.lock().unwrap().clone() {
+            Some(mce) => mce,
+            None      => return None,
+        };
+
+        self.mark_dirty();
+
+        if let input::MouseEvent::Button(b) = event {
+            if b.button == button {
+                if b.down {
+                    // the button up for this drag never arrived, end the drag
+                    // before handling the new press
+                    let up = input::MouseEvent::Button(input::MouseButtonEvent {
+                        x: b.x,
+                        y: b.y,
+                        button: button,
+                        down: false,
+                    });
+
+                    mce.element.process_mouse_event(mce.offset_x, mce.offset_y, &up);
+                    self.clear_mouse_capture();
+
+                    return None;
+                }
+
+                mce.element.process_mouse_event(mce.offset_x, mce.offset_y, event);
+                self.clear_mouse_capture();
+
+                return Some(true);
+            }
+        }
+
+        mce.element.process_mouse_event(mce.offset_x, mce.offset_y, event);
+
+        return Some(true);
+    }
+
+    /// Flags the UI as changed so that it will be redrawn during the next frame.
+    ///
+    /// See [mark_dirty].
+    pub fn mark_dirty(&self) {
+        mark_dirty();
+    }
+
+    /// Returns `true` if the UI has changed since it was last drawn.
+    pub fn is_dirty(&self) -> bool {
+        DIRTY.load(atomic::Ordering::Relaxed)
     }
 
     pub fn draw(&self, frame: &mut crate::dx::SwapChainLock) {
@@ -444,20 +596,121 @@ impl Ui {
         ui_size.1 = frame.render_target_height();
         drop(ui_size);
 
-        //self.input_elements.lock().unwrap().clear();
-        let mut ielf = self.input_elements_last_frame.lock().unwrap();
+        // this is cleared before drawing so that any changes made while the
+        // elements are being drawn will cause another redraw next frame
+        let dirty = DIRTY.swap(false, atomic::Ordering::Relaxed);
 
-        ielf.clear();
+        let draws_before = frame.frame_draw_calls();
 
-        for ie in self.input_elements.lock().unwrap().drain(..) {
-            ielf.push_back(ie);
+        self.draw_cached(frame, dirty);
+
+        let draws = frame.frame_draw_calls() - draws_before;
+        self.draw_calls.store(draws, atomic::Ordering::Relaxed);
+    }
+
+    /// Returns the number of draw calls the UI made during the last frame and
+    /// the number made the last time the elements were drawn.
+    ///
+    /// When the UI is cached in a layer and hasn't changed, the first is only
+    /// the draw of the layer itself. The difference between the two is the
+    /// number of draw calls saved by the layer each frame.
+    pub fn draw_stats(&self) -> (u32, u32) {
+        (
+            self.draw_calls.load(atomic::Ordering::Relaxed),
+            self.element_draw_calls.load(atomic::Ordering::Relaxed),
+        )
+    }
+
+    // draws the UI using the layer if there is one
+    fn draw_cached(&self, frame: &mut crate::dx::SwapChainLock, dirty: bool) {
+        let mut layer_lock = self.layer.lock().unwrap();
+
+        if let Some(layer) = layer_lock.as_ref() {
+            match layer.update_size(frame) {
+                Ok(recreated) => {
+                    if recreated || dirty {
+                        layer.begin(frame);
+                        self.draw_elements(frame);
+                        layer.end(frame);
+                    }
+
+                    layer.draw(frame);
+
+                    return;
+                },
+                Err(err) => {
+                    error!("Couldn't create UI layer, the UI will be drawn every frame: {}", err);
+                    *layer_lock = None;
+                },
+            }
         }
 
+        self.draw_elements(frame);
+    }
+
+    /// Sets the render channel of `element`.
+    ///
+    /// Channels only apply to top level elements, child elements are always
+    /// drawn with their parent. See [Ui::draw_channels].
+    pub fn set_element_channel(&self, element: &Arc<Element>, channel: &str) {
+        let mut channels = self.element_channels.lock().unwrap();
+
+        channels.retain(|(e, _)| e.strong_count() > 0 && e.as_ptr() != Arc::as_ptr(element));
+
+        if channel != dx::lua::DEFAULT_CHANNEL {
+            channels.push((Arc::downgrade(element), String::from(channel)));
+        }
+    }
+
+    /// Returns the render channel of `element`.
+    pub fn element_channel(&self, element: &Arc<Element>) -> String {
+        for (e, channel) in self.element_channels.lock().unwrap().iter() {
+            if e.as_ptr() == Arc::as_ptr(element) { return channel.clone(); }
+        }
+
+        return String::from(dx::lua::DEFAULT_CHANNEL);
+    }
+
+    /// Draws only the top level elements in the given render channels.
+    ///
+    /// This is used for capture passes, elements are drawn directly to the
+    /// current render target and input is not affected.
+    pub fn draw_channels(&self, frame: &mut crate::dx::SwapChainLock, channels: &HashSet<String>) {
+        let top_level = self.top_level_elements.lock().unwrap().clone();
+
+        for e in &top_level {
+            if channels.contains(&self.element_channel(e)) {
+                e.draw(0, 0, frame);
+            }
+        }
+
+        // input elements are only gathered from the normal draw
+        self.input_elements.lock().unwrap().clear();
+    }
+
+    fn draw_elements(&self, frame: &mut crate::dx::SwapChainLock) {
+        let draws_before = frame.frame_draw_calls();
+
         let top_level = self.top_level_elements.lock().unwrap().clone();
 
         for e in &top_level {
             e.draw(0, 0, frame);
         }
+
+        let draws = frame.frame_draw_calls() - draws_before;
+        self.element_draw_calls.store(draws, atomic::Ordering::Relaxed);
+
+        // input elements are gathered while drawing. they are swapped in once
+        // the elements are drawn because the UI may not be drawn again until
+        // something changes
+        //self.input_elements.lock().unwrap().clear();
+        let mut ielf = self.input_elements_last_frame.lock().unwrap();
+
+        ielf.clear();
+
+        for ie in self.input_elements.lock().unwrap().drain(..) {
+            ielf.push_back(ie);
+        }
     }
 
     pub fn process_mouse_event(&self, event: &input::MouseEvent) -> bool {
@@ -465,6 +718,12 @@ impl Ui {
         self.last_mouse_x.store(event.x(), atomic::Ordering::Relaxed);
         self.last_mouse_y.store(event.y(), atomic::Ordering::Relaxed);
 
+        let drag_button = *self.mouse_capture_button.lock().unwrap();
+
+        if let Some(button) = drag_button {
+            if let Some(r) = self.process_drag_mouse_event(event, button) { return r; }
+        }
+
         let mut e_under_mouse: Option<&InputElement> = None;
 
         let input_elements = self.input_elements_last_frame.lock().unwrap();
@@ -476,6 +735,15 @@ impl Ui {
             }
         }
 
+        // hover states may change whenever the mouse is over an element or has
+        // just left one
+        if e_under_mouse.is_some() ||
+           self.mouse_over_element.lock().unwrap().is_some() ||
+           self.mouse_capture_element.lock().unwrap().is_some()
+        {
+            self.mark_dirty();
+        }
+
         if let Some(e) = e_under_mouse { // there is an element currently under the mouse
             if let Some(moe) = self.mouse_over_element.lock().unwrap().as_ref() {
                 // there was something under the mouse during the last event
@@ -547,8 +815,11 @@ impl Ui {
     }
 
     pub fn process_keyboard_event(&self, event: &input::KeyboardEvent) -> bool {
+        if self.perf.process_keyboard_event(event) { return true; }
+
         if let Some(e) = self.focus_element.lock().unwrap().as_ref() {
             if e.process_keyboard_event(event) {
+                self.mark_dirty();
                 return true
             }
         }
@@ -569,6 +840,10 @@ impl Ui {
             e.on_lost_focus();
         }
 
+        if lock.is_some() || element.is_some() {
+            self.mark_dirty();
+        }
+
         *lock = element;
     }
 
@@ -645,7 +920,7 @@ impl Drop for Ui {
 /// Colors are 32bit integers, stored in RGBA format.
 /// This means that they can be conveniently conveyed in hex format,
 /// a.k.a. HTML colors. ie. red = 0xFF0000FF
-#[derive(Copy,Clone)]
+#[derive(Copy,Clone,PartialEq)]
 pub struct Color(u32);
 
 impl From<u32> for Color {
@@ -696,8 +971,71 @@ impl Color {
 
     /// Returns the alpha component of the color as a value between 0.0 and 1.0.
     pub fn a_f32(&self) -> f32 { self.a_u8() as f32 / 255.0f32 }
+
+    /// Parses a color from a string.
+    ///
+    /// `text` can be `#RGB`, `#RRGGBB` or `#RRGGBBAA` hex, or one of the named
+    /// colors in [NAMED_COLORS]. Colors without an alpha are fully opaque.
+    pub fn parse(text: &str) -> Option<Color> {
+        let text = text.trim();
+
+        let hex = match text.strip_prefix('#') {
+            Some(h) => h,
+            None    => {
+                let lower = text.to_ascii_lowercase();
+
+                return NAMED_COLORS.iter().find(|(name, _)| *name == lower).map(|(_, c)| Color(*c));
+            }
+        };
+
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) { return None; }
+
+        let val = u32::from_str_radix(hex, 16).ok()?;
+
+        match hex.len() {
+            3 => {
+                // each digit is doubled, #F80 is #FF8800
+                let r = (val >> 8) & 0xF;
+                let g = (val >> 4) & 0xF;
+                let b =  val       & 0xF;
+
+                Some(Color((r * 0x11) << 24 | (g * 0x11) << 16 | (b * 0x11) << 8 | 0xFF))
+            },
+            6 => Some(Color(val << 8 | 0xFF)),
+            8 => Some(Color(val)),
+            _ => None,
+        }
+    }
+
+    /// Returns the color as a `#RRGGBBAA` string.
+    pub fn to_hex(&self) -> String {
+        format!("#{:08X}", self.0)
+    }
 }
 
+/// Colors that can be used by name in [Color::parse].
+///
+/// These are the basic CSS colors.
+pub const NAMED_COLORS: [(&str, u32); 17] = [
+    ("transparent", 0x00000000),
+    ("black"      , 0x000000FF),
+    ("silver"     , 0xC0C0C0FF),
+    ("gray"       , 0x808080FF),
+    ("white"      , 0xFFFFFFFF),
+    ("maroon"     , 0x800000FF),
+    ("red"        , 0xFF0000FF),
+    ("purple"     , 0x800080FF),
+    ("fuchsia"    , 0xFF00FFFF),
+    ("green"      , 0x008000FF),
+    ("lime"       , 0x00FF00FF),
+    ("olive"      , 0x808000FF),
+    ("yellow"     , 0xFFFF00FF),
+    ("navy"       , 0x000080FF),
+    ("blue"       , 0x0000FFFF),
+    ("teal"       , 0x008080FF),
+    ("aqua"       , 0x00FFFFFF),
+];
+
 #[derive(Clone)]
 struct InputElement {
     offset_x: i64,
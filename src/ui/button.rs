@@ -266,7 +266,7 @@ impl ButtonInner {
             input::MouseEvent::Button(btn) => {
                 if btn.down {
                     self.highlight = true;
-                    self.ui.upgrade().unwrap().set_mouse_capture(element, offset_x, offset_y, self.last_scissor.clone());
+                    self.ui.upgrade().unwrap().capture_mouse_drag(element, offset_x, offset_y, self.last_scissor.clone(), btn.button);
                 } else {
                     let btn_x = self.x + offset_x;
                     let btn_y = self.y + offset_y;
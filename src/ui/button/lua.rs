@@ -39,6 +39,8 @@ use std::mem::ManuallyDrop;
 
 use std::collections::HashSet;
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
@@ -154,6 +156,8 @@ unsafe extern "C" fn child(l: &lua_State) -> i32 {
 
     btn.inner.lock().unwrap().child = Some((*c).clone());
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -178,7 +182,7 @@ unsafe extern "C" fn add_event_handler(l: &lua_State) -> i32 {
 
     lua::pushvalue(l, 2);
 
-    let ehref = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let ehref = module_permissions::ref_callback(l);
 
     let mut events: HashSet<String> = HashSet::new();
 
@@ -243,6 +247,8 @@ unsafe extern "C" fn background_hover(l: &lua_State) -> i32 {
 
     btn.inner.lock().unwrap().bg_hover = color;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -264,6 +270,8 @@ unsafe extern "C" fn background_highlight(l: &lua_State) -> i32 {
 
     btn.inner.lock().unwrap().bg_highlight = color;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -285,6 +293,8 @@ unsafe extern "C" fn border(l: &lua_State) -> i32 {
 
     btn.inner.lock().unwrap().border = color;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -307,6 +317,8 @@ unsafe extern "C" fn border_width(l: &lua_State) -> i32 {
 
     btn.inner.lock().unwrap().border_width = pixels;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -336,6 +348,8 @@ unsafe extern "C" fn check_state(l: &lua_State) -> i32 {
         let value = lua::toboolean(l, 2);
 
         btn.inner.lock().unwrap().toggle_state = value;
+
+        ui::mark_dirty();
     }
 
     let ret = btn.inner.lock().unwrap().toggle_state;
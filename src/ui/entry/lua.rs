@@ -27,6 +27,8 @@ use std::mem::ManuallyDrop;
 
 use std::collections::HashSet;
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
@@ -111,6 +113,8 @@ unsafe extern "C" fn text(l: &lua_State) -> i32 {
             let mut inner = entry.inner.lock().unwrap();
             inner.text = String::from(t);
             inner.update_caret_x();
+
+            ui::mark_dirty();
         } else {
             crate::overlay::lua::luaerror!(l, "text argument #1 must be a string.");
         }
@@ -145,6 +149,8 @@ unsafe extern "C" fn hint(l: &lua_State) -> i32 {
 
     entry.inner.lock().unwrap().hint = Some(String::from(hint));
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -166,6 +172,8 @@ unsafe extern "C" fn pref_width(l: &lua_State) -> i32 {
 
     entry.inner.lock().unwrap().pref_width = w;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -260,7 +268,7 @@ unsafe extern "C" fn add_event_handler(l: &lua_State) -> i32 {
     }
 
     lua::pushvalue(l, 2);
-    let ehref = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let ehref = module_permissions::ref_callback(l);
 
     _ = entry.inner.lock().unwrap().event_handlers.insert(ehref, events);
 
@@ -314,6 +322,8 @@ unsafe extern "C" fn read_only(l: &lua_State) -> i32 {
 
     entry.inner.lock().unwrap().readonly = val;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
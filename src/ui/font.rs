@@ -242,7 +242,7 @@ impl FontManager {
             GLYPH_TEX_SIZE as u32,
             1,
             1
-        );
+        ).expect("Couldn't create font texture.");
         tex.set_name(format!("EG-Overlay D3D12 Font Texture: {}|{}", path, size).as_str());
 
         let key = FontKey {
@@ -316,6 +316,11 @@ struct FontMutData {
 }
 
 impl Font {
+    /// The size of this font in pixels.
+    pub fn size(&self) -> u32 {
+        self.key.size
+    }
+
     // Render a glyph to the underlying texture
     // the glyph here is a UTF 32bit codepoint
     fn render_glyph(&self, glyph: char) {
@@ -363,7 +368,7 @@ impl Font {
                 GLYPH_TEX_SIZE as u32,
                 font_data.texture_levels + 1,
                 1
-            );
+            ).expect("Couldn't create font texture.");
             new_texture.set_name(format!("EG-Overlay D3D12 Font Texture: {}|{}", self.key.path, self.key.size).as_str());
 
             new_texture.copy_subresources_from(&font_data.texture, font_data.texture_levels as u32);
@@ -414,7 +419,7 @@ impl Font {
             bitmap.rows,
             Dxgi::Common::DXGI_FORMAT_R8_UNORM,
             &pixels
-        );
+        ).expect("Couldn't write glyph to font texture.");
     }
 
     /// Renders text with this font at the given location.
@@ -38,6 +38,15 @@ in a ``fonts`` table on the :lua:mod:`ui` module as ``regular``,
     local monofont = ui.fonts.monospace
     local iconfont = ui.fonts.icon
 
+Sizes
+-----
+
+Each combination of font file, size and font variables has its own glyph
+texture that glyphs are rendered into the first time they are drawn. Text is
+always drawn at the exact pixel size of its font and is never scaled, so
+headings and body text can be mixed freely by using fonts of different sizes,
+see :lua:meth:`uifont.tosize` and :lua:meth:`uitext.fontsize`.
+
 Functions
 ---------
 */
@@ -61,6 +70,7 @@ const FONT_METATABLE_NAME: &str = "ui::Font";
 #[doc(hidden)]
 const FONT_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"path"      , path,
+    c"size"      , size,
     c"__gc"      , __gc,
     c"tosize"    , to_size,
     c"tosizeperc", to_size_perc,
@@ -236,6 +246,24 @@ unsafe extern "C" fn path(l: &lua_State) -> i32 {
     return 1;
 }
 
+/*** RST
+    .. lua:method:: size()
+
+        Return the size of this font in pixels.
+
+        :rtype: integer
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn size(l: &lua_State) -> i32 {
+    let f = unsafe { checkfont(l, 1) };
+
+    lua::pushinteger(l, f.size() as i64);
+
+    return 1;
+}
+
 unsafe extern "C" fn __gc(l: &lua_State) -> i32 {
     let font_ptr = unsafe { checkfont_ptr(l, 1) };
 
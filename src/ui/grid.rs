@@ -338,5 +338,7 @@ impl GridInner {
         let cell = ((row * self.cols) + col) as usize;
 
         self.cells[cell] = item;
+
+        ui::mark_dirty();
     }
 }
@@ -283,6 +283,8 @@ unsafe extern "C" fn rowspacing(l: &lua_State) -> i32 {
 
     grid.inner.lock().unwrap().rowspacing[(row-1) as usize] = spacing;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -339,6 +341,8 @@ unsafe extern "C" fn colspacing(l: &lua_State) -> i32 {
 
     grid.inner.lock().unwrap().colspacing[(col-1) as usize] = spacing;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -0,0 +1,145 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! A cached copy of the UI.
+//!
+//! The UI is drawn to a render target only when something has changed and the
+//! render target is then drawn each frame, so static UI only costs a single
+//! draw.
+#[allow(unused_imports)]
+use crate::logging::{debug, info, warn, error};
+
+use crate::overlay;
+use crate::dx;
+
+use std::sync::Mutex;
+
+use windows::Win32::Graphics::Direct3D12;
+use windows::Win32::Graphics::Direct3D;
+use windows::Win32::Graphics::Dxgi;
+
+const VERT_CSO : &str = "shaders/image.vs.cso";
+const PIXEL_CSO: &str = "shaders/ui-layer.ps.cso";
+
+pub struct Layer {
+    pso: Direct3D12::ID3D12PipelineState,
+
+    target: Mutex<Option<dx::RenderTarget>>,
+}
+
+impl Layer {
+    pub fn new() -> Layer {
+        debug!("init");
+
+        debug!("Loading vertex shader from {}...", VERT_CSO);
+        let vertcso = std::fs::read(VERT_CSO).expect(format!("Couldn't read {}",VERT_CSO).as_str());
+
+        debug!("Loading pixel shader from {}...", PIXEL_CSO);
+        let pixelcso = std::fs::read(PIXEL_CSO).expect(format!("Couldn't read {}",PIXEL_CSO).as_str());
+
+        let mut psodesc = Direct3D12::D3D12_GRAPHICS_PIPELINE_STATE_DESC::default();
+        psodesc.VS.pShaderBytecode = vertcso.as_ptr() as *const _;
+        psodesc.VS.BytecodeLength  = vertcso.len();
+        psodesc.PS.pShaderBytecode = pixelcso.as_ptr() as *const _;
+        psodesc.PS.BytecodeLength  = pixelcso.len();
+
+        psodesc.RasterizerState.FillMode             = Direct3D12::D3D12_FILL_MODE_SOLID;
+        psodesc.RasterizerState.CullMode             = Direct3D12::D3D12_CULL_MODE_NONE;
+        psodesc.RasterizerState.DepthBias            = Direct3D12::D3D12_DEFAULT_DEPTH_BIAS;
+        psodesc.RasterizerState.DepthBiasClamp       = Direct3D12::D3D12_DEFAULT_DEPTH_BIAS_CLAMP;
+        psodesc.RasterizerState.SlopeScaledDepthBias = Direct3D12::D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS;
+        psodesc.RasterizerState.DepthClipEnable      = true.into();
+        psodesc.RasterizerState.ConservativeRaster   = Direct3D12::D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF;
+
+        psodesc.BlendState.RenderTarget[0].BlendEnable           = true.into();
+        psodesc.BlendState.RenderTarget[0].SrcBlend              = Direct3D12::D3D12_BLEND_ONE;
+        psodesc.BlendState.RenderTarget[0].DestBlend             = Direct3D12::D3D12_BLEND_INV_SRC_ALPHA;
+        psodesc.BlendState.RenderTarget[0].BlendOp               = Direct3D12::D3D12_BLEND_OP_ADD;
+        psodesc.BlendState.RenderTarget[0].SrcBlendAlpha         = Direct3D12::D3D12_BLEND_ONE;
+        psodesc.BlendState.RenderTarget[0].DestBlendAlpha        = Direct3D12::D3D12_BLEND_INV_SRC_ALPHA;
+        psodesc.BlendState.RenderTarget[0].BlendOpAlpha          = Direct3D12::D3D12_BLEND_OP_ADD;
+        psodesc.BlendState.RenderTarget[0].RenderTargetWriteMask = Direct3D12::D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8;
+
+        psodesc.DepthStencilState.DepthEnable   = false.into();
+        psodesc.DepthStencilState.StencilEnable = false.into();
+
+        psodesc.SampleMask = std::ffi::c_uint::MAX; //UINT_MAX;
+        psodesc.PrimitiveTopologyType = Direct3D12::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE;
+        psodesc.NumRenderTargets = 1;
+        psodesc.RTVFormats[0] = Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM;
+        psodesc.SampleDesc.Count = 1;
+
+        let dx = overlay::dx();
+
+        let pso = dx.create_pipeline_state(&mut psodesc,"EG-Overlay D3D12 ui.layer Pipeline State")
+            .expect("Couldn't create Layer Pipeline State.");
+
+        Layer {
+            pso: pso,
+            target: Mutex::new(None),
+        }
+    }
+
+    /// Starts drawing to the layer.
+    ///
+    /// The layer is cleared and all drawing until [Layer::end] will be done to
+    /// the layer instead of the backbuffer.
+    pub fn begin(&self, frame: &mut dx::SwapChainLock) {
+        let target = self.target.lock().unwrap();
+
+        frame.push_render_target(target.as_ref().unwrap(), false);
+    }
+
+    pub fn end(&self, frame: &mut dx::SwapChainLock) {
+        let target = self.target.lock().unwrap();
+
+        frame.pop_render_target(target.as_ref().unwrap());
+    }
+
+    /// (Re)Creates the layer's render target if it doesn't match the size of
+    /// the current render target.
+    ///
+    /// Returns `true` if the layer was recreated, and therefore is empty, or
+    /// an error if the render target couldn't be created.
+    pub fn update_size(&self, frame: &dx::SwapChainLock) -> Result<bool, dx::DxError> {
+        let width = frame.render_target_width();
+        let height = frame.render_target_height();
+
+        let mut target = self.target.lock().unwrap();
+
+        if let Some(t) = target.as_ref() {
+            if t.width() == width && t.height() == height { return Ok(false); }
+        }
+
+        debug!("Creating {}x{} UI layer.", width, height);
+
+        // free the old target before creating the new one
+        *target = None;
+        *target = Some(overlay::dx().new_render_target(width, height, "EG-Overlay D3D12 UI Layer")?);
+
+        return Ok(true);
+    }
+
+    /// Draws the contents of the layer to the backbuffer.
+    pub fn draw(&self, frame: &mut dx::SwapChainLock) {
+        let target = self.target.lock().unwrap();
+        let target = target.as_ref().unwrap();
+
+        frame.set_pipeline_state(&self.pso);
+
+        frame.set_root_constant_float(0.0                   , 0,  0); // left
+        frame.set_root_constant_float(0.0                   , 0,  1); // top
+        frame.set_root_constant_float(target.width() as f32 , 0,  2); // right
+        frame.set_root_constant_float(target.height() as f32, 0,  3); // bottom
+        frame.set_root_constant_float4(&[1.0, 1.0, 1.0, 1.0], 0,  4); // color
+        frame.set_root_constant_ortho_proj(                   0,  8); // proj
+        frame.set_root_constant_float(1.0                   , 0, 24); // maxu
+        frame.set_root_constant_float(1.0                   , 0, 25); // maxv
+
+        frame.set_texture(0, target.texture());
+
+        frame.set_primitive_topology(Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
+        frame.draw_instanced(4, 1, 0, 0);
+    }
+}
@@ -35,6 +35,7 @@ pub const ELEMENT_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"width"    , element_width,
     c"height"   , element_height,
     c"bgcolor"  , element_bg_color,
+    c"channel"  , element_channel,
 };
 
 /// Checks if the value at the index is a UI Element and returns it if so.
@@ -465,6 +466,23 @@ unsafe extern "C" fn element_bg_color(l: &lua_State) -> i32 {
     return 1;
 }
 
+#[doc(hidden)]
+unsafe extern "C" fn element_channel(l: &lua_State) -> i32 {
+    let e = unsafe { checkelement(l, 1) };
+
+    let ui = crate::overlay::ui();
+
+    if lua::gettop(l) == 2 {
+        lua::checkargstring!(l, 2);
+
+        ui.set_element_channel(&e, &lua::tostring(l, 2).unwrap());
+    }
+
+    lua::pushstring(l, &ui.element_channel(&e));
+
+    return 1;
+}
+
 /*** RST
 .. _colors:
 
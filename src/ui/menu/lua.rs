@@ -33,6 +33,8 @@ use std::collections::HashSet;
 
 use crate::overlay::lua::{luawarn, luaerror};
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
@@ -557,6 +559,8 @@ unsafe extern "C" fn menuitem_enabled(l: &lua_State) -> i32 {
 
     mi.inner.lock().unwrap().enabled = lua::toboolean(l, 2);
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -578,6 +582,8 @@ unsafe extern "C" fn menuitem_element(l: &lua_State) -> i32 {
 
     mi.inner.lock().unwrap().element = Some((*e).clone());
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -605,6 +611,8 @@ unsafe extern "C" fn menuitem_icon(l: &lua_State) -> i32 {
     if lua::gettop(l) >= 2 {
         if let Some(icon) = lua::tostring(l, 2) {
             mi.inner.lock().unwrap().icon_text = icon;
+
+            ui::mark_dirty();
         } else {
             luaerror!(l, "codepoint must be a string");
         }
@@ -641,6 +649,8 @@ unsafe extern "C" fn menuitem_submenu(l: &lua_State) -> i32 {
 
     mi.inner.lock().unwrap().child_menu = Some((*menu_e).clone());
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -669,7 +679,7 @@ unsafe extern "C" fn menuitem_add_event_handler(l: &lua_State) -> i32 {
 
     lua::pushvalue(l, 2);
 
-    let ehref = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let ehref = module_permissions::ref_callback(l);
 
     let mut events: HashSet<String> = HashSet::new();
 
@@ -0,0 +1,179 @@
+// EG-Overlay
+// Copyright (c) 2025 Taylor Talkington
+// SPDX-License-Identifier: MIT
+
+//! A built-in performance HUD.
+//!
+//! The HUD shows FPS, a frame time graph, video memory usage, draw calls, the
+//! draw calls made by the UI and the number of objects skipped by frustum
+//! culling in the top right corner of the overlay. It is drawn directly each frame, after the rest of the UI, so
+//! it doesn't depend on any Lua modules being loaded.
+#[allow(unused_imports)]
+use crate::logging::{debug, info, warn, error};
+
+use crate::ui;
+use crate::overlay;
+use crate::dx;
+
+use std::sync::Mutex;
+use std::sync::atomic;
+
+use std::collections::VecDeque;
+
+// the number of frames shown in the frame time graph
+const GRAPH_FRAMES: usize = 112;
+
+const HUD_WIDTH   : i64 = 240;
+const GRAPH_HEIGHT: i64 = 40;
+const PADDING     : i64 = 8;
+const MARGIN      : i64 = 10;
+
+const BAR_COLOR     : u32 = 0x4CAF50FF;
+const BAR_SLOW_COLOR: u32 = 0xE53935FF;
+const TARGET_COLOR  : u32 = 0xFFFFFF60;
+
+pub struct PerfHud {
+    visible: atomic::AtomicBool,
+
+    hotkey: String,
+    frame_target: f64,
+
+    bg_color: ui::Color,
+    text_color: ui::Color,
+    accent_color: ui::Color,
+
+    // (uptime at the start of the frame in seconds, frame time in ms), most
+    // recent last
+    frames: Mutex<VecDeque<(f64, f64)>>,
+}
+
+impl PerfHud {
+    pub fn new() -> PerfHud {
+        let o_settings = overlay::settings();
+
+        o_settings.set_default_value("overlay.ui.perfHotkey", "ctrl-shift-f12");
+
+        return PerfHud {
+            visible: atomic::AtomicBool::new(false),
+
+            hotkey: o_settings.get_string("overlay.ui.perfHotkey").unwrap(),
+            frame_target: o_settings.get_f64("overlay.frameTargetTime").unwrap(),
+
+            bg_color: ui::Color::from(o_settings.get_u64("overlay.ui.colors.windowBG").unwrap() as u32),
+            text_color: ui::Color::from(o_settings.get_u64("overlay.ui.colors.text").unwrap() as u32),
+            accent_color: ui::Color::from(o_settings.get_u64("overlay.ui.colors.accentText").unwrap() as u32),
+
+            frames: Mutex::new(VecDeque::with_capacity(GRAPH_FRAMES)),
+        };
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        if self.visible.swap(visible, atomic::Ordering::Relaxed) != visible {
+            overlay::request_redraw();
+        }
+    }
+
+    /// Toggles the HUD if `event` is the perf HUD hotkey.
+    ///
+    /// Returns `true` if the event was consumed.
+    pub fn process_keyboard_event(&self, event: &crate::input::KeyboardEvent) -> bool {
+        if self.hotkey.is_empty() || event.full_name() != self.hotkey { return false; }
+
+        if event.down {
+            self.set_visible(!self.is_visible());
+        }
+
+        return true;
+    }
+
+    /// Records a rendered frame that started at `begin` and took `frame_time`
+    /// milliseconds.
+    pub fn record_frame(&self, begin: f64, frame_time: f64) {
+        let mut frames = self.frames.lock().unwrap();
+
+        if frames.len() >= GRAPH_FRAMES { frames.pop_front(); }
+
+        frames.push_back((begin, frame_time));
+    }
+
+    /// Frames per second over the last second of recorded frames.
+    fn fps(frames: &VecDeque<(f64, f64)>) -> f64 {
+        let last = match frames.back() {
+            Some(f) => f.0,
+            None    => return 0.0,
+        };
+
+        let recent: Vec<f64> = frames.iter().map(|f| f.0).filter(|b| last - b <= 1.0).collect();
+
+        if recent.len() < 2 { return 0.0; }
+
+        return (recent.len() - 1) as f64 / (last - recent[0]);
+    }
+
+    pub fn draw(&self, frame: &mut dx::SwapChainLock, ui: &ui::Ui) {
+        if !self.is_visible() { return; }
+
+        let frames = self.frames.lock().unwrap().clone();
+
+        let font = &ui.mono_font;
+        let line_height = font.get_line_spacing() as i64;
+
+        let height = (PADDING * 2) + (line_height * 6) + GRAPH_HEIGHT + PADDING;
+
+        let x = frame.render_target_width() as i64 - HUD_WIDTH - MARGIN;
+        let mut y = MARGIN;
+
+        ui.rect.draw(frame, x, y, HUD_WIDTH, height, self.bg_color);
+
+        y += PADDING;
+
+        let last_time = frames.back().map(|f| f.1).unwrap_or(0.0);
+        let max_time = frames.iter().map(|f| f.1).fold(0.0, f64::max);
+
+        font.render_text(frame, x + PADDING, y, &format!("FPS  : {:.1}", PerfHud::fps(&frames)), self.accent_color);
+        y += line_height;
+
+        font.render_text(frame, x + PADDING, y, &format!("Frame: {:.2}ms (max {:.2}ms)", last_time, max_time), self.text_color);
+        y += line_height + (PADDING / 2);
+
+        // the graph is scaled so that the frame target is at least half way up
+        let graph_max = max_time.max(self.frame_target * 2.0);
+        let graph_bottom = y + GRAPH_HEIGHT;
+        let bar_width = (HUD_WIDTH - (PADDING * 2)) / GRAPH_FRAMES as i64;
+
+        let mut bar_x = x + PADDING + ((GRAPH_FRAMES - frames.len()) as i64 * bar_width);
+
+        for (_, t) in frames.iter() {
+            let bar_height = (((t / graph_max) * GRAPH_HEIGHT as f64) as i64).max(1);
+            let color = if *t > self.frame_target { BAR_SLOW_COLOR } else { BAR_COLOR };
+
+            ui.rect.draw(frame, bar_x, graph_bottom - bar_height, bar_width, bar_height, ui::Color::from(color));
+
+            bar_x += bar_width;
+        }
+
+        let target_y = graph_bottom - ((self.frame_target / graph_max) * GRAPH_HEIGHT as f64) as i64;
+        ui.rect.draw(frame, x + PADDING, target_y, HUD_WIDTH - (PADDING * 2), 1, ui::Color::from(TARGET_COLOR));
+
+        y = graph_bottom + (PADDING / 2);
+
+        let vram = overlay::dx().get_video_mem_used() as f64 / 1024.0 / 1024.0;
+
+        font.render_text(frame, x + PADDING, y, &format!("VRAM : {:.1} MiB", vram), self.text_color);
+        y += line_height;
+
+        font.render_text(frame, x + PADDING, y, &format!("Draws: {}", frame.draw_calls()), self.text_color);
+        y += line_height;
+
+        // the draws the UI made last frame and would make if it wasn't cached
+        let (ui_draws, element_draws) = ui.draw_stats();
+        font.render_text(frame, x + PADDING, y, &format!("UI   : {} ({} uncached)", ui_draws, element_draws), self.text_color);
+        y += line_height;
+
+        font.render_text(frame, x + PADDING, y, &format!("Culled: {}", frame.culled()), self.text_color);
+    }
+}
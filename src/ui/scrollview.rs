@@ -379,7 +379,7 @@ impl ScrollViewInner {
                 self.last_drag_x = button.x;
                 self.last_drag_y = button.y;
 
-                self.ui.upgrade().unwrap().set_mouse_capture(element, offset_x, offset_y, self.last_scissor.clone());
+                self.ui.upgrade().unwrap().capture_mouse_drag(element, offset_x, offset_y, self.last_scissor.clone(), button.button);
 
                 return true;
             }
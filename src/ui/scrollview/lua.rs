@@ -92,6 +92,8 @@ unsafe extern "C" fn child(l: &lua_State) -> i32 {
 
     sv.inner.lock().unwrap().child = Some((*c).clone());
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -134,6 +136,8 @@ unsafe extern "C" fn scroll_y(l: &lua_State) -> i32 {
 
         inner.disp_y = (max_y as f64 * y) as i64;
 
+        ui::mark_dirty();
+
         return 0;
     } else {
         let inner = sv.inner.lock().unwrap();
@@ -92,6 +92,8 @@ unsafe extern "C" fn thickness(l: &lua_State) -> i32 {
 
     sep.inner.lock().unwrap().thickness = thickness;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -142,6 +142,21 @@ impl Text {
         self.text.lock().unwrap().bg_color = color;
     }
 
+    pub fn get_font(&self) -> Arc<ui::font::Font> {
+        self.text.lock().unwrap().font.clone()
+    }
+
+    /// Changes the font used to draw this text, which also changes its
+    /// preferred size.
+    pub fn set_font(&self, font: &Arc<ui::font::Font>) {
+        let mut t = self.text.lock().unwrap();
+
+        t.font = font.clone();
+        t.update_text_size();
+
+        ui::mark_dirty();
+    }
+
     pub fn on_lost_focus(&self)  { }
 }
 
@@ -28,6 +28,8 @@ use crate::logging::{debug, info, warn, error};
 use std::sync::Arc;
 use std::mem::ManuallyDrop;
 
+use crate::module_permissions;
+
 use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
@@ -52,6 +54,8 @@ const TEXT_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
     c"addeventhandler"   , add_event_handler,
     c"removeeventhandler", remove_event_handler,
     c"textcolor"         , text_color,
+    c"font"              , font,
+    c"fontsize"          , font_size,
 };
 
 pub fn register_module_functions(l: &lua_State) {
@@ -59,24 +63,50 @@ pub fn register_module_functions(l: &lua_State) {
 }
 
 /*** RST
-.. lua:function:: text(text_value, color, font)
+.. lua:function:: text(text_value, color, font[, size])
 
     Create a new :lua:class:'uitext' element.
 
     :param string text: The text string to display.
     :param integer color: Text color. See :ref:`colors`.
     :param uifont font: The font to use to render the text. See :lua:class:`uifont`.
+    :param integer size: (Optional) The size in pixels to render the text at,
+        using the same font file and variables as ``font``. This is the same as
+        using ``font:tosize(size)``.
 
     :return: A Text element
 
+    .. code-block:: lua
+        :caption: Example
+
+        local ui = require 'ui'
+
+        local heading = ui.text('Heading', 0xFFFFFFFF, ui.fonts.regular, 24)
+        local body = ui.text('Body text', 0xFFFFFFFF, ui.fonts.regular)
+
     .. versionhistory::
         :0.0.1: Added
+        :0.3.0: Added ``size``
 */
 unsafe extern "C" fn new_text(l: &lua_State) -> i32 {
     lua::checkargstring!(l, 1);
     lua::checkarginteger!(l, 2);
 
-    let font = unsafe { ui::font::lua::checkfont(l, 3) };
+    let base_font = unsafe { ui::font::lua::checkfont(l, 3) };
+    let mut font = Arc::clone(&base_font);
+
+    if lua::gettop(l) >= 4 && lua::luatype(l, 4) != lua::LuaType::LUA_TNIL {
+        lua::checkarginteger!(l, 4);
+
+        let size = lua::tointeger(l, 4);
+
+        if size < 1 {
+            luaerror!(l, "text: size must be greater than 0.");
+            return 0;
+        }
+
+        font = crate::overlay::ui().font_manager.get_font_from_font_with_size(&font, size as u32);
+    }
 
     let text = lua::tostring(l, 1).unwrap();
     let color = ui::Color::from(lua::tointeger(l, 2) as u32);
@@ -117,6 +147,8 @@ unsafe extern "C" fn text(l: &lua_State) -> i32 {
             t.text = newtext.clone();
             t.update_text_size();
 
+            ui::mark_dirty();
+
             lua::pushstring(l, &newtext);
 
             return 1;
@@ -173,7 +205,7 @@ unsafe extern "C" fn add_event_handler(l: &lua_State) -> i32 {
 
     lua::pushvalue(l, 2);
 
-    let ehref = lua::L::ref_(l, lua::LUA_REGISTRYINDEX);
+    let ehref = module_permissions::ref_callback(l);
 
     let mut events: HashSet<String> = HashSet::new();
 
@@ -236,9 +268,82 @@ unsafe extern "C" fn text_color(l: &lua_State) -> i32 {
 
     text.text.lock().unwrap().fg_color = color;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
+/*** RST
+    .. lua:method:: font([newfont])
+
+        Get or set the font used to render this text.
+
+        :param uifont newfont: (Optional)
+        :rtype: uifont
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn font(l: &lua_State) -> i32 {
+    let e = unsafe { ui::lua::checkelement(l, 1) };
+    let text = unsafe { checktext(l, &e) };
+
+    if lua::gettop(l) >= 2 {
+        let f = unsafe { ui::font::lua::checkfont(l, 2) };
+
+        text.set_font(&f);
+    }
+
+    ui::font::lua::pushfont(l, &text.get_font());
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: fontsize([size])
+
+        Get or set the size in pixels this text is rendered at.
+
+        Setting the size changes the font to one with the same font file and
+        variables but a different size. Glyphs are rendered at each size
+        separately, so text stays sharp at any size.
+
+        :param integer size: (Optional)
+        :rtype: integer
+
+        .. code-block:: lua
+            :caption: Example
+
+            local title = ui.text('Title', 0xFFFFFFFF, ui.fonts.regular)
+            title:fontsize(20)
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn font_size(l: &lua_State) -> i32 {
+    let e = unsafe { ui::lua::checkelement(l, 1) };
+    let text = unsafe { checktext(l, &e) };
+
+    if lua::gettop(l) >= 2 {
+        lua::checkarginteger!(l, 2);
+
+        let size = lua::tointeger(l, 2);
+
+        if size < 1 {
+            luaerror!(l, "fontsize: size must be greater than 0.");
+            return 0;
+        }
+
+        let font = crate::overlay::ui().font_manager.get_font_from_font_with_size(&text.get_font(), size as u32);
+
+        text.set_font(&font);
+    }
+
+    lua::pushinteger(l, text.get_font().size() as i64);
+
+    return 1;
+}
+
 /*** RST
     .. note::
 
@@ -154,6 +154,8 @@ impl Box {
             alignment: alignment,
             expand: expand
         });
+
+        ui::mark_dirty();
     }
 
     pub fn push_back(&self, item: &Arc<ui::Element>, alignment: ui::ElementAlignment, expand: bool) {
@@ -162,18 +164,26 @@ impl Box {
             alignment: alignment,
             expand: expand,
         });
+
+        ui::mark_dirty();
     }
 
     pub fn pop_front(&self) {
         self.inner.lock().unwrap().items.pop_front();
+
+        ui::mark_dirty();
     }
 
     pub fn pop_back(&self) {
         self.inner.lock().unwrap().items.pop_back();
+
+        ui::mark_dirty();
     }
 
     pub fn remove_item(&self, item: &Arc<ui::Element>) {
         self.inner.lock().unwrap().items.retain(|x| !Arc::ptr_eq(&x.element, item));
+
+        ui::mark_dirty();
     }
 
     pub fn insert_before(&self, before: &Arc<ui::Element>, item: &Arc<ui::Element>, alignment: ui::ElementAlignment, expand: bool) -> bool {
@@ -186,6 +196,8 @@ impl Box {
                     alignment: alignment,
                     expand: expand,
                 });
+
+                ui::mark_dirty();
                 return true;
             }
         }
@@ -204,6 +216,7 @@ impl Box {
                     expand: expand,
                 });
 
+                ui::mark_dirty();
                 return true;
             }
         }
@@ -213,6 +226,8 @@ impl Box {
 
     pub fn clear(&self) {
         self.inner.lock().unwrap().items.clear();
+
+        ui::mark_dirty();
     }
 
     pub fn on_lost_focus(&self) { }
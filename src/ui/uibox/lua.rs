@@ -387,6 +387,8 @@ unsafe extern "C" fn padding_left(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().padding_left = p;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -410,6 +412,8 @@ unsafe extern "C" fn padding_right(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().padding_right = p;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -433,6 +437,8 @@ unsafe extern "C" fn padding_top(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().padding_top = p;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -456,6 +462,8 @@ unsafe extern "C" fn padding_bottom(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().padding_bottom = p;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -478,6 +486,8 @@ unsafe extern "C" fn spacing(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().spacing = s;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -496,6 +506,8 @@ unsafe extern "C" fn alignment(l: &lua_State) -> i32 {
 
     bx.inner.lock().unwrap().alignment = ui::ElementAlignment::from(align.as_str());
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -376,7 +376,7 @@ impl WindowInner {
             self.moving = true;
             self.move_last_x = event.x;
             self.move_last_y = event.y;
-            overlay::ui().set_mouse_capture(element, offset_x, offset_y, self.last_scissor.clone());
+            overlay::ui().capture_mouse_drag(element, offset_x, offset_y, self.last_scissor.clone(), event.button);
         } else if self.moving && event.button == input::MouseButtonEventButton::Left && !event.down {
             self.moving = false;
             overlay::ui().clear_mouse_capture();
@@ -389,7 +389,7 @@ impl WindowInner {
             self.resizing = true;
             self.move_last_x = event.x;
             self.move_last_y = event.y;
-            overlay::ui().set_mouse_capture(element, offset_x, offset_y, self.last_scissor.clone());
+            overlay::ui().capture_mouse_drag(element, offset_x, offset_y, self.last_scissor.clone(), event.button);
         } else if self.resizing && event.button == input::MouseButtonEventButton::Left && !event.down {
             self.resizing = false;
             overlay::ui().clear_mouse_capture();
@@ -518,6 +518,8 @@ impl WindowInner {
         self.y = y;
         self.width = w;
         self.height = h;
+
+        ui::mark_dirty();
     }
 
     pub fn save_to_settings(&self) {
@@ -131,6 +131,8 @@ unsafe extern "C" fn caption(l: &lua_State) -> i32 {
     if lua::gettop(l) >= 2 {
         if let Some(newcaption) = lua::tostring(l, 2) {
             win.win.lock().unwrap().caption = String::from(newcaption);
+
+            ui::mark_dirty();
         } else {
             crate::overlay::lua::luaerror!(l, "caption argument #1 must be a string.");
         }
@@ -172,6 +174,8 @@ unsafe extern "C" fn child(l: &lua_State) -> i32 {
 
     win.win.lock().unwrap().child = child;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -298,6 +302,8 @@ unsafe extern "C" fn resizable(l: &lua_State) -> i32 {
 
     win.win.lock().unwrap().resizable = lua::toboolean(l, 2);
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -330,6 +336,8 @@ unsafe extern "C" fn position(l: &lua_State) -> i32 {
     w.x = x;
     w.y = y;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -354,6 +362,8 @@ unsafe extern "C" fn titlebar(l: &lua_State) -> i32 {
 
     win.win.lock().unwrap().show_titlebar = show;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -400,6 +410,8 @@ unsafe extern "C" fn update_size(l: &lua_State) -> i32 {
 
     inner.update_size();
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -455,6 +467,8 @@ unsafe extern "C" fn border_color(l: &lua_State) -> i32 {
 
     win.win.lock().unwrap().border_color = color;
 
+    ui::mark_dirty();
+
     return 0;
 }
 
@@ -8,9 +8,12 @@ use windows::Win32::System::DataExchange;
 use windows::Win32::System::Console;
 use windows::Win32::System::Com;
 use windows::Win32::System::Memory;
+use windows::Win32::System::Registry;
 use windows::Win32::Foundation;
 
-use crate::logging::error;
+use crate::logging::{debug, error};
+
+use crate::zip::zlib;
 
 /// Returns [true] if the overlay process has a console available for output,
 /// [false] otherwise.
@@ -74,6 +77,39 @@ pub fn get_clipboard_text() -> Option<String> {
     }
 }
 
+/// Starts sending `WM_CLIPBOARDUPDATE` messages to `hwnd` each time the
+/// clipboard contents change.
+pub fn add_clipboard_listener(hwnd: Foundation::HWND) {
+    if let Err(err) = unsafe { DataExchange::AddClipboardFormatListener(hwnd) } {
+        error!("Couldn't add clipboard listener: {}", err);
+    }
+}
+
+/// Stops sending clipboard updates to `hwnd`, see [add_clipboard_listener].
+pub fn remove_clipboard_listener(hwnd: Foundation::HWND) {
+    let _ = unsafe { DataExchange::RemoveClipboardFormatListener(hwnd) };
+}
+
+/// Queues a `clipboard-changed` event, with the clipboard text as the event
+/// data if the clipboard contains text.
+///
+/// This should be called when the overlay window receives `WM_CLIPBOARDUPDATE`.
+pub fn clipboard_changed() {
+    // non-text data is sent as nil instead of logging an error
+    let text = if unsafe { DataExchange::IsClipboardFormatAvailable(0x01) }.is_ok() { // CF_TEXT
+        get_clipboard_text()
+    } else {
+        None
+    };
+
+    debug!("Clipboard changed, text: {}", text.is_some());
+
+    match text {
+        Some(t) => crate::lua_manager::queue_event("clipboard-changed", Some(Box::new(t))),
+        None    => crate::lua_manager::queue_event("clipboard-changed", None),
+    }
+}
+
 /// Set the clipboard contents to the given text
 pub fn set_clipboard_text(text: &str) {
     if let Err(err) = unsafe { DataExchange::OpenClipboard(None) } {
@@ -112,3 +148,335 @@ pub fn set_clipboard_text(text: &str) {
     }
     unsafe { let _ = DataExchange::CloseClipboard(); }
 }
+
+/// A value read from the Windows registry.
+pub enum RegistryValue {
+    String(String),
+    MultiString(Vec<String>),
+    Integer(u64),
+    Binary(Vec<u8>),
+}
+
+/// Read `value` from `key` within `hive`.
+///
+/// `hive` must be one of `HKEY_LOCAL_MACHINE`, `HKEY_CURRENT_USER` or
+/// `HKEY_CLASSES_ROOT`. [None] is returned if the hive is not recognized or the
+/// value does not exist.
+pub fn read_registry_value(hive: &str, key: &str, value: &str) -> Option<RegistryValue> {
+    let hkey = match hive {
+        "HKEY_LOCAL_MACHINE" | "HKLM" => Registry::HKEY_LOCAL_MACHINE,
+        "HKEY_CURRENT_USER"  | "HKCU" => Registry::HKEY_CURRENT_USER,
+        "HKEY_CLASSES_ROOT"  | "HKCR" => Registry::HKEY_CLASSES_ROOT,
+        _ => {
+            error!("Unsupported registry hive: {}", hive);
+            return None;
+        }
+    };
+
+    let mut keyu16: Vec<u16> = key.encode_utf16().collect();
+    keyu16.push(0u16);
+    let mut valueu16: Vec<u16> = value.encode_utf16().collect();
+    valueu16.push(0u16);
+
+    let keyw = windows::core::PCWSTR::from_raw(keyu16.as_ptr());
+    let valuew = windows::core::PCWSTR::from_raw(valueu16.as_ptr());
+
+    let mut valtype = Registry::REG_VALUE_TYPE::default();
+    let mut size: u32 = 0;
+
+    // first get the type and size of the data
+    let r = unsafe { Registry::RegGetValueW(
+        hkey,
+        keyw,
+        valuew,
+        Registry::RRF_RT_ANY | Registry::RRF_NOEXPAND,
+        Some(&mut valtype),
+        None,
+        Some(&mut size),
+    ) };
+
+    if r.is_err() {
+        debug!("Couldn't read registry value {}\\{}\\{}: {:?}", hive, key, value, r);
+        return None;
+    }
+
+    let mut data: Vec<u8> = vec![0u8; size as usize];
+
+    let r = unsafe { Registry::RegGetValueW(
+        hkey,
+        keyw,
+        valuew,
+        Registry::RRF_RT_ANY | Registry::RRF_NOEXPAND,
+        Some(&mut valtype),
+        Some(data.as_mut_ptr() as *mut std::ffi::c_void),
+        Some(&mut size),
+    ) };
+
+    if r.is_err() {
+        error!("Couldn't read registry value {}\\{}\\{}: {:?}", hive, key, value, r);
+        return None;
+    }
+
+    data.truncate(size as usize);
+
+    let wide: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+
+    match valtype {
+        Registry::REG_SZ | Registry::REG_EXPAND_SZ => {
+            let s = String::from_utf16_lossy(&wide);
+
+            return Some(RegistryValue::String(String::from(s.trim_end_matches('\0'))));
+        },
+        Registry::REG_MULTI_SZ => {
+            let strings = String::from_utf16_lossy(&wide)
+                .split('\0')
+                .filter(|s| s.len() > 0)
+                .map(|s| String::from(s))
+                .collect();
+
+            return Some(RegistryValue::MultiString(strings));
+        },
+        Registry::REG_DWORD => {
+            return Some(RegistryValue::Integer(u32::from_le_bytes(data[..4].try_into().unwrap()) as u64));
+        },
+        Registry::REG_QWORD => {
+            return Some(RegistryValue::Integer(u64::from_le_bytes(data[..8].try_into().unwrap())));
+        },
+        _ => {
+            return Some(RegistryValue::Binary(data));
+        }
+    }
+}
+
+/// Write `data` to `path`, replacing any existing file atomically.
+///
+/// The data is written to a temporary file next to `path` which is then renamed
+/// over `path`, so a crash while writing never leaves a partially written file.
+pub fn write_file_atomic(path: &str, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let tmp_path = format!("{}.{}.tmp", path, std::process::id());
+
+    let r = (|| {
+        let mut f = std::fs::File::create(&tmp_path)?;
+
+        f.write_all(data)?;
+        f.sync_all()?;
+
+        std::fs::rename(&tmp_path, path)
+    })();
+
+    if r.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+
+    return r;
+}
+
+/// Compress `data` with gzip.
+pub fn gzip_compress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut strm = zlib::z_stream::default();
+
+    if unsafe { zlib::deflateInit2_(
+        &mut strm,
+        zlib::Z_DEFAULT_COMPRESSION,
+        zlib::Z_DEFLATED,
+        15 + 16, // max window size, gzip headers
+        8,
+        zlib::Z_DEFAULT_STRATEGY,
+        c"1.3".as_ptr(),
+        std::mem::size_of::<zlib::z_stream>() as i32
+    )} != zlib::Z_OK {
+        return Err(String::from("couldn't initialize zlib"));
+    }
+
+    let bound = unsafe { zlib::deflateBound(&mut strm, data.len() as _) };
+    let mut compressed = vec![0u8; bound as usize];
+
+    strm.next_in = data.as_ptr();
+    strm.avail_in = data.len() as u32;
+    strm.next_out = compressed.as_mut_ptr();
+    strm.avail_out = compressed.len() as u32;
+
+    let r = unsafe { zlib::deflate(&mut strm, zlib::Z_FINISH) };
+    let total_out = strm.total_out as usize;
+
+    unsafe { zlib::deflateEnd(&mut strm) };
+
+    if r != zlib::Z_STREAM_END {
+        return Err(format!("deflate failed: {}", r));
+    }
+
+    compressed.truncate(total_out);
+
+    return Ok(compressed);
+}
+
+/// Decompress gzip `data`.
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut strm = zlib::z_stream::default();
+
+    if unsafe { zlib::inflateInit2_(
+        &mut strm,
+        15 + 16, // max window size, gzip headers only
+        c"1.3".as_ptr(),
+        std::mem::size_of::<zlib::z_stream>() as i32
+    )} != zlib::Z_OK {
+        return Err(String::from("couldn't initialize zlib"));
+    }
+
+    // the uncompressed size isn't known up front, so grow the output as needed
+    let mut decompressed = vec![0u8; (data.len() * 4).max(1024)];
+
+    strm.next_in = data.as_ptr();
+    strm.avail_in = data.len() as u32;
+
+    let r = loop {
+        let total_out = strm.total_out as usize;
+
+        strm.next_out = unsafe { decompressed.as_mut_ptr().add(total_out) };
+        strm.avail_out = (decompressed.len() - total_out) as u32;
+
+        let r = unsafe { zlib::inflate(&mut strm, zlib::Z_FINISH) };
+
+        // out of output space, more is needed
+        if (r == zlib::Z_BUF_ERROR || r == zlib::Z_OK) && strm.avail_out == 0 {
+            decompressed.resize(decompressed.len() * 2, 0);
+            continue;
+        }
+
+        break r;
+    };
+
+    let total_out = strm.total_out as usize;
+
+    unsafe { zlib::inflateEnd(&mut strm) };
+
+    if r != zlib::Z_STREAM_END {
+        return Err(String::from("invalid or truncated gzip data"));
+    }
+
+    decompressed.truncate(total_out);
+
+    return Ok(decompressed);
+}
+
+/// A small, seedable pseudo-random number generator.
+///
+/// This is xoshiro256**, seeded with splitmix64. The same seed will always
+/// produce the same sequence, regardless of platform or Lua version.
+#[derive(Clone,Copy)]
+pub struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut sm = seed;
+        let mut s = [0u64; 4];
+
+        for v in &mut s {
+            sm = sm.wrapping_add(0x9E3779B97F4A7C15);
+
+            let mut z = sm;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            *v = z ^ (z >> 31);
+        }
+
+        return Rng { s: s };
+    }
+
+    /// Returns the next 64 bit value in the sequence.
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+
+        let t = self.s[1] << 17;
+
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+
+        self.s[2] ^= t;
+
+        self.s[3] = self.s[3].rotate_left(45);
+
+        return result;
+    }
+
+    /// Returns a float in the range `[0.0, 1.0)`.
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64);
+    }
+
+    /// Returns an integer in the range `[min, max]`, without modulo bias.
+    pub fn range(&mut self, min: i64, max: i64) -> i64 {
+        let span = (max as i128 - min as i128 + 1) as u128;
+
+        // the full range of i64
+        if span > u64::MAX as u128 { return self.next_u64() as i64; }
+
+        let span = span as u64;
+        let zone = u64::MAX - (u64::MAX % span);
+
+        loop {
+            let v = self.next_u64();
+
+            if v < zone {
+                return (min as i128 + (v % span) as i128) as i64;
+            }
+        }
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as standard base64, with padding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        out.push(BASE64_ALPHABET[(n >> 18) as usize & 0x3f] as char);
+        out.push(BASE64_ALPHABET[(n >> 12) as usize & 0x3f] as char);
+
+        if chunk.len() > 1 { out.push(BASE64_ALPHABET[(n >> 6) as usize & 0x3f] as char); } else { out.push('='); }
+        if chunk.len() > 2 { out.push(BASE64_ALPHABET[n as usize & 0x3f] as char); } else { out.push('='); }
+    }
+
+    return out;
+}
+
+/// Decodes standard base64 `text`, with or without padding.
+///
+/// Returns [None] if `text` contains characters outside of the base64
+/// alphabet or is an invalid length.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+
+    // a single character left over can't encode a whole byte
+    if text.len() % 4 == 1 { return None; }
+
+    let mut out: Vec<u8> = Vec::with_capacity(text.len() * 3 / 4);
+
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+
+    for c in text.bytes() {
+        let v = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+
+        acc = (acc << 6) | v;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    return Some(out);
+}
@@ -3,13 +3,23 @@
 // SPDX-License-Identifier: MIT
 
 //! Asynchronous HTTP(s) requests
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicBool, Ordering};
+//!
+//! Requests are performed by a pool of worker threads, the size of which is
+//! set by the `overlay.webRequestWorkers` setting. Requests beyond that are
+//! queued and started as workers become free.
+//!
+//! Each request has an ID that can be used to cancel it with [cancel_request].
+//! Cancelled, timed out and failed requests still complete, with a status of 0
+//! and an error describing what happened.
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use std::collections::{VecDeque, HashMap};
 
 use std::ffi::{CString, CStr};
 
+use std::io::Write;
+
 #[allow(unused_imports)]
 use crate::logging::{info,debug,warn,error};
 
@@ -17,18 +27,29 @@ use windows::Win32::Networking::WinInet;
 use windows::Win32::UI::Shell;
 use windows::Win32::Foundation;
 
+// the maximum number of worker threads, regardless of the setting
+const MAX_WORKERS: u64 = 16;
+
 static WR_STATE: Mutex<WebRequestState> = Mutex::new(WebRequestState {
     internet: 0,
-    thread: None,
+    threads: Vec::new(),
 });
 
 static WR_RUNNING: AtomicBool = AtomicBool::new(true);
 
 static WR_REQUESTS: Mutex<VecDeque<Request>> = Mutex::new(VecDeque::new());
 
+// the number of requests currently being performed by workers
+static WR_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+static WR_NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// the cancelled flags of queued and in progress requests, by request ID
+static WR_CANCEL_FLAGS: Mutex<Option<HashMap<u64, Arc<AtomicBool>>>> = Mutex::new(None);
+
 struct WebRequestState {
     internet: usize,
-    thread: Option<std::thread::JoinHandle<()>>,
+    threads: Vec<std::thread::JoinHandle<()>>,
 }
 
 pub fn init() {
@@ -46,20 +67,31 @@ pub fn init() {
 
     WR_STATE.lock().unwrap().internet = hint as usize;
 
-    let t = std::thread::Builder::new().name("EG-Overlay Web Request Thread".to_string()).spawn(move || {
-        web_request_thread();
-    }).expect("Couldn't spawn web request thread.");
+    let workers = crate::overlay::settings().get_u64("overlay.webRequestWorkers").unwrap().clamp(1, MAX_WORKERS);
+
+    debug!("Starting {} web request workers.", workers);
 
-    WR_STATE.lock().unwrap().thread = Some(t);
+    for i in 0..workers {
+        let t = std::thread::Builder::new().name(format!("EG-Overlay Web Request Thread {}", i)).spawn(move || {
+            web_request_thread();
+        }).expect("Couldn't spawn web request thread.");
+
+        WR_STATE.lock().unwrap().threads.push(t);
+    }
 }
 
 pub fn cleanup() {
-    let t = WR_STATE.lock().unwrap().thread.take().unwrap();
+    let threads: Vec<std::thread::JoinHandle<()>> = WR_STATE.lock().unwrap().threads.drain(..).collect();
 
     WR_RUNNING.store(false, Ordering::Relaxed);
 
-    t.thread().unpark();
-    t.join().unwrap();
+    for t in &threads {
+        t.thread().unpark();
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
 
     let hint = WR_STATE.lock().unwrap().internet as *const std::ffi::c_void;
 
@@ -70,61 +102,204 @@ fn web_request_thread() {
     debug!("Request thread starting...");
 
     while WR_RUNNING.load(Ordering::Relaxed) {
-        if let Some(req) = WR_REQUESTS.lock().unwrap().pop_front() {
+        // don't keep the queue locked while the request is performed
+        let req = WR_REQUESTS.lock().unwrap().pop_front();
+
+        if let Some(req) = req {
+            WR_ACTIVE.fetch_add(1, Ordering::Relaxed);
             perform(&req);
-        }
+            WR_ACTIVE.fetch_sub(1, Ordering::Relaxed);
 
-        std::thread::park();
+            if let Some(flags) = WR_CANCEL_FLAGS.lock().unwrap().as_mut() {
+                flags.remove(&req.id);
+            }
+        } else {
+            std::thread::park();
+        }
     }
 
     debug!("Request thread ending...");
 }
 
+/// An HTTP request method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    /// Returns the method named `name`, i.e. `"POST"`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Method> {
+        match name.to_ascii_uppercase().as_str() {
+            "GET"    => Some(Method::Get),
+            "POST"   => Some(Method::Post),
+            "PUT"    => Some(Method::Put),
+            "DELETE" => Some(Method::Delete),
+            _        => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Get    => "GET",
+            Method::Post   => "POST",
+            Method::Put    => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// Everything about a request other than the URL and callbacks.
+pub struct RequestOptions {
+    pub method: Method,
+
+    /// Sent as the request body.
+    pub body: Option<Vec<u8>>,
+
+    pub headers: Vec<(String, String)>,
+    pub query_params: Vec<(String, String)>,
+
+    /// How long the request can take in total, including the response body.
+    pub timeout: Option<std::time::Duration>,
+
+    /// Write the response body to this file instead of returning it.
+    ///
+    /// The file is only replaced if the request succeeds with a 2xx or 3xx
+    /// status, and any missing parent directories are created.
+    pub download_path: Option<String>,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        RequestOptions {
+            method: Method::Get,
+            body: None,
+            headers: Vec::new(),
+            query_params: Vec::new(),
+            timeout: None,
+            download_path: None,
+        }
+    }
+}
+
 struct Request {
+    id: u64,
+
     url: String,
 
-    headers: Vec<(String, String)>,
-    query_params: Vec<(String, String)>,
+    options: RequestOptions,
+
+    cancelled: Arc<AtomicBool>,
 
     lua_callback: i64,
+    lua_chunk_callback: Option<i64>,
     lua_source: String,
 }
 
-/// Queues a web request
+/// Queues a web request and returns its ID.
 ///
 /// Currently, this assumes URL is HTTP or HTTPS.
 /// `callback` must be a Lua reference ID to a Lua callback function.
+/// `chunk_callback`, if given, must also be a Lua reference ID to a function
+/// that will be called with each chunk of the response body as it is read. In
+/// this case the body is not buffered and `callback` will receive a `nil` body.
 /// `source` is used to log where in code this request came from.
 pub fn queue_request(
     url: &str,
-    headers: Vec<(String, String)>,
-    query_params: Vec<(String, String)>,
-    callback: i64, source: &str
-) {
+    options: RequestOptions,
+    callback: i64,
+    chunk_callback: Option<i64>,
+    source: &str
+) -> u64 {
+    let id = WR_NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    WR_CANCEL_FLAGS.lock().unwrap().get_or_insert_with(HashMap::new).insert(id, cancelled.clone());
+
     let req = Request {
+        id: id,
+
         url: String::from(url),
 
-        headers: headers,
-        query_params: query_params,
+        options: options,
+
+        cancelled: cancelled,
 
         lua_callback: callback,
+        lua_chunk_callback: chunk_callback,
         lua_source: String::from(source),
     };
 
     WR_REQUESTS.lock().unwrap().push_back(req);
-    WR_STATE.lock().unwrap().thread.as_ref().unwrap().thread().unpark();
+
+    // wake every worker, any that are busy will pick up the next request when
+    // they finish and the rest will go back to sleep if the queue is empty
+    for t in WR_STATE.lock().unwrap().threads.iter() {
+        t.thread().unpark();
+    }
+
+    return id;
+}
+
+/// Cancels the request with the given ID.
+///
+/// A request that hasn't started yet is never performed, one that is in
+/// progress is stopped before its next read. Either way its callback receives
+/// a `cancelled` error.
+///
+/// Returns `false` if the request has already completed or doesn't exist.
+pub fn cancel_request(id: u64) -> bool {
+    match WR_CANCEL_FLAGS.lock().unwrap().as_ref().and_then(|f| f.get(&id)) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Returns the number of requests waiting for a free worker and the number
+/// that are currently being performed.
+pub fn queue_depth() -> (usize, usize) {
+    (WR_REQUESTS.lock().unwrap().len(), WR_ACTIVE.load(Ordering::Relaxed))
 }
 
 struct Response {
+    id: u64,
     status: i64,
-    body: Vec<i8>,
+    error: Option<String>,
+    body: Option<Vec<i8>>,
     headers: HashMap<String, String>,
     target_ref: i64,
+    chunk_ref: Option<i64>,
 }
 
 impl Drop for Response {
     fn drop(&mut self) {
         crate::lua_manager::unref(self.target_ref);
+
+        if let Some(r) = self.chunk_ref {
+            crate::lua_manager::unref(r);
+        }
+    }
+}
+
+/// A single chunk of a response body, sent to the chunk callback of a streaming
+/// request.
+///
+/// The callback reference is freed when the final [Response] is dropped, which
+/// is always queued after the last chunk.
+struct ResponseChunk {
+    data: Vec<i8>,
+}
+
+impl crate::lua_manager::ToLua for ResponseChunk {
+    fn push_to_lua(&self, l: &crate::lua::lua_State) {
+        crate::lua::pushbytes(l, self.data.as_slice());
     }
 }
 
@@ -132,10 +307,24 @@ impl crate::lua_manager::ToLua for Response {
     fn push_to_lua(&self, l: &crate::lua::lua_State) {
         crate::lua::newtable(l);
 
+        crate::lua::pushinteger(l, self.id as i64);
+        crate::lua::setfield(l, -2, "id");
+
         crate::lua::pushinteger(l, self.status);
         crate::lua::setfield(l, -2, "status");
 
-        crate::lua::pushbytes(l, self.body.as_slice());
+        if let Some(err) = &self.error {
+            crate::lua::pushstring(l, err);
+        } else {
+            crate::lua::pushnil(l);
+        }
+        crate::lua::setfield(l, -2, "error");
+
+        if let Some(body) = &self.body {
+            crate::lua::pushbytes(l, body.as_slice());
+        } else {
+            crate::lua::pushnil(l);
+        }
         crate::lua::setfield(l, -2, "body");
 
         crate::lua::newtable(l);
@@ -250,12 +439,176 @@ fn get_resp_headers(hreq: *const std::ffi::c_void) -> HashMap<String, String> {
     hdrs
 }
 
+// splits an http(s) URL into whether it is https, the host, port and the
+// path including any query
+fn split_url(url: &str) -> Option<(bool, String, u16, String)> {
+    let (secure, rest) = if let Some(r) = url.strip_prefix("https://") {
+        (true, r)
+    } else if let Some(r) = url.strip_prefix("http://") {
+        (false, r)
+    } else {
+        return None;
+    };
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None    => (rest, "/"),
+    };
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse::<u16>().ok()?),
+        None         => (authority, if secure { 443 } else { 80 }),
+    };
+
+    if host.is_empty() { return None; }
+
+    let path = if path.starts_with('?') { format!("/{}", path) } else { path.to_string() };
+
+    Some((secure, host.to_string(), port, path))
+}
+
+// opens a request for anything other than a plain GET, which
+// InternetOpenUrlA can't do. returns the request and connection handles
+fn open_request(
+    request: &Request,
+    url: &str,
+    headers: Option<&[u8]>,
+    hint: *const std::ffi::c_void
+) -> Option<(*mut std::ffi::c_void, *mut std::ffi::c_void)> {
+    let (secure, host, port, path) = match split_url(url) {
+        Some(u) => u,
+        None    => {
+            error!("Couldn't parse URL: {}", url);
+            return None;
+        }
+    };
+
+    let host_c = CString::new(host.as_str()).unwrap();
+    let path_c = CString::new(path.as_str()).unwrap();
+    let verb_c = CString::new(request.options.method.name()).unwrap();
+
+    let hconnect = unsafe { WinInet::InternetConnectA(
+        hint,
+        windows::core::PCSTR(host_c.as_bytes().as_ptr()),
+        port,
+        windows::core::PCSTR::null(),
+        windows::core::PCSTR::null(),
+        WinInet::INTERNET_SERVICE_HTTP,
+        0,
+        None
+    )};
+
+    if hconnect.is_null() {
+        error!("Couldn't connect to {}:{}", host, port);
+        return None;
+    }
+
+    // request handles inherit these from the connection
+    if let Some(timeout) = request.options.timeout {
+        let ms = (timeout.as_millis() as u32).max(1);
+
+        for opt in [
+            WinInet::INTERNET_OPTION_CONNECT_TIMEOUT,
+            WinInet::INTERNET_OPTION_SEND_TIMEOUT,
+            WinInet::INTERNET_OPTION_RECEIVE_TIMEOUT,
+        ] {
+            if let Err(err) = unsafe { WinInet::InternetSetOptionA(
+                Some(hconnect),
+                opt,
+                Some(&ms as *const u32 as *const std::ffi::c_void),
+                std::mem::size_of::<u32>() as u32
+            )} {
+                warn!("Couldn't set request timeout: {}", err);
+            }
+        }
+    }
+
+    let mut flags = WinInet::INTERNET_FLAG_RELOAD | WinInet::INTERNET_FLAG_NO_CACHE_WRITE;
+    if secure { flags |= WinInet::INTERNET_FLAG_SECURE; }
+
+    let hreq = unsafe { WinInet::HttpOpenRequestA(
+        hconnect,
+        windows::core::PCSTR(verb_c.as_bytes().as_ptr()),
+        windows::core::PCSTR(path_c.as_bytes().as_ptr()),
+        windows::core::PCSTR::null(),
+        windows::core::PCSTR::null(),
+        None,
+        flags,
+        None
+    )};
+
+    if hreq.is_null() {
+        error!("Couldn't open {} request: {}", request.options.method.name(), url);
+        unsafe { WinInet::InternetCloseHandle(hconnect).unwrap(); }
+        return None;
+    }
+
+    let (body_ptr, body_len) = match &request.options.body {
+        Some(b) => (Some(b.as_ptr() as *const std::ffi::c_void), b.len() as u32),
+        None    => (None, 0),
+    };
+
+    if let Err(err) = unsafe { WinInet::HttpSendRequestA(hreq, headers, body_ptr, body_len) } {
+        error!("Couldn't send {} request ({}): {}", request.options.method.name(), url, err);
+        unsafe {
+            WinInet::InternetCloseHandle(hreq).unwrap();
+            WinInet::InternetCloseHandle(hconnect).unwrap();
+        }
+        return None;
+    }
+
+    Some((hreq, hconnect))
+}
+
+// creates the temporary file a download is written to, next to path
+fn open_download(path: &str, id: u64) -> std::io::Result<(String, std::fs::File)> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let tmp_path = format!("{}.{}.download", path, id);
+
+    let file = std::fs::File::create(&tmp_path)?;
+
+    Ok((tmp_path, file))
+}
+
+// closes and removes an incomplete download
+fn discard_download(download: Option<(String, std::fs::File)>) {
+    if let Some((tmp_path, file)) = download {
+        drop(file);
+
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+}
+
+// completes request without a response, error is sent to the callback
+fn send_failure(request: &Request, url: &str, error: &str) {
+    warn!("{}: {} {} -> {}", request.lua_source, request.options.method.name(), url, error);
+
+    let resp = Box::new(Response {
+        id: request.id,
+        status: 0,
+        error: Some(error.to_string()),
+        body: None,
+        target_ref: request.lua_callback,
+        chunk_ref: request.lua_chunk_callback,
+        headers: HashMap::new(),
+    });
+
+    crate::lua_manager::queue_targeted_event(request.lua_callback, Some(resp));
+}
+
 fn perform(request: &Request) {
+    let deadline = request.options.timeout.map(|t| std::time::Instant::now() + t);
+
     // the entire URL with query parameters
     let mut url = request.url.clone();
 
-    for p in &request.query_params {
-        if request.query_params[0] == *p {
+    for p in &request.options.query_params {
+        if request.options.query_params[0] == *p {
             url += "?";
         } else {
             url += "&";
@@ -264,6 +617,11 @@ fn perform(request: &Request) {
         url += format!("{}={}", p.0, p.1).as_str();
     }
 
+    if request.cancelled.load(Ordering::Relaxed) {
+        send_failure(request, &url, "cancelled");
+        return;
+    }
+
     let escaped_url: String;
 
     let re = escape_url(&url);
@@ -272,12 +630,13 @@ fn perform(request: &Request) {
         Ok(eu) => escaped_url = eu,
         Err(err) => {
             error!("Couldn't escape URL ({}): {}", url, err);
+            send_failure(request, &url, "invalid URL");
             return;
         }
     }
 
     let mut hdrs = String::new();
-    for h in &request.headers {
+    for h in &request.options.headers {
         hdrs += format!("{}: {}\r\n", h.0, h.1).as_str();
     }
 
@@ -293,24 +652,118 @@ fn perform(request: &Request) {
     let escaped_url_c = CString::new(escaped_url.as_str()).unwrap();
     let escaped_url_pcstr = windows::core::PCSTR(escaped_url_c.as_bytes().as_ptr());
 
-    let hreq = unsafe { WinInet::InternetOpenUrlA(hint, escaped_url_pcstr, headers, 0, None) };
+    // only set for requests opened by open_request
+    let mut hconnect: Option<*mut std::ffi::c_void> = None;
 
-    if hreq.is_null() {
-        error!("Couldn't open URL: {}", escaped_url);
-        return;
-    }
+    // InternetOpenUrlA can only do GET requests and doesn't have timeouts
+    let simple = request.options.method == Method::Get &&
+                 request.options.body.is_none() &&
+                 request.options.timeout.is_none();
+
+    let hreq = if simple {
+        let hreq = unsafe { WinInet::InternetOpenUrlA(hint, escaped_url_pcstr, headers, 0, None) };
+
+        if hreq.is_null() {
+            error!("Couldn't open URL: {}", escaped_url);
+            send_failure(request, &url, "couldn't open URL");
+            return;
+        }
+
+        hreq
+    } else {
+        match open_request(request, &escaped_url, headers, hint) {
+            Some((hreq, hconn)) => {
+                hconnect = Some(hconn);
+                hreq
+            },
+            None => {
+                let expired = deadline.is_some_and(|d| std::time::Instant::now() >= d);
+
+                send_failure(request, &url, if expired { "timeout" } else { "couldn't open URL" });
+                return;
+            },
+        }
+    };
+
+    let close_handles = || {
+        unsafe { WinInet::InternetCloseHandle(hreq).unwrap(); }
+
+        if let Some(hconn) = hconnect {
+            unsafe { WinInet::InternetCloseHandle(hconn).unwrap(); }
+        }
+    };
 
     let mut data: Vec<i8> = Vec::new();
 
     let mut chunk = vec![0i8; 1024];
     let mut bytes_read: u32 = 0;
 
-    while unsafe {
-        WinInet::InternetReadFile(hreq, chunk.as_mut_ptr() as *mut std::ffi::c_void, 1024, &mut bytes_read)
-    }.is_ok() {
+    // set if the request is stopped before the whole body is read
+    let mut failure: Option<&str> = None;
+
+    // downloads are written to a temporary file that replaces download_path
+    // once the whole body has been read
+    let mut download: Option<(String, std::fs::File)> = None;
+
+    if let Some(path) = &request.options.download_path {
+        match open_download(path, request.id) {
+            Ok(d) => download = Some(d),
+            Err(err) => {
+                close_handles();
+                error!("Couldn't create {}: {}", path, err);
+                send_failure(request, &url, "couldn't write file");
+                return;
+            }
+        }
+    }
+
+    loop {
+        if request.cancelled.load(Ordering::Relaxed) {
+            failure = Some("cancelled");
+            break;
+        }
+
+        if deadline.is_some_and(|d| std::time::Instant::now() >= d) {
+            failure = Some("timeout");
+            break;
+        }
+
+        if unsafe {
+            WinInet::InternetReadFile(hreq, chunk.as_mut_ptr() as *mut std::ffi::c_void, 1024, &mut bytes_read)
+        }.is_err() {
+            // WinInet's own timeouts end up here
+            failure = Some(if deadline.is_some() { "timeout" } else { "read error" });
+            break;
+        }
+
         if bytes_read == 0 { break; }
 
-        data.extend_from_slice(&chunk[0..bytes_read as usize]);
+        if let Some(chunk_ref) = request.lua_chunk_callback {
+            // streaming, send each chunk to Lua as it comes in instead of
+            // buffering the whole body
+            let c = Box::new(ResponseChunk {
+                data: chunk[0..bytes_read as usize].to_vec(),
+            });
+
+            crate::lua_manager::queue_targeted_event(chunk_ref, Some(c));
+        } else if let Some((tmp_path, file)) = download.as_mut() {
+            let bytes = unsafe { std::slice::from_raw_parts(chunk.as_ptr() as *const u8, bytes_read as usize) };
+
+            if let Err(err) = file.write_all(bytes) {
+                error!("Couldn't write {}: {}", tmp_path, err);
+                failure = Some("couldn't write file");
+                break;
+            }
+        } else {
+            data.extend_from_slice(&chunk[0..bytes_read as usize]);
+        }
+    }
+
+    if let Some(f) = failure {
+        close_handles();
+        discard_download(download);
+        send_failure(request, &url, f);
+        return;
     }
 
     let resp_hdrs = get_resp_headers(hreq);
@@ -325,23 +778,50 @@ fn perform(request: &Request) {
         &mut code_len,
         None
     )} {
-        unsafe { WinInet::InternetCloseHandle(hreq).unwrap(); }
+        close_handles();
+        discard_download(download);
         error!("Couldn't get HTTP Query Info: {}", err);
+        send_failure(request, &url, "no response");
         return;
     }
 
-    unsafe { WinInet::InternetCloseHandle(hreq).unwrap(); }
+    close_handles();
 
     if status_code >= 200 && status_code <400 {
-        info!("{}: GET {} -> {}", request.lua_source, url, status_code);
+        info!("{}: {} {} -> {}", request.lua_source, request.options.method.name(), url, status_code);
     } else {
-        warn!("{}: GET {} -> {}", request.lua_source, url, status_code);
+        warn!("{}: {} {} -> {}", request.lua_source, request.options.method.name(), url, status_code);
     }
 
+    let mut error: Option<String> = None;
+
+    if let Some((tmp_path, file)) = download {
+        let dest = request.options.download_path.as_ref().unwrap();
+
+        // don't replace the destination with an error page
+        if status_code >= 200 && status_code < 400 {
+            let r = file.sync_all();
+            drop(file);
+
+            if let Err(err) = r.and_then(|_| std::fs::rename(&tmp_path, dest)) {
+                error!("Couldn't write {}: {}", dest, err);
+                let _ = std::fs::remove_file(&tmp_path);
+                error = Some(String::from("couldn't write file"));
+            }
+        } else {
+            discard_download(Some((tmp_path, file)));
+        }
+    }
+
+    let downloaded = request.options.download_path.is_some();
+
     let resp = Box::new(Response {
+        id: request.id,
         status: status_code as i64,
-        body: data,
+        error: error,
+        body: if request.lua_chunk_callback.is_some() || downloaded { None } else { Some(data) },
         target_ref: request.lua_callback,
+        chunk_ref: request.lua_chunk_callback,
         headers: resp_hdrs,
     });
 
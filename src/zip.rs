@@ -6,14 +6,17 @@
 pub mod lua;
 
 use std::rc::Rc;
+use std::cell::RefCell;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use std::fs::File;
-use std::io::{Seek, Read};
+use std::io::{Seek, Read, Write};
 use std::io::SeekFrom;
 use std::io::ErrorKind;
 
+use std::path::{Path, PathBuf, Component};
+
 /// A compressed (zip) file
 pub struct ZipFile {
     //path: String,
@@ -52,8 +55,110 @@ struct ZipCentralDirectoryHeader {
     file_comment: String,
 }
 
+/// The most decompressed data a [ZipVfs] will keep cached, in bytes.
+const VFS_CACHE_SIZE: usize = 32 * 1024 * 1024;
+
+/// A zip file mounted as a read-only file system.
+///
+/// Files are decompressed when they are first read and then cached, up to
+/// [VFS_CACHE_SIZE] bytes. Once the cache is full the oldest files are dropped
+/// first.
+pub struct ZipVfs {
+    zip: RefCell<ZipFile>,
+
+    cache: RefCell<HashMap<String, Rc<Vec<u8>>>>,
+
+    // cached paths, oldest first
+    cache_order: RefCell<VecDeque<String>>,
+    cache_size: RefCell<usize>,
+}
+
+/// How a file added to a [ZipWriter] is stored.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ZipCompression {
+    /// Uncompressed
+    Store,
+
+    /// Deflate compressed
+    Deflate,
+}
+
+/// Writes a new compressed (zip) file.
+///
+/// Files are written as they are added. The central directory is only written
+/// by [ZipWriter::close], so the archive will not be valid until then.
+pub struct ZipWriter {
+    path: String,
+
+    // None once closed
+    file: RefCell<Option<File>>,
+
+    central_directory: RefCell<Vec<ZipCentralDirectoryHeader>>,
+}
+
 /// Opens a compressed archive file.
 pub fn open_zip(path: &str) -> std::io::Result<Rc<ZipFile>> {
+    Ok(Rc::new(read_zip(path)?))
+}
+
+/// Opens a compressed archive file as a [ZipVfs].
+pub fn mount_zip(path: &str) -> std::io::Result<Rc<ZipVfs>> {
+    let zip = read_zip(path)?;
+
+    Ok(Rc::new(ZipVfs {
+        zip: RefCell::new(zip),
+        cache: RefCell::new(HashMap::new()),
+        cache_order: RefCell::new(VecDeque::new()),
+        cache_size: RefCell::new(0),
+    }))
+}
+
+/// Creates a new compressed archive file at `path`, replacing any existing file.
+pub fn create_zip(path: &str) -> std::io::Result<Rc<ZipWriter>> {
+    let f = File::create(path)?;
+
+    Ok(Rc::new(ZipWriter {
+        path: String::from(path),
+        file: RefCell::new(Some(f)),
+        central_directory: RefCell::new(Vec::new()),
+    }))
+}
+
+/// Paths in an archive are case insensitive and always use `/`.
+fn normalize_path(path: &str) -> String {
+    path.to_lowercase().replace("\\","/")
+}
+
+/// Returns the path an entry named `name` should be extracted to within `dest`.
+///
+/// Returns [None] if `name` is absolute or would otherwise resolve to a path
+/// outside of `dest`.
+fn entry_dest_path(dest: &Path, name: &str) -> Option<PathBuf> {
+    let name = name.replace("\\", "/");
+
+    if name.starts_with('/') { return None; }
+
+    let mut path = dest.to_path_buf();
+    let mut parts = 0;
+
+    for c in Path::new(&name).components() {
+        match c {
+            Component::Normal(p) => {
+                path.push(p);
+                parts += 1;
+            },
+            Component::CurDir => {},
+            // .., a root or a drive prefix
+            _ => return None,
+        }
+    }
+
+    if parts == 0 { return None; }
+
+    Some(path)
+}
+
+fn read_zip(path: &str) -> std::io::Result<ZipFile> {
     let f = std::fs::OpenOptions::new().read(true).open(path)?;
 
     let mut zip = ZipFile {
@@ -69,10 +174,339 @@ pub fn open_zip(path: &str) -> std::io::Result<Rc<ZipFile>> {
     zip.find_central_directory()?;
     zip.load_central_directory()?;
 
-    Ok(Rc::new(zip))
+    Ok(zip)
+}
+
+impl ZipVfs {
+    /// Returns `true` if there is a file or directory at `path`.
+    pub fn exists(&self, path: &str) -> bool {
+        self.zip.borrow().central_directory.contains_key(&normalize_path(path))
+    }
+
+    /// Returns the paths of all files that start with `prefix`, sorted.
+    ///
+    /// Directory entries are not included.
+    pub fn list(&self, prefix: &str) -> Vec<String> {
+        let prefix = normalize_path(prefix);
+
+        let zip = self.zip.borrow();
+
+        let mut paths: Vec<String> = zip.central_directory.iter()
+            .filter(|(name, cd)| name.starts_with(&prefix) && !cd.is_directory())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        paths.sort();
+
+        paths
+    }
+
+    /// Returns the content of the file at `path`, decompressing it if it isn't
+    /// already cached.
+    pub fn read(&self, path: &str) -> std::io::Result<Rc<Vec<u8>>> {
+        let path = normalize_path(path);
+
+        if let Some(data) = self.cache.borrow().get(&path) {
+            return Ok(data.clone());
+        }
+
+        let data = Rc::new(self.zip.borrow_mut().file_content(&path)?);
+
+        // files larger than the entire cache are never cached
+        if data.len() > VFS_CACHE_SIZE { return Ok(data); }
+
+        let mut cache = self.cache.borrow_mut();
+        let mut order = self.cache_order.borrow_mut();
+        let mut size = self.cache_size.borrow_mut();
+
+        while *size + data.len() > VFS_CACHE_SIZE {
+            let oldest = match order.pop_front() {
+                Some(o) => o,
+                None    => break,
+            };
+
+            if let Some(old) = cache.remove(&oldest) {
+                *size -= old.len();
+            }
+        }
+
+        cache.insert(path.clone(), data.clone());
+        order.push_back(path);
+        *size += data.len();
+
+        Ok(data)
+    }
+}
+
+impl ZipWriter {
+    /// The path of the archive being written.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns `true` if [ZipWriter::close] has been called.
+    pub fn is_closed(&self) -> bool {
+        self.file.borrow().is_none()
+    }
+
+    /// Adds a file named `name` containing `data` to the archive.
+    pub fn add(&self, name: &str, data: &[u8], compression: ZipCompression) -> std::io::Result<()> {
+        let mut file_ref = self.file.borrow_mut();
+        let file = match file_ref.as_mut() {
+            Some(f) => f,
+            None    => return Err(std::io::Error::new(ErrorKind::Other, "zip file is closed")),
+        };
+
+        let name = name.replace("\\", "/");
+
+        if name.is_empty() {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "file name is empty"));
+        }
+
+        if self.central_directory.borrow().iter().any(|cd| normalize_path(&cd.file_name) == normalize_path(&name)) {
+            return Err(std::io::Error::new(ErrorKind::AlreadyExists, "a file with that name was already added"));
+        }
+
+        if data.len() >= u32::MAX as usize || name.len() > u16::MAX as usize {
+            return Err(std::io::Error::new(ErrorKind::Other, "zip64 not implemented"));
+        }
+
+        let crc = unsafe { zlib::crc32(zlib::crc32(0, std::ptr::null(), 0), data.as_ptr(), data.len() as u32) };
+
+        let compressed = match compression {
+            ZipCompression::Store   => None,
+            ZipCompression::Deflate => Some(deflate_raw(data)?),
+        };
+        let stored: &[u8] = compressed.as_deref().unwrap_or(data);
+
+        let offset = file.stream_position()?;
+
+        // no ZIP64 support, see find_central_directory
+        if offset >= u32::MAX as u64 || stored.len() >= u32::MAX as usize {
+            return Err(std::io::Error::new(ErrorKind::Other, "zip64 not implemented"));
+        }
+
+        let (mod_time, mod_date) = dos_time_now();
+
+        let mut hdr = ZipCentralDirectoryHeader::default();
+
+        hdr.version_by             = 20;
+        hdr.version_extract        = 20;
+        hdr.flags                  = 0x0800; // file name is UTF-8
+        hdr.compression            = if compressed.is_some() { 8 } else { 0 };
+        hdr.file_mod_time          = mod_time;
+        hdr.file_mod_date          = mod_date;
+        hdr.file_crc               = crc as u32;
+        hdr.file_compressed_size   = stored.len() as u32;
+        hdr.file_uncompressed_size = data.len() as u32;
+        hdr.file_name_len          = name.len() as u16;
+        hdr.file_offset            = offset as u32;
+        hdr.file_name              = name;
+
+        let mut local = Vec::with_capacity(30 + hdr.file_name.len());
+
+        local.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        local.extend_from_slice(&hdr.version_extract.to_le_bytes());
+        local.extend_from_slice(&hdr.flags.to_le_bytes());
+        local.extend_from_slice(&hdr.compression.to_le_bytes());
+        local.extend_from_slice(&hdr.file_mod_time.to_le_bytes());
+        local.extend_from_slice(&hdr.file_mod_date.to_le_bytes());
+        local.extend_from_slice(&hdr.file_crc.to_le_bytes());
+        local.extend_from_slice(&hdr.file_compressed_size.to_le_bytes());
+        local.extend_from_slice(&hdr.file_uncompressed_size.to_le_bytes());
+        local.extend_from_slice(&hdr.file_name_len.to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        local.extend_from_slice(hdr.file_name.as_bytes());
+
+        file.write_all(&local)?;
+        file.write_all(stored)?;
+
+        self.central_directory.borrow_mut().push(hdr);
+
+        Ok(())
+    }
+
+    /// Adds the file at `src_path` to the archive as `name`.
+    pub fn add_file(&self, name: &str, src_path: &str, compression: ZipCompression) -> std::io::Result<()> {
+        let data = std::fs::read(src_path)?;
+
+        self.add(name, &data, compression)
+    }
+
+    /// Writes the central directory and closes the archive.
+    ///
+    /// No more files can be added after this. Calling this again does nothing.
+    pub fn close(&self) -> std::io::Result<()> {
+        let mut file = match self.file.borrow_mut().take() {
+            Some(f) => f,
+            None    => return Ok(()),
+        };
+
+        let cd_offset = file.stream_position()?;
+        let entries = self.central_directory.borrow();
+
+        if cd_offset >= u32::MAX as u64 || entries.len() >= u16::MAX as usize {
+            return Err(std::io::Error::new(ErrorKind::Other, "zip64 not implemented"));
+        }
+
+        let mut cd = Vec::new();
+
+        for hdr in entries.iter() {
+            cd.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            cd.extend_from_slice(&hdr.version_by.to_le_bytes());
+            cd.extend_from_slice(&hdr.version_extract.to_le_bytes());
+            cd.extend_from_slice(&hdr.flags.to_le_bytes());
+            cd.extend_from_slice(&hdr.compression.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_mod_time.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_mod_date.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_crc.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_compressed_size.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_uncompressed_size.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_name_len.to_le_bytes());
+            cd.extend_from_slice(&hdr.extra_field_len.to_le_bytes());
+            cd.extend_from_slice(&hdr.comment_len.to_le_bytes());
+            cd.extend_from_slice(&hdr.disk_num.to_le_bytes());
+            cd.extend_from_slice(&hdr.internal_attrs.to_le_bytes());
+            cd.extend_from_slice(&hdr.external_attrs.to_le_bytes());
+            cd.extend_from_slice(&hdr.file_offset.to_le_bytes());
+            cd.extend_from_slice(hdr.file_name.as_bytes());
+        }
+
+        let cd_size = cd.len() as u32;
+
+        // end of central directory record
+        cd.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        cd.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        cd.extend_from_slice(&0u16.to_le_bytes()); // central directory start disk
+        cd.extend_from_slice(&(entries.len() as u16).to_le_bytes()); // records on this disk
+        cd.extend_from_slice(&(entries.len() as u16).to_le_bytes()); // total records
+        cd.extend_from_slice(&cd_size.to_le_bytes());
+        cd.extend_from_slice(&(cd_offset as u32).to_le_bytes());
+        cd.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        file.write_all(&cd)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Compresses `data` with raw deflate, as stored in zip files.
+fn deflate_raw(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut strm = zlib::z_stream::default();
+
+    if unsafe { zlib::deflateInit2_(
+        &mut strm,
+        zlib::Z_DEFAULT_COMPRESSION,
+        zlib::Z_DEFLATED,
+        -15, // raw compression, no zlib headers
+        8,
+        zlib::Z_DEFAULT_STRATEGY,
+        c"1.3".as_ptr(),
+        std::mem::size_of::<zlib::z_stream>() as i32
+    )} != zlib::Z_OK {
+        return Err(std::io::Error::new(ErrorKind::Other, "couldn't initialize zlib"));
+    }
+
+    let bound = unsafe { zlib::deflateBound(&mut strm, data.len() as _) };
+    let mut compressed = vec![0u8; bound as usize];
+
+    strm.next_in = data.as_ptr();
+    strm.avail_in = data.len() as u32;
+    strm.next_out = compressed.as_mut_ptr();
+    strm.avail_out = compressed.len() as u32;
+
+    let r = unsafe { zlib::deflate(&mut strm, zlib::Z_FINISH) };
+    let total_out = strm.total_out as usize;
+
+    unsafe { zlib::deflateEnd(&mut strm) };
+
+    if r != zlib::Z_STREAM_END {
+        return Err(std::io::Error::new(ErrorKind::Other, format!("deflate failed: {}", r)));
+    }
+
+    compressed.truncate(total_out);
+
+    Ok(compressed)
+}
+
+/// The current local time as MS-DOS `(time, date)`, as used in zip headers.
+fn dos_time_now() -> (u16, u16) {
+    let st = unsafe { windows::Win32::System::SystemInformation::GetLocalTime() };
+
+    let time = (st.wHour << 11) | (st.wMinute << 5) | (st.wSecond / 2);
+    let date = (st.wYear.saturating_sub(1980) << 9) | (st.wMonth << 5) | st.wDay;
+
+    (time, date)
 }
 
 impl ZipFile {
+    /// Returns `true` if there is a file or directory at `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        self.central_directory.contains_key(&normalize_path(path))
+    }
+
+    /// Extracts every file in the archive into `dest`, creating it and any
+    /// intermediate directories as needed.
+    ///
+    /// Entries with a path that would end up outside of `dest`, such as
+    /// absolute paths or paths containing `..`, are not extracted. These and
+    /// any entries that can't be read or written are passed to `on_error` and
+    /// skipped.
+    ///
+    /// Returns the number of files written.
+    pub fn extract_all<F>(&mut self, dest: &Path, mut on_error: F) -> std::io::Result<usize>
+    where
+        F: FnMut(&str, &std::io::Error)
+    {
+        std::fs::create_dir_all(dest)?;
+
+        let mut entries: Vec<(String, bool)> = self.central_directory.values()
+            .map(|cd| (cd.file_name.clone(), cd.is_directory()))
+            .collect();
+
+        entries.sort();
+
+        let mut written = 0;
+
+        for (name, is_dir) in entries {
+            let path = match entry_dest_path(dest, &name) {
+                Some(p) => p,
+                None => {
+                    on_error(&name, &std::io::Error::new(ErrorKind::InvalidInput, "path is outside of the destination folder"));
+                    continue;
+                },
+            };
+
+            if is_dir {
+                if let Err(err) = std::fs::create_dir_all(&path) { on_error(&name, &err); }
+                continue;
+            }
+
+            if let Some(parent) = path.parent() {
+                if let Err(err) = std::fs::create_dir_all(parent) {
+                    on_error(&name, &err);
+                    continue;
+                }
+            }
+
+            let data = match self.file_content(&name) {
+                Ok(d) => d,
+                Err(err) => {
+                    on_error(&name, &err);
+                    continue;
+                },
+            };
+
+            match std::fs::write(&path, &data) {
+                Ok(_) => written += 1,
+                Err(err) => on_error(&name, &err),
+            }
+        }
+
+        Ok(written)
+    }
+
     fn find_central_directory(&mut self) -> std::io::Result<()> {
         let mut eocd_start: i64 = -22;
 
@@ -122,7 +556,7 @@ impl ZipFile {
         while cd_read_size < self.cd_size {
             let cd = self.read_central_directory_header()?;
             cd_read_size += cd.size() as u64;
-            self.central_directory.insert(cd.file_name.to_lowercase().replace("\\","/"),cd);
+            self.central_directory.insert(normalize_path(&cd.file_name),cd);
         }
 
         Ok(())
@@ -160,7 +594,7 @@ impl ZipFile {
     }
 
     fn file_content(&mut self, path: &str) -> std::io::Result<Vec<u8>> {
-        let pathlower = path.to_lowercase().replace("\\","/");
+        let pathlower = normalize_path(path);
 
         if !self.central_directory.contains_key(&pathlower) {
             return Err(std::io::Error::from(ErrorKind::NotFound));
@@ -269,6 +703,10 @@ impl ZipFile {
 }
 
 impl ZipCentralDirectoryHeader {
+    fn is_directory(&self) -> bool {
+        self.external_attrs & 0x010 > 0
+    }
+
     fn size(&self) -> usize {
         /*
         4 signature
@@ -293,11 +731,17 @@ impl ZipCentralDirectoryHeader {
     }
 }
 
-mod zlib {
+pub(crate) mod zlib {
     use std::ffi::{c_int, c_uint, c_ulong, c_char, c_void};
 
+    pub const Z_OK: c_int = 0;
     pub const Z_FINISH: c_int = 4;
     pub const Z_STREAM_END: c_int = 1;
+    pub const Z_BUF_ERROR: c_int = -5;
+
+    pub const Z_DEFLATED: c_int = 8;
+    pub const Z_DEFAULT_COMPRESSION: c_int = -1;
+    pub const Z_DEFAULT_STRATEGY: c_int = 0;
 
     #[repr(C)]
     pub struct z_stream {
@@ -359,6 +803,21 @@ mod zlib {
         pub fn inflate(strm: *mut z_stream, flush: c_int) -> c_int;
         pub fn inflateEnd(strm: *mut z_stream) -> c_int;
 
+        pub fn deflateInit2_(
+            strm: *mut z_stream,
+            level: c_int,
+            method: c_int,
+            windowBits: c_int,
+            memLevel: c_int,
+            strategy: c_int,
+            version: *const c_char,
+            stream_size: c_int
+        ) -> c_int;
+
+        pub fn deflate(strm: *mut z_stream, flush: c_int) -> c_int;
+        pub fn deflateEnd(strm: *mut z_stream) -> c_int;
+        pub fn deflateBound(strm: *mut z_stream, sourceLen: c_ulong) -> c_ulong;
+
         pub fn crc32(crc: c_ulong, buf: *const u8, len: c_uint) -> c_ulong;
     }
 }
@@ -11,7 +11,7 @@ Zip Files
     The ZipFile class allows access to a zip file and the compressed files within.
 
 */
-use crate::zip::ZipFile;
+use crate::zip::{ZipFile, ZipVfs, ZipWriter, ZipCompression};
 
 use std::rc::Rc;
 use std::mem::ManuallyDrop;
@@ -20,15 +20,19 @@ use crate::lua;
 use crate::lua::lua_State;
 use crate::lua::luaL_Reg;
 use crate::lua::luaL_Reg_list;
+use crate::module_permissions::{self, Permission};
 
 use std::ops::DerefMut;
 
 const ZIP_METATABLE_NAME: &str = "ZipFile";
 
 const ZIP_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
-    c"entries", entries,
-    c"__gc"   , __gc,
-    c"content", content,
+    c"entries"   , entries,
+    c"__gc"      , __gc,
+    c"content"   , content,
+    c"has"       , has,
+    c"read"      , read,
+    c"extractall", extract_all,
 };
 
 pub fn pushzipfile(l: &lua_State, zip: Rc<ZipFile>) {
@@ -149,3 +153,496 @@ unsafe extern "C" fn content(l: &lua_State) -> i32 {
 
     return 1;
 }
+
+/*** RST
+    .. lua:method:: has(path)
+
+        Returns ``true`` if there is a file or directory at ``path``.
+
+        :param string path:
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn has(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let zip = unsafe { checkzipfile(l, 1) };
+    let path = lua::tostring(l, 2).unwrap();
+
+    lua::pushboolean(l, zip.contains(&path));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: read(path)
+
+        Returns the decompressed content of the file at ``path``, or ``nil``
+        if there is no such file.
+
+        Unlike :lua:meth:`content`, a missing file is not logged. If the entry
+        exists but can't be decompressed, for example because it is corrupt,
+        an error is logged and ``nil`` is returned.
+
+        :param string path:
+        :rtype: string
+
+        .. code-block:: lua
+            :caption: Example
+
+            local pack = overlay.openzip('markers.zip')
+
+            if pack:has('markers.xml') then
+                local xml = pack:read('markers.xml')
+            end
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn read(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let mut zipref = unsafe { checkzipfile(l, 1) };
+    let path = lua::tostring(l, 2).unwrap();
+
+    let zip: &mut ZipFile = match Rc::get_mut(zipref.deref_mut()) {
+        Some(z) => z,
+        None => {
+            crate::overlay::lua::luaerror!(l, "Couldn't read {} from zip: zip file is in use", path);
+            lua::pushnil(l);
+            return 1;
+        },
+    };
+
+    match zip.file_content(&path) {
+        Ok(data) => {
+            let bytes: &[i8] = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const i8, data.len()) };
+            lua::pushbytes(l, bytes);
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => lua::pushnil(l),
+        Err(err) => {
+            crate::overlay::lua::luaerror!(l, "Error while reading {} from zip: {}", path, err);
+            lua::pushnil(l);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: extractall(destfolder)
+
+        Extracts every file in this zip file into ``destfolder``, keeping the
+        directory structure of the zip. ``destfolder`` and any directories
+        within it are created if they do not already exist.
+
+        Entries with a path that would be written outside of ``destfolder``,
+        such as absolute paths or paths containing ``..``, are not extracted.
+        An error is logged for each of these, and for any entry that can't be
+        read or written, and extraction continues with the next entry.
+
+        Existing files are overwritten.
+
+        :param string destfolder:
+        :returns: The number of files written, or ``nil`` if ``destfolder``
+            couldn't be created.
+        :rtype: integer
+
+        .. code-block:: lua
+            :caption: Example
+
+            local overlay = require 'overlay'
+
+            local pack = overlay.openzip('downloads/pack.zip')
+            local count = pack:extractall(overlay.datafolder('my-module') .. '/pack')
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn extract_all(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 2);
+    let mut zipref = unsafe { checkzipfile(l, 1) };
+    let dest = lua::tostring(l, 2).unwrap();
+
+    let zip: &mut ZipFile = match Rc::get_mut(zipref.deref_mut()) {
+        Some(z) => z,
+        None => {
+            crate::overlay::lua::luaerror!(l, "Couldn't extract zip: zip file is in use");
+            return 0;
+        },
+    };
+
+    let r = zip.extract_all(std::path::Path::new(&dest), |name, err| {
+        crate::overlay::lua::luaerror!(l, "Couldn't extract {}: {}", name, err);
+    });
+
+    match r {
+        Ok(count) => lua::pushinteger(l, count as i64),
+        Err(err) => {
+            crate::overlay::lua::luaerror!(l, "Couldn't create {}: {}", dest, err);
+            return 0;
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:class:: zipvfs
+
+    A zip file mounted as a read-only file system, see :lua:func:`mountzip`.
+
+    Paths are relative to the root of the zip file and are not case sensitive.
+    Files are decompressed the first time they are read and kept in memory so
+    that reading the same file again is inexpensive.
+
+    A mounted pack can be combined with :lua:func:`setresourceloader` so that
+    textures can be loaded from it directly:
+
+    .. code-block:: lua
+        :caption: Example
+
+        local pack = overlay.mountzip('packs/tekkit.zip')
+
+        overlay.setresourceloader('tekkit', function(uri)
+            return pack:read(uri:sub(#'tekkit://' + 1))
+        end)
+
+        textures:add('heart', 'tekkit://icons/heart.png')
+
+*/
+const ZIPVFS_METATABLE_NAME: &str = "ZipVfs";
+
+const ZIPVFS_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__gc"  , vfs_gc,
+    c"read"  , vfs_read,
+    c"exists", vfs_exists,
+    c"list"  , vfs_list,
+};
+
+pub fn pushzipvfs(l: &lua_State, vfs: Rc<ZipVfs>) {
+    let vfs_ptr = Rc::into_raw(vfs.clone());
+
+    let lua_vfs_ptr: *mut *const ZipVfs = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<*const ZipVfs>(), 0))
+    };
+
+    unsafe { *lua_vfs_ptr = vfs_ptr; }
+
+    if lua::L::newmetatable(l, ZIPVFS_METATABLE_NAME) {
+        lua::pushvalue(l, -1);
+        lua::setfield(l, -2, "__index");
+
+        lua::L::setfuncs(l, ZIPVFS_FUNCS, 0);
+    }
+    lua::setmetatable(l, -2);
+}
+
+pub unsafe fn checkzipvfs(l: &lua_State, ind: i32) -> ManuallyDrop<Rc<ZipVfs>> {
+    let ptr: *mut *const ZipVfs = unsafe { std::mem::transmute(lua::L::checkudata(l, ind, ZIPVFS_METATABLE_NAME)) };
+
+    ManuallyDrop::new(unsafe { Rc::from_raw(*ptr) })
+}
+
+unsafe extern "C" fn vfs_gc(l: &lua_State) -> i32 {
+    let mut vfs = unsafe { checkzipvfs(l, 1) };
+
+    unsafe { ManuallyDrop::drop(&mut vfs); }
+
+    return 0;
+}
+
+/*** RST
+    .. lua:method:: read(path)
+
+        Returns the content of the file at ``path``, or ``nil`` if it doesn't
+        exist or couldn't be read.
+
+        :param string path:
+        :rtype: string
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vfs_read(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let vfs = unsafe { checkzipvfs(l, 1) };
+    let path = lua::tostring(l, 2).unwrap();
+
+    match vfs.read(&path) {
+        Ok(data) => {
+            let bytes: &[i8] = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const i8, data.len()) };
+            lua::pushbytes(l, bytes);
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => lua::pushnil(l),
+        Err(err) => {
+            crate::overlay::lua::luawarn!(l, "Error while reading {} from zip: {}", path, err);
+            lua::pushnil(l);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: exists(path)
+
+        Returns ``true`` if there is a file or directory at ``path``.
+
+        :param string path:
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vfs_exists(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    let vfs = unsafe { checkzipvfs(l, 1) };
+    let path = lua::tostring(l, 2).unwrap();
+
+    lua::pushboolean(l, vfs.exists(&path));
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: list([prefix])
+
+        Returns a sequence of the paths of all files that start with ``prefix``,
+        or all files if ``prefix`` isn't given. Directories are not included.
+
+        Paths are returned in lower case, sorted alphabetically.
+
+        :param string prefix: (Optional)
+        :rtype: table
+
+        .. code-block:: lua
+            :caption: Example
+
+            for _, path in ipairs(pack:list('data/')) do
+                if path:match('%.xml$') then
+                    loadmarkers(pack:read(path))
+                end
+            end
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn vfs_list(l: &lua_State) -> i32 {
+    let vfs = unsafe { checkzipvfs(l, 1) };
+
+    let prefix = if lua::gettop(l) >= 2 && lua::luatype(l, 2) != lua::LuaType::LUA_TNIL {
+        lua::checkargstring!(l, 2);
+        lua::tostring(l, 2).unwrap()
+    } else {
+        String::new()
+    };
+
+    let paths = vfs.list(&prefix);
+
+    lua::createtable(l, paths.len() as i32, 0);
+
+    for (i, path) in paths.iter().enumerate() {
+        lua::pushstring(l, path);
+        lua::seti(l, -2, i as i64 + 1);
+    }
+
+    return 1;
+}
+
+/*** RST
+.. lua:class:: zipwriter
+
+    Creates a new zip file, see :lua:func:`createzip`.
+
+    Files are written to the zip as they are added, but the zip will not be
+    valid until :lua:meth:`close` is called. If a zipwriter is garbage
+    collected without being closed it will be closed then and a warning will be
+    logged.
+
+    .. code-block:: lua
+        :caption: Example
+
+        local overlay = require 'overlay'
+
+        local z = overlay.createzip(overlay.datafolder('my-module') .. '/export.zip')
+
+        z:add('routes.json', overlay.tojson(routes))
+        z:addfile('icons/route.png', 'icons/route.png', 'store')
+        z:close()
+
+*/
+const ZIPWRITER_METATABLE_NAME: &str = "ZipWriter";
+
+const ZIPWRITER_FUNCS: &[luaL_Reg] = luaL_Reg_list!{
+    c"__gc"   , writer_gc,
+    c"add"    , writer_add,
+    c"addfile", writer_add_file,
+    c"close"  , writer_close,
+};
+
+pub fn pushzipwriter(l: &lua_State, writer: Rc<ZipWriter>) {
+    let writer_ptr = Rc::into_raw(writer.clone());
+
+    let lua_writer_ptr: *mut *const ZipWriter = unsafe {
+        std::mem::transmute(lua::newuserdatauv(l, std::mem::size_of::<*const ZipWriter>(), 0))
+    };
+
+    unsafe { *lua_writer_ptr = writer_ptr; }
+
+    if lua::L::newmetatable(l, ZIPWRITER_METATABLE_NAME) {
+        lua::pushvalue(l, -1);
+        lua::setfield(l, -2, "__index");
+
+        lua::L::setfuncs(l, ZIPWRITER_FUNCS, 0);
+    }
+    lua::setmetatable(l, -2);
+}
+
+pub unsafe fn checkzipwriter(l: &lua_State, ind: i32) -> ManuallyDrop<Rc<ZipWriter>> {
+    let ptr: *mut *const ZipWriter = unsafe { std::mem::transmute(lua::L::checkudata(l, ind, ZIPWRITER_METATABLE_NAME)) };
+
+    ManuallyDrop::new(unsafe { Rc::from_raw(*ptr) })
+}
+
+unsafe extern "C" fn writer_gc(l: &lua_State) -> i32 {
+    let mut writer = unsafe { checkzipwriter(l, 1) };
+
+    if !writer.is_closed() {
+        crate::logging::warn!("zipwriter for {} was not closed, closing it now.", writer.path());
+
+        if let Err(err) = writer.close() {
+            crate::logging::error!("Couldn't close {}: {}", writer.path(), err);
+        }
+    }
+
+    unsafe { ManuallyDrop::drop(&mut writer); }
+
+    return 0;
+}
+
+/// Returns the compression named by the optional argument at `ind`.
+///
+/// Raises a Lua error if it is not a valid name.
+fn checkcompression(l: &lua_State, ind: i32) -> ZipCompression {
+    if lua::gettop(l) < ind || lua::luatype(l, ind) == lua::LuaType::LUA_TNIL {
+        return ZipCompression::Deflate;
+    }
+
+    match lua::tostring(l, ind).as_deref() {
+        Some("deflate") => ZipCompression::Deflate,
+        Some("store")   => ZipCompression::Store,
+        _ => {
+            lua::pushstring(l, "compression must be 'deflate' or 'store'");
+            unsafe { lua::error(l); }
+            unreachable!();
+        },
+    }
+}
+
+/*** RST
+    .. lua:method:: add(name, data[, compression])
+
+        Adds a file named ``name`` to the zip containing ``data``.
+
+        ``compression`` can be ``'deflate'`` (the default) or ``'store'``,
+        which stores the data uncompressed. ``'store'`` is best for data that
+        is already compressed, such as PNG images.
+
+        :param string name: The path of the file within the zip.
+        :param string data:
+        :param string compression: (Optional)
+        :returns: ``true`` if the file was added, otherwise an error is logged
+            and ``false`` is returned.
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn writer_add(l: &lua_State) -> i32 {
+    lua::checkargstring!(l, 2);
+    lua::checkargstring!(l, 3);
+    let writer = unsafe { checkzipwriter(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+    let data: &[u8] = lua::tobytes(l, 3);
+    let compression = checkcompression(l, 4);
+
+    match writer.add(&name, data, compression) {
+        Ok(_) => lua::pushboolean(l, true),
+        Err(err) => {
+            crate::overlay::lua::luaerror!(l, "Couldn't add {} to {}: {}", name, writer.path(), err);
+            lua::pushboolean(l, false);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: addfile(name, srcpath[, compression])
+
+        Adds the file at ``srcpath`` to the zip as ``name``.
+
+        ``compression`` is the same as :lua:meth:`add`.
+
+        :param string name: The path of the file within the zip.
+        :param string srcpath: The path of the file to add.
+        :param string compression: (Optional)
+        :returns: ``true`` if the file was added, otherwise an error is logged
+            and ``false`` is returned.
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn writer_add_file(l: &lua_State) -> i32 {
+    if !module_permissions::check(l, Permission::Files) { return 0; }
+
+    lua::checkargstring!(l, 2);
+    lua::checkargstring!(l, 3);
+    let writer = unsafe { checkzipwriter(l, 1) };
+    let name = lua::tostring(l, 2).unwrap();
+    let src = lua::tostring(l, 3).unwrap();
+    let compression = checkcompression(l, 4);
+
+    match writer.add_file(&name, &src, compression) {
+        Ok(_) => lua::pushboolean(l, true),
+        Err(err) => {
+            crate::overlay::lua::luaerror!(l, "Couldn't add {} to {}: {}", src, writer.path(), err);
+            lua::pushboolean(l, false);
+        },
+    }
+
+    return 1;
+}
+
+/*** RST
+    .. lua:method:: close()
+
+        Finishes writing the zip and closes it. No more files can be added
+        after this.
+
+        Calling this on a zipwriter that is already closed does nothing.
+
+        :returns: ``true`` if the zip was written, otherwise an error is logged
+            and ``false`` is returned.
+        :rtype: boolean
+
+        .. versionhistory::
+            :0.3.0: Added
+*/
+unsafe extern "C" fn writer_close(l: &lua_State) -> i32 {
+    let writer = unsafe { checkzipwriter(l, 1) };
+
+    match writer.close() {
+        Ok(_) => lua::pushboolean(l, true),
+        Err(err) => {
+            crate::overlay::lua::luaerror!(l, "Couldn't close {}: {}", writer.path(), err);
+            lua::pushboolean(l, false);
+        },
+    }
+
+    return 1;
+}